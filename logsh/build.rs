@@ -41,7 +41,7 @@ fn write_string(s: &mut String, key: &'_ str, value: &'_ str) {
         .trim_start_matches('"')
         .trim_end_matches('"')
         .replace('\"', "\\\"");
-    let upper = key.to_ascii_uppercase();
+    let upper = key.to_ascii_uppercase().replace('-', "_");
     s.push_str(&format!(
         concat!(
             "/// Generated accessor for package.{} from Cargo.toml\n",
@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Builds a query response body shaped like the server's kusto search results.
+fn response(rows: usize) -> String {
+    let mut s = String::from(r#"{"header":["timestamp","level","message","count","payload"],"results":["#);
+    for i in 0..rows {
+        if i > 0 {
+            s.push(',');
+        }
+        s.push_str(&format!(
+            r#"{{"timestamp":"2023-09-01T12:00:{:02}Z","level":"Information","message":"Request \"{}\" completed\nin time","count":{},"payload":{{"id":{},"ok":true}}}}"#,
+            i % 60,
+            i,
+            i,
+            i
+        ));
+    }
+    s.push_str("]}");
+    s
+}
+
+fn write_csv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_csv");
+    for rows in [1_000, 100_000] {
+        let body = response(rows);
+        let result = logsh_core::query::result(&body).expect("valid query result");
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &result, |b, result| {
+            b.iter(|| logsh_core::csv::write_csv(result, std::io::sink()).expect("csv written"))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, write_csv);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use thiserror::Error;
+
+use crate::{
+    error::FrameError,
+    frame::{Cell, Column, DataFrame},
+    query::QueryResult,
+};
+
+#[derive(Debug, Error)]
+pub enum ArrowError {
+    #[error("Failed to write arrow stream: {0}")]
+    FailedWrite(#[from] arrow_schema::ArrowError),
+
+    #[error("{0}")]
+    FailedRead(#[from] FrameError),
+}
+
+/// Writes a query result as a single record batch in the Arrow IPC streaming format.
+///
+/// Columns take their [DataFrame] types: integers become Int64, other numbers Float64,
+/// booleans Boolean, and timestamps UTC microsecond timestamps. Anything else is written as
+/// Utf8, with objects and arrays as JSON text.
+pub fn write_arrow<W: std::io::Write>(query: &QueryResult<'_>, to: W) -> Result<(), ArrowError> {
+    let frame = DataFrame::from_result(query)?;
+    let mut fields = Vec::with_capacity(frame.names().len());
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(frame.names().len());
+    for (name, column) in frame.columns() {
+        let (typ, array) = build_column(column);
+        fields.push(Field::new(name, typ, true));
+        columns.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut wtr = StreamWriter::try_new(to, &schema)?;
+    wtr.write(&batch)?;
+    wtr.finish()?;
+    Ok(())
+}
+
+fn build_column(column: &Column) -> (DataType, ArrayRef) {
+    match column {
+        Column::Bool(values) => {
+            let mut b = BooleanBuilder::with_capacity(values.len());
+            values.iter().for_each(|v| b.append_option(*v));
+            (DataType::Boolean, Arc::new(b.finish()))
+        }
+        Column::Int(values) => {
+            let mut b = Int64Builder::with_capacity(values.len());
+            values.iter().for_each(|v| b.append_option(*v));
+            (DataType::Int64, Arc::new(b.finish()))
+        }
+        Column::Float(values) => {
+            let mut b = Float64Builder::with_capacity(values.len());
+            values.iter().for_each(|v| b.append_option(*v));
+            (DataType::Float64, Arc::new(b.finish()))
+        }
+        Column::DateTime(values) => {
+            let mut b = TimestampMicrosecondBuilder::with_capacity(values.len()).with_timezone("UTC");
+            values.iter().for_each(|v| b.append_option(v.map(|dt| dt.timestamp_micros())));
+            let typ = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+            (typ, Arc::new(b.finish()))
+        }
+        _ => {
+            let mut b = StringBuilder::new();
+            column.iter().for_each(|cell| match cell {
+                Cell::Null => b.append_null(),
+                cell => b.append_value(cell.to_string()),
+            });
+            (DataType::Utf8, Arc::new(b.finish()))
+        }
+    }
+}
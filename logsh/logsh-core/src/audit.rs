@@ -0,0 +1,51 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capabilities::Capability,
+    error::{self, AuditError},
+    logship_client::LogshClientHandler,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventModel {
+    pub timestamp: DateTime<Utc>,
+    pub category: String,
+    pub action: String,
+    pub user_id: Option<uuid::Uuid>,
+    pub username: Option<String>,
+    pub target: Option<String>,
+    pub source_address: Option<String>,
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+/// Lists audit events for the default subscription, such as logins, queries and admin actions.
+pub fn list_audit_events(
+    connection: &LogshClientHandler,
+    since: DateTime<Utc>,
+    category: Option<&str>,
+    limit: Option<u32>,
+) -> Result<Vec<AuditEventModel>, AuditError> {
+    connection.require(Capability::Audit)?;
+    let subscription = connection.get_subscription()?;
+    let mut query_url = format!(
+        "accounts/{}/audit?since={}",
+        subscription,
+        since.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    if let Some(category) = category {
+        query_url.push_str(&format!("&category={}", category));
+    }
+    if let Some(limit) = limit {
+        query_url.push_str(&format!("&limit={}", limit));
+    }
+
+    let result = connection.execute_func(&|client| -> Result<Vec<AuditEventModel>, error::ClientError> {
+        let result = client.get_json(&query_url)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
@@ -1,6 +1,7 @@
 use std::{collections::HashMap, ops::Add};
 
-use chrono::{Utc, Duration};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
 use reqwest::blocking::Client;
 use serde::Deserialize;
 
@@ -21,10 +22,7 @@ where
     map.insert("username", username);
     map.insert("password", password()?);
     let res = client
-        .post(format!(
-            "{}/auth/token",
-            connection.server.trim_end_matches('/')
-        ))
+        .post(crate::endpoint::url(&connection.server, "auth/token")?)
         .json(&map)
         .send()?
         .error_for_status()?;
@@ -36,6 +34,20 @@ where
     })
 }
 
+/// The expiry in a JWT's `exp` claim. None for tokens that aren't JWTs or don't expire. The
+/// signature isn't checked; that's the server's job.
+pub fn expiry(token: &str) -> Option<DateTime<Utc>> {
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    DateTime::from_timestamp(claims.exp?, 0)
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    exp: Option<i64>,
+}
+
 #[derive(Deserialize)]
 struct TokenResponse {
     token: String,
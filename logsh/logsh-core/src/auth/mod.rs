@@ -2,12 +2,18 @@ use chrono::{DateTime, Utc};
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::{connect::Connection, error::ConnectError};
+use crate::{
+    connect::Connection,
+    error::{AuthError, ConnectError, OAuthError},
+};
 
-use self::oauth::{OAuthData, OAuthFlow};
+use self::oauth::{IdentityProvider, OAuthData, OAuthFlow, OAuthOptions};
 
+pub mod hmac;
 pub mod jwt;
+pub mod negotiate;
 pub mod oauth;
+pub mod token_cache;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum AuthData {
@@ -19,6 +25,22 @@ pub enum AuthData {
         expires: Option<DateTime<Utc>>,
         data: OAuthData
     },
+    /// Signs every request with a shared secret instead of sending a token.
+    Hmac {
+        key_id: String,
+        secret: String,
+    },
+    /// Kerberos single sign-on. A fresh ticket is presented with every request, so nothing
+    /// secret is stored.
+    Negotiate {
+        spn: Option<String>,
+        principal: Option<String>,
+    },
+    /// A bearer token issued elsewhere, such as LOGSH_TOKEN. It can't be renewed, only replaced.
+    Token {
+        expires: Option<DateTime<Utc>>,
+        token: String,
+    },
 }
 
 pub enum AuthRequest<F>
@@ -36,6 +58,21 @@ where
         authorize_endpoint: String,
         token_endpoint: String,
         flow: OAuthFlow,
+        /// Fills in endpoints and scopes left empty, instead of discovering them from the server.
+        provider: Option<IdentityProvider>,
+        tenant: Option<String>,
+    },
+    Hmac {
+        key_id: String,
+        secret: F,
+    },
+    Negotiate {
+        spn: Option<String>,
+        principal: Option<String>,
+    },
+    /// A bearer token issued elsewhere, such as one handed to a container.
+    Token {
+        token: F,
     },
 }
 
@@ -52,17 +89,39 @@ where
                 client_id,
                 flow,
                 device_endpoint,
-                scopes: _,
+                scopes,
                 authorize_endpoint,
                 token_endpoint,
+                provider,
+                tenant,
             } => {
-                log::debug!("Refreshing oauth info from server.");
                 let mut client_id = client_id;
                 let mut authorize_endpoint = authorize_endpoint;
                 let mut token_endpoint = token_endpoint;
                 let mut device_endpoint = device_endpoint;
-                let mut scopes = vec![];
-                if client_id.trim() == "" {
+                let mut scopes = scopes;
+                if let Some(provider) = provider {
+                    let preset = provider.endpoints(tenant.as_deref()).map_err(AuthError::OAuth)?;
+                    if client_id.trim() == "" {
+                        return Err(AuthError::OAuth(OAuthError::Provider(format!(
+                            "{:?} logins need the client id of an application registered with the identity provider.",
+                            provider
+                        ))))?;
+                    }
+                    if authorize_endpoint.trim() == "" {
+                        authorize_endpoint = preset.authorize_endpoint;
+                    }
+                    if token_endpoint.trim() == "" {
+                        token_endpoint = preset.token_endpoint;
+                    }
+                    if device_endpoint.is_none() {
+                        device_endpoint = Some(preset.device_endpoint);
+                    }
+                    if scopes.is_empty() {
+                        scopes = preset.scopes;
+                    }
+                } else if client_id.trim() == "" {
+                    log::debug!("Refreshing oauth info from server.");
                     let oauth = connection.refresh_oauth()?;
                     client_id = oauth.client_id;
                     authorize_endpoint = oauth.authorize_endpoint;
@@ -71,19 +130,52 @@ where
                     scopes = oauth.scopes;
                 }
 
+                let cache_key = token_cache::key(&token_endpoint, &client_id, &scopes);
+                let cache = token_cache::load()
+                    .map_err(|err| log::warn!("Ignoring the OAuth token cache: {}", err))
+                    .ok();
+                if let Some((expires, mut data)) = cache.as_ref().and_then(|c| c.get(&cache_key)) {
+                    log::info!("Reusing a cached OAuth token from another connection.");
+                    data.provider = provider;
+                    data.tenant = tenant;
+                    return Ok(AuthData::OAuth { expires, data });
+                }
+
                 let never = || -> Result<String, ConnectError> { Ok(String::new()) };
-                return oauth::authenticate(
+                let auth = oauth::authenticate(
                     connection,
                     &client,
                     None,
                     Some(never),
-                    client_id,
-                    authorize_endpoint,
-                    token_endpoint,
-                    scopes,
-                    device_endpoint,
-                    flow,
-                );
+                    OAuthOptions {
+                        client_id,
+                        authorize_endpoint,
+                        token_endpoint,
+                        scopes,
+                        device_endpoint,
+                        flow,
+                        provider,
+                        tenant,
+                    },
+                )?;
+
+                if let (Some(mut cache), AuthData::OAuth { expires, data }) = (cache, &auth) {
+                    cache.insert(cache_key, *expires, data.clone());
+                    if let Err(err) = token_cache::save(cache) {
+                        log::warn!("Failed to save the OAuth token cache: {}", err);
+                    }
+                }
+                Ok(auth)
+            }
+            AuthRequest::Hmac { key_id, secret } => Ok(AuthData::Hmac { key_id, secret: secret()? }),
+            AuthRequest::Token { token } => {
+                let token = token()?;
+                Ok(AuthData::Token { expires: jwt::expiry(&token), token })
+            }
+            AuthRequest::Negotiate { spn, principal } => {
+                // Acquire a ticket now, so a missing or expired login is reported while adding the connection.
+                negotiate::authorization(&connection.server, spn.as_deref(), principal.as_deref())?;
+                Ok(AuthData::Negotiate { spn, principal })
             }
         }
     }
@@ -0,0 +1,45 @@
+use crate::error::{AuthError, ConnectError};
+
+/// Service class of the principal a logship server runs as.
+const SERVICE: &str = "HTTP";
+
+/// Builds the `Authorization` header value for Kerberos single sign-on, using a ticket for the
+/// server's principal from the user's credential cache, such as one filled by `kinit` or a
+/// domain login.
+///
+/// `spn` overrides the server principal, which defaults to `HTTP@host` (`HTTP/host` on
+/// Windows). `principal` picks the client principal when the cache holds more than one.
+#[cfg(feature = "negotiate")]
+pub fn authorization(server: &str, spn: Option<&str>, principal: Option<&str>) -> Result<String, ConnectError> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use cross_krb5::{ClientCtx, InitiateFlags};
+
+    let spn = match spn {
+        Some(spn) => spn.to_string(),
+        None => default_spn(server)?,
+    };
+    log::debug!("Acquiring negotiate token for {}", spn);
+    let (_pending, token) = ClientCtx::new(InitiateFlags::empty(), principal, &spn, None)
+        .map_err(|err| AuthError::Negotiate(format!("failed to acquire a ticket for {}: {}", spn, err)))?;
+    Ok(format!("Negotiate {}", STANDARD.encode(&*token)))
+}
+
+#[cfg(not(feature = "negotiate"))]
+pub fn authorization(_server: &str, _spn: Option<&str>, _principal: Option<&str>) -> Result<String, ConnectError> {
+    Err(AuthError::Negotiate(
+        "this build of logsh doesn't support negotiate authentication. Rebuild with the \"negotiate\" feature.".to_string(),
+    ))?
+}
+
+#[cfg_attr(not(feature = "negotiate"), allow(dead_code))]
+fn default_spn(server: &str) -> Result<String, ConnectError> {
+    let host = reqwest::Url::parse(server)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .ok_or_else(|| AuthError::Negotiate(format!("can't derive a service principal from server \"{}\". Set one with --spn.", server)))?;
+    if cfg!(windows) {
+        Ok(format!("{}/{}", SERVICE, host))
+    } else {
+        Ok(format!("{}@{}", SERVICE, host))
+    }
+}
@@ -5,7 +5,7 @@ use oauth2::{
     basic::{BasicClient, BasicTokenType},
     reqwest::http_client,
     AuthUrl, ClientId, DeviceAuthorizationUrl, EmptyExtraTokenFields, Scope,
-    StandardDeviceAuthorizationResponse, StandardTokenResponse, TokenUrl,
+    StandardDeviceAuthorizationResponse, StandardTokenResponse, TokenResponse, TokenUrl,
 };
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
@@ -36,6 +36,148 @@ pub struct OAuthData {
     pub scopes: Vec<String>,
     pub token: OAuthToken,
     pub flow: OAuthFlow,
+    /// Identity provider the endpoints came from, when they weren't discovered from the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<IdentityProvider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+/// Identity providers with well known OAuth endpoints, so a connection can log in without the
+/// server's `/auth/oauth` discovery endpoint.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentityProvider {
+    /// Microsoft Entra ID. The tenant is a directory id or domain, and defaults to `organizations`.
+    Azure,
+    /// Okta. The tenant is the Okta domain, such as `example.okta.com`, using its default
+    /// authorization server.
+    Okta,
+    /// Google. There's no tenant.
+    Google,
+}
+
+/// OAuth endpoints and scopes for logging in through an identity provider.
+#[derive(Clone, Debug)]
+pub struct ProviderEndpoints {
+    pub authorize_endpoint: String,
+    pub token_endpoint: String,
+    pub device_endpoint: String,
+    pub scopes: Vec<String>,
+}
+
+impl IdentityProvider {
+    pub fn endpoints(&self, tenant: Option<&str>) -> Result<ProviderEndpoints, OAuthError> {
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        let endpoints = match self {
+            IdentityProvider::Azure => {
+                let base = format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0",
+                    tenant.unwrap_or("organizations")
+                );
+                ProviderEndpoints {
+                    authorize_endpoint: format!("{}/authorize", base),
+                    token_endpoint: format!("{}/token", base),
+                    device_endpoint: format!("{}/devicecode", base),
+                    scopes: scopes(&["openid", "profile", "email", "offline_access"]),
+                }
+            }
+            IdentityProvider::Okta => {
+                let domain = tenant
+                    .map(|t| t.trim_start_matches("https://").trim_end_matches('/'))
+                    .ok_or_else(|| OAuthError::Provider("Okta needs --tenant set to the Okta domain, such as example.okta.com.".to_string()))?;
+                let base = format!("https://{}/oauth2/default/v1", domain);
+                ProviderEndpoints {
+                    authorize_endpoint: format!("{}/authorize", base),
+                    token_endpoint: format!("{}/token", base),
+                    device_endpoint: format!("{}/device/authorize", base),
+                    scopes: scopes(&["openid", "profile", "email", "offline_access"]),
+                }
+            }
+            IdentityProvider::Google => ProviderEndpoints {
+                authorize_endpoint: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+                token_endpoint: "https://oauth2.googleapis.com/token".to_string(),
+                device_endpoint: "https://oauth2.googleapis.com/device/code".to_string(),
+                scopes: scopes(&["openid", "profile", "email"]),
+            },
+        };
+
+        Ok(endpoints)
+    }
+}
+
+/// Prints a QR code of the verification URL, so a login started over SSH can be finished on a
+/// phone. The URL includes the code when the provider supports it.
+#[cfg(feature = "qr")]
+fn print_qr(url: &str) {
+    use qrcode::{render::unicode::Dense1x2, QrCode};
+    use std::io::IsTerminal;
+
+    if !std::io::stdout().is_terminal() {
+        return;
+    }
+    match QrCode::new(url) {
+        // Light modules are drawn as blocks, which reads correctly on dark terminal backgrounds.
+        Ok(code) => println!(
+            "{}",
+            code.render::<Dense1x2>()
+                .dark_color(Dense1x2::Light)
+                .light_color(Dense1x2::Dark)
+                .build()
+        ),
+        Err(err) => log::debug!("Skipping verification QR code: {}", err),
+    }
+}
+
+#[cfg(not(feature = "qr"))]
+fn print_qr(_url: &str) {}
+
+/// Exchanges the refresh token of a login for a new access token, without prompting. Returns
+/// None when the login has no refresh token.
+pub fn refresh(data: &OAuthData) -> Result<Option<AuthData>, AuthError> {
+    let Some(refresh_token) = data.token.refresh_token() else {
+        return Ok(None);
+    };
+
+    log::debug!("Exchanging the OAuth refresh token.");
+    let c = BasicClient::new(
+        ClientId::new(data.client_id.clone()),
+        None,
+        AuthUrl::new(data.authorize_endpoint.clone()).map_err(|err| AuthError::OAuth(OAuthError::ParseError(err)))?,
+        Some(TokenUrl::new(data.token_endpoint.clone()).map_err(|err| AuthError::OAuth(OAuthError::ParseError(err)))?),
+    );
+    let mut token = c
+        .exchange_refresh_token(refresh_token)
+        .add_scopes(data.scopes.iter().map(|s| Scope::new(s.clone())))
+        .request(http_client)
+        .map_err(|err| AuthError::OAuth(OAuthError::RefreshTokenErrorResponse(err)))?;
+    // Providers may keep the refresh token the same and leave it out of the response.
+    if token.refresh_token().is_none() {
+        token.set_refresh_token(Some(refresh_token.clone()));
+    }
+
+    Ok(Some(AuthData::OAuth {
+        expires: token.expires_in().map(|lifetime| Utc::now().add(lifetime)),
+        data: OAuthData {
+            received: Utc::now(),
+            token,
+            ..data.clone()
+        },
+    }))
+}
+
+/// The client, endpoints and flow of an OAuth login.
+#[derive(Clone, Debug)]
+pub struct OAuthOptions {
+    pub client_id: String,
+    pub authorize_endpoint: String,
+    pub token_endpoint: String,
+    pub scopes: Vec<String>,
+    pub device_endpoint: Option<String>,
+    pub flow: OAuthFlow,
+    /// Identity provider the endpoints came from, saved with the token.
+    pub provider: Option<IdentityProvider>,
+    pub tenant: Option<String>,
 }
 
 pub fn authenticate<F>(
@@ -43,16 +185,12 @@ pub fn authenticate<F>(
     _client: &Client,
     _username: Option<String>,
     _password: Option<F>,
-    client_id: String,
-    authorize_endpoint: String,
-    token_endpoint: String,
-    scopes: Vec<String>,
-    device_endpoint: Option<String>,
-    flow: OAuthFlow,
+    options: OAuthOptions,
 ) -> Result<AuthData, ConnectError>
 where
     F: FnOnce() -> Result<String, ConnectError>,
 {
+    let OAuthOptions { client_id, authorize_endpoint, token_endpoint, scopes, device_endpoint, flow, provider, tenant } = options;
     let scopes: HashSet<String> = scopes.into_iter().collect();
     // scopes.insert("profile".to_string());
     // scopes.insert("email".to_string());
@@ -84,15 +222,20 @@ where
                 details.verification_uri().to_string(),
                 details.user_code().secret().to_string(),
             );
+            match details.verification_uri_complete() {
+                Some(complete) => print_qr(complete.secret()),
+                None => print_qr(details.verification_uri()),
+            }
 
             let token_result = c
                 .exchange_device_access_token(&details)
                 .request(http_client, std::thread::sleep, None)
                 .map_err(|err| AuthError::OAuth(OAuthError::TokenErrorResponse(err)))?;
             Ok(AuthData::OAuth {
-                expires: Some(Utc::now().add(details.expires_in())),
+                // The device code's lifetime isn't the token's; tokens without one don't expire.
+                expires: token_result.expires_in().map(|lifetime| Utc::now().add(lifetime)),
                 data: OAuthData {
-                    received: Utc::now().add(details.expires_in()),
+                    received: Utc::now(),
                     authorize_endpoint: authorize_endpoint.clone(),
                     client_id: client_id.clone(),
                     token_endpoint: token_endpoint.clone(),
@@ -100,6 +243,8 @@ where
                     scopes: scopes.clone().into_iter().collect(),
                     token: token_result,
                     flow: OAuthFlow::Device,
+                    provider,
+                    tenant,
                 },
             })
         }
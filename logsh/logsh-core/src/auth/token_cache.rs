@@ -0,0 +1,81 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::ConfigError};
+
+use super::oauth::OAuthData;
+
+/// OAuth tokens shared between connections that log in through the same identity provider
+/// and client, so adding another environment reuses a token that's still valid instead of
+/// starting another device login.
+#[derive(Serialize, Deserialize, Default)]
+pub struct TokenCache {
+    tokens: HashMap<String, CachedToken>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedToken {
+    expires: Option<DateTime<Utc>>,
+    data: OAuthData,
+}
+
+impl TokenCache {
+    /// A cached token for the same issuer, client and scopes, if it hasn't expired.
+    pub fn get(&self, key: &str) -> Option<(Option<DateTime<Utc>>, OAuthData)> {
+        let cached = self.tokens.get(key)?;
+        if cached.expires.is_some_and(|expires| Utc::now() >= expires) {
+            return None;
+        }
+        Some((cached.expires, cached.data.clone()))
+    }
+
+    pub fn insert(&mut self, key: String, expires: Option<DateTime<Utc>>, data: OAuthData) {
+        self.tokens.insert(key, CachedToken { expires, data });
+        self.tokens
+            .retain(|_, cached| cached.expires.is_none_or(|expires| Utc::now() < expires));
+    }
+}
+
+/// Cache key of an OAuth login. The token endpoint stands in for the issuer, and scopes are
+/// sorted so their order doesn't matter.
+pub fn key(token_endpoint: &str, client_id: &str, scopes: &[String]) -> String {
+    let mut scopes: Vec<&str> = scopes.iter().map(|s| s.as_str()).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+    format!("{} {} {}", token_endpoint.trim(), client_id.trim(), scopes.join(" "))
+}
+
+/// The token cache lives next to the logsh configuration file.
+pub fn get_token_cache_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("tokens.json"))
+}
+
+pub fn load() -> Result<TokenCache, ConfigError> {
+    let path = get_token_cache_path()?;
+    if !path.exists() {
+        return Ok(TokenCache::default());
+    }
+
+    let tokens = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&tokens).map_err(ConfigError::FailedDeserialize)
+}
+
+/// Saves the cache, unless logsh is in read-only mode.
+pub fn save(cache: TokenCache) -> Result<TokenCache, ConfigError> {
+    if config::read_only() {
+        return Ok(cache);
+    }
+
+    let path = get_token_cache_path()?;
+    let serialized = serde_json::to_string(&cache).map_err(ConfigError::FailedSerialize)?;
+    // Tokens are credentials, so only the user may read them.
+    config::replace(&path, &serialized, true)?;
+    Ok(cache)
+}
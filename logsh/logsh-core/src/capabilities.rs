@@ -0,0 +1,210 @@
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::{
+    config,
+    connect::{Connection, Timeouts},
+    endpoint,
+    error::{CommonError, ConfigError, ConnectError},
+};
+
+/// How long a server's reported version is trusted before it's asked again.
+const CACHE_HOURS: i64 = 24;
+
+/// How long a failure to ask the server is remembered, so commands in the meantime don't each
+/// wait on a server that isn't answering.
+const ERROR_MINUTES: i64 = 5;
+
+/// How long to wait for the version when the connection has no timeout of its own.
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Newer server APIs that logsh only relies on once the server reports them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    Explain,
+    Inflow,
+    Audit,
+    SharedQueries,
+    StreamingUpload,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Explain,
+        Capability::Inflow,
+        Capability::Audit,
+        Capability::SharedQueries,
+        Capability::StreamingUpload,
+    ];
+
+    /// The name the server reports the capability as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Explain => "query.explain",
+            Capability::Inflow => "inflow.list",
+            Capability::Audit => "audit",
+            Capability::SharedQueries => "queries.shared",
+            Capability::StreamingUpload => "upload.stream",
+        }
+    }
+
+    /// What the capability is for, to finish "too old for ...".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Capability::Explain => "explaining queries",
+            Capability::Inflow => "listing inflow endpoints",
+            Capability::Audit => "the audit log",
+            Capability::SharedQueries => "shared queries",
+            Capability::StreamingUpload => "streamed uploads",
+        }
+    }
+}
+
+/// What a server reports from its `version` endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerVersion {
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<u32>,
+    /// None when the server doesn't list its capabilities, so nothing is known about them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl ServerVersion {
+    /// Whether the server reports `capability`. Servers that don't list their capabilities are
+    /// assumed to support everything, so requests aren't refused on a guess.
+    pub fn supports(&self, capability: Capability) -> bool {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.iter().any(|c| c.eq_ignore_ascii_case(capability.name())),
+            None => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CachedVersion {
+    checked: DateTime<Utc>,
+    /// None when the server has no version endpoint, so nothing is known about it.
+    version: Option<ServerVersion>,
+    /// Why the server couldn't be asked at `checked`. The version is the one known before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Reported versions of every server, keyed by server URL.
+#[derive(Serialize, Deserialize, Default)]
+pub struct CapabilityCache {
+    servers: HashMap<String, CachedVersion>,
+}
+
+/// Asks the server for its version and capabilities. None when it's too old to say.
+pub fn probe(connection: &Connection) -> Result<Option<ServerVersion>, ConnectError> {
+    log::debug!("Asking {} for its version", connection.server);
+    let timeouts = connection.timeouts.or(Timeouts::total(Some(PROBE_TIMEOUT)));
+    let client = connection.client_builder(&timeouts).build()?;
+    let response = connection
+        .authenticate_request(client.get(endpoint::url(&connection.server, "version")?))
+        .send()?;
+    if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) {
+        return Ok(None);
+    }
+
+    Ok(Some(response.error_for_status()?.json()?))
+}
+
+/// The server's version, from the cache when it was asked within the last day. When asking
+/// failed within the last few minutes, the version known before is used without asking again.
+pub fn get(connection: &Connection) -> Result<Option<ServerVersion>, ConnectError> {
+    let cache = load()?;
+    if let Some(cached) = cache.servers.get(connection.server.trim()) {
+        let age = Utc::now() - cached.checked;
+        match &cached.error {
+            None if age < Duration::hours(CACHE_HOURS) => return Ok(cached.version.clone()),
+            Some(err) if age < Duration::minutes(ERROR_MINUTES) => {
+                log::debug!("Not asking {} for its version again, as it failed recently: {}", connection.server, err);
+                return Ok(cached.version.clone());
+            }
+            _ => {}
+        }
+    }
+
+    refresh(connection)
+}
+
+/// The server's version as last cached, however long ago, without contacting the server.
+pub fn cached(connection: &Connection) -> Result<Option<ServerVersion>, ConfigError> {
+    Ok(load()?.servers.remove(connection.server.trim()).and_then(|c| c.version))
+}
+
+/// Asks the server for its version again, replacing what was cached. A failure is cached too,
+/// along with the version known before it.
+pub fn refresh(connection: &Connection) -> Result<Option<ServerVersion>, ConnectError> {
+    let probed = probe(connection);
+    let mut cache = load()?;
+    let server = connection.server.trim().to_string();
+    let cached = match &probed {
+        Ok(version) => CachedVersion { checked: Utc::now(), version: version.clone(), error: None },
+        Err(err) => CachedVersion {
+            checked: Utc::now(),
+            version: cache.servers.get(&server).and_then(|c| c.version.clone()),
+            error: Some(err.to_string()),
+        },
+    };
+    cache.servers.insert(server, cached);
+    save(cache)?;
+    probed
+}
+
+/// Fails when the server reports capabilities and `capability` isn't one of them. Servers that
+/// can't be asked are given the benefit of the doubt, so the request itself reports the problem.
+pub fn require(connection: &Connection, capability: Capability) -> Result<(), CommonError> {
+    match get(connection) {
+        Ok(Some(version)) if !version.supports(capability) => Err(CommonError::ServerTooOld(capability.description(), version.version)),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            log::debug!("Unable to check the server supports {}: {}", capability.description(), err);
+            Ok(())
+        }
+    }
+}
+
+/// The capability cache lives next to the logsh configuration file.
+pub fn get_capabilities_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("capabilities.json"))
+}
+
+pub fn load() -> Result<CapabilityCache, ConfigError> {
+    let path = get_capabilities_path()?;
+    if !path.exists() {
+        return Ok(CapabilityCache::default());
+    }
+
+    let cache = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&cache).map_err(ConfigError::FailedDeserialize)
+}
+
+/// Saves the cache, unless logsh is in read-only mode.
+pub fn save(cache: CapabilityCache) -> Result<CapabilityCache, ConfigError> {
+    if config::read_only() {
+        return Ok(cache);
+    }
+
+    let path = get_capabilities_path()?;
+    let serialized = serde_json::to_string(&cache).map_err(ConfigError::FailedSerialize)?;
+    fs::write(path, serialized).map_err(ConfigError::FailedWrite)?;
+    Ok(cache)
+}
@@ -0,0 +1,46 @@
+use crate::error::CloudError;
+
+/// URL schemes of the object storage `put` writes to.
+const SCHEMES: &[&str] = &["s3", "az"];
+
+/// Whether `path` names an object storage destination, like `s3://bucket/key` or
+/// `az://container/path`, rather than a local file.
+pub fn is_cloud_url(path: &str) -> bool {
+    path.split_once("://")
+        .is_some_and(|(scheme, _)| SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
+/// Writes `contents` to an object storage URL, replacing any object already there.
+///
+/// Credentials and regions come from the environment, as for the providers' own tools:
+/// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_REGION` for S3, and
+/// `AZURE_STORAGE_ACCOUNT_NAME` with `AZURE_STORAGE_ACCOUNT_KEY` for Azure.
+#[cfg(feature = "cloud")]
+pub fn put(url: &str, contents: Vec<u8>) -> Result<(), CloudError> {
+    use object_store::{aws::AmazonS3Builder, azure::MicrosoftAzureBuilder, path::Path, ObjectStore};
+
+    let parsed = reqwest::Url::parse(url).map_err(|err| CloudError::InvalidUrl(url.to_string(), err.to_string()))?;
+    let store: Box<dyn ObjectStore> = match parsed.scheme() {
+        "s3" => Box::new(AmazonS3Builder::from_env().with_url(url).build().map_err(|err| CloudError::Store(err.to_string()))?),
+        "az" => Box::new(MicrosoftAzureBuilder::from_env().with_url(url).build().map_err(|err| CloudError::Store(err.to_string()))?),
+        other => return Err(CloudError::InvalidUrl(url.to_string(), format!("unsupported scheme \"{}\"", other))),
+    };
+    let path = Path::from_url_path(parsed.path().trim_start_matches('/'))
+        .map_err(|err| CloudError::InvalidUrl(url.to_string(), err.to_string()))?;
+
+    // The object store client is async. A runtime for the one request keeps the rest of logsh blocking.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(CloudError::Runtime)?;
+    log::debug!("Writing {} bytes to {}", contents.len(), url);
+    runtime
+        .block_on(store.put(&path, contents.into()))
+        .map_err(|err| CloudError::Store(err.to_string()))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "cloud"))]
+pub fn put(_url: &str, _contents: Vec<u8>) -> Result<(), CloudError> {
+    Err(CloudError::NotEnabled)
+}
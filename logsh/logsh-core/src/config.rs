@@ -1,20 +1,115 @@
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fs,
+    io::Write,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
 };
 
-use crate::{connect::Connection, error::ConfigError};
+use crate::{auth::AuthData, connect::{Connection, Group}, error::ConfigError};
 static mut CONFIG_PATH: OnceLock<Result<PathBuf, ConfigError>> = OnceLock::new();
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+/// The configuration as saved by this process in read-only mode. It's never written to disk.
+static EPHEMERAL: Mutex<Option<Configuration>> = Mutex::new(None);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Configuration {
     pub default_connection: String,
     pub connections: HashMap<String, Connection>,
+    #[serde(default)]
+    pub diagnostics: DiagnosticsConfig,
+    /// Minutes before the default connection's token expires to start warning about it, at the
+    /// start of each command. 0 turns the warning off.
+    #[serde(default = "default_expiry_warning_minutes")]
+    pub expiry_warning_minutes: u64,
+    /// Minutes cached schema, table and column names are used before they're fetched again.
+    #[serde(default = "default_metadata_ttl_minutes")]
+    pub metadata_ttl_minutes: u64,
+    /// Column name patterns, like `*password*`, whose values are masked in every query result.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact_columns: Vec<String>,
+    /// Record each query, with credentials masked, and its connection, duration and row count
+    /// in query_history.jsonl, which `logsh stats` summarizes. Off unless turned on, so queries
+    /// stay off disk by default.
+    #[serde(default = "default_query_history")]
+    pub query_history: bool,
+    /// Output format of commands run without `-o`, by any name `-o` accepts, such as `csv`.
+    /// Tables when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_output: Option<String>,
+    /// Whether subscription IDs, trace IDs and server URLs in terminal output link to the web UI.
+    #[serde(default)]
+    pub links: Links,
+    /// Incremented by every save, so a process can tell another one saved since it loaded.
+    #[serde(default)]
+    pub generation: u64,
+    /// The configuration as loaded, to merge this process' changes with another's on save.
+    #[serde(skip)]
+    loaded: Option<Box<Configuration>>,
 }
 
+/// Each connection's secrets by connection name, kept out of the configuration file so it can
+/// be shared or committed without token material.
+#[derive(Serialize, Deserialize, Default)]
+struct Credentials {
+    connections: HashMap<String, AuthData>,
+}
+
+/// When terminal output uses OSC 8 hyperlinks.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Links {
+    /// When the terminal is known to support them.
+    #[default]
+    Auto,
+    /// Whenever output is colored, for terminals logsh doesn't recognize.
+    Always,
+    Never,
+}
+
+fn default_expiry_warning_minutes() -> u64 {
+    120
+}
+
+fn default_metadata_ttl_minutes() -> u64 {
+    60
+}
+
+fn default_query_history() -> bool {
+    false
+}
+
+/// Settings for the diagnostic log file, which records internal logging regardless of the
+/// verbosity chosen on the command line.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    /// Write internal logging to a rotating file in the logs directory.
+    pub log_file: bool,
+    /// Most verbose level written to the log file: error, warn, info, debug or trace.
+    pub log_level: String,
+    /// Size in bytes a log file grows to before it is rotated.
+    pub max_file_bytes: u64,
+    /// Number of rotated log files kept besides the current one.
+    pub max_files: usize,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            log_file: false,
+            log_level: "debug".to_string(),
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ConnectionConfig {
     pub name: String,
     pub connection: Connection,
@@ -22,6 +117,17 @@ pub struct ConnectionConfig {
 
 
 impl Configuration {
+    /// The connections tagged with every one of `groups`, sorted by name. All of them when
+    /// there are no groups.
+    pub fn connections_in(&self, groups: &[Group]) -> Vec<ConnectionConfig> {
+        let mut connections: Vec<ConnectionConfig> = self.connections.iter()
+            .filter(|(_, c)| groups.iter().all(|g| c.in_group(g)))
+            .map(|(name, c)| ConnectionConfig { name: name.clone(), connection: c.clone() })
+            .collect();
+        connections.sort_by(|a, b| a.name.cmp(&b.name));
+        connections
+    }
+
     pub fn get_default_connection(&self) -> Option<ConnectionConfig> {
         if self.connections.len() == 0 {
             return None;
@@ -53,6 +159,15 @@ impl Default for Configuration {
         Self {
             default_connection: Default::default(),
             connections: Default::default(),
+            diagnostics: Default::default(),
+            expiry_warning_minutes: default_expiry_warning_minutes(),
+            metadata_ttl_minutes: default_metadata_ttl_minutes(),
+            redact_columns: Default::default(),
+            query_history: default_query_history(),
+            default_output: None,
+            links: Links::default(),
+            generation: 0,
+            loaded: None,
         }
     }
 }
@@ -88,7 +203,7 @@ pub fn get_configuration_path() -> Result<PathBuf, ConfigError> {
                 })
                 .ok_or(ConfigError::NoHome)?;
             log::trace!("Configuration path: {}", &path.display());
-            if let Some(parent) = path.parent() {
+            if let Some(parent) = path.parent().filter(|_| !read_only()) {
                 if false == parent.exists() {
                     log::debug!(
                         "Configuration parent doesn't exist. Creating: {}",
@@ -118,21 +233,346 @@ pub fn get_configuration_path() -> Result<PathBuf, ConfigError> {
     }
 }
 
+/// Directory holding diagnostic log files, next to the configuration file.
+pub fn get_logs_path() -> Result<PathBuf, ConfigError> {
+    let config = get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("logs"))
+}
+
+/// Directory holding the HTML views `query --open` writes, next to the configuration file.
+pub fn get_views_path() -> Result<PathBuf, ConfigError> {
+    let config = get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("views"))
+}
+
+/// The credentials file lives next to the configuration file.
+pub fn get_credentials_path() -> Result<PathBuf, ConfigError> {
+    let config = get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("credentials.json"))
+}
+
+/// Stops this process from writing the configuration and credentials, such as for read-only
+/// home directories. Saved changes, like refreshed tokens or a new default connection, last
+/// until the process exits. Setting `LOGSH_READONLY=1` has the same effect.
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::Relaxed);
+}
+
+pub fn read_only() -> bool {
+    if READ_ONLY.load(Ordering::Relaxed) {
+        return true;
+    }
+
+    match std::env::var("LOGSH_READONLY") {
+        Ok(value) => !matches!(value.trim().to_ascii_lowercase().as_str(), "" | "0" | "false" | "no"),
+        Err(_) => false,
+    }
+}
+
 pub fn load() -> Result<Configuration, ConfigError> {
-    let cfg = get_configuration_path()?;
-    if cfg.exists() {
-        let cfg = fs::read_to_string(cfg).map_err(ConfigError::FailedRead)?;
-        let config = serde_json::from_str(&cfg).map_err(ConfigError::FailedDeserialize)?;
-        return Ok(config);
-    } else {
-        return Ok(Configuration::default());
+    let (mut config, inline) = current()?;
+    if inline && !read_only() {
+        log::info!("Moving connection credentials to {}.", get_credentials_path()?.display());
+        config = save(config)?;
+    }
+    Ok(config)
+}
+
+/// The configuration as last saved, and whether its file still holds credentials.
+fn current() -> Result<(Configuration, bool), ConfigError> {
+    if read_only() {
+        if let Some(config) = EPHEMERAL.lock().unwrap_or_else(|e| e.into_inner()).clone() {
+            return Ok((config, false));
+        }
     }
+
+    let (mut config, inline) = read(&get_configuration_path()?)?.unwrap_or_default();
+    config.loaded = Some(Box::new(config.clone()));
+    Ok((config, inline))
 }
 
+/// Reads the configuration and fills in each connection's credentials. Also returns whether
+/// the configuration file still holds credentials, as it did before they had their own file.
+fn read(path: &Path) -> Result<Option<(Configuration, bool)>, ConfigError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let cfg = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    let mut config: Configuration = serde_json::from_str(&cfg).map_err(ConfigError::FailedDeserialize)?;
+    let inline = config.connections.values().any(|c| c.auth.is_some());
+
+    let credentials = get_credentials_path()?;
+    let mut credentials: Credentials = match credentials.exists() {
+        true => {
+            let text = fs::read_to_string(credentials).map_err(ConfigError::FailedRead)?;
+            serde_json::from_str(&text).map_err(ConfigError::FailedDeserialize)?
+        },
+        false => Credentials::default(),
+    };
+    for (name, connection) in config.connections.iter_mut() {
+        if connection.auth.is_none() {
+            connection.auth = credentials.connections.remove(name);
+        }
+    }
+    Ok(Some((config, inline)))
+}
+
+/// Saves the configuration. When another process saved it since this one loaded it, such as
+/// when several logsh commands refresh the same token at once, the changes are merged instead of
+/// overwriting the other process' ones. The file is replaced atomically, so readers never see
+/// a partial write. In [read-only mode](set_read_only) nothing is written, and the
+/// configuration is kept for later loads by this process instead.
 pub fn save(config: Configuration) -> Result<Configuration, ConfigError> {
+    let _lock = lock()?;
+    write(config)
+}
+
+/// Saves the configuration, with the lock on it already taken.
+fn write(mut config: Configuration) -> Result<Configuration, ConfigError> {
+    if read_only() {
+        log::warn!("Read-only mode. Configuration changes last until logsh exits.");
+        config.loaded = None;
+        config.loaded = Some(Box::new(config.clone()));
+        *EPHEMERAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(config.clone());
+        return Ok(config);
+    }
+
     let path = get_configuration_path()?;
+    let mut config = match read(&path)? {
+        Some((current, _)) if current.generation != config.generation => {
+            log::debug!(
+                "Configuration was saved by another process (generation {}, loaded {}). Merging.",
+                current.generation,
+                config.generation
+            );
+            let base = config.loaded.clone().map(|b| *b).unwrap_or_default();
+            merge(&base, config, current)
+        }
+        _ => config,
+    };
+    config.generation += 1;
+
+    let credentials = Credentials {
+        connections: config
+            .connections
+            .iter()
+            .filter_map(|(name, c)| c.auth.clone().map(|auth| (name.to_string(), auth)))
+            .collect(),
+    };
+    let serialized = serde_json::to_string(&credentials).map_err(ConfigError::FailedSerialize)?;
+    replace(&get_credentials_path()?, &serialized, true)?;
+
     let serialized: String =
         serde_json::to_string(&config).map_err(ConfigError::FailedSerialize)?;
-    fs::write(&path, serialized).map_err(ConfigError::FailedWrite)?;
+    replace(&path, &serialized, false)?;
+
+    // Cleared first, so the snapshot doesn't hold the previous one.
+    config.loaded = None;
+    config.loaded = Some(Box::new(config.clone()));
     Ok(config)
 }
+
+/// Loads the configuration, changes it and saves it. Other logsh processes updating it wait
+/// until this one has saved, so neither's change is lost.
+pub fn update<F>(change: F) -> Result<Configuration, ConfigError>
+where
+    F: FnOnce(&mut Configuration),
+{
+    let _lock = lock()?;
+    let (mut config, _) = current()?;
+    change(&mut config);
+    write(config)
+}
+
+/// Takes the lock on the file next to the configuration, released when the file is dropped.
+/// Saves wait on it, so two processes never merge onto the same configuration. In read-only
+/// mode nothing is written, so there's nothing to lock.
+fn lock() -> Result<Option<fs::File>, ConfigError> {
+    if read_only() {
+        return Ok(None);
+    }
+
+    let path = get_configuration_path()?.with_extension("json.lock");
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(ConfigError::FailedWrite)?;
+    file.lock().map_err(ConfigError::FailedWrite)?;
+    Ok(Some(file))
+}
+
+/// Merges this process' changes to the configuration since `base` was loaded into `theirs`,
+/// saved by another process meanwhile. Settings and connections this process didn't change
+/// come from `theirs`. When both changed a connection, the one whose token expires later wins,
+/// so a refreshed token isn't replaced with an older one.
+fn merge(base: &Configuration, ours: Configuration, theirs: Configuration) -> Configuration {
+    let names: BTreeSet<&String> = base
+        .connections
+        .keys()
+        .chain(ours.connections.keys())
+        .chain(theirs.connections.keys())
+        .collect();
+    let mut connections = HashMap::new();
+    for name in names {
+        let (base, ours, theirs) = (
+            base.connections.get(name),
+            ours.connections.get(name),
+            theirs.connections.get(name),
+        );
+        let connection = if !connection_changed(base, ours) {
+            theirs
+        } else if !connection_changed(base, theirs) {
+            ours
+        } else {
+            match (ours, theirs) {
+                (Some(o), Some(t)) if t.token_expires() > o.token_expires() => theirs,
+                _ => ours,
+            }
+        };
+        if let Some(connection) = connection {
+            connections.insert(name.to_string(), connection.clone());
+        }
+    }
+
+    Configuration {
+        default_connection: pick(&base.default_connection, ours.default_connection, theirs.default_connection),
+        connections,
+        diagnostics: pick(&base.diagnostics, ours.diagnostics, theirs.diagnostics),
+        expiry_warning_minutes: pick(&base.expiry_warning_minutes, ours.expiry_warning_minutes, theirs.expiry_warning_minutes),
+        metadata_ttl_minutes: pick(&base.metadata_ttl_minutes, ours.metadata_ttl_minutes, theirs.metadata_ttl_minutes),
+        redact_columns: pick(&base.redact_columns, ours.redact_columns, theirs.redact_columns),
+        query_history: pick(&base.query_history, ours.query_history, theirs.query_history),
+        default_output: pick(&base.default_output, ours.default_output, theirs.default_output),
+        links: pick(&base.links, ours.links, theirs.links),
+        generation: theirs.generation,
+        loaded: None,
+    }
+}
+
+/// Our value when we changed it, otherwise theirs.
+fn pick<T: Serialize>(base: &T, ours: T, theirs: T) -> T {
+    match changed(base, &ours) {
+        true => ours,
+        false => theirs,
+    }
+}
+
+/// Compares credentials too, which aren't serialized with the connection.
+fn connection_changed(base: Option<&Connection>, value: Option<&Connection>) -> bool {
+    changed(&base, &value) || changed(&base.and_then(|c| c.auth.as_ref()), &value.and_then(|c| c.auth.as_ref()))
+}
+
+fn changed<T: Serialize>(base: &T, value: &T) -> bool {
+    serde_json::to_value(base).ok() != serde_json::to_value(value).ok()
+}
+
+/// Replaces a file atomically through a temporary file next to it. Private files are only
+/// readable and writable by the user.
+pub(crate) fn replace(path: &Path, contents: &str, private: bool) -> Result<(), ConfigError> {
+    let temp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|err| {
+            let _ = fs::remove_file(&temp);
+            ConfigError::FailedWrite(err)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn connection(server: &str, expires_hour: Option<u32>) -> Connection {
+        let mut connection = Connection::new(server);
+        connection.auth = expires_hour.map(|hour| AuthData::Jwt { expires: Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).single(), token: server.to_string() });
+        connection
+    }
+
+    fn base() -> Configuration {
+        let mut base = Configuration { default_connection: "a".to_string(), generation: 1, ..Default::default() };
+        base.connections.insert("a".to_string(), connection("https://a", Some(1)));
+        base
+    }
+
+    #[test]
+    fn keeps_settings_each_side_changed() {
+        let (base, mut ours, mut theirs) = (base(), base(), base());
+        ours.metadata_ttl_minutes = 5;
+        theirs.redact_columns = vec!["*secret*".to_string()];
+        theirs.default_connection = "b".to_string();
+        theirs.generation = 2;
+
+        let merged = merge(&base, ours, theirs);
+        assert_eq!(merged.metadata_ttl_minutes, 5);
+        assert_eq!(merged.redact_columns, ["*secret*"]);
+        assert_eq!(merged.default_connection, "b");
+        assert_eq!(merged.generation, 2);
+    }
+
+    #[test]
+    fn ours_wins_settings_both_changed() {
+        let (base, mut ours, mut theirs) = (base(), base(), base());
+        ours.expiry_warning_minutes = 1;
+        theirs.expiry_warning_minutes = 2;
+        assert_eq!(merge(&base, ours, theirs).expiry_warning_minutes, 1);
+    }
+
+    #[test]
+    fn keeps_connections_added_and_removed_by_either() {
+        let (base, mut ours, mut theirs) = (base(), base(), base());
+        ours.connections.insert("ours".to_string(), connection("https://ours", None));
+        theirs.connections.insert("theirs".to_string(), connection("https://theirs", None));
+        theirs.connections.remove("a");
+
+        let merged = merge(&base, ours, theirs);
+        let names: BTreeSet<&str> = merged.connections.keys().map(String::as_str).collect();
+        assert_eq!(names, BTreeSet::from(["ours", "theirs"]));
+    }
+
+    #[test]
+    fn later_token_wins_connections_both_changed() {
+        let (base, mut ours, mut theirs) = (base(), base(), base());
+        ours.connections.insert("a".to_string(), connection("https://ours", Some(3)));
+        theirs.connections.insert("a".to_string(), connection("https://theirs", Some(2)));
+        assert_eq!(merge(&base, ours.clone(), theirs.clone()).connections["a"].server, "https://ours");
+
+        theirs.connections.insert("a".to_string(), connection("https://theirs", Some(4)));
+        assert_eq!(merge(&base, ours, theirs).connections["a"].server, "https://theirs");
+    }
+
+    #[test]
+    fn notices_changed_credentials() {
+        let (base, ours, mut theirs) = (base(), base(), base());
+        theirs.connections.get_mut("a").unwrap().auth = Some(AuthData::Jwt { expires: None, token: "refreshed".to_string() });
+        let merged = merge(&base, ours, theirs);
+        assert!(matches!(&merged.connections["a"].auth, Some(AuthData::Jwt { token, .. }) if token == "refreshed"));
+    }
+}
@@ -2,16 +2,25 @@ use chrono::{Utc, DateTime};
 use log::debug;
 use oauth2::TokenResponse;
 use reqwest::StatusCode;
-use reqwest::blocking::RequestBuilder;
-use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH, RETRY_AFTER};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
+use crate::auth::oauth::OAuthData;
 use crate::auth::{AuthData, AuthRequest};
 use crate::common::ApiErrorModel;
 use crate::error::{AuthError, ConnectError, OAuthError, QueryError, ConfigError};
 use crate::config;
-use crate::query::QueryRequest;
+use crate::endpoint;
+use crate::progress::{NoProgress, Progress, ProgressWriter};
+use crate::query::{QueryPlan, QueryRequest};
+use crate::tenant::TenantModel;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Connection {
@@ -19,7 +28,208 @@ pub struct Connection {
     pub user_id: uuid::Uuid,
     pub username: String,
     pub default_subscription: Option<uuid::Uuid>,
-    auth: Option<AuthData>,
+    /// Saved in the credentials file rather than the configuration. Still read from the
+    /// configuration, where older versions saved it, until it's migrated.
+    #[serde(default, skip_serializing)]
+    pub(crate) auth: Option<AuthData>,
+    #[serde(default, skip_serializing_if = "Timeouts::is_unset")]
+    pub timeouts: Timeouts,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolve: Vec<Resolve>,
+    /// Groups the connection is tagged with, such as `env` to `prod`, for picking out several
+    /// connections at once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, String>,
+}
+
+static RESOLVE_OVERRIDES: OnceLock<Vec<Resolve>> = OnceLock::new();
+
+/// Sends requests for `host:port` to `addr` instead of the address DNS gives, like curl's
+/// `--resolve`. Saved in the configuration as `host:port:addr`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Resolve {
+    pub host: String,
+    pub port: u16,
+    pub addr: IpAddr,
+}
+
+impl Resolve {
+    fn matches(&self, host: &str, port: u16) -> bool {
+        self.port == port && self.host.eq_ignore_ascii_case(host)
+    }
+}
+
+impl FromStr for Resolve {
+    type Err = ConnectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConnectError::InvalidResolve(s.to_string());
+        let mut parts = s.splitn(3, ':');
+        let (Some(host), Some(port), Some(addr)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let addr = addr.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port: port.parse().map_err(|_| invalid())?,
+            addr: addr.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for Resolve {
+    type Error = ConnectError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Resolve> for String {
+    fn from(r: Resolve) -> Self {
+        r.to_string()
+    }
+}
+
+impl fmt::Display for Resolve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            IpAddr::V4(addr) => write!(f, "{}:{}:{}", self.host, self.port, addr),
+            IpAddr::V6(addr) => write!(f, "{}:{}:[{}]", self.host, self.port, addr),
+        }
+    }
+}
+
+/// A group connections can be tagged with and picked out by, written `key=value` such as
+/// `env=prod`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Group {
+    type Err = ConnectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((key, value)) = s.split_once('=') else {
+            return Err(ConnectError::InvalidGroup(s.to_string()));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() || value.is_empty() {
+            return Err(ConnectError::InvalidGroup(s.to_string()));
+        }
+
+        Ok(Self { key: key.to_string(), value: value.to_string() })
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// Sets resolve overrides for every connection in this process, such as from the command
+/// line. They take precedence over the ones saved with a connection. Only the first call has
+/// an effect.
+pub fn set_resolve_overrides(overrides: Vec<Resolve>) {
+    let _ = RESOLVE_OVERRIDES.set(overrides);
+}
+
+/// Total limit for queries when neither the command nor the connection sets one.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a request may take. A request is bounded by every limit that's set.
+///
+/// The blocking client waits at most one limit for the response to start and again for each
+/// read of the body, so the read limit is the longest a response may stall. The total limit
+/// is checked while the body is read, and a response that outlasts it is cut off. Unset limits
+/// are left to the client, which waits 30 seconds. A zero limit is no limit, so a command's
+/// `--timeout none` can lift the connection's.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    #[serde(default, rename = "connect_seconds", with = "optional_seconds", skip_serializing_if = "Option::is_none")]
+    pub connect: Option<Duration>,
+    #[serde(default, rename = "read_seconds", with = "optional_seconds", skip_serializing_if = "Option::is_none")]
+    pub read: Option<Duration>,
+    #[serde(default, rename = "total_seconds", with = "optional_seconds", skip_serializing_if = "Option::is_none")]
+    pub total: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Only a total limit, such as a command's `--timeout`.
+    pub fn total(total: Option<Duration>) -> Self {
+        Self { total, ..Self::default() }
+    }
+
+    pub fn is_unset(&self) -> bool {
+        self.connect.is_none() && self.read.is_none() && self.total.is_none()
+    }
+
+    /// Fills each unset limit from `fallback`, such as the connection's configured timeouts.
+    pub fn or(self, fallback: Timeouts) -> Self {
+        Self {
+            connect: self.connect.or(fallback.connect),
+            read: self.read.or(fallback.read),
+            total: self.total.or(fallback.total),
+        }
+    }
+
+    /// The total limit, unless it's unset or none.
+    pub fn total_limit(&self) -> Option<Duration> {
+        self.total.filter(|total| !total.is_zero())
+    }
+
+    /// Longest single wait on the blocking client: the smaller of the read and total limits, or
+    /// `Some(None)` when both are none. Unset when neither is set, leaving the client's default.
+    fn wait(&self) -> Option<Option<Duration>> {
+        match (self.read, self.total) {
+            (None, None) => None,
+            (read, total) => Some(read.into_iter().chain(total).filter(|d| !d.is_zero()).min()),
+        }
+    }
+}
+
+impl From<Option<Duration>> for Timeouts {
+    fn from(total: Option<Duration>) -> Self {
+        Self::total(total)
+    }
+}
+
+impl fmt::Display for Timeouts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limit = |d: Option<Duration>| match d {
+            None => "default".to_string(),
+            Some(d) if d.is_zero() => "none".to_string(),
+            Some(d) => format!("{:?}", d),
+        };
+        write!(f, "connect {}, read {}, total {}", limit(self.connect), limit(self.read), limit(self.total))
+    }
+}
+
+/// Saves durations as a number of seconds, so they can be edited by hand.
+mod optional_seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(d) => serializer.serialize_f64(d.as_secs_f64()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(deserializer)?
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -77,9 +287,39 @@ impl Connection {
             username: String::default(),
             default_subscription: None,
             auth: None,
+            timeouts: Timeouts::default(),
+            resolve: Vec::new(),
+            groups: BTreeMap::new(),
         }
     }
 
+    /// Whether the connection is tagged with the group. Keys and values ignore case.
+    pub fn in_group(&self, group: &Group) -> bool {
+        self.groups.iter().any(|(key, value)| key.eq_ignore_ascii_case(&group.key) && value.eq_ignore_ascii_case(&group.value))
+    }
+
+    /// A client for requests to this connection's server, with its resolve overrides applied.
+    pub(crate) fn client_builder(&self, timeouts: &Timeouts) -> reqwest::blocking::ClientBuilder {
+        let builder = client_builder(timeouts);
+        let Some((host, port)) = reqwest::Url::parse(&self.server)
+            .ok()
+            .and_then(|url| Some((url.host_str()?.to_string(), url.port_or_known_default()?)))
+        else {
+            return builder;
+        };
+
+        // Saved overrides come first, so ones given for this run replace them.
+        let overrides = RESOLVE_OVERRIDES.get().into_iter().flatten();
+        self.resolve
+            .iter()
+            .chain(overrides)
+            .filter(|r| r.matches(&host, port))
+            .fold(builder, |builder, r| {
+                log::debug!("Resolving {}:{} to {}", host, port, r.addr);
+                builder.resolve(&host, SocketAddr::new(r.addr, port))
+            })
+    }
+
     pub fn default_subscription(&self) -> Option<uuid::Uuid> {
         return self.default_subscription;
     }
@@ -91,14 +331,43 @@ impl Connection {
         }
     }
 
+    /// Whether the connection uses a bearer token issued elsewhere, which logsh can't renew.
+    pub fn is_token_auth(&self) -> bool {
+        matches!(self.auth, Some(AuthData::Token { expires: _, token: _ }))
+    }
+
     pub fn get_token(&self) -> Option<String> {
         match &self.auth {
-            Some(AuthData::Jwt { expires: expiration, token }) => get_token_if_not_expired(expiration, token.to_owned()),
+            Some(AuthData::Jwt { expires: expiration, token }) | Some(AuthData::Token { expires: expiration, token }) => get_token_if_not_expired(expiration, token.to_owned()),
             Some(AuthData::OAuth { expires: expiration, data }) => get_token_if_not_expired(expiration, data.token.access_token().secret().to_string()),
+            Some(AuthData::Hmac { key_id: _, secret: _ }) => None,
+            Some(AuthData::Negotiate { spn: _, principal: _ }) => None,
             None => None,
         }
     }
 
+    /// Whether requests carry credentials: an unexpired token, a request signature or a
+    /// Kerberos ticket.
+    pub fn has_credentials(&self) -> bool {
+        self.is_hmac_auth() || self.is_negotiate_auth() || self.get_token().is_some()
+    }
+
+    pub fn is_negotiate_auth(&self) -> bool {
+        matches!(self.auth, Some(AuthData::Negotiate { spn: _, principal: _ }))
+    }
+
+    pub fn is_hmac_auth(&self) -> bool {
+        matches!(self.auth, Some(AuthData::Hmac { key_id: _, secret: _ }))
+    }
+
+    /// Key id of a signing connection, which is safe to show unlike the secret.
+    pub fn hmac_key_id(&self) -> Option<&str> {
+        match &self.auth {
+            Some(AuthData::Hmac { key_id, secret: _ }) => Some(key_id),
+            _ => None,
+        }
+    }
+
     pub fn is_oauth_auth(&self) -> bool {
         match self.auth {
             Some(AuthData::OAuth { expires: _, data: _ }) => true,
@@ -106,21 +375,64 @@ impl Connection {
         }
     }
 
+    pub fn oauth_data(&self) -> Option<&OAuthData> {
+        match &self.auth {
+            Some(AuthData::OAuth { expires: _, data }) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Name of the stored authentication method, if any.
+    pub fn auth_type(&self) -> Option<&'static str> {
+        match self.auth {
+            Some(AuthData::Jwt { expires: _, token: _ }) => Some("basic"),
+            Some(AuthData::OAuth { expires: _, data: _ }) => Some("oauth"),
+            Some(AuthData::Hmac { key_id: _, secret: _ }) => Some("hmac"),
+            Some(AuthData::Negotiate { spn: _, principal: _ }) => Some("negotiate"),
+            Some(AuthData::Token { expires: _, token: _ }) => Some("token"),
+            None => None,
+        }
+    }
+
+    pub fn token_expires(&self) -> Option<DateTime<Utc>> {
+        match &self.auth {
+            Some(AuthData::Jwt { expires, token: _ }) => *expires,
+            Some(AuthData::OAuth { expires, data: _ }) => *expires,
+            Some(AuthData::Hmac { key_id: _, secret: _ }) => None,
+            Some(AuthData::Negotiate { spn: _, principal: _ }) => None,
+            Some(AuthData::Token { expires, token: _ }) => *expires,
+            None => None,
+        }
+    }
+
+    /// Adds the connection's credentials to a request. Signed requests cover the body, so set
+    /// it before calling this.
     pub fn authenticate_request(&self, builder: RequestBuilder) -> RequestBuilder {
         match &self.auth {
-            Some(AuthData::Jwt { expires: _, token }) => builder.bearer_auth(token),
+            Some(AuthData::Jwt { expires: _, token }) | Some(AuthData::Token { expires: _, token }) => builder.bearer_auth(token),
             Some(AuthData::OAuth { expires: _, data }) => {
                 builder.bearer_auth(data.token.access_token().secret())
             }
+            Some(AuthData::Hmac { key_id, secret }) => crate::auth::hmac::sign(builder, key_id, secret),
+            Some(AuthData::Negotiate { spn, principal }) => {
+                match crate::auth::negotiate::authorization(&self.server, spn.as_deref(), principal.as_deref()) {
+                    Ok(authorization) => builder.header(reqwest::header::AUTHORIZATION, authorization),
+                    Err(err) => {
+                        // Send the request anyway; the server's rejection is reported as unauthorized.
+                        log::warn!("{}", err);
+                        builder
+                    }
+                }
+            }
             None => builder,
         }
     }
 
     pub(crate) fn refresh_oauth(&self) -> Result<OAuthConfigResponse, ConnectError> {
         log::trace!("Requesting OAuth config for connection.");
-        let client = client_builder().build()?;
+        let client = self.client_builder(&self.timeouts).build()?;
         let res = client
-            .get(format!("{}/auth/oauth", self.server.trim_end_matches('/')))
+            .get(endpoint::url(&self.server, "auth/oauth")?)
             .send()?
             .error_for_status()?;
         if res.status() == StatusCode::NO_CONTENT {
@@ -135,10 +447,10 @@ impl Connection {
 
     pub fn who_am_i(&self) -> Result<UserModel, ConnectError> {
         log::debug!("Executing who am I query");
-        let client = client_builder().build()?;
+        let client = self.client_builder(&self.timeouts).build()?;
         let response: UserModel = self
             .authenticate_request(
-                client.get(format!("{}/whoami", &self.server.trim_end_matches('/'))),
+                client.get(endpoint::url(&self.server, "whoami")?),
             )
             .send()?
             .error_for_status()?
@@ -146,12 +458,37 @@ impl Connection {
         Ok(response)
     }
 
-    pub fn subscriptions(&self, user: uuid::Uuid) -> Result<Vec<SubscriptionsModel>, ConnectError> {
+    /// Times a request to the server's health endpoint, from sending it to the response
+    /// arriving. Fails when the server can't be reached within `timeout` or isn't healthy.
+    pub fn ping(&self, timeout: Duration) -> Result<Duration, ConnectError> {
+        self.health(None, timeout)
+    }
+
+    /// Like `ping`, for the health of one part of the server, such as `query` or `inflow`.
+    pub fn health(&self, component: Option<&str>, timeout: Duration) -> Result<Duration, ConnectError> {
+        let path = match component {
+            Some(component) => format!("health/{}", component),
+            None => "health".to_string(),
+        };
+        log::debug!("Checking {} of {}", path, self.server);
+        let timeouts = Timeouts { connect: Some(timeout), read: None, total: Some(timeout) };
+        let client = self.client_builder(&timeouts).build()?;
+        let request = self.authenticate_request(client.get(endpoint::url(&self.server, &path)?));
+        let started = Instant::now();
+        let response = request.send()?;
+        let elapsed = started.elapsed();
+        if !response.status().is_success() {
+            return Err(ConnectError::HttpResponseFailed(response.status()));
+        }
+        Ok(elapsed)
+    }
+
+    pub fn subscriptions(&self, user: uuid::Uuid) -> Result<Vec<TenantModel>, ConnectError> {
         log::debug!("Executing accounts query");
-        let client = client_builder().build()?;
-        let response: Vec<SubscriptionsModel> = self
+        let client = self.client_builder(&self.timeouts).build()?;
+        let response: Vec<TenantModel> = self
             .authenticate_request(
-                client.get(format!("{}/users/{}/accounts", &self.server.trim_end_matches('/'), user)),
+                client.get(endpoint::url(&self.server, &format!("users/{}/accounts", user))?),
             )
             .send()?
             .error_for_status()?
@@ -164,13 +501,13 @@ impl Connection {
         F: FnOnce() -> Result<String, ConnectError>,
     {
         log::debug!("Refreshing authentication for {self}");
-        let client = client_builder().build()?;
+        let client = self.client_builder(&self.timeouts).build()?;
         match (&self.auth, auth) {
             (None, None) => {
                 return Err(ConnectError::NoAuthentication);
             }
             (Some(a), None) => match a {
-                AuthData::Jwt { expires: _, token: _ } => return Err(ConnectError::Auth(AuthError::Expired)),
+                AuthData::Jwt { expires: _, token: _ } | AuthData::Token { expires: _, token: _ } => return Err(ConnectError::Auth(AuthError::Expired)),
                 AuthData::OAuth { expires: _, data } => {
                     if let Some(expires_in) = data.token.expires_in() {
                         let expiry = data.received
@@ -178,12 +515,17 @@ impl Connection {
                             .ok_or(ConnectError::Auth(AuthError::Expired))?;
                         if Utc::now() > expiry {
                             log::warn!("OAuth token is expired.");
-                            return Err(ConnectError::Auth(AuthError::Expired));
+                            return match self.refresh_token()? {
+                                true => Ok(()),
+                                false => Err(ConnectError::Auth(AuthError::Expired)),
+                            };
                         }
                     }
 
                     return Ok(());
                 }
+                AuthData::Hmac { key_id: _, secret: _ } => Ok(()),
+                AuthData::Negotiate { spn: _, principal: _ } => Ok(()),
             },
             (_, Some(a)) => {
                 let auth = a.authenticate(client, self)?;
@@ -193,7 +535,97 @@ impl Connection {
         }
     }
 
-    pub fn query_raw(&self, query: &str, timeout: Option<std::time::Duration>) -> Result<String, QueryError> {
+    /// Renews an OAuth login with its refresh token, without prompting. Returns false when the
+    /// connection has nothing to renew it with.
+    pub fn refresh_token(&mut self) -> Result<bool, ConnectError> {
+        let Some(AuthData::OAuth { expires: _, data }) = &self.auth else {
+            return Ok(false);
+        };
+
+        match crate::auth::oauth::refresh(data)? {
+            Some(auth) => {
+                self.auth = Some(auth);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Executes a query and returns the raw response body. Limits in `timeouts` that aren't set
+    /// fall back to the connection's.
+    pub fn query_raw(&self, query: &str, timeouts: Timeouts) -> Result<String, QueryError> {
+        self.query_raw_with_progress(query, timeouts, &NoProgress)
+    }
+
+    /// Executes a query and returns the raw response body, reporting the download to `progress`.
+    pub fn query_raw_with_progress(&self, query: &str, timeouts: Timeouts, progress: &dyn Progress) -> Result<String, QueryError> {
+        let mut body = Vec::new();
+        self.query_to_writer(query, timeouts, &mut body, progress)?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Executes a query and streams the raw response body into `write`, returning the number of bytes written.
+    pub fn query_to_writer<W: std::io::Write>(&self, query: &str, timeouts: Timeouts, write: &mut W, progress: &dyn Progress) -> Result<u64, QueryError> {
+        let timeouts = timeouts.or(self.timeouts).or(Timeouts::total(Some(QUERY_TIMEOUT)));
+        let started = Instant::now();
+        progress.start("Running query", None);
+        let result = self.query_response("kusto", query, &timeouts, None).and_then(|mut response| {
+            progress.start("Downloading results", response.content_length());
+            let mut write = ProgressWriter::new(write, progress);
+            match timeouts.total_limit() {
+                Some(total) => copy_within(&mut response, &mut write, started + total)?
+                    .ok_or(QueryError::TimedOut(total)),
+                None => Ok(response.copy_to(&mut write)?),
+            }
+        });
+        progress.finish();
+        result
+    }
+
+    /// Asks the server how it would execute a query, without running it.
+    pub fn explain(&self, query: &str, timeouts: Timeouts) -> Result<QueryPlan, QueryError> {
+        crate::capabilities::require(self, crate::capabilities::Capability::Explain)?;
+        let timeouts = timeouts.or(self.timeouts).or(Timeouts::total(Some(QUERY_TIMEOUT)));
+        match self.query_response("kusto/explain", query, &timeouts, None) {
+            Ok(response) => Ok(response.json()?),
+            Err(QueryError::Request(err))
+                if matches!(
+                    err.status(),
+                    Some(StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED)
+                ) =>
+            {
+                Err(QueryError::ExplainUnsupported)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Executes a query unless its result still has the ETag `etag`. Returns None when the server
+    /// answers 304 Not Modified. Servers that don't tag results always send the result.
+    pub fn query_if_changed(&self, query: &str, timeouts: Timeouts, etag: Option<&str>) -> Result<Option<TaggedResponse>, QueryError> {
+        let timeouts = timeouts.or(self.timeouts).or(Timeouts::total(Some(QUERY_TIMEOUT)));
+        let started = Instant::now();
+        let mut response = self.query_response("kusto", query, &timeouts, etag)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let etag = response.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(String::from);
+        let mut body = Vec::new();
+        match timeouts.total_limit() {
+            Some(total) => {
+                copy_within(&mut response, &mut body, started + total)?.ok_or(QueryError::TimedOut(total))?;
+            }
+            None => {
+                response.copy_to(&mut body)?;
+            }
+        }
+
+        Ok(Some(TaggedResponse { body: String::from_utf8_lossy(&body).into_owned(), etag }))
+    }
+
+    /// Sends a query. With `etag`, the server may answer 304 Not Modified instead of the result.
+    fn query_response(&self, endpoint: &str, query: &str, timeouts: &Timeouts, etag: Option<&str>) -> Result<Response, QueryError> {
         if query.trim().is_empty() {
             return Err(QueryError::NoInput);
         }
@@ -206,23 +638,23 @@ impl Connection {
 
         let sub = &self.default_subscription()
             .ok_or(QueryError::Config(ConfigError::NoDefaultSubscription))?;
-        let client = client_builder()
-            .timeout(timeout)
-            .build()?;
+        let client = self.client_builder(timeouts).build()?;
         let req = self
-            .authenticate_request(client.post(format!(
-                "{}/search/{}/kusto",
-                &self.server.trim_end_matches('/'),
-                sub
-            )))
-            .json(&req)
-            .build()?;
+            .authenticate_request(client.post(endpoint::url(&self.server, &format!("search/{}/{}", sub, endpoint))?).json(&req));
+        let req = match etag {
+            Some(etag) => req.header(IF_NONE_MATCH, etag),
+            None => req,
+        }
+        .build()?;
             
         let response = client.execute(req)?;
 
         debug!("WTF {} content length {}", response.status(), response.content_length().unwrap_or(0));
-        if response.status().is_success() {
-            return Ok(response.text()?);
+        if matches!(response.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) {
+            return Err(QueryError::Throttled(response.status(), retry_after(response.headers())));
+        }
+        if response.status().is_success() || (etag.is_some() && response.status() == StatusCode::NOT_MODIFIED) {
+            return Ok(response);
         }
         else if response.status() == StatusCode::BAD_REQUEST {
             let error_text = response.text()?;
@@ -243,6 +675,22 @@ impl Connection {
     }
 }
 
+/// How long a throttling server asks to be left alone for, in seconds or until an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => (DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc) - Utc::now()).to_std().ok(),
+    }
+}
+
+/// A query response body, with the ETag the server tagged it with.
+#[derive(Debug, Clone)]
+pub struct TaggedResponse {
+    pub body: String,
+    pub etag: Option<String>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OAuthConfigResponse {
@@ -253,19 +701,34 @@ pub struct OAuthConfigResponse {
     pub scopes: Vec<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct SubscriptionsModel {
-    pub permissions: Vec<String>,
-    pub account_id: uuid::Uuid,
-    pub account_name: String,
-}
-
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
-pub(crate) fn client_builder() -> reqwest::blocking::ClientBuilder {
-    reqwest::blocking::Client::builder()
+/// Copies a response body, giving up once `deadline` passes. Returns `None` when it does.
+fn copy_within<W: std::io::Write>(response: &mut Response, write: &mut W, deadline: Instant) -> Result<Option<u64>, QueryError> {
+    let mut buf = [0u8; 64 * 1024];
+    let mut copied = 0u64;
+    while Instant::now() <= deadline {
+        let read = match std::io::Read::read(response, &mut buf) {
+            Ok(0) => return Ok(Some(copied)),
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(QueryError::FailedResponse(err)),
+        };
+        write.write_all(&buf[..read]).map_err(QueryError::FailedWrite)?;
+        copied += read as u64;
+    }
+
+    Ok(None)
+}
+
+pub(crate) fn client_builder(timeouts: &Timeouts) -> reqwest::blocking::ClientBuilder {
+    let builder = match timeouts.wait() {
+        Some(wait) => reqwest::blocking::Client::builder().timeout(wait),
+        None => reqwest::blocking::Client::builder(),
+    };
+    builder
+        .connect_timeout(timeouts.connect.filter(|connect| !connect.is_zero()))
         .user_agent(USER_AGENT)
         .default_headers({
             let mut h = HeaderMap::new();
@@ -277,56 +740,97 @@ pub(crate) fn client_builder() -> reqwest::blocking::ClientBuilder {
         })
 }
 
-pub fn add_connect<'a, F>(
+/// Authenticates and saves a connection. When the user has several subscriptions and the
+/// connection has no default yet, `choose` picks one from the list sorted by name.
+pub fn add_connect<'a, F, C>(
     name: String,
-    mut connection: Option<Connection>,
+    connection: Option<Connection>,
     auth: Option<AuthRequest<F>>,
+    choose: C,
 ) -> Result<Connection, ConnectError>
 where
     F: FnOnce() -> Result<String, ConnectError>,
+    C: FnOnce(&[TenantModel]) -> Option<uuid::Uuid>,
 {
-    let connection: Connection = {
-        let mut cfg = config::load()?;
-        let conn_entry = cfg.connections.entry(name.clone());
-        let c = if let Some(c) = connection.as_mut() {
-            c.refresh_auth(auth)?;
-            let user = c.who_am_i()?;
-            let mut subs = c.subscriptions(user.user_id)?;
-            subs.sort_by(|a, b| a.account_name.cmp(&b.account_name));
-            c.user_id = user.user_id;
-            c.username = user.user_name;
-
-            if c.default_subscription.is_none() {
-                c.default_subscription = subs.first().map(|s| s.account_id);
-            }
+    let saved = connection.is_none();
+    let mut c = match connection {
+        Some(c) => c,
+        None => config::load()?
+            .connections
+            .remove(&name)
+            .ok_or_else(|| ConnectError::NoConnection(name.clone()))?,
+    };
+    authenticate(&mut c, auth)?;
 
-            Ok(c.clone())
-        } else {
-            match conn_entry {
-                std::collections::hash_map::Entry::Occupied(mut o) => {
-                    let c = o.get_mut();
-                    c.refresh_auth(auth)?;
-                    let user = c.who_am_i()?;
-                    let mut subs = c.subscriptions(user.user_id)?;
-                    subs.sort_by(|a, b| a.account_name.cmp(&b.account_name));
-                    c.user_id = user.user_id;
-                    c.username = user.user_name;
-
-                    if c.default_subscription.is_none() {
-                        c.default_subscription = subs.first().map(|s| s.account_id);
-                    }
+    if c.default_subscription.is_none() {
+        let mut subs = c.subscriptions(c.user_id)?;
+        subs.sort_by(|a, b| a.account_name.cmp(&b.account_name));
+        c.default_subscription = choose_subscription(&subs, choose);
+    }
 
-                    Ok(c.clone())
-                },
-                std::collections::hash_map::Entry::Vacant(_) => {
-                    Err(ConnectError::NoConnection(name))
-                }
-            }
-        }?;
+    if saved {
+        // Logging in can take a while, and other logsh processes may save the configuration
+        // meanwhile. Only this connection is written back, onto the latest configuration.
+        config::update(|cfg| {
+            cfg.connections.insert(name.clone(), c.clone());
+        })?;
+    }
+    Ok(c)
+}
 
-        let _cfg = config::save(cfg)?;
-        c
-    };
+/// Authenticates a connection with `auth` and fills in the user it's logged in as.
+fn authenticate<F>(c: &mut Connection, auth: Option<AuthRequest<F>>) -> Result<(), ConnectError>
+where
+    F: FnOnce() -> Result<String, ConnectError>,
+{
+    c.refresh_auth(auth)?;
+    let user = c.who_am_i()?;
+    c.user_id = user.user_id;
+    c.username = user.user_name;
+    Ok(())
+}
 
-    Ok(connection)
+/// Logs an existing connection in again with `auth`, refreshing its credentials in place. Its
+/// server, default subscription, groups and whether it's the default connection are left as
+/// they are; only its credentials and the user they belong to are saved.
+pub fn login<F>(name: &str, auth: AuthRequest<F>) -> Result<Connection, ConnectError>
+where
+    F: FnOnce() -> Result<String, ConnectError>,
+{
+    let mut c = config::load()?
+        .connections
+        .remove(name)
+        .ok_or_else(|| ConnectError::NoConnection(name.to_string()))?;
+    c.refresh_auth(Some(auth))?;
+    let user = c.who_am_i()?;
+    if !c.user_id.is_nil() && c.user_id != user.user_id {
+        log::warn!("Logged in to {} as {} rather than {}. Its default subscription is kept, check it with \"logsh sub ls\".", name, user.user_name, c.username);
+    }
+    c.user_id = user.user_id;
+    c.username = user.user_name;
+
+    // Only the credentials are written back, onto the latest configuration, so changes other
+    // logsh processes saved while logging in aren't lost.
+    config::update(|cfg| match cfg.connections.get_mut(name) {
+        Some(saved) => {
+            saved.auth = c.auth.clone();
+            saved.user_id = c.user_id;
+            saved.username = c.username.clone();
+        }
+        None => {
+            cfg.connections.insert(name.to_string(), c.clone());
+        }
+    })?;
+    Ok(c)
+}
+
+fn choose_subscription<C>(subs: &[TenantModel], choose: C) -> Option<uuid::Uuid>
+where
+    C: FnOnce(&[TenantModel]) -> Option<uuid::Uuid>,
+{
+    match subs {
+        [] => None,
+        [only] => Some(only.account_id),
+        many => choose(many),
+    }
 }
\ No newline at end of file
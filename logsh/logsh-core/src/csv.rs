@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use serde_json::value::RawValue;
+use std::collections::HashMap;
 use thiserror::Error;
 
-use crate::query::QueryResult;
+use crate::query::{QueryResult, RowSink};
 
 #[derive(Debug, Error)]
 pub enum CsvError {
@@ -10,39 +11,190 @@ pub enum CsvError {
 
     #[error("Failed to flush csv: {0}")]
     FailedFlush(std::io::Error),
+
+    #[error("Failed to read query result: {0}")]
+    FailedRead(#[from] serde_json::Error),
 }
 
+/// Writes a query result as CSV.
+///
+/// Fields are written straight from the borrowed response body. JSON strings are
+/// written without their quotes, and only strings containing escape sequences are
+/// decoded, into a single scratch buffer reused for the whole result set.
 pub fn write_csv<'a, W: std::io::Write>(
     query: &QueryResult<'a>,
     to: W,
 ) -> Result<(), CsvError> {
-    let mut wtr = csv::Writer::from_writer(to);
-
-    // write headers
-    wtr.write_record(&query.header)
-        .map_err(CsvError::FailedWrite)?;
+    write_delimited(query, b',', to)
+}
 
-    let map = BTreeMap::<&str, usize>::from_iter(
-        query
-            .header
-            .iter()
-            .enumerate()
-            .map(|tup| (tup.1.as_ref(), tup.0)),
-    );
+/// Writes a query result as tab separated values, quoted like CSV where a field needs it.
+pub fn write_tsv<'a, W: std::io::Write>(
+    query: &QueryResult<'a>,
+    to: W,
+) -> Result<(), CsvError> {
+    write_delimited(query, b'\t', to)
+}
 
+fn write_delimited<'a, W: std::io::Write>(
+    query: &QueryResult<'a>,
+    delimiter: u8,
+    to: W,
+) -> Result<(), CsvError> {
+    let mut wtr = RecordWriter::new(to, delimiter);
+    wtr.header(&query.header)?;
     for r in query.results.iter() {
-        let mut arr = vec![String::default(); query.header.len()];
-        for (k, v) in r.iter() {
-            let i = map.get(k).copied().unwrap_or_else(|| {
-                log::error!("Invalid query result. Field \"{k}\" not in headers");
-                0
-            });
-
-            arr[i] = v.to_string();
+        wtr.record(&query.header, |h| r.get(h).copied())?;
+    }
+
+    wtr.flush()
+}
+
+/// Converts a query result read from `from` into CSV without loading it into memory.
+pub fn write_csv_from_reader<R: std::io::Read, W: std::io::Write>(
+    from: R,
+    to: W,
+) -> Result<(), CsvError> {
+    let mut wtr = RecordWriter::new(to, b',');
+    crate::query::stream_result(from, &mut wtr)?;
+    wtr.flush()
+}
+
+struct RecordWriter<W: std::io::Write> {
+    wtr: csv::Writer<W>,
+    scratch: Vec<u8>,
+}
+
+impl<W: std::io::Write> RecordWriter<W> {
+    fn new(to: W, delimiter: u8) -> Self {
+        Self {
+            wtr: csv::WriterBuilder::new().delimiter(delimiter).from_writer(to),
+            scratch: Vec::new(),
+        }
+    }
+
+    fn header(&mut self, header: &[String]) -> Result<(), CsvError> {
+        self.wtr.write_record(header).map_err(CsvError::FailedWrite)
+    }
+
+    fn record<'v, F>(&mut self, header: &[String], get: F) -> Result<(), CsvError>
+    where
+        F: Fn(&str) -> Option<&'v RawValue>,
+    {
+        for h in header.iter() {
+            let field = match get(h.as_str()) {
+                Some(value) => field_bytes(value.get(), &mut self.scratch),
+                None => &[],
+            };
+            self.wtr.write_field(field).map_err(CsvError::FailedWrite)?;
+        }
+
+        // Terminate the record started by the individual fields.
+        self.wtr.write_record(None::<&[u8]>).map_err(CsvError::FailedWrite)
+    }
+
+    fn flush(&mut self) -> Result<(), CsvError> {
+        self.wtr.flush().map_err(CsvError::FailedFlush)
+    }
+}
+
+impl<W: std::io::Write> RowSink for RecordWriter<W> {
+    type Error = CsvError;
+
+    fn header(&mut self, header: &[String]) -> Result<(), CsvError> {
+        RecordWriter::header(self, header)
+    }
+
+    fn row(&mut self, header: &[String], row: &HashMap<String, Box<RawValue>>) -> Result<(), CsvError> {
+        self.record(header, |h| row.get(h).map(|v| v.as_ref()))
+    }
+}
+
+/// Returns the CSV bytes for a raw JSON value, borrowing from `raw` whenever possible.
+fn field_bytes<'b>(raw: &'b str, scratch: &'b mut Vec<u8>) -> &'b [u8] {
+    let bytes = raw.as_bytes();
+    if bytes.len() < 2 || bytes[0] != b'"' || bytes[bytes.len() - 1] != b'"' {
+        return bytes;
+    }
+
+    let inner = &bytes[1..bytes.len() - 1];
+    if !inner.contains(&b'\\') {
+        return inner;
+    }
+
+    scratch.clear();
+    if unescape_into(inner, scratch) {
+        scratch.as_slice()
+    } else {
+        log::warn!("Invalid escape sequence in query result string. Writing raw value.");
+        bytes
+    }
+}
+
+/// Decodes JSON string escapes from `escaped` into `out`. Returns false on malformed input.
+fn unescape_into(escaped: &[u8], out: &mut Vec<u8>) -> bool {
+    let mut i = 0;
+    while i < escaped.len() {
+        let start = i;
+        while i < escaped.len() && escaped[i] != b'\\' {
+            i += 1;
         }
-        wtr.write_record(arr).map_err(CsvError::FailedWrite)?;
+        out.extend_from_slice(&escaped[start..i]);
+        if i >= escaped.len() {
+            break;
+        }
+
+        // Skip the backslash.
+        i += 1;
+        let Some(&c) = escaped.get(i) else {
+            return false;
+        };
+        i += 1;
+        match c {
+            b'"' => out.push(b'"'),
+            b'\\' => out.push(b'\\'),
+            b'/' => out.push(b'/'),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0C),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'u' => {
+                let Some(high) = read_hex4(escaped, i) else {
+                    return false;
+                };
+                i += 4;
+
+                let ch = if (0xD800..0xDC00).contains(&high) {
+                    // Surrogate pair, expect a trailing \uXXXX low surrogate.
+                    match (escaped.get(i..i + 2), read_hex4(escaped, i + 2)) {
+                        (Some(b"\\u"), Some(low)) if (0xDC00..0xE000).contains(&low) => {
+                            i += 6;
+                            char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                        }
+                        _ => None,
+                    }
+                } else {
+                    char::from_u32(high)
+                };
+
+                let mut buf = [0u8; 4];
+                let ch = ch.unwrap_or(char::REPLACEMENT_CHARACTER);
+                out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            }
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+fn read_hex4(bytes: &[u8], at: usize) -> Option<u32> {
+    let digits = bytes.get(at..at + 4)?;
+    let mut value = 0u32;
+    for d in digits {
+        value = (value << 4) | (*d as char).to_digit(16)?;
     }
 
-    wtr.flush().map_err(CsvError::FailedFlush)?;
-    Ok(())
+    Some(value)
 }
@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+
+use crate::{error::QueryError, kusto, query::QueryResult};
+
+/// Remembers the largest value seen in a cursor column, so that repeated runs of a query
+/// only return rows newer than the previous run.
+#[derive(Debug, Clone)]
+pub struct Cursor {
+    column: String,
+    watermark: Option<CursorValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CursorValue {
+    Number(f64, String),
+    DateTime(DateTime<FixedOffset>),
+    String(String),
+}
+
+impl Cursor {
+    pub fn new(column: &str) -> Self {
+        Self {
+            column: column.to_string(),
+            watermark: None,
+        }
+    }
+
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Restricts `query` to rows past the watermark, before the rest of the query runs, so the
+    /// cursor column is a column of its source table. Returns the query unchanged before the
+    /// first advance.
+    pub fn apply(&self, query: &str) -> String {
+        match self.watermark.as_ref() {
+            Some(watermark) => kusto::filter_source(query, &format!(
+                "['{}'] > {}",
+                self.column.replace('\'', "\\'"),
+                watermark.literal()
+            )),
+            None => query.to_string(),
+        }
+    }
+
+    /// Drops rows at or before the watermark and moves the watermark to the largest remaining
+    /// cursor value. Returns the number of rows kept.
+    pub fn advance(&mut self, result: &mut QueryResult<'_>) -> Result<usize, QueryError> {
+        if !result.header.contains(&self.column) {
+            return Err(QueryError::UnknownColumn(self.column.to_string()));
+        }
+
+        let column = self.column.as_str();
+        let watermark = self.watermark.take();
+        let mut next = watermark.clone();
+        result.results.retain(|row| {
+            let Some(value) = row.get(column).and_then(|v| CursorValue::parse(v.get())) else {
+                // Rows without a cursor value can't be placed, only show them on the first run.
+                return watermark.is_none();
+            };
+            if let Some(Ordering::Less | Ordering::Equal) =
+                watermark.as_ref().and_then(|w| value.partial_cmp(w))
+            {
+                return false;
+            }
+
+            if next.as_ref().is_none_or(|n| value.partial_cmp(n) == Some(Ordering::Greater)) {
+                next = Some(value);
+            }
+            true
+        });
+
+        self.watermark = next;
+        Ok(result.results.len())
+    }
+}
+
+impl CursorValue {
+    fn parse(raw: &str) -> Option<Self> {
+        match serde_json::from_str::<Value>(raw).ok()? {
+            Value::Number(n) => Some(Self::Number(n.as_f64()?, raw.to_string())),
+            Value::String(s) => Some(match DateTime::parse_from_rfc3339(&s) {
+                Ok(dt) => Self::DateTime(dt),
+                Err(_) => Self::String(s),
+            }),
+            _ => None,
+        }
+    }
+
+    fn literal(&self) -> String {
+        match self {
+            Self::Number(_, raw) => raw.to_string(),
+            Self::DateTime(dt) => format!("datetime({})", dt.to_rfc3339()),
+            Self::String(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+impl PartialOrd for CursorValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Number(a, _), Self::Number(b, _)) => a.partial_cmp(b),
+            (Self::DateTime(a), Self::DateTime(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::result;
+
+    #[test]
+    fn leaves_the_query_alone_before_the_first_advance() {
+        assert_eq!(Cursor::new("time").apply("T | take 5;"), "T | take 5;");
+    }
+
+    #[test]
+    fn advances_past_the_largest_number() {
+        let mut cursor = Cursor::new("id");
+        let mut first = result(r#"{"header":["id"],"results":[{"id":3},{"id":7},{"id":5}]}"#).unwrap();
+        assert_eq!(cursor.advance(&mut first).unwrap(), 3);
+        assert_eq!(cursor.apply("T;"), "T\n| where ['id'] > 7");
+        assert_eq!(cursor.apply("T | top 5 by id"), "T\n| where ['id'] > 7\n| top 5 by id");
+
+        let mut second = result(r#"{"header":["id"],"results":[{"id":7},{"id":8},{"id":null}]}"#).unwrap();
+        assert_eq!(cursor.advance(&mut second).unwrap(), 1);
+        assert_eq!(second.results[0]["id"].get(), "8");
+    }
+
+    #[test]
+    fn compares_timestamps_rather_than_their_text() {
+        let mut cursor = Cursor::new("time");
+        let mut first = result(r#"{"header":["time"],"results":[{"time":"2024-03-01T10:00:00+02:00"},{"time":"2024-03-01T09:00:00Z"}]}"#).unwrap();
+        cursor.advance(&mut first).unwrap();
+        assert_eq!(cursor.apply("T"), "T\n| where ['time'] > datetime(2024-03-01T09:00:00+00:00)");
+    }
+
+    #[test]
+    fn quotes_string_watermarks() {
+        let mut cursor = Cursor::new("it's");
+        let mut first = result(r#"{"header":["it's"],"results":[{"it's":"a\"b"}]}"#).unwrap();
+        cursor.advance(&mut first).unwrap();
+        assert_eq!(cursor.apply("T"), "T\n| where ['it\\'s'] > \"a\\\"b\"");
+    }
+
+    #[test]
+    fn rejects_an_unknown_cursor_column() {
+        let mut result = result(r#"{"header":["id"],"results":[]}"#).unwrap();
+        assert!(matches!(Cursor::new("time").advance(&mut result), Err(QueryError::UnknownColumn(c)) if c == "time"));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::error::CommonError;
+
+const UNIX_SCHEME: &str = "unix://";
+
+/// Builds the URL of an API path on a server.
+///
+/// Servers may be hosted under a base path, such as `https://host/logship`, and the path is
+/// joined onto it with exactly one slash between them. `unix://` servers are rejected, as the
+/// HTTP client only connects over TCP.
+///
+/// While requests are being recorded or replayed, they go through the session's loopback
+/// listener for the server instead.
+pub fn url(server: &str, path: &str) -> Result<String, CommonError> {
+    let server = server.trim();
+    if server.starts_with(UNIX_SCHEME) {
+        return Err(CommonError::UnixSocket(server.to_string()));
+    }
+
+    let base = match crate::session::route(server)? {
+        Some(base) => base,
+        None => server.trim_end_matches('/').to_string(),
+    };
+
+    Ok(format!("{}/{}", base, path.trim_start_matches('/')))
+}
+
+/// Joins the segments of an API path, and its query, percent-encoding each so names holding
+/// `/`, `?`, `#` or spaces stay one segment.
+pub fn path(segments: &[&str], query: &[(&str, &str)]) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/").expect("the base URL is valid");
+    url.path_segments_mut().expect("the base URL has a path").pop_if_empty().extend(segments);
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+
+    let path = url.path().trim_start_matches('/');
+    match url.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_base_paths_with_one_slash() {
+        assert_eq!(url("https://host/logship/", "/search/x").unwrap(), "https://host/logship/search/x");
+        assert_eq!(url(" https://host ", "search").unwrap(), "https://host/search");
+    }
+
+    #[test]
+    fn rejects_unix_sockets() {
+        assert!(matches!(url("unix:///var/run/logship.sock", "search"), Err(CommonError::UnixSocket(_))));
+    }
+}
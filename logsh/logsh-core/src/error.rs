@@ -21,6 +21,12 @@ pub enum CommonError {
 
     #[error("JSON Error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("Unix socket servers such as {0} aren't supported, as the HTTP client only connects over TCP. Connect to the server over HTTP or HTTPS instead.")]
+    UnixSocket(String),
+
+    #[error("The server is too old for {0}. It reports version {1}.")]
+    ServerTooOld(&'static str, String),
 }
 
 #[derive(Debug, Error)]
@@ -83,12 +89,30 @@ pub enum QueryError {
     #[error("Query string was empty.")]
     NoInput,
 
+    #[error("Invalid query. {}", .0.iter().map(|f| f.message.as_str()).collect::<Vec<_>>().join(" "))]
+    Syntax(Vec<crate::kusto::Finding>),
+
+    #[error("Column \"{0}\" does not exist in the query result.")]
+    UnknownColumn(String),
+
     #[error("Failed to read from STDIN")]
     FailedRead(std::io::Error),
 
     #[error("Failed to write to STDOUT")]
     FailedWrite(std::io::Error),
 
+    #[error("Failed to read the query response: {0}")]
+    FailedResponse(std::io::Error),
+
+    #[error("Query took longer than the total timeout of {0:?}.")]
+    TimedOut(std::time::Duration),
+
+    #[error("The server doesn't support explaining queries.")]
+    ExplainUnsupported,
+
+    #[error("The server is too busy to run the query ({0}).")]
+    Throttled(reqwest::StatusCode, Option<std::time::Duration>),
+
     #[error("Request Error: {0}")]
     Request(#[from] reqwest::Error),
 
@@ -117,12 +141,36 @@ pub enum UploadError {
     #[error("Failed to upload, status: {0}, message: {1}")]
     UploadFailureStatus(i32, String),
 
+    #[error("Failed to read CSV: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("Invalid delimiter \"{0}\". Use a single character, or \"tab\".")]
+    InvalidDelimiter(String),
+
+    #[error("Invalid column mapping: {0}")]
+    InvalidColumns(String),
+
+    #[error("CSV parsing options can't be used to upload .{0} files.")]
+    UnsupportedCsvOptions(String),
+
+    #[error("Only .csv, .ndjson and .jsonl files read as they are can be resumed, not {0}.")]
+    NotResumable(String),
+
+    #[error("Only .json, .ndjson and .jsonl files can be flattened, not .{0} files.")]
+    UnsupportedFlatten(String),
+
+    #[error("Uploading stdin needs a format, such as csv or ndjson.")]
+    StdinFormat,
+
     #[error("File IO error: {0}")]
     FileIO(#[from] std::io::Error),
 }
 
 #[derive(Debug, Error)]
 pub enum ConnectError {
+    #[error("{0}")]
+    Common(#[from] CommonError),
+
     #[error("Configuration Error: {0}")]
     Config(#[from] ConfigError),
 
@@ -146,6 +194,22 @@ pub enum ConnectError {
 
     #[error("Invalid OAuth Configuration: {0}")]
     InvalidConfigError(String),
+
+    #[error("Invalid resolve override \"{0}\". Expected HOST:PORT:ADDR, such as example.com:443:10.0.0.1.")]
+    InvalidResolve(String),
+
+    #[error("Invalid group \"{0}\". Expected KEY=VALUE, such as env=prod.")]
+    InvalidGroup(String),
+}
+
+impl ConnectError {
+    /// Whether the server couldn't be reached at all, as opposed to rejecting the request.
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            ConnectError::Network(err) => err.is_connect() || err.is_timeout(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -158,12 +222,18 @@ pub enum AuthError {
 
     #[error("OAuth Error: {0}")]
     OAuth(#[from] OAuthError),
+
+    #[error("Kerberos Error: {0}")]
+    Negotiate(String),
 }
 
 #[derive(Debug, Error)]
 pub enum BasicAuthError {
     #[error("IO Error: {0}")]
     IOError(#[from] std::io::Error),
+
+    #[error("No password was read from {0}.")]
+    Empty(String),
 }
 
 #[derive(Debug, Error)]
@@ -192,8 +262,19 @@ pub enum OAuthError {
         >,
     ),
 
+    #[error("Refresh Token Error: {0}")]
+    RefreshTokenErrorResponse(
+        oauth2::RequestTokenError<
+            oauth2::reqwest::Error<reqwest::Error>,
+            oauth2::StandardErrorResponse<oauth2::basic::BasicErrorResponseType>,
+        >,
+    ),
+
     #[error("Missing or empty endpoint: {0}")]
     MissingEndpoint(String),
+
+    #[error("Identity Provider Error: {0}")]
+    Provider(String),
 }
 
 #[derive(Debug, Error)]
@@ -218,7 +299,167 @@ pub enum LoginError {
 }
 
 #[derive(Debug, Error)]
-pub enum SubscriptionError {
-    #[error("Client error during login: {0}")]
-    ConfigError(#[from] ClientError),
+pub enum TenantError {
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("No subscription matches \"{0}\". List them with \"logsh sub ls\".")]
+    NotFound(String),
+
+    #[error("\"{0}\" matches more than one subscription: {1}")]
+    Ambiguous(String, String),
+}
+
+#[derive(Debug, Error)]
+pub enum MatchError {
+    #[error("No {0} matches \"{1}\".")]
+    NotFound(String, String),
+
+    #[error("\"{0}\" matches more than one {1}: {2}")]
+    Ambiguous(String, String, String),
+}
+
+#[derive(Debug, Error)]
+pub enum TableError {
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    #[error("Configuration Error: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("Table error: {0}")]
+    Table(#[from] TableError),
+
+    #[error("Subscription error: {0}")]
+    Tenant(#[from] TenantError),
+}
+
+#[derive(Debug, Error)]
+pub enum InflowError {
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("The server doesn't list its inflow endpoints. Check the schema with \"logsh tables ls\" instead.")]
+    Unsupported,
+}
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+#[derive(Debug, Error)]
+pub enum SavedQueryError {
+    #[error("No saved query named \"{0}\".")]
+    NotFound(String),
+
+    #[error("Configuration Error: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("Failed to read query result value: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Column \"{0}\" does not exist in the query result.")]
+    UnknownColumn(String),
+}
+
+#[derive(Debug, Error)]
+pub enum TransformError {
+    #[error("Invalid derived column \"{0}\": {1}.")]
+    Parse(String, String),
+
+    #[error("Column \"{0}\" does not exist in the query result.")]
+    UnknownColumn(String),
+
+    #[error("Failed to read the query result: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum SnippetError {
+    #[error("No snippet named \"{0}\".")]
+    NotFound(String),
+
+    #[error("Snippet reference at offset {0} is missing its closing \"}}}}\".")]
+    Unterminated(usize),
+
+    #[error("Configuration Error: {0}")]
+    Config(#[from] ConfigError),
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Invalid dashboard JSON: {0}")]
+    InvalidJson(serde_json::Error),
+
+    #[error("Not a Grafana dashboard, it has no panels.")]
+    NoPanels,
+}
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("Invalid metrics address \"{0}\": {1}")]
+    InvalidAddress(String, String),
+
+    #[error("Failed to send metrics: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to send metrics: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Failed to read recording {0}: {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Invalid recording {0}: {1}")]
+    Invalid(String, serde_json::Error),
+
+    #[error("Failed to write recording {0}: {1}")]
+    Write(String, std::io::Error),
+
+    #[error("Requests are already being recorded or replayed.")]
+    AlreadyStarted,
+}
+
+#[derive(Debug, Error)]
+pub enum CloudError {
+    #[error("Invalid object storage URL \"{0}\": {1}")]
+    InvalidUrl(String, String),
+
+    #[error("Object storage error: {0}")]
+    Store(String),
+
+    #[error("Failed to start the object storage client: {0}")]
+    Runtime(std::io::Error),
+
+    #[error("This build of logsh can't write to object storage. Rebuild with the \"cloud\" feature.")]
+    NotEnabled,
+}
+
+/// Returns true when the error, or any error it wraps, is a 401 response from the server.
+pub fn is_unauthorized(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            return err.status() == Some(reqwest::StatusCode::UNAUTHORIZED);
+        }
+        source = err.source();
+    }
+
+    false
 }
\ No newline at end of file
@@ -0,0 +1,376 @@
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use serde_json::Value;
+
+use crate::{error::FrameError, query::QueryResult};
+
+/// The type of a [Column], inferred from its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// Strings that are all RFC 3339 timestamps.
+    DateTime,
+    /// Objects, arrays, or values of mixed types.
+    Json,
+}
+
+/// The values of one column. Missing and null values are `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    String(Vec<Option<String>>),
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Bool(Vec<Option<bool>>),
+    DateTime(Vec<Option<DateTime<FixedOffset>>>),
+    Json(Vec<Option<Value>>),
+}
+
+/// A single value of a [DataFrame].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cell<'f> {
+    Null,
+    String(&'f str),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    DateTime(DateTime<FixedOffset>),
+    Json(&'f Value),
+}
+
+/// A query result with typed columns, parsed once from the raw response values.
+///
+/// Column types are inferred from the values: integers become [ColumnType::Int], columns
+/// mixing integers and other numbers [ColumnType::Float], and strings that are all RFC 3339
+/// timestamps [ColumnType::DateTime]. Objects, arrays and columns of mixed types are kept as
+/// [ColumnType::Json]. Columns with only nulls are strings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataFrame {
+    names: Vec<String>,
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+/// A row of a [DataFrame], borrowed from it.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameRow<'f> {
+    frame: &'f DataFrame,
+    index: usize,
+}
+
+impl DataFrame {
+    pub fn from_result(result: &QueryResult<'_>) -> Result<Self, FrameError> {
+        let mut columns = Vec::with_capacity(result.header.len());
+        for name in result.header.iter() {
+            let values: Vec<Value> = result
+                .results
+                .iter()
+                .map(|row| row.get(name.as_str()).map_or(Ok(Value::Null), |v| serde_json::from_str(v.get())))
+                .collect::<Result<_, _>>()?;
+            columns.push(Column::from_values(values));
+        }
+
+        Ok(Self {
+            names: result.header.clone(),
+            columns,
+            rows: result.results.len(),
+        })
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Column names, in result order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &Column)> {
+        self.names.iter().map(String::as_str).zip(self.columns.iter())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.position(name).map(|i| &self.columns[i])
+    }
+
+    /// Looks up a column, failing with the column name when it doesn't exist.
+    pub fn try_column(&self, name: &str) -> Result<&Column, FrameError> {
+        self.column(name).ok_or_else(|| FrameError::UnknownColumn(name.to_string()))
+    }
+
+    pub fn row(&self, index: usize) -> Option<FrameRow<'_>> {
+        (index < self.rows).then_some(FrameRow { frame: self, index })
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = FrameRow<'_>> {
+        (0..self.rows).map(move |index| FrameRow { frame: self, index })
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+}
+
+impl TryFrom<&QueryResult<'_>> for DataFrame {
+    type Error = FrameError;
+
+    fn try_from(result: &QueryResult<'_>) -> Result<Self, Self::Error> {
+        Self::from_result(result)
+    }
+}
+
+impl<'f> FrameRow<'f> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The row's value in the named column, or `None` when there's no such column.
+    pub fn get(&self, name: &str) -> Option<Cell<'f>> {
+        self.frame.column(name).map(|c| c.get(self.index))
+    }
+
+    /// The row's values, in column order.
+    pub fn cells(&self) -> impl Iterator<Item = Cell<'f>> + 'f {
+        let index = self.index;
+        self.frame.columns.iter().map(move |c| c.get(index))
+    }
+}
+
+impl Column {
+    fn from_values(values: Vec<Value>) -> Self {
+        match infer(&values) {
+            ColumnType::Bool => Column::Bool(values.iter().map(Value::as_bool).collect()),
+            ColumnType::Int => Column::Int(values.iter().map(Value::as_i64).collect()),
+            ColumnType::Float => Column::Float(values.iter().map(Value::as_f64).collect()),
+            ColumnType::DateTime => Column::DateTime(
+                values
+                    .iter()
+                    .map(|v| v.as_str().and_then(|s| DateTime::parse_from_rfc3339(s).ok()))
+                    .collect(),
+            ),
+            ColumnType::String => Column::String(
+                values
+                    .into_iter()
+                    .map(|v| match v {
+                        Value::String(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            ColumnType::Json => Column::Json(
+                values
+                    .into_iter()
+                    .map(|v| Some(v).filter(|v| !v.is_null()))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn typ(&self) -> ColumnType {
+        match self {
+            Column::String(_) => ColumnType::String,
+            Column::Int(_) => ColumnType::Int,
+            Column::Float(_) => ColumnType::Float,
+            Column::Bool(_) => ColumnType::Bool,
+            Column::DateTime(_) => ColumnType::DateTime,
+            Column::Json(_) => ColumnType::Json,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Column::String(v) => v.len(),
+            Column::Int(v) => v.len(),
+            Column::Float(v) => v.len(),
+            Column::Bool(v) => v.len(),
+            Column::DateTime(v) => v.len(),
+            Column::Json(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value at `index`, or [Cell::Null] past the end.
+    pub fn get(&self, index: usize) -> Cell<'_> {
+        let cell = match self {
+            Column::String(v) => v.get(index).and_then(|v| v.as_deref()).map(Cell::String),
+            Column::Int(v) => v.get(index).copied().flatten().map(Cell::Int),
+            Column::Float(v) => v.get(index).copied().flatten().map(Cell::Float),
+            Column::Bool(v) => v.get(index).copied().flatten().map(Cell::Bool),
+            Column::DateTime(v) => v.get(index).copied().flatten().map(Cell::DateTime),
+            Column::Json(v) => v.get(index).and_then(Option::as_ref).map(Cell::Json),
+        };
+        cell.unwrap_or(Cell::Null)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cell<'_>> {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    pub fn as_strings(&self) -> Option<&[Option<String>]> {
+        match self {
+            Column::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_ints(&self) -> Option<&[Option<i64>]> {
+        match self {
+            Column::Int(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_floats(&self) -> Option<&[Option<f64>]> {
+        match self {
+            Column::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bools(&self) -> Option<&[Option<bool>]> {
+        match self {
+            Column::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_datetimes(&self) -> Option<&[Option<DateTime<FixedOffset>>]> {
+        match self {
+            Column::DateTime(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_json(&self) -> Option<&[Option<Value>]> {
+        match self {
+            Column::Json(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl Cell<'_> {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Cell::Null)
+    }
+
+    /// The cell as the JSON value it was parsed from. Timestamps become RFC 3339 strings.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Cell::Null => Value::Null,
+            Cell::String(s) => Value::String(s.to_string()),
+            Cell::Int(i) => Value::from(*i),
+            Cell::Float(f) => Value::from(*f),
+            Cell::Bool(b) => Value::Bool(*b),
+            Cell::DateTime(d) => Value::String(d.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            Cell::Json(v) => (*v).clone(),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::DateTime => "datetime",
+            ColumnType::Json => "json",
+        })
+    }
+}
+
+/// Writes the cell as plain text: strings unquoted, nulls empty and everything else as JSON.
+impl fmt::Display for Cell<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Null => Ok(()),
+            Cell::String(s) => f.write_str(s),
+            Cell::Json(Value::String(s)) => f.write_str(s),
+            Cell::DateTime(d) => f.write_str(&d.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            other => write!(f, "{}", other.to_value()),
+        }
+    }
+}
+
+/// Picks the narrowest type that fits every non-null value.
+fn infer(values: &[Value]) -> ColumnType {
+    let mut typ = None;
+    for value in values {
+        typ = match (typ, value) {
+            (t, Value::Null) => t,
+            (None | Some(ColumnType::Bool), Value::Bool(_)) => Some(ColumnType::Bool),
+            (None | Some(ColumnType::Int), Value::Number(n)) if n.is_i64() => Some(ColumnType::Int),
+            (None | Some(ColumnType::Int) | Some(ColumnType::Float), Value::Number(_)) => Some(ColumnType::Float),
+            (None | Some(ColumnType::DateTime), Value::String(s)) if DateTime::parse_from_rfc3339(s).is_ok() => {
+                Some(ColumnType::DateTime)
+            }
+            (None | Some(ColumnType::String) | Some(ColumnType::DateTime), Value::String(_)) => Some(ColumnType::String),
+            _ => return ColumnType::Json,
+        };
+    }
+
+    typ.unwrap_or(ColumnType::String)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::result;
+
+    fn frame(response: &str) -> DataFrame {
+        DataFrame::from_result(&result(response).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn infers_the_narrowest_column_types() {
+        let frame = frame(r#"{"header":["i","f","b","t","s","j","n"],"results":[
+            {"i":1,"f":1,"b":true,"t":"2024-03-01T10:30:00Z","s":"2024-03-01T10:30:00Z","j":1,"n":null},
+            {"i":null,"f":1.5,"b":false,"t":"2024-03-02T08:00:00+02:00","s":"x","j":"x"}]}"#);
+        let types: Vec<(&str, ColumnType)> = frame.columns().map(|(name, column)| (name, column.typ())).collect();
+        assert_eq!(
+            types,
+            [
+                ("i", ColumnType::Int),
+                ("f", ColumnType::Float),
+                ("b", ColumnType::Bool),
+                ("t", ColumnType::DateTime),
+                ("s", ColumnType::String),
+                ("j", ColumnType::Json),
+                ("n", ColumnType::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_cells_by_row_and_column() {
+        let frame = frame(r#"{"header":["name","count"],"results":[{"name":"a","count":2},{"name":"b"}]}"#);
+        assert_eq!(frame.len(), 2);
+        let row = frame.row(1).unwrap();
+        assert_eq!(row.get("name"), Some(Cell::String("b")));
+        assert_eq!(row.get("count"), Some(Cell::Null));
+        assert_eq!(frame.try_column("count").unwrap().as_ints(), Some(&[Some(2), None][..]));
+        assert!(matches!(frame.try_column("missing"), Err(FrameError::UnknownColumn(c)) if c == "missing"));
+    }
+
+    #[test]
+    fn displays_cells_as_plain_text() {
+        let frame = frame(r#"{"header":["s","n","j"],"results":[{"s":"a b","n":null,"j":{"k":[1]}}]}"#);
+        let cells: Vec<String> = frame.row(0).unwrap().cells().map(|c| c.to_string()).collect();
+        assert_eq!(cells, ["a b", "", r#"{"k":[1]}"#]);
+    }
+}
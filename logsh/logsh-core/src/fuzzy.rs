@@ -0,0 +1,88 @@
+use crate::error::MatchError;
+
+/// How [find] picked out an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// By its whole name, ignoring case.
+    Exact,
+    /// By the start of its name.
+    Prefix,
+    /// By any part of its name, or its characters in order. Worth confirming before acting on it.
+    Fuzzy,
+}
+
+/// The items whose names match `query` best, ignoring case, and how they matched: by whole name,
+/// then by the start of the name, then by any part of it, then by its characters in order.
+/// None when nothing matches.
+pub fn find<'a, T, F>(items: &'a [T], name: F, query: &str) -> Option<(Vec<&'a T>, MatchKind)>
+where
+    F: Fn(&T) -> &str,
+{
+    type Pass<'p> = (MatchKind, &'p dyn Fn(&str) -> bool);
+    let lower = query.to_lowercase();
+    let passes: [Pass; 4] = [
+        (MatchKind::Exact, &|n: &str| n == lower),
+        (MatchKind::Prefix, &|n: &str| n.starts_with(&lower)),
+        (MatchKind::Fuzzy, &|n: &str| n.contains(&lower)),
+        (MatchKind::Fuzzy, &|n: &str| is_subsequence(&lower, n)),
+    ];
+    for (kind, pass) in passes {
+        let matches: Vec<&T> = items.iter().filter(|i| pass(&name(i).to_lowercase())).collect();
+        if !matches.is_empty() {
+            return Some((matches, kind));
+        }
+    }
+
+    None
+}
+
+/// The one item whose name matches `query` best, as [find] ranks them. Fails when nothing matches
+/// or the best kind of match finds more than one. `what` names the kind of item in errors.
+pub fn find_one<'a, T, F>(items: &'a [T], name: F, query: &str, what: &str) -> Result<&'a T, MatchError>
+where
+    F: Fn(&T) -> &str,
+{
+    match find(items, &name, query) {
+        Some((matches, _)) => match matches.as_slice() {
+            [one] => Ok(one),
+            many => {
+                let names: Vec<&str> = many.iter().map(|i| name(i)).collect();
+                Err(MatchError::Ambiguous(query.to_string(), what.to_string(), names.join(", ")))
+            }
+        },
+        None => Err(MatchError::NotFound(what.to_string(), query.to_string())),
+    }
+}
+
+/// Whether every character of `needle` appears in `haystack`, in order.
+pub fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["prod", "prod-eu", "staging", "my-production"];
+
+    fn found(query: &str) -> Option<(Vec<&'static str>, MatchKind)> {
+        find(&NAMES, |n| n, query).map(|(matches, kind)| (matches.into_iter().copied().collect(), kind))
+    }
+
+    #[test]
+    fn ranks_whole_names_then_prefixes_then_parts() {
+        assert_eq!(found("PROD"), Some((vec!["prod"], MatchKind::Exact)));
+        assert_eq!(found("prod-"), Some((vec!["prod-eu"], MatchKind::Prefix)));
+        assert_eq!(found("duct"), Some((vec!["my-production"], MatchKind::Fuzzy)));
+        assert_eq!(found("stg"), Some((vec!["staging"], MatchKind::Fuzzy)));
+        assert_eq!(found("xyz"), None);
+    }
+
+    #[test]
+    fn finds_one_or_explains_why_not() {
+        assert_eq!(find_one(&NAMES, |n| n, "stag", "connection").unwrap(), &"staging");
+        assert!(matches!(find_one(&NAMES, |n| n, "pr", "connection"), Err(MatchError::Ambiguous(..))));
+        assert_eq!(find_one(&NAMES, |n| n, "xyz", "connection").unwrap_err().to_string(), "No connection matches \"xyz\".");
+    }
+}
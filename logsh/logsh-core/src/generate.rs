@@ -0,0 +1,253 @@
+use std::{fmt, io::Write};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::Value;
+use thiserror::Error;
+
+const WORDS: &[&str] = &[
+    "request", "completed", "failed", "user", "session", "cache", "miss", "hit", "timeout", "retry",
+    "connection", "opened", "closed", "queue", "worker", "started", "stopped", "payment", "order",
+    "shipped", "login", "logout", "token", "refreshed", "disk", "usage", "high", "low", "service",
+    "healthy", "degraded", "upstream", "latency", "database", "query", "slow", "index", "rebuilt",
+];
+
+/// Template used when none is given: a typical application log line.
+const DEFAULT_TEMPLATE: &str = r#"{
+    "timestamp": "{{timestamp}}",
+    "level": "{{one_of:debug,info,info,info,warn,error}}",
+    "host": "{{one_of:web-01,web-02,web-03,worker-01,worker-02}}",
+    "service": "{{one_of:api,auth,billing,search}}",
+    "message": "{{words:6}}",
+    "duration_ms": "{{int:1..2000}}",
+    "status": "{{one_of:200,200,200,201,204,400,404,500}}",
+    "request_id": "{{uuid}}"
+}"#;
+
+#[derive(Debug, Error)]
+pub enum GenerateError {
+    #[error("Invalid template: {0}")]
+    InvalidTemplate(String),
+
+    #[error("Failed to read template: {0}")]
+    FailedRead(#[from] serde_json::Error),
+
+    #[error("Failed to write rows: {0}")]
+    FailedWrite(#[from] csv::Error),
+
+    #[error("Failed to write rows: {0}")]
+    FailedFlush(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+enum Generator {
+    Literal(String),
+    Timestamp,
+    /// A random time within this window before now.
+    TimestampWithin(chrono::Duration),
+    Uuid,
+    Sequence,
+    Bool,
+    Ipv4,
+    Int(i64, i64),
+    Float(f64, f64),
+    OneOf(Vec<String>),
+    Words(usize),
+}
+
+/// Fake row template: a JSON object whose string values may be placeholders.
+///
+/// Supported placeholders are `{{timestamp}}`, `{{timestamp:WINDOW}}` (a random time within a
+/// window before now, such as `24h`), `{{uuid}}`, `{{seq}}`, `{{bool}}`, `{{ipv4}}`,
+/// `{{int:MIN..MAX}}`, `{{float:MIN..MAX}}`, `{{one_of:a,b,c}}` and `{{words:N}}`. Any other
+/// value is copied into every row as-is.
+#[derive(Debug, Clone)]
+pub struct Template {
+    fields: Vec<(String, Generator)>,
+    sequence: u64,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl Template {
+    pub fn parse(json: &str) -> Result<Self, GenerateError> {
+        let OrderedObject(entries) = serde_json::from_str(json)?;
+        if entries.is_empty() {
+            return Err(GenerateError::InvalidTemplate("the template has no fields".to_string()));
+        }
+
+        let fields = entries
+            .into_iter()
+            .map(|(name, value)| {
+                let generator = match value {
+                    Value::String(s) => match s.strip_prefix("{{").and_then(|s| s.strip_suffix("}}")) {
+                        Some(placeholder) => parse_placeholder(placeholder.trim())?,
+                        None => Generator::Literal(s),
+                    },
+                    other => Generator::Literal(other.to_string()),
+                };
+                Ok((name, generator))
+            })
+            .collect::<Result<_, GenerateError>>()?;
+
+        Ok(Self { fields, sequence: 0 })
+    }
+
+    pub fn header(&self) -> Vec<&str> {
+        self.fields.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Writes `rows` fake rows, with a header, as CSV.
+    pub fn write_csv<W: Write>(&mut self, rows: usize, to: W) -> Result<(), GenerateError> {
+        let mut wtr = csv::Writer::from_writer(to);
+        wtr.write_record(self.header())?;
+        let mut record = Vec::with_capacity(self.fields.len());
+        for _ in 0..rows {
+            self.sequence += 1;
+            record.clear();
+            record.extend(self.fields.iter().map(|(_, g)| generate(g, self.sequence)));
+            wtr.write_record(&record)?;
+        }
+
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Generator, GenerateError> {
+    let invalid = |reason: &str| GenerateError::InvalidTemplate(format!("{{{{{}}}}}: {}", placeholder, reason));
+    let (name, args) = placeholder.split_once(':').map_or((placeholder, None), |(n, a)| (n, Some(a.trim())));
+    let range = args
+        .and_then(|a| a.split_once(".."))
+        .map(|(min, max)| (min.trim(), max.trim()))
+        .ok_or_else(|| invalid("expected a MIN..MAX range"));
+
+    let generator = match name.trim() {
+        "timestamp" | "now" => match args {
+            Some(window) => Generator::TimestampWithin(
+                parse_window(window).ok_or_else(|| invalid("expected a window such as 30m, 24h or 7d"))?,
+            ),
+            None => Generator::Timestamp,
+        },
+        "uuid" => Generator::Uuid,
+        "seq" => Generator::Sequence,
+        "bool" => Generator::Bool,
+        "ipv4" => Generator::Ipv4,
+        "int" => {
+            let (min, max) = range?;
+            let min = min.parse().map_err(|_| invalid("MIN is not an integer"))?;
+            let max = max.parse().map_err(|_| invalid("MAX is not an integer"))?;
+            if min > max {
+                return Err(invalid("MIN is larger than MAX"));
+            }
+            Generator::Int(min, max)
+        }
+        "float" => {
+            let (min, max) = range?;
+            let min: f64 = min.parse().map_err(|_| invalid("MIN is not a number"))?;
+            let max: f64 = max.parse().map_err(|_| invalid("MAX is not a number"))?;
+            if min.is_nan() || max.is_nan() || min > max {
+                return Err(invalid("MIN is larger than MAX"));
+            }
+            Generator::Float(min, max)
+        }
+        "one_of" => {
+            let values: Vec<String> = args
+                .unwrap_or_default()
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect();
+            if values.is_empty() {
+                return Err(invalid("expected comma separated values"));
+            }
+            Generator::OneOf(values)
+        }
+        "words" => Generator::Words(
+            args.unwrap_or("5").parse().map_err(|_| invalid("expected a word count"))?,
+        ),
+        other => return Err(GenerateError::InvalidTemplate(format!("unknown placeholder \"{}\"", other))),
+    };
+
+    Ok(generator)
+}
+
+/// Parses a window such as `90s`, `30m`, `24h` or `7d`.
+fn parse_window(window: &str) -> Option<chrono::Duration> {
+    let unit_at = window.find(|c: char| !c.is_ascii_digit())?;
+    let count: i64 = window[..unit_at].parse().ok()?;
+    let unit: i64 = match window[unit_at..].trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    // Keep the window within what chrono can hold in milliseconds.
+    let seconds = count.checked_mul(unit).filter(|s| *s > 0 && *s < i64::MAX / 1000)?;
+    Some(chrono::Duration::seconds(seconds))
+}
+
+fn generate(generator: &Generator, sequence: u64) -> String {
+    match generator {
+        Generator::Literal(s) => s.to_string(),
+        Generator::Timestamp => Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        Generator::TimestampWithin(window) => {
+            let offset = chrono::Duration::milliseconds(fastrand::i64(0..window.num_milliseconds()));
+            (Utc::now() - offset).to_rfc3339_opts(SecondsFormat::Millis, true)
+        }
+        Generator::Uuid => uuid::Builder::from_random_bytes(fastrand::u128(..).to_le_bytes())
+            .into_uuid()
+            .to_string(),
+        Generator::Sequence => sequence.to_string(),
+        Generator::Bool => fastrand::bool().to_string(),
+        Generator::Ipv4 => format!(
+            "10.{}.{}.{}",
+            fastrand::u8(..),
+            fastrand::u8(..),
+            fastrand::u8(1..255)
+        ),
+        Generator::Int(min, max) => fastrand::i64(*min..=*max).to_string(),
+        Generator::Float(min, max) => format!("{:.3}", min + fastrand::f64() * (max - min)),
+        Generator::OneOf(values) => values[fastrand::usize(..values.len())].to_string(),
+        Generator::Words(count) => (0..*count)
+            .map(|_| WORDS[fastrand::usize(..WORDS.len())])
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// A JSON object that keeps its keys in document order, so template columns come out in
+/// the order they were written.
+struct OrderedObject(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for OrderedObject {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedVisitor;
+
+        impl<'de> Visitor<'de> for OrderedVisitor {
+            type Value = OrderedObject;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedObject(entries))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedVisitor)
+    }
+}
@@ -0,0 +1,700 @@
+use serde_json::Value;
+
+use crate::error::ImportError;
+
+/// Language of a panel query, from its data source or, failing that, its syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    LogQl,
+    PromQl,
+    Other,
+}
+
+impl Language {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::LogQl => "LogQL",
+            Language::PromQl => "PromQL",
+            Language::Other => "other",
+        }
+    }
+}
+
+/// A query of a dashboard panel.
+#[derive(Clone, Debug)]
+pub struct PanelQuery {
+    pub panel: String,
+    /// Grafana's letter for the query within its panel, such as A.
+    pub ref_id: String,
+    pub language: Language,
+    pub expr: String,
+}
+
+/// The queries of a Grafana dashboard, in panel order.
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    pub title: String,
+    pub queries: Vec<PanelQuery>,
+}
+
+/// Names a converted LogQL query runs against. Stream labels are expected to be columns of the
+/// table, next to the log line and its timestamp.
+#[derive(Clone, Copy, Debug)]
+pub struct LogQlTarget<'a> {
+    pub table: &'a str,
+    pub message: &'a str,
+    pub timestamp: &'a str,
+}
+
+/// A panel query ready to be saved.
+#[derive(Clone, Debug)]
+pub struct Imported {
+    pub name: String,
+    pub panel: String,
+    pub language: Language,
+    /// The converted query, annotated with the original, or the original as a TODO comment.
+    pub query: String,
+    /// Why the query wasn't converted, when it wasn't.
+    pub todo: Option<String>,
+}
+
+/// Reads a dashboard as exported by Grafana, either on its own or wrapped in the `dashboard`
+/// field the HTTP API returns. Rows, whether collapsed or from older versions, are walked too.
+pub fn parse(text: &str) -> Result<Dashboard, ImportError> {
+    let value: Value = serde_json::from_str(text).map_err(ImportError::InvalidJson)?;
+    let dashboard = value.get("dashboard").unwrap_or(&value);
+    let mut panels = Vec::new();
+    if let Some(list) = dashboard.get("panels").and_then(Value::as_array) {
+        collect_panels(list, &mut panels);
+    }
+    for row in dashboard
+        .get("rows")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(list) = row.get("panels").and_then(Value::as_array) {
+            collect_panels(list, &mut panels);
+        }
+    }
+    if panels.is_empty() && dashboard.get("panels").is_none() && dashboard.get("rows").is_none() {
+        return Err(ImportError::NoPanels);
+    }
+
+    let default_source = dashboard.get("datasource");
+    let mut queries = Vec::new();
+    for panel in panels {
+        let title = panel
+            .get("title")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .trim();
+        let panel_source = panel.get("datasource").or(default_source);
+        for target in panel
+            .get("targets")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let Some(expr) = ["expr", "query", "rawSql"]
+                .iter()
+                .find_map(|field| target.get(*field).and_then(Value::as_str))
+                .map(str::trim)
+                .filter(|expr| !expr.is_empty())
+            else {
+                continue;
+            };
+
+            let source = target
+                .get("datasource")
+                .filter(|s| !s.is_null())
+                .or(panel_source);
+            queries.push(PanelQuery {
+                panel: match title.is_empty() {
+                    true => format!(
+                        "panel {}",
+                        panel.get("id").map_or("?".to_string(), Value::to_string)
+                    ),
+                    false => title.to_string(),
+                },
+                ref_id: target
+                    .get("refId")
+                    .and_then(Value::as_str)
+                    .unwrap_or("A")
+                    .to_string(),
+                language: language(source, target, expr),
+                expr: expr.to_string(),
+            });
+        }
+    }
+
+    Ok(Dashboard {
+        title: dashboard
+            .get("title")
+            .and_then(Value::as_str)
+            .unwrap_or("dashboard")
+            .to_string(),
+        queries,
+    })
+}
+
+fn collect_panels<'a>(list: &'a [Value], panels: &mut Vec<&'a Value>) {
+    for panel in list {
+        panels.push(panel);
+        if let Some(nested) = panel.get("panels").and_then(Value::as_array) {
+            collect_panels(nested, panels);
+        }
+    }
+}
+
+/// The data source's type when it has one, then its name, then the query's syntax. LogQL
+/// starts with a stream selector, or a function of one, where PromQL names a metric first.
+fn language(source: Option<&Value>, target: &Value, expr: &str) -> Language {
+    if target.get("rawSql").is_some() {
+        return Language::Other;
+    }
+
+    let source = match source {
+        Some(Value::Object(source)) => source.get("type").and_then(Value::as_str),
+        Some(Value::String(name)) => Some(name.as_str()),
+        _ => None,
+    }
+    .unwrap_or_default()
+    .to_lowercase();
+    if source.contains("loki") {
+        return Language::LogQl;
+    }
+    if source.contains("prometheus") {
+        return Language::PromQl;
+    }
+    if !source.is_empty() && !source.starts_with('$') {
+        return Language::Other;
+    }
+
+    match expr.find('{').map(|i| expr[..i].trim_end()) {
+        Some(before) if before.is_empty() || before.ends_with('(') => Language::LogQl,
+        _ => Language::PromQl,
+    }
+}
+
+/// Converts each of the dashboard's queries that can be, and annotates the rest as TODOs. Names
+/// are the prefix and panel title, with the query's letter when a panel has several.
+pub fn import(dashboard: &Dashboard, prefix: &str, target: &LogQlTarget) -> Vec<Imported> {
+    let mut imported: Vec<Imported> = Vec::with_capacity(dashboard.queries.len());
+    for query in dashboard.queries.iter() {
+        let several = dashboard
+            .queries
+            .iter()
+            .filter(|q| q.panel == query.panel)
+            .count()
+            > 1;
+        let mut name = match several {
+            true => format!("{}.{}-{}", prefix, slug(&query.panel), slug(&query.ref_id)),
+            false => format!("{}.{}", prefix, slug(&query.panel)),
+        };
+        let base = name.clone();
+        let mut n = 2;
+        while imported.iter().any(|i| i.name == name) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+
+        let source = format!(
+            "Grafana dashboard \"{}\", panel \"{}\"",
+            dashboard.title, query.panel
+        );
+        let converted = match query.language {
+            Language::LogQl => convert_logql(&query.expr, target),
+            Language::PromQl => Err("PromQL queries metrics rather than logs".to_string()),
+            Language::Other => Err("only LogQL can be converted".to_string()),
+        };
+        let (text, todo) = match converted {
+            Ok(converted) => (
+                format!(
+                    "// Imported from {} ({}):\n{}\n{}",
+                    source,
+                    query.language.name(),
+                    comment(&query.expr),
+                    converted
+                ),
+                None,
+            ),
+            Err(reason) => {
+                let kind = match query.language {
+                    Language::Other => "query".to_string(),
+                    language => format!("{} query", language.name()),
+                };
+                (
+                    format!(
+                        "// TODO: convert this {} from {}: {}.\n{}",
+                        kind,
+                        source,
+                        reason,
+                        comment(&query.expr)
+                    ),
+                    Some(reason),
+                )
+            }
+        };
+        imported.push(Imported {
+            name,
+            panel: query.panel.clone(),
+            language: query.language,
+            query: text,
+            todo,
+        });
+    }
+    imported
+}
+
+/// Lowercase letters and digits, with dashes between words.
+pub fn slug(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    if slug.is_empty() {
+        "query".to_string()
+    } else {
+        slug
+    }
+}
+
+fn comment(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("// {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Converts a LogQL query, or says what it uses that can't be. Supports a stream selector with
+/// line filters, and `count_over_time` or `rate` of one, optionally summed by labels. Parsers
+/// such as `| json`, and anything after them, aren't converted.
+pub fn convert_logql(expr: &str, target: &LogQlTarget) -> Result<String, String> {
+    if let Some(name) = variable(expr) {
+        return Err(format!("it uses the dashboard variable {}", name));
+    }
+
+    let mut parser = Parser { rest: expr };
+    let query = parser.metric_query()?;
+    parser.skip_whitespace();
+    if !parser.rest.is_empty() {
+        return Err(format!(
+            "couldn't read \"{}\"",
+            parser.rest.chars().take(20).collect::<String>()
+        ));
+    }
+
+    let mut lines = vec![kql_name(target.table)];
+    let matchers: Vec<String> = query
+        .log
+        .matchers
+        .iter()
+        .map(|(label, op, value)| {
+            let label = kql_name(label);
+            let anchored = kql_string(&format!("^(?:{})$", value));
+            match *op {
+                "=" => format!("{} == {}", label, kql_string(value)),
+                "!=" => format!("{} != {}", label, kql_string(value)),
+                "=~" => format!("{} matches regex {}", label, anchored),
+                _ => format!("not({} matches regex {})", label, anchored),
+            }
+        })
+        .collect();
+    if !matchers.is_empty() {
+        lines.push(format!("| where {}", matchers.join(" and ")));
+    }
+
+    let message = kql_name(target.message);
+    for (op, value) in query.log.filters.iter() {
+        let value = kql_string(value);
+        lines.push(match *op {
+            "|=" => format!("| where {} contains_cs {}", message, value),
+            "!=" => format!("| where {} !contains_cs {}", message, value),
+            "|~" => format!("| where {} matches regex {}", message, value),
+            _ => format!("| where not({} matches regex {})", message, value),
+        });
+    }
+
+    if let Some(aggregate) = query.aggregate {
+        let mut by = vec![format!(
+            "bin({}, {})",
+            kql_name(target.timestamp),
+            aggregate.range
+        )];
+        by.extend(aggregate.by.iter().map(|label| kql_name(label)));
+        let value = match aggregate.function {
+            "rate" => format!("rate = count() / {:.1}", aggregate.seconds),
+            _ => "count = count()".to_string(),
+        };
+        lines.push(format!("| summarize {} by {}", value, by.join(", ")));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// The first dashboard variable, `$name` or `${name}`, in a query. Other dollar signs, such as
+/// the anchors of regular expressions, aren't variables.
+fn variable(expr: &str) -> Option<&str> {
+    let is_start = |c: char| c.is_alphabetic() || c == '_';
+    expr.match_indices('$').find_map(|(i, _)| {
+        let after = &expr[i + 1..];
+        let len = match after.strip_prefix('{') {
+            Some(braced) if braced.starts_with(is_start) => braced.find('}')? + 2,
+            Some(_) => return None,
+            None if after.starts_with(is_start) => after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len()),
+            None => return None,
+        };
+        Some(&expr[i..i + 1 + len])
+    })
+}
+
+struct LogQuery<'a> {
+    /// Label, operator and value of each stream matcher.
+    matchers: Vec<(&'a str, &'static str, String)>,
+    /// Operator and text of each line filter.
+    filters: Vec<(&'static str, String)>,
+}
+
+struct Aggregate<'a> {
+    function: &'a str,
+    range: &'a str,
+    seconds: f64,
+    by: Vec<&'a str>,
+}
+
+struct MetricQuery<'a> {
+    log: LogQuery<'a>,
+    aggregate: Option<Aggregate<'a>>,
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(format!(
+                "expected \"{}\" before \"{}\"",
+                token,
+                self.rest.chars().take(20).collect::<String>()
+            )),
+        }
+    }
+
+    fn identifier(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 || self.rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let (identifier, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(identifier)
+    }
+
+    /// A double quoted string with escapes, or a raw string between backticks.
+    fn string(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if let Some(raw) = self.rest.strip_prefix('`') {
+            let end = raw
+                .find('`')
+                .ok_or("a string is missing its closing backtick")?;
+            self.rest = &raw[end + 1..];
+            return Ok(raw[..end].to_string());
+        }
+
+        let quoted = self.rest.strip_prefix('"').ok_or_else(|| {
+            format!(
+                "expected a string before \"{}\"",
+                self.rest.chars().take(20).collect::<String>()
+            )
+        })?;
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &quoted[i + 1..];
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("a string is missing its closing quote".to_string())
+    }
+
+    /// `sum [by (labels)] (range aggregation) [by (labels)]`, a range aggregation, or a log query.
+    fn metric_query(&mut self) -> Result<MetricQuery<'a>, String> {
+        let start = self.rest;
+        match self.identifier() {
+            Some("sum") => {
+                let mut by = self.grouping()?;
+                self.expect("(")?;
+                let mut query = self.range_aggregation()?;
+                self.expect(")")?;
+                if by.is_empty() {
+                    by = self.grouping()?;
+                }
+                if let Some(aggregate) = query.aggregate.as_mut() {
+                    aggregate.by = by;
+                }
+                Ok(query)
+            }
+            Some("count_over_time" | "rate") => {
+                self.rest = start;
+                self.range_aggregation()
+            }
+            Some(function) => Err(format!("the {} function isn't supported", function)),
+            None => Ok(MetricQuery {
+                log: self.log_query()?,
+                aggregate: None,
+            }),
+        }
+    }
+
+    /// `by (label, ...)`, or nothing.
+    fn grouping(&mut self) -> Result<Vec<&'a str>, String> {
+        let start = self.rest;
+        match self.identifier() {
+            Some("by") => {}
+            Some("without") => {
+                return Err("grouping \"without\" labels isn't supported".to_string())
+            }
+            _ => {
+                self.rest = start;
+                return Ok(Vec::new());
+            }
+        }
+
+        self.expect("(")?;
+        let mut labels = Vec::new();
+        while !self.eat(")") {
+            labels.push(self.identifier().ok_or("expected a label to group by")?);
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(labels)
+    }
+
+    /// `count_over_time(log query [range])` or `rate(...)`.
+    fn range_aggregation(&mut self) -> Result<MetricQuery<'a>, String> {
+        let function = match self.identifier() {
+            Some(function @ ("count_over_time" | "rate")) => function,
+            Some(function) => return Err(format!("the {} function isn't supported", function)),
+            None => return Err("expected count_over_time or rate".to_string()),
+        };
+        self.expect("(")?;
+        let log = self.log_query()?;
+        self.expect("[")?;
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(']')
+            .ok_or("a range is missing its closing \"]\"")?;
+        let range = self.rest[..end].trim();
+        let seconds = range_seconds(range)
+            .ok_or_else(|| format!("the range [{}] isn't a single duration such as 5m", range))?;
+        self.rest = &self.rest[end + 1..];
+        self.expect(")")?;
+        Ok(MetricQuery {
+            log,
+            aggregate: Some(Aggregate {
+                function,
+                range,
+                seconds,
+                by: Vec::new(),
+            }),
+        })
+    }
+
+    /// A stream selector followed by line filters.
+    fn log_query(&mut self) -> Result<LogQuery<'a>, String> {
+        self.expect("{")?;
+        let mut matchers = Vec::new();
+        while !self.eat("}") {
+            let label = self
+                .identifier()
+                .ok_or("expected a label in the stream selector")?;
+            let op = ["=~", "!~", "!=", "="]
+                .into_iter()
+                .find(|op| self.eat(op))
+                .ok_or_else(|| format!("expected an operator after {}", label))?;
+            matchers.push((label, op, self.string()?));
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+
+        let mut filters = Vec::new();
+        loop {
+            match ["|=", "!=", "|~", "!~"].into_iter().find(|op| self.eat(op)) {
+                Some(op) => filters.push((op, self.string()?)),
+                None if self.eat("|") => {
+                    let stage = self
+                        .identifier()
+                        .map_or_else(|| self.rest.chars().take(20).collect(), str::to_string);
+                    return Err(format!("the \"| {}\" stage isn't supported", stage));
+                }
+                None => break,
+            }
+        }
+        Ok(LogQuery { matchers, filters })
+    }
+}
+
+/// Seconds in a LogQL range such as `5m`, which is written the same way as a timespan in the
+/// query language. Only single units are accepted.
+fn range_seconds(range: &str) -> Option<f64> {
+    let split = range.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = range.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+    let unit = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return None,
+    };
+    Some(amount * unit)
+}
+
+fn kql_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t")
+    )
+}
+
+/// A table or column name, bracketed unless it's a plain identifier.
+fn kql_name(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match plain {
+        true => name.to_string(),
+        false => format!("['{}']", name.replace('\'', "\\'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: LogQlTarget = LogQlTarget {
+        table: "Logs",
+        message: "Message",
+        timestamp: "Timestamp",
+    };
+
+    #[test]
+    fn converts_stream_selectors() {
+        assert_eq!(
+            convert_logql(r#"{app="api", env=~"prod|stage", tier!="db"}"#, &TARGET).unwrap(),
+            "Logs\n| where app == \"api\" and env matches regex \"^(?:prod|stage)$\" and tier != \"db\""
+        );
+        assert_eq!(
+            convert_logql(r#"{app!~"test.*"}"#, &TARGET).unwrap(),
+            "Logs\n| where not(app matches regex \"^(?:test.*)$\")"
+        );
+    }
+
+    #[test]
+    fn converts_line_filters() {
+        assert_eq!(
+            convert_logql(
+                r#"{app="api"} |= "error" != "debug" |~ "timeout$" !~ `^GET`"#,
+                &TARGET
+            )
+            .unwrap(),
+            "Logs\n| where app == \"api\"\n| where Message contains_cs \"error\"\n\
+             | where Message !contains_cs \"debug\"\n| where Message matches regex \"timeout$\"\n\
+             | where not(Message matches regex \"^GET\")"
+        );
+    }
+
+    #[test]
+    fn converts_sums_by_labels() {
+        assert_eq!(
+            convert_logql(r#"sum by (level) (count_over_time({app="api"}[1h]))"#, &TARGET).unwrap(),
+            "Logs\n| where app == \"api\"\n| summarize count = count() by bin(Timestamp, 1h), level"
+        );
+        assert_eq!(
+            convert_logql(
+                r#"sum(count_over_time({app="api"}[5m])) by (host, level)"#,
+                &TARGET
+            )
+            .unwrap(),
+            "Logs\n| where app == \"api\"\n\
+             | summarize count = count() by bin(Timestamp, 5m), host, level"
+        );
+    }
+
+    #[test]
+    fn converts_rates() {
+        assert_eq!(
+            convert_logql(r#"rate({app="api"} |= "error" [5m])"#, &TARGET).unwrap(),
+            "Logs\n| where app == \"api\"\n| where Message contains_cs \"error\"\n\
+             | summarize rate = count() / 300.0 by bin(Timestamp, 5m)"
+        );
+    }
+
+    #[test]
+    fn refuses_dashboard_variables() {
+        let err = convert_logql(r#"{app="$app"}"#, &TARGET).unwrap_err();
+        assert_eq!(err, "it uses the dashboard variable $app");
+        let err = convert_logql(r#"rate({app="api"}[${__interval}])"#, &TARGET).unwrap_err();
+        assert_eq!(err, "it uses the dashboard variable ${__interval}");
+        assert!(convert_logql(r#"{app="api"} |~ "^a$|b$""#, &TARGET).is_ok());
+    }
+
+    #[test]
+    fn refuses_what_it_cant_convert() {
+        let err = convert_logql(r#"{app="api"} | json"#, &TARGET).unwrap_err();
+        assert_eq!(err, "the \"| json\" stage isn't supported");
+        let err = convert_logql(r#"avg_over_time({app="api"}[5m])"#, &TARGET).unwrap_err();
+        assert_eq!(err, "the avg_over_time function isn't supported");
+        let err = convert_logql(r#"rate({app="api"}[5m:1m])"#, &TARGET).unwrap_err();
+        assert_eq!(err, "the range [5m:1m] isn't a single duration such as 5m");
+    }
+}
@@ -0,0 +1,199 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{config, error::ConfigError, redact::redact};
+
+/// Size the history file grows to before its older half is dropped.
+const MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// One query run by `logsh query` or the REPL.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub connection: String,
+    pub query: String,
+    pub duration_ms: u64,
+    /// Rows returned. None when the query failed, or the result was streamed to the output
+    /// without being counted.
+    pub rows: Option<usize>,
+    pub bytes: u64,
+    #[serde(default)]
+    pub failed: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(connection: &str, query: &str, duration: Duration, rows: Option<usize>, bytes: u64) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            connection: connection.to_string(),
+            query: query.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            rows,
+            bytes,
+            failed: false,
+        }
+    }
+
+    pub fn failed(connection: &str, query: &str, duration: Duration) -> Self {
+        Self { failed: true, ..Self::new(connection, query, duration, None, 0) }
+    }
+}
+
+/// The query history lives next to the logsh configuration file, one JSON entry per line.
+pub fn get_history_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("query_history.jsonl"))
+}
+
+/// Appends an entry, when the `query_history` setting is on and logsh isn't in read-only mode.
+/// Credentials in the query are masked first, and only the user may read the file.
+pub fn record(cfg: &config::Configuration, entry: &HistoryEntry) -> Result<(), ConfigError> {
+    if !cfg.query_history || config::read_only() {
+        return Ok(());
+    }
+
+    let path = get_history_path()?;
+    let entry = HistoryEntry { query: redact(&entry.query).into_owned(), ..entry.clone() };
+    let mut line = serde_json::to_string(&entry).map_err(ConfigError::FailedSerialize)?;
+    line.push('\n');
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).map_err(ConfigError::FailedWrite)?;
+    file.write_all(line.as_bytes()).map_err(ConfigError::FailedWrite)?;
+
+    if file.metadata().map_err(ConfigError::FailedRead)?.len() > MAX_BYTES {
+        let text = fs::read_to_string(&path).map_err(ConfigError::FailedRead)?;
+        let lines: Vec<&str> = text.lines().collect();
+        let mut kept = lines[lines.len() / 2..].join("\n");
+        kept.push('\n');
+        config::replace(&path, &kept, true)?;
+    }
+    Ok(())
+}
+
+/// Entries recorded since `since`, oldest first. Lines that can't be read are skipped.
+pub fn load(since: DateTime<Utc>) -> Result<Vec<HistoryEntry>, ConfigError> {
+    let path = get_history_path()?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let text = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+        .filter(|entry| entry.timestamp >= since)
+        .collect())
+}
+
+/// Totals of a set of runs, of one query or connection, or of all of them.
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub runs: usize,
+    pub failures: usize,
+    pub rows: u64,
+    pub bytes: u64,
+    pub average_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Usage {
+    fn of<'a>(entries: impl Iterator<Item = &'a HistoryEntry>) -> Self {
+        let mut usage = Self::default();
+        let mut total_ms = 0;
+        for entry in entries {
+            usage.runs += 1;
+            usage.failures += entry.failed as usize;
+            usage.rows += entry.rows.unwrap_or_default() as u64;
+            usage.bytes += entry.bytes;
+            usage.max_ms = usage.max_ms.max(entry.duration_ms);
+            total_ms += entry.duration_ms;
+        }
+        usage.average_ms = total_ms.checked_div(usage.runs as u64).unwrap_or_default();
+        usage
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryUsage {
+    pub query: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionUsage {
+    pub connection: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+/// Usage summarized from the history, with queries counted as the same when they differ only
+/// in whitespace.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    pub since: DateTime<Utc>,
+    pub total: Usage,
+    /// The `top` most run queries, most runs first.
+    pub most_used: Vec<QueryUsage>,
+    /// The `top` queries with the highest average duration, slowest first.
+    pub slowest: Vec<QueryUsage>,
+    /// Every connection queried, most runs first.
+    pub connections: Vec<ConnectionUsage>,
+}
+
+pub fn summarize(entries: &[HistoryEntry], since: DateTime<Utc>, top: usize) -> UsageSummary {
+    let mut by_query: HashMap<String, Vec<&HistoryEntry>> = HashMap::new();
+    let mut by_connection: HashMap<&str, Vec<&HistoryEntry>> = HashMap::new();
+    for entry in entries {
+        let query = entry.query.split_whitespace().collect::<Vec<_>>().join(" ");
+        by_query.entry(query).or_default().push(entry);
+        by_connection.entry(entry.connection.as_str()).or_default().push(entry);
+    }
+
+    let queries: Vec<QueryUsage> = by_query
+        .into_iter()
+        .map(|(query, entries)| QueryUsage { query, usage: Usage::of(entries.into_iter()) })
+        .collect();
+    let mut most_used = queries.clone();
+    most_used.sort_by(|a, b| b.usage.runs.cmp(&a.usage.runs).then_with(|| a.query.cmp(&b.query)));
+    most_used.truncate(top);
+    let mut slowest = queries;
+    slowest.sort_by(|a, b| b.usage.average_ms.cmp(&a.usage.average_ms).then_with(|| a.query.cmp(&b.query)));
+    slowest.truncate(top);
+
+    let mut connections: Vec<ConnectionUsage> = by_connection
+        .into_iter()
+        .map(|(connection, entries)| ConnectionUsage { connection: connection.to_string(), usage: Usage::of(entries.into_iter()) })
+        .collect();
+    connections.sort_by(|a, b| b.usage.runs.cmp(&a.usage.runs).then_with(|| a.connection.cmp(&b.connection)));
+
+    UsageSummary {
+        since,
+        total: Usage::of(entries.iter()),
+        most_used,
+        slowest,
+        connections,
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use serde_json::Value;
+
+use crate::query::QueryResult;
+
+/// Writes a query result as a standalone HTML page. Columns sort when their header is clicked,
+/// and a filter box hides rows that don't contain the typed text.
+pub fn write_html<W: Write>(query: &QueryResult<'_>, mut to: W) -> Result<(), std::io::Error> {
+    write!(to, "{}", PAGE_START)?;
+    write!(to, "<thead><tr>")?;
+    for h in query.header.iter() {
+        write!(to, "<th>{}</th>", escape(h))?;
+    }
+    write!(to, "</tr></thead>\n<tbody>\n")?;
+    for row in query.results.iter() {
+        write!(to, "<tr>")?;
+        for h in query.header.iter() {
+            let cell = match row.get(h.as_str()).map(|v| serde_json::from_str::<Value>(v.get())) {
+                Some(Ok(Value::String(s))) => s,
+                Some(Ok(Value::Null)) | None => String::new(),
+                Some(Ok(other)) => other.to_string(),
+                Some(Err(_)) => row[h.as_str()].get().to_string(),
+            };
+            write!(to, "<td>{}</td>", escape(&cell))?;
+        }
+        writeln!(to, "</tr>")?;
+    }
+    write!(to, "</tbody>{}", PAGE_END)?;
+    to.flush()
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+const PAGE_START: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>logsh query result</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+input { margin-bottom: 1em; padding: 0.3em; width: 20em; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; white-space: pre-wrap; }
+th { background: #f2f2f2; cursor: pointer; position: sticky; top: 0; }
+tbody tr:nth-child(even) { background: #fafafa; }
+</style>
+</head>
+<body>
+<input id="filter" type="search" placeholder="Filter rows">
+<span id="count"></span>
+<table id="result">
+"#;
+
+const PAGE_END: &str = r#"</table>
+<script>
+const table = document.getElementById("result");
+const body = table.tBodies[0];
+const count = document.getElementById("count");
+const showCount = () => {
+  const visible = Array.from(body.rows).filter(r => !r.hidden).length;
+  count.textContent = visible + " of " + body.rows.length + " rows";
+};
+document.getElementById("filter").addEventListener("input", e => {
+  const text = e.target.value.toLowerCase();
+  for (const row of body.rows) {
+    row.hidden = text !== "" && !row.textContent.toLowerCase().includes(text);
+  }
+  showCount();
+});
+table.tHead.querySelectorAll("th").forEach((th, i) => {
+  th.addEventListener("click", () => {
+    const asc = th.dataset.order !== "asc";
+    table.tHead.querySelectorAll("th").forEach(h => delete h.dataset.order);
+    th.dataset.order = asc ? "asc" : "desc";
+    const rows = Array.from(body.rows);
+    rows.sort((a, b) => {
+      const x = a.cells[i].textContent, y = b.cells[i].textContent;
+      const nx = parseFloat(x), ny = parseFloat(y);
+      const c = !isNaN(nx) && !isNaN(ny) ? nx - ny : x.localeCompare(y);
+      return asc ? c : -c;
+    });
+    rows.forEach(r => body.appendChild(r));
+  });
+});
+showCount();
+</script>
+</body>
+</html>
+"#;
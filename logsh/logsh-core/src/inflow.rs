@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capabilities::Capability,
+    error::{self, InflowError},
+    logship_client::LogshClientHandler,
+};
+
+/// A schema the server accepts uploads for, and the body formats it reads, such as `csv` or
+/// `json`. Files are posted to `inflow/{subscription}/{schema}/{format}`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InflowModel {
+    pub schema: String,
+    #[serde(default)]
+    pub formats: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl InflowModel {
+    /// Whether a file with this extension can be uploaded to the schema.
+    pub fn accepts(&self, format: &str) -> bool {
+        self.formats.iter().any(|f| f.eq_ignore_ascii_case(format))
+    }
+}
+
+/// Lists the inflow endpoints of the default subscription.
+pub fn list_inflows(connection: &LogshClientHandler) -> Result<Vec<InflowModel>, InflowError> {
+    connection.require(Capability::Inflow)?;
+    let subscription = connection.get_subscription()?;
+    let query_url = format!("inflow/{}", subscription);
+
+    let result = connection.execute_func(&|client| -> Result<Option<Vec<InflowModel>>, error::ClientError> {
+        let result = client.get_json_if_found(&query_url)?;
+        Ok(result)
+    })?;
+
+    result.ok_or(InflowError::Unsupported)
+}
@@ -0,0 +1,511 @@
+use crate::error::QueryError;
+
+/// Tabular operators, which follow a pipe.
+pub const OPERATORS: &[&str] = &[
+    "as",
+    "consume",
+    "count",
+    "distinct",
+    "evaluate",
+    "extend",
+    "facet",
+    "filter",
+    "find",
+    "fork",
+    "getschema",
+    "invoke",
+    "join",
+    "limit",
+    "lookup",
+    "make-series",
+    "mv-apply",
+    "mv-expand",
+    "order",
+    "parse",
+    "parse-kv",
+    "parse-where",
+    "partition",
+    "project",
+    "project-away",
+    "project-keep",
+    "project-rename",
+    "project-reorder",
+    "range",
+    "reduce",
+    "render",
+    "sample",
+    "sample-distinct",
+    "scan",
+    "search",
+    "serialize",
+    "sort",
+    "summarize",
+    "take",
+    "top",
+    "top-hitters",
+    "top-nested",
+    "union",
+    "where",
+];
+
+/// Words with a meaning inside operators and expressions.
+const KEYWORDS: &[&str] = &[
+    "and",
+    "asc",
+    "between",
+    "by",
+    "contains",
+    "contains_cs",
+    "datatable",
+    "desc",
+    "endswith",
+    "false",
+    "first",
+    "from",
+    "has",
+    "has_all",
+    "has_any",
+    "hasprefix",
+    "hassuffix",
+    "in",
+    "kind",
+    "last",
+    "let",
+    "matches",
+    "not",
+    "nulls",
+    "on",
+    "or",
+    "print",
+    "regex",
+    "set",
+    "startswith",
+    "step",
+    "to",
+    "true",
+    "with",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Comment,
+    String,
+    Number,
+    Operator,
+    Keyword,
+    Function,
+    Identifier,
+    Pipe,
+    Punctuation,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'q> {
+    pub kind: TokenKind,
+    pub text: &'q str,
+    /// Byte offset of the token in the query.
+    pub start: usize,
+}
+
+/// Splits a Kusto query into tokens. Every character of the query lands in exactly one token,
+/// so joining the tokens gives the query back, and nothing fails: an unterminated string or
+/// comment runs to the end of its line.
+pub fn tokenize(query: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = match c {
+            c if c.is_whitespace() => (TokenKind::Whitespace, span(rest, char::is_whitespace)),
+            '/' if rest.starts_with("//") => {
+                (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+            }
+            '`' if rest.starts_with("```") => (
+                TokenKind::String,
+                rest[3..].find("```").map_or(rest.len(), |end| end + 6),
+            ),
+            '@' if rest[1..].starts_with(['\'', '"']) => {
+                (TokenKind::String, 1 + quoted(&rest[1..], false))
+            }
+            '\'' | '"' => (TokenKind::String, quoted(rest, true)),
+            c if c.is_ascii_digit() => (
+                TokenKind::Number,
+                span(rest, |c| c.is_ascii_alphanumeric() || c == '.'),
+            ),
+            c if c.is_alphabetic() || c == '_' || c == '$' => word(rest),
+            '|' => (TokenKind::Pipe, 1),
+            c => (TokenKind::Punctuation, c.len_utf8()),
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &rest[..len],
+            start: query.len() - rest.len(),
+        });
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+fn span(s: &str, matches: impl Fn(char) -> bool) -> usize {
+    s.find(|c| !matches(c)).unwrap_or(s.len())
+}
+
+/// Length of the string starting at `s`'s opening quote, including the closing quote.
+fn quoted(s: &str, escapes: bool) -> usize {
+    let mut chars = s.char_indices();
+    let Some((_, quote)) = chars.next() else {
+        return 0;
+    };
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if escapes => {
+                chars.next();
+            }
+            '\n' => return i,
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+    }
+
+    s.len()
+}
+
+/// An identifier, keyword or function name. Hyphens only join words into known operators in
+/// any case, such as `project-away`, so `a-b` stays a subtraction.
+fn word(s: &str) -> (TokenKind, usize) {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let mut len = span(s, is_word);
+    while s[len..].starts_with('-') {
+        let next = len + 1 + span(&s[len + 1..], is_word);
+        if next == len + 1
+            || !OPERATORS
+                .iter()
+                .any(|op| op.eq_ignore_ascii_case(&s[..next]))
+        {
+            break;
+        }
+        len = next;
+    }
+
+    let text = &s[..len];
+    let kind = if OPERATORS.contains(&text) {
+        TokenKind::Operator
+    } else if KEYWORDS.contains(&text) {
+        TokenKind::Keyword
+    } else if s[len..].trim_start().starts_with('(') {
+        TokenKind::Function
+    } else {
+        TokenKind::Identifier
+    };
+    (kind, len)
+}
+
+/// A mistake found in a query before it's sent.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub message: String,
+    /// Byte offset of the start of the text the finding points at.
+    pub start: usize,
+    /// Byte offset just past the end of that text.
+    pub end: usize,
+}
+
+impl Finding {
+    fn new(message: impl Into<String>, start: usize, text: &str) -> Self {
+        Self {
+            message: message.into(),
+            start,
+            end: start + text.len(),
+        }
+    }
+}
+
+/// Checks a query for mistakes that don't need the server to spot: unterminated strings,
+/// unbalanced brackets and unknown operators after a pipe. The check is shallow, so a query
+/// that passes can still be rejected by the server.
+pub fn validate(query: &str) -> Result<(), QueryError> {
+    let findings = check(query);
+    if findings.is_empty() {
+        Ok(())
+    } else {
+        Err(QueryError::Syntax(findings))
+    }
+}
+
+/// Every mistake [validate] reports, in the order they appear in the query.
+pub fn check(query: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut open: Vec<Token> = Vec::new();
+    let mut pipe: Option<Token> = None;
+    for token in tokenize(query) {
+        if matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment) {
+            continue;
+        }
+
+        if pipe.take().is_some() {
+            if !matches!(
+                token.kind,
+                TokenKind::Operator
+                    | TokenKind::Keyword
+                    | TokenKind::Function
+                    | TokenKind::Identifier
+            ) {
+                findings.push(Finding::new(
+                    "Expected an operator after '|'.",
+                    token.start,
+                    token.text,
+                ));
+            } else if !OPERATORS.contains(&token.text) {
+                findings.push(Finding::new(
+                    unknown_operator(token.text),
+                    token.start,
+                    token.text,
+                ));
+            }
+        }
+
+        match token.kind {
+            TokenKind::Pipe => pipe = Some(token),
+            TokenKind::String if !is_terminated(token.text) => {
+                findings.push(Finding::new(
+                    "Unterminated string.",
+                    token.start,
+                    token.text,
+                ));
+            }
+            TokenKind::Punctuation => match token.text {
+                "(" | "[" | "{" => open.push(token),
+                ")" | "]" | "}" => match open.pop() {
+                    Some(opening) if closing(opening.text) == token.text => {}
+                    Some(opening) => findings.push(Finding::new(
+                        format!(
+                            "Expected '{}' to close the '{}' at offset {}.",
+                            closing(opening.text),
+                            opening.text,
+                            opening.start
+                        ),
+                        token.start,
+                        token.text,
+                    )),
+                    None => findings.push(Finding::new(
+                        format!("'{}' has nothing to close.", token.text),
+                        token.start,
+                        token.text,
+                    )),
+                },
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    if let Some(pipe) = pipe {
+        findings.push(Finding::new(
+            "Expected an operator after '|'.",
+            pipe.start,
+            pipe.text,
+        ));
+    }
+    for opening in open {
+        findings.push(Finding::new(
+            format!("'{}' is never closed.", opening.text),
+            opening.start,
+            opening.text,
+        ));
+    }
+
+    findings.sort_by_key(|f| f.start);
+    findings
+}
+
+fn closing(opening: &str) -> &'static str {
+    match opening {
+        "(" => ")",
+        "[" => "]",
+        _ => "}",
+    }
+}
+
+fn is_terminated(text: &str) -> bool {
+    if let Some(rest) = text.strip_prefix("```") {
+        return rest.ends_with("```");
+    }
+    let (text, escapes) = match text.strip_prefix('@') {
+        Some(verbatim) => (verbatim, false),
+        None => (text, true),
+    };
+
+    let mut chars = text.chars();
+    let Some(quote) = chars.next() else {
+        return false;
+    };
+    while let Some(c) = chars.next() {
+        if escapes && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn unknown_operator(text: &str) -> String {
+    if let Some(operator) = OPERATORS.iter().find(|op| op.eq_ignore_ascii_case(text)) {
+        return format!("Operators are lowercase. Did you mean '{}'?", operator);
+    }
+
+    match OPERATORS
+        .iter()
+        .map(|op| (distance(op, text), op))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+    {
+        Some((_, operator)) => format!("Unknown operator '{}'. Did you mean '{}'?", text, operator),
+        None => format!("Unknown operator '{}'.", text),
+    }
+}
+
+/// Levenshtein distance between two words.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != *cb);
+            current.push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Filters a query's rows with `predicate` right after the source of its last statement,
+/// before any operator, so a `take`, `summarize` or `project` later in the query can't change
+/// which rows it sees. The columns it compares must be columns of the source.
+pub fn filter_source(query: &str, predicate: &str) -> String {
+    let query = query.trim_end().trim_end_matches(';').trim_end();
+    let mut depth = 0usize;
+    let mut pipe = None;
+    for token in tokenize(query) {
+        match (token.kind, token.text) {
+            (TokenKind::Punctuation, "(" | "[" | "{") => depth += 1,
+            (TokenKind::Punctuation, ")" | "]" | "}") => depth = depth.saturating_sub(1),
+            (TokenKind::Punctuation, ";") if depth == 0 => pipe = None,
+            (TokenKind::Pipe, _) if depth == 0 && pipe.is_none() => pipe = Some(token.start),
+            _ => {}
+        }
+    }
+
+    match pipe {
+        Some(pipe) => format!(
+            "{}\n| where {}\n{}",
+            query[..pipe].trim_end(),
+            predicate,
+            &query[pipe..]
+        ),
+        None => format!("{}\n| where {}", query, predicate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(query: &str) -> Vec<String> {
+        check(query).into_iter().map(|f| f.message).collect()
+    }
+
+    #[test]
+    fn tokens_join_back_into_the_query() {
+        let query = "T | where msg == 'a \\' b' // note\n| take 10";
+        let joined: String = tokenize(query).iter().map(|t| t.text).collect();
+        assert_eq!(joined, query);
+    }
+
+    #[test]
+    fn tokenizes_operators_strings_and_pipes() {
+        let kinds: Vec<TokenKind> = tokenize("T | take @'x'")
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Identifier,
+                TokenKind::Pipe,
+                TokenKind::Operator,
+                TokenKind::String
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_valid_queries() {
+        assert!(
+            validate("T | where (a > 1 and b in ('x', \"y\")) | summarize count() by c").is_ok()
+        );
+        assert!(validate("T | project x = ```multi\nline``` // trailing comment").is_ok());
+    }
+
+    #[test]
+    fn reports_unknown_operators_with_suggestions() {
+        assert_eq!(
+            messages("T | wher x > 1"),
+            ["Unknown operator 'wher'. Did you mean 'where'?"]
+        );
+        assert_eq!(
+            messages("T | Take 1"),
+            ["Operators are lowercase. Did you mean 'take'?"]
+        );
+        assert_eq!(
+            messages("T | frobnicate"),
+            ["Unknown operator 'frobnicate'."]
+        );
+        assert_eq!(messages("T |"), ["Expected an operator after '|'."]);
+    }
+
+    #[test]
+    fn reports_unbalanced_brackets() {
+        assert_eq!(messages("T | where (a > 1"), ["'(' is never closed."]);
+        assert_eq!(messages("T | where a > 1)"), ["')' has nothing to close."]);
+        assert_eq!(
+            messages("T | where (a[1)"),
+            [
+                "'(' is never closed.",
+                "Expected ']' to close the '[' at offset 12."
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_strings_at_their_offset() {
+        let findings = check("T | where a == 'open");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].message, "Unterminated string.");
+        assert_eq!((findings[0].start, findings[0].end), (15, 20));
+        assert!(validate("T | where a == @'c:\\'").is_ok());
+    }
+
+    #[test]
+    fn filters_right_after_the_source() {
+        assert_eq!(
+            filter_source("T | summarize count() by bin(t, 1h) | take 5;", "x > 1"),
+            "T\n| where x > 1\n| summarize count() by bin(t, 1h) | take 5"
+        );
+        assert_eq!(filter_source("T", "x > 1"), "T\n| where x > 1");
+    }
+
+    #[test]
+    fn filters_the_last_statement_outside_brackets_and_strings() {
+        assert_eq!(
+            filter_source("let a = T | take 1;\nunion (U | take 2), a | where m == '|;'", "x > 1"),
+            "let a = T | take 1;\nunion (U | take 2), a\n| where x > 1\n| where m == '|;'"
+        );
+    }
+}
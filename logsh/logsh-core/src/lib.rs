@@ -1,10 +1,44 @@
+pub mod arrow;
+pub mod audit;
+pub mod capabilities;
 pub mod common;
 pub mod logship_client;
 pub mod auth;
+pub mod cloud;
 pub mod config;
 pub mod connect;
 pub mod csv;
+pub mod cursor;
+pub mod endpoint;
 pub mod error;
+pub mod frame;
+pub mod fuzzy;
+pub mod generate;
+pub mod grafana;
+pub mod history;
+pub mod html;
+pub mod inflow;
+pub mod kusto;
+pub mod metadata;
+pub mod metrics;
+pub mod notify;
+pub mod planner;
+pub mod progress;
+pub mod prometheus;
 pub mod query;
+pub mod redact;
+pub mod render;
+pub mod saved_query;
+pub mod schedule;
+pub mod session;
+pub mod snippet;
+pub mod sql;
+pub mod sqlite;
+pub mod stream;
 pub mod upload;
-pub mod subscription;
\ No newline at end of file
+pub mod upload_state;
+pub mod tables;
+pub mod template;
+pub mod tenant;
+pub mod transform;
+pub mod watch;
\ No newline at end of file
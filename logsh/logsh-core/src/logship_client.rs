@@ -1,8 +1,10 @@
-use crate::{error::{self}, config, common::ApiErrorModel, connect::Connection};
+use reqwest::StatusCode;
+
+use crate::{capabilities::{self, Capability}, error::{self}, config, common::ApiErrorModel, connect::Connection};
 
 pub struct LogshClient {
     pub server : String,
-    pub token : String
+    connection : Connection
 }
 
 pub trait LogshClientHandlerExecute<T> {
@@ -13,14 +15,6 @@ pub struct LogshClientHandler {
     override_connection_name : Option<String>
 }
 
-fn get_clean_path(path: &str) -> &str {
-    let mut path_clean = path;
-    if path.len() > 0 && path.chars().nth(0).unwrap() == '/' {
-        path_clean = path[1..].as_ref();
-    }
-    path_clean
-}
-
 fn map_api_error(response : reqwest::blocking::Response) -> error::ClientError {
     let error = response.json::<ApiErrorModel>()
         .unwrap_or(ApiErrorModel {
@@ -32,21 +26,23 @@ fn map_api_error(response : reqwest::blocking::Response) -> error::ClientError {
 }
 
 impl LogshClient {
-    pub fn new(server: &str, token : String) -> Self {
+    pub fn new(connection: Connection) -> Self {
         Self {
-            server: server.trim().to_string(),
-            token: token.trim().to_string()
+            server: connection.server.trim().to_string(),
+            connection
         }
     }
 
+    /// A client for the connection, so its resolve overrides and timeouts apply.
+    fn client(&self) -> Result<reqwest::blocking::Client, error::ClientError> {
+        Ok(self.connection.client_builder(&self.connection.timeouts).build()?)
+    }
+
     pub fn get_json<TResult :  for<'de> serde::Deserialize<'de>>(&self, path: &str) -> Result<TResult, error::ClientError> {
-        let path_clean = get_clean_path(path);
-        let url = format!("{}/{}", self.server, path_clean);
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
         log::debug!("[GET] {}", url);
-        let client = reqwest::blocking::Client::new();
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.token).parse().unwrap());
-        let response = client.get(&url).headers(headers).send()?;
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.get(&url)).send()?;
         if !response.status().is_success() {
             return Err(map_api_error(response));
         }
@@ -54,14 +50,40 @@ impl LogshClient {
         Ok(json)
     }
 
+    /// Like `get_json`, but None when the server doesn't have the endpoint, such as an older
+    /// server without a newer API.
+    pub fn get_json_if_found<TResult :  for<'de> serde::Deserialize<'de>>(&self, path: &str) -> Result<Option<TResult>, error::ClientError> {
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
+        log::debug!("[GET] {}", url);
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.get(&url)).send()?;
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(map_api_error(response));
+        }
+        let json = response.json()?;
+        Ok(Some(json))
+    }
+
     pub fn post_json<TRequest : serde::Serialize, TResult :  for<'de> serde::Deserialize<'de>>(&self, path: &str, request : &TRequest) -> Result<TResult, error::ClientError> {
-        let path_clean = get_clean_path(path);
-        let url = format!("{}/{}", self.server, path_clean);
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
         log::debug!("[POST] {}", url);
-        let client = reqwest::blocking::Client::new();
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.token).parse().unwrap());
-        let response = client.post(&url).headers(headers).json(request).send()?;
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.post(&url).json(request)).send()?;
+        if !response.status().is_success() {
+            return Err(map_api_error(response));
+        }
+        let json = response.json()?;
+        Ok(json)
+    }
+
+    pub fn put_json<TRequest : serde::Serialize, TResult :  for<'de> serde::Deserialize<'de>>(&self, path: &str, request : &TRequest) -> Result<TResult, error::ClientError> {
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
+        log::debug!("[PUT] {}", url);
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.put(&url).json(request)).send()?;
         if !response.status().is_success() {
             return Err(map_api_error(response));
         }
@@ -70,13 +92,10 @@ impl LogshClient {
     }
 
     pub fn put<TRequest : Into<reqwest::blocking::Body>, TResult :  for<'de> serde::Deserialize<'de>>(&self, path: &str, request : TRequest) -> Result<TResult, error::ClientError> {
-        let path_clean = get_clean_path(path);
-        let url = format!("{}/{}", self.server, path_clean);
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
         log::debug!("[POST] {}", url);
-        let client = reqwest::blocking::Client::new();
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.token).parse().unwrap());
-        let response = client.put(&url).headers(headers).body(request).send()?;
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.put(&url).body(request)).send()?;
         if !response.status().is_success() {
             return Err(map_api_error(response));
         }
@@ -85,13 +104,10 @@ impl LogshClient {
     }
 
     pub fn delete(&self, path: &str) -> Result<(), error::ClientError> {
-        let path_clean = get_clean_path(path);
-        let url = format!("{}/{}", self.server, path_clean);
+        let url = crate::endpoint::url(&self.server, path).map_err(error::ClientError::Common)?;
         log::debug!("[DELETE] {}", url);
-        let client = reqwest::blocking::Client::new();
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", format!("Bearer {}", self.token).parse().unwrap());
-        let response = client.delete(&url).headers(headers).send()?;
+        let client = self.client()?;
+        let response = self.connection.authenticate_request(client.delete(&url)).send()?;
         if !response.status().is_success() {
             return Err(map_api_error(response));
         }
@@ -106,6 +122,13 @@ impl LogshClientHandler {
         }
     }
 
+    /// Uses the named connection instead of the default connection.
+    pub fn for_connection(name: &str) -> Self {
+        Self {
+            override_connection_name: Some(name.to_string())
+        }
+    }
+
     pub fn get_connection(&self) -> Result<Connection, error::ClientError> {
         let default_config = config::load()?;
         let connection = match &self.override_connection_name {
@@ -115,11 +138,25 @@ impl LogshClientHandler {
         Ok(connection)
     }
 
+    pub fn get_subscription(&self) -> Result<uuid::Uuid, error::ClientError> {
+        let connection = self.get_connection()?;
+        let subscription = connection.default_subscription().ok_or(error::ConfigError::NoDefaultSubscription)?;
+        Ok(subscription)
+    }
+
+    /// Fails with a clear error when the server reports it doesn't have `capability`.
+    pub fn require(&self, capability: Capability) -> Result<(), error::ClientError> {
+        let connection = self.get_connection()?;
+        capabilities::require(&connection, capability).map_err(error::ClientError::Common)
+    }
+
     pub fn execute<T>(&self, arg : &dyn LogshClientHandlerExecute<T>) -> Result<T, error::ClientError> {
         let connection = self.get_connection()?;
-        let token = connection.get_token().ok_or(error::ClientError::NoToken)?;
+        if !connection.has_credentials() {
+            return Err(error::ClientError::NoToken);
+        }
 
-        let client = LogshClient::new(connection.server.as_ref(), token);
+        let client = LogshClient::new(connection);
         return arg.execute(&client)
     }
 
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    error::{ConfigError, MetadataError},
+    logship_client::LogshClientHandler,
+    tables::{self, ColumnModel},
+    tenant::{self, TenantModel},
+};
+
+/// Schemas, tables and columns of a subscription, saved locally so completions and query
+/// validation don't need a round trip to the server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub refreshed: DateTime<Utc>,
+    pub tables: Vec<TableMetadata>,
+    /// Subscriptions the connection's user can use. Missing from caches written before they
+    /// were saved.
+    #[serde(default)]
+    pub subscriptions: Vec<TenantModel>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TableMetadata {
+    pub schema: String,
+    pub name: String,
+    pub columns: Vec<ColumnModel>,
+}
+
+/// Metadata of every connection, keyed by server and subscription.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MetadataCache {
+    connections: HashMap<String, Metadata>,
+}
+
+impl Metadata {
+    /// Whether the metadata is older than the configured `metadata_ttl_minutes`.
+    pub fn is_stale(&self, ttl_minutes: u64) -> bool {
+        let age = Utc::now() - self.refreshed;
+        age.num_minutes() >= ttl_minutes as i64
+    }
+
+    pub fn schemas(&self) -> Vec<&str> {
+        let mut schemas: Vec<&str> = self.tables.iter().map(|t| t.schema.as_str()).collect();
+        schemas.sort_unstable();
+        schemas.dedup();
+        schemas
+    }
+
+    /// Looks up a table by name, ignoring case like the server does.
+    pub fn table(&self, name: &str) -> Option<&TableMetadata> {
+        self.tables.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Distinct column names across all tables.
+    pub fn column_names(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = self
+            .tables
+            .iter()
+            .flat_map(|t| t.columns.iter().map(|c| c.name.as_str()))
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+}
+
+/// Fetches the tables of the connection's default subscription and the columns of each, along
+/// with the subscriptions the user can use. When the subscriptions can't be listed, such as
+/// when the user may not list them, the ones cached before are kept and the tables are still
+/// returned.
+pub fn fetch(connection: &LogshClientHandler) -> Result<Metadata, MetadataError> {
+    let mut result = vec![];
+    for table in tables::list_tables(connection, None)? {
+        let details = tables::describe_table(connection, &table.name)?;
+        result.push(TableMetadata {
+            schema: table.schema,
+            name: table.name,
+            columns: details.columns,
+        });
+    }
+
+    let user_id = connection.get_connection()?.user_id;
+    let subscriptions = match tenant::list_tenants(connection, user_id, false) {
+        Ok(subscriptions) => subscriptions,
+        Err(err) => {
+            log::warn!("Unable to list subscriptions, keeping the cached ones: {}", err);
+            cached(connection)
+                .ok()
+                .flatten()
+                .map(|m| m.subscriptions)
+                .unwrap_or_default()
+        },
+    };
+
+    Ok(Metadata {
+        refreshed: Utc::now(),
+        tables: result,
+        subscriptions,
+    })
+}
+
+/// Fetches and saves the connection's metadata, replacing what was cached.
+pub fn refresh(connection: &LogshClientHandler) -> Result<Metadata, MetadataError> {
+    let key = cache_key(connection)?;
+    let metadata = fetch(connection)?;
+    let mut cache = load()?;
+    cache.connections.insert(key, metadata.clone());
+    save(cache)?;
+    Ok(metadata)
+}
+
+/// The connection's cached metadata, refreshed first when it's missing or older than the TTL.
+pub fn get(connection: &LogshClientHandler) -> Result<Metadata, MetadataError> {
+    let ttl_minutes = config::load()?.metadata_ttl_minutes;
+    match cached(connection)? {
+        Some(metadata) if !metadata.is_stale(ttl_minutes) => Ok(metadata),
+        _ => refresh(connection),
+    }
+}
+
+/// The connection's cached metadata however old it is, without contacting the server.
+pub fn cached(connection: &LogshClientHandler) -> Result<Option<Metadata>, MetadataError> {
+    let key = cache_key(connection)?;
+    Ok(load()?.connections.remove(&key))
+}
+
+/// Subscriptions cached for any connection, without duplicates. Doesn't contact the server.
+pub fn cached_subscriptions() -> Result<Vec<TenantModel>, ConfigError> {
+    let mut subscriptions: Vec<TenantModel> = load()?
+        .connections
+        .into_values()
+        .flat_map(|m| m.subscriptions)
+        .collect();
+    subscriptions.sort_by(|a, b| a.account_name.cmp(&b.account_name).then(a.account_id.cmp(&b.account_id)));
+    subscriptions.dedup_by_key(|s| s.account_id);
+    Ok(subscriptions)
+}
+
+/// Drops the cached metadata of every connection.
+pub fn clear() -> Result<(), MetadataError> {
+    save(MetadataCache::default())?;
+    Ok(())
+}
+
+fn cache_key(connection: &LogshClientHandler) -> Result<String, MetadataError> {
+    let server = connection.get_connection()?.server;
+    let subscription = connection.get_subscription()?;
+    Ok(format!("{} {}", server, subscription))
+}
+
+/// The metadata cache lives next to the logsh configuration file.
+pub fn get_metadata_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("metadata.json"))
+}
+
+pub fn load() -> Result<MetadataCache, ConfigError> {
+    let path = get_metadata_path()?;
+    if !path.exists() {
+        return Ok(MetadataCache::default());
+    }
+
+    let metadata = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&metadata).map_err(ConfigError::FailedDeserialize)
+}
+
+/// Saves the cache, unless logsh is in read-only mode.
+pub fn save(cache: MetadataCache) -> Result<MetadataCache, ConfigError> {
+    if config::read_only() {
+        return Ok(cache);
+    }
+
+    let path = get_metadata_path()?;
+    let serialized = serde_json::to_string(&cache).map_err(ConfigError::FailedSerialize)?;
+    fs::write(path, serialized).map_err(ConfigError::FailedWrite)?;
+    Ok(cache)
+}
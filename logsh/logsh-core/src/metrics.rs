@@ -0,0 +1,200 @@
+use std::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::{error::MetricsError, query::QueryResult};
+
+/// Largest StatsD datagram sent, which fits in an Ethernet frame with room for headers.
+const STATSD_DATAGRAM: usize = 1432;
+
+/// How long an OTLP collector may take to accept metrics.
+const OTLP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A numeric value of a query result, labelled by the text columns of its row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub column: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+/// Each numeric or boolean value of a result, in column then row order. Booleans are 1 or 0.
+/// Columns with a number in any row are values, and the rest label them. Nulls are skipped.
+pub fn samples(result: &QueryResult) -> Vec<Sample> {
+    let rows: Vec<Vec<(&str, Value)>> = result.results.iter()
+        .map(|row| result.header.iter()
+            .filter_map(|h| row.get(h.as_str()).map(|v| (h.as_str(), serde_json::from_str(v.get()).unwrap_or(Value::Null))))
+            .collect())
+        .collect();
+    let numeric = |column: &str| rows.iter().any(|row| row.iter().any(|(h, v)| *h == column && (v.is_number() || v.is_boolean())));
+    let (values, labels): (Vec<&String>, Vec<&String>) = result.header.iter().partition(|h| numeric(h));
+
+    let mut samples = Vec::new();
+    for column in values {
+        for row in rows.iter() {
+            let value = match row.iter().find(|(h, _)| h == column).map(|(_, v)| v) {
+                Some(Value::Number(n)) => n.as_f64(),
+                Some(Value::Bool(b)) => Some(if *b { 1.0 } else { 0.0 }),
+                _ => None,
+            };
+            let Some(value) = value else {
+                continue;
+            };
+
+            let labels = labels.iter()
+                .filter_map(|label| match row.iter().find(|(h, _)| *h == label.as_str()).map(|(_, v)| v) {
+                    Some(Value::String(s)) => Some((label.to_string(), s.to_string())),
+                    Some(Value::Null) | None => None,
+                    Some(other) => Some((label.to_string(), other.to_string())),
+                })
+                .collect();
+            samples.push(Sample { column: column.to_string(), labels, value });
+        }
+    }
+    samples
+}
+
+/// Sends the samples to a StatsD server at `host:port` as gauges named `{prefix}.{column}`,
+/// with labels as DogStatsD tags, which Telegraf and the Datadog agent read too.
+pub fn send_statsd(address: &str, prefix: &str, samples: &[Sample]) -> Result<(), MetricsError> {
+    let target = address.to_socket_addrs()
+        .map_err(|err| MetricsError::InvalidAddress(address.to_string(), err.to_string()))?
+        .next()
+        .ok_or_else(|| MetricsError::InvalidAddress(address.to_string(), "it doesn't resolve to an address".to_string()))?;
+    let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+
+    let mut datagram = String::new();
+    for sample in samples {
+        let mut line = format!("{}:{}|g", statsd_name(&format!("{}.{}", prefix, sample.column)), sample.value);
+        if !sample.labels.is_empty() {
+            let tags: Vec<String> = sample.labels.iter().map(|(k, v)| format!("{}:{}", statsd_tag(k), statsd_tag(v))).collect();
+            line.push_str("|#");
+            line.push_str(&tags.join(","));
+        }
+
+        if !datagram.is_empty() && datagram.len() + 1 + line.len() > STATSD_DATAGRAM {
+            socket.send_to(datagram.as_bytes(), target)?;
+            datagram.clear();
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram.push_str(&line);
+    }
+    if !datagram.is_empty() {
+        socket.send_to(datagram.as_bytes(), target)?;
+    }
+    log::debug!("Sent {} samples to StatsD at {}", samples.len(), address);
+    Ok(())
+}
+
+/// Sends the samples to an OpenTelemetry collector over OTLP/HTTP with JSON encoding, as
+/// gauges named `{prefix}.{column}` at `time`. The endpoint may be the collector's base URL,
+/// such as `http://localhost:4318`, or the full metrics path.
+pub fn send_otlp(endpoint: &str, prefix: &str, samples: &[Sample], time: DateTime<Utc>) -> Result<(), MetricsError> {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    let url = match endpoint.ends_with("/v1/metrics") {
+        true => endpoint.to_string(),
+        false => format!("{}/v1/metrics", endpoint),
+    };
+
+    let client = reqwest::blocking::Client::builder().timeout(OTLP_TIMEOUT).build()?;
+    client.post(&url).json(&otlp_body(prefix, samples, time)).send()?.error_for_status()?;
+    log::debug!("Sent {} samples to OTLP collector at {}", samples.len(), url);
+    Ok(())
+}
+
+/// An OTLP `ExportMetricsServiceRequest`, with a gauge per column.
+fn otlp_body(prefix: &str, samples: &[Sample], time: DateTime<Utc>) -> Value {
+    let time = time.timestamp_nanos_opt().unwrap_or_default().to_string();
+    let mut metrics: Vec<(String, Vec<Value>)> = Vec::new();
+    for sample in samples {
+        let name = format!("{}.{}", prefix, sample.column);
+        let point = json!({
+            "asDouble": sample.value,
+            "timeUnixNano": time,
+            "attributes": sample.labels.iter()
+                .map(|(k, v)| json!({ "key": k, "value": { "stringValue": v } }))
+                .collect::<Vec<_>>(),
+        });
+        match metrics.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, points)) => points.push(point),
+            None => metrics.push((name, vec![point])),
+        }
+    }
+
+    json!({
+        "resourceMetrics": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": "logsh" } }] },
+            "scopeMetrics": [{
+                "scope": { "name": "logsh", "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics.into_iter()
+                    .map(|(name, points)| json!({ "name": name, "gauge": { "dataPoints": points } }))
+                    .collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+/// StatsD names can't hold the characters that separate the name, value and tags.
+fn statsd_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' }).collect()
+}
+
+fn statsd_tag(tag: &str) -> String {
+    tag.chars().map(|c| if matches!(c, ':' | ',' | '|' | '#' | '\n') { '_' } else { c }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(column: &str, labels: &[(&str, &str)], value: f64) -> Sample {
+        Sample {
+            column: column.to_string(),
+            labels: labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            value,
+        }
+    }
+
+    #[test]
+    fn numeric_columns_are_values_and_the_rest_labels() {
+        let result = crate::query::result(r#"{"header":["host","count","up"],"results":[{"host":"a","count":3,"up":true},{"host":"b","count":1.5,"up":false}]}"#).unwrap();
+        assert_eq!(samples(&result), vec![
+            sample("count", &[("host", "a")], 3.0),
+            sample("count", &[("host", "b")], 1.5),
+            sample("up", &[("host", "a")], 1.0),
+            sample("up", &[("host", "b")], 0.0),
+        ]);
+    }
+
+    #[test]
+    fn skips_nulls() {
+        let result = crate::query::result(r#"{"header":["host","zone","count"],"results":[{"host":"a","zone":null,"count":null},{"host":null,"zone":2,"count":4}]}"#).unwrap();
+        assert_eq!(samples(&result), vec![sample("zone", &[], 2.0), sample("count", &[], 4.0)]);
+    }
+
+    #[test]
+    fn escapes_statsd_separators() {
+        assert_eq!(statsd_name("logsh.error count|x"), "logsh.error_count_x");
+        assert_eq!(statsd_tag("a:b,c|d#e"), "a_b_c_d_e");
+    }
+
+    #[test]
+    fn groups_otlp_points_by_column() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let body = otlp_body("logsh", &[sample("count", &[("host", "a")], 3.0), sample("count", &[], 1.0)], time);
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics.as_array().unwrap().len(), 1);
+        assert_eq!(metrics[0]["name"], "logsh.count");
+        let points = &metrics[0]["gauge"]["dataPoints"];
+        assert_eq!(points[0]["asDouble"], 3.0);
+        assert_eq!(points[0]["attributes"][0]["value"]["stringValue"], "a");
+        assert_eq!(points[1]["timeUnixNano"], "1709251200000000000");
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{value::RawValue, Value};
+use thiserror::Error;
+
+use crate::query::QueryResult;
+
+/// Most rows included in a notification. Larger results are truncated.
+const MAX_ROWS: usize = 100;
+
+/// Most rows shown in the table of a Slack message.
+const MAX_SLACK_ROWS: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("Invalid condition \"{0}\". Expected \"rows <op> <count>\", where op is one of >, >=, <, <=, ==, !=.")]
+    InvalidCondition(String),
+
+    #[error("Failed to send notification: {0}")]
+    Reqwest(#[from] reqwest::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+/// A test of a result's row count, such as `rows > 0`, deciding when to send a notification or
+/// whether an assertion passes. `count` can be written in place of `rows`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    comparison: Comparison,
+    count: usize,
+}
+
+impl Condition {
+    /// `rows > 0`
+    pub fn has_rows() -> Self {
+        Self { comparison: Comparison::Greater, count: 0 }
+    }
+
+    /// `rows == 0`
+    pub fn no_rows() -> Self {
+        Self { comparison: Comparison::Equal, count: 0 }
+    }
+
+    pub fn matches(&self, rows: usize) -> bool {
+        match self.comparison {
+            Comparison::Greater => rows > self.count,
+            Comparison::GreaterOrEqual => rows >= self.count,
+            Comparison::Less => rows < self.count,
+            Comparison::LessOrEqual => rows <= self.count,
+            Comparison::Equal => rows == self.count,
+            Comparison::NotEqual => rows != self.count,
+        }
+    }
+}
+
+impl Default for Condition {
+    /// Notifies when the result has any rows.
+    fn default() -> Self {
+        Self::has_rows()
+    }
+}
+
+impl FromStr for Condition {
+    type Err = NotifyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NotifyError::InvalidCondition(s.to_string());
+        let condition = s.trim();
+        let rest = condition
+            .strip_prefix("rows")
+            .or_else(|| condition.strip_prefix("count"))
+            .ok_or_else(invalid)?
+            .trim_start();
+        // Two character operators first, so ">=" isn't read as ">".
+        let (comparison, count) = [
+            (">=", Comparison::GreaterOrEqual),
+            ("<=", Comparison::LessOrEqual),
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            (">", Comparison::Greater),
+            ("<", Comparison::Less),
+        ]
+        .iter()
+        .find_map(|(op, comparison)| rest.strip_prefix(op).map(|count| (*comparison, count)))
+        .ok_or_else(invalid)?;
+        let count = count.trim().parse().map_err(|_| invalid())?;
+        Ok(Self { comparison, count })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.comparison {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        };
+        write!(f, "rows {} {}", op, self.count)
+    }
+}
+
+/// Payload of a notification target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyFormat {
+    /// The query, row count, and rows as JSON.
+    Webhook,
+    /// A Slack incoming webhook message.
+    Slack,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayload<'a> {
+    query: &'a str,
+    condition: String,
+    rows: usize,
+    header: &'a [String],
+    results: &'a [std::collections::HashMap<&'a str, &'a RawValue>],
+    truncated: bool,
+    sent_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct SlackPayload {
+    text: String,
+}
+
+/// Sends a query result to a notification target with a blocking POST. Only the first rows
+/// are sent. Callers decide whether to notify, usually with `Condition::matches`.
+pub fn notify(
+    url: &str,
+    format: NotifyFormat,
+    query: &str,
+    condition: &Condition,
+    result: &QueryResult<'_>,
+    timeout: Option<Duration>,
+) -> Result<(), NotifyError> {
+    let mut builder = reqwest::blocking::Client::builder();
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    let request = builder.build()?.post(url);
+    let request = match format {
+        NotifyFormat::Webhook => {
+            let rows = result.results.len();
+            request.json(&WebhookPayload {
+                query,
+                condition: condition.to_string(),
+                rows,
+                header: &result.header,
+                results: &result.results[..rows.min(MAX_ROWS)],
+                truncated: rows > MAX_ROWS,
+                sent_at: Utc::now(),
+            })
+        }
+        NotifyFormat::Slack => request.json(&SlackPayload { text: slack_text(query, condition, result) }),
+    };
+
+    log::debug!("Sending {:?} notification", format);
+    // Webhook URLs often carry their secret in the path, so keep them out of errors.
+    request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.without_url())?;
+    Ok(())
+}
+
+fn slack_text(query: &str, condition: &Condition, result: &QueryResult<'_>) -> String {
+    let rows = result.results.len();
+    let mut text = format!(
+        "*logsh*: query returned {} {} ({}).\n```{}```",
+        rows,
+        if rows == 1 { "row" } else { "rows" },
+        condition,
+        query.trim()
+    );
+    if rows == 0 || result.header.is_empty() {
+        return text;
+    }
+
+    text.push_str("\n```");
+    text.push_str(&result.header.join(" | "));
+    for row in result.results.iter().take(MAX_SLACK_ROWS) {
+        let cells: Vec<String> = result
+            .header
+            .iter()
+            .map(|h| match row.get(h.as_str()).map(|v| serde_json::from_str::<Value>(v.get())) {
+                Some(Ok(Value::String(s))) => s,
+                Some(Ok(Value::Null)) | None => String::new(),
+                Some(Ok(other)) => other.to_string(),
+                Some(Err(_)) => row[h.as_str()].get().to_string(),
+            })
+            .collect();
+        text.push('\n');
+        text.push_str(&cells.join(" | "));
+    }
+    if rows > MAX_SLACK_ROWS {
+        text.push_str(&format!("\n… {} more", rows - MAX_SLACK_ROWS));
+    }
+    text.push_str("```");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!("rows > 0".parse::<Condition>().unwrap(), Condition::has_rows());
+        assert_eq!(" count==0 ".parse::<Condition>().unwrap(), Condition::no_rows());
+        let condition: Condition = "rows >= 10".parse().unwrap();
+        assert_eq!(condition.to_string(), "rows >= 10");
+        assert_eq!("rows<=5".parse::<Condition>().unwrap().to_string(), "rows <= 5");
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        for condition in ["", "rows", "rows = 1", "lines > 0", "rows > -1", "rows > many"] {
+            assert!(matches!(condition.parse::<Condition>(), Err(NotifyError::InvalidCondition(_))), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn matches_row_counts() {
+        let matching = |condition: &str| (0..4).filter(|rows| condition.parse::<Condition>().unwrap().matches(*rows)).collect::<Vec<_>>();
+        assert_eq!(matching("rows > 1"), vec![2, 3]);
+        assert_eq!(matching("rows >= 1"), vec![1, 2, 3]);
+        assert_eq!(matching("rows < 2"), vec![0, 1]);
+        assert_eq!(matching("rows <= 2"), vec![0, 1, 2]);
+        assert_eq!(matching("rows == 2"), vec![2]);
+        assert_eq!(matching("rows != 2"), vec![0, 1, 3]);
+    }
+}
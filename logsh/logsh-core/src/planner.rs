@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::schedule::Window;
+
+/// Shortest shard a time range is split into. Shards that still fail at this length fail the
+/// export rather than being split further.
+const MIN_SHARD: Duration = Duration::seconds(1);
+
+/// Wait after the first throttled shard, doubled for each throttled shard after it.
+const BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Longest wait after a throttled shard, unless the server asks for longer.
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Shards throttled in a row, without one completing, before the export gives up.
+const MAX_THROTTLED: u32 = 8;
+
+/// Most a shard grows or shrinks by at once, so one unusually fast or slow query doesn't swing
+/// the next shard's length too far.
+const MAX_STEP: f64 = 2.0;
+
+/// Splits a time range into shards, one query each, sized from how long the server took with
+/// the shards before them.
+///
+/// Each completed shard scales the next ones so a query takes about `target`: a shard that
+/// came back in half the time makes the next twice as long. Shards that time out are split in
+/// half and queued again. Throttled shards are queued again after a growing wait, and lower
+/// how many shards run at once. Shards tile the range without gaps, so their results can be put back in
+/// order by start time.
+#[derive(Debug)]
+pub struct Planner {
+    cursor: DateTime<Utc>,
+    end: DateTime<Utc>,
+    shard: Duration,
+    target: std::time::Duration,
+    /// Shards that failed and were split, handed out before any new ones.
+    retries: VecDeque<Window>,
+    concurrency: usize,
+    /// Shards throttled since one last completed.
+    throttled: u32,
+}
+
+impl Planner {
+    /// Plans shards of `initial` length from `start` up to `end`, `concurrency` at a time.
+    pub fn new(start: DateTime<Utc>, end: DateTime<Utc>, initial: Duration, target: std::time::Duration, concurrency: usize) -> Self {
+        Self {
+            cursor: start,
+            end,
+            shard: initial.max(MIN_SHARD),
+            target,
+            retries: VecDeque::new(),
+            concurrency: concurrency.max(1),
+            throttled: 0,
+        }
+    }
+
+    /// The next shard to query, or None when every shard has been handed out.
+    pub fn next_shard(&mut self) -> Option<Window> {
+        if let Some(retry) = self.retries.pop_front() {
+            return Some(retry);
+        }
+        if self.cursor >= self.end {
+            return None;
+        }
+
+        let end = (self.cursor + self.shard).min(self.end);
+        let window = Window { start: self.cursor, end };
+        self.cursor = end;
+        Some(window)
+    }
+
+    /// Sizes the shards not handed out yet from how long `window` took.
+    pub fn observe(&mut self, window: &Window, elapsed: std::time::Duration) {
+        self.throttled = 0;
+        let took = elapsed.as_secs_f64().max(0.001);
+        let factor = (self.target.as_secs_f64() / took).clamp(1.0 / MAX_STEP, MAX_STEP);
+        let length = (window.end - window.start).num_milliseconds() as f64;
+        // Only shards as long as the planned ones say much about the next, since the last shard
+        // of the range is usually cut short.
+        if length * MAX_STEP < self.shard.num_milliseconds() as f64 {
+            return;
+        }
+
+        let scaled = Duration::milliseconds((length * factor) as i64).max(MIN_SHARD);
+        log::debug!("Shard of {}s took {:.2}s, next shards are {}s", length / 1000.0, took, scaled.num_seconds());
+        self.shard = scaled;
+    }
+
+    /// Queues the two halves of a shard that timed out, and shortens the shards after it.
+    /// False when the shard is already as short as shards get.
+    pub fn split(&mut self, window: Window) -> bool {
+        let half = (window.end - window.start) / 2;
+        if half < MIN_SHARD {
+            return false;
+        }
+
+        let middle = window.start + half;
+        self.retries.push_back(Window { start: window.start, end: middle });
+        self.retries.push_back(Window { start: middle, end: window.end });
+        self.shard = self.shard.min(half);
+        true
+    }
+
+    /// Queues a throttled shard again and runs one fewer shard at a time, down to one. Returns
+    /// how long to wait before querying more shards: `retry_after` when the server said, and
+    /// otherwise twice as long as after the last throttled shard. None when too many shards
+    /// were throttled in a row, and the export should give up.
+    pub fn throttle(&mut self, window: Window, retry_after: Option<std::time::Duration>) -> Option<std::time::Duration> {
+        self.throttled += 1;
+        if self.throttled > MAX_THROTTLED {
+            return None;
+        }
+
+        self.retries.push_back(window);
+        self.concurrency = (self.concurrency - 1).max(1);
+        let backoff = BACKOFF.saturating_mul(1 << (self.throttled - 1)).min(MAX_BACKOFF);
+        Some(retry_after.unwrap_or(backoff))
+    }
+
+    /// How many shards to have running at once.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration as StdDuration;
+
+    fn planner(hours: i64) -> Planner {
+        let start = DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        Planner::new(start, start + Duration::hours(hours), Duration::hours(1), StdDuration::from_secs(10), 4)
+    }
+
+    #[test]
+    fn shards_tile_the_range() {
+        let mut planner = planner(3);
+        let first = planner.next_shard().unwrap();
+        let second = planner.next_shard().unwrap();
+        let third = planner.next_shard().unwrap();
+        assert_eq!(first.end, second.start);
+        assert_eq!(second.end, third.start);
+        assert_eq!(third.end - first.start, Duration::hours(3));
+        assert!(planner.next_shard().is_none());
+    }
+
+    #[test]
+    fn shards_scale_towards_the_target_within_a_step() {
+        let mut planner = planner(24);
+        let shard = planner.next_shard().unwrap();
+        planner.observe(&shard, StdDuration::from_secs(5));
+        assert_eq!(planner.next_shard().unwrap().end - shard.end, Duration::hours(2));
+
+        let mut planner = self::planner(24);
+        let shard = planner.next_shard().unwrap();
+        planner.observe(&shard, StdDuration::from_secs(100));
+        assert_eq!(planner.next_shard().unwrap().end - shard.end, Duration::minutes(30));
+    }
+
+    #[test]
+    fn timed_out_shards_are_split_and_retried_first() {
+        let mut planner = planner(3);
+        let shard = planner.next_shard().unwrap();
+        assert!(planner.split(shard));
+        let first = planner.next_shard().unwrap();
+        let second = planner.next_shard().unwrap();
+        assert_eq!((first.start, first.end, second.end), (shard.start, second.start, shard.end));
+        assert_eq!(first.end - first.start, Duration::minutes(30));
+
+        let tiny = Window { start: shard.start, end: shard.start + Duration::seconds(1) };
+        assert!(!planner.split(tiny));
+    }
+
+    #[test]
+    fn throttling_backs_off_and_gives_up() {
+        let mut planner = planner(3);
+        let shard = planner.next_shard().unwrap();
+        assert_eq!(planner.throttle(shard, None), Some(StdDuration::from_secs(1)));
+        assert_eq!(planner.concurrency(), 3);
+        assert_eq!(planner.throttle(shard, None), Some(StdDuration::from_secs(2)));
+        assert_eq!(planner.throttle(shard, Some(StdDuration::from_secs(30))), Some(StdDuration::from_secs(30)));
+        assert_eq!(planner.next_shard(), Some(shard));
+
+        planner.observe(&shard, StdDuration::from_secs(10));
+        assert_eq!(planner.throttle(shard, None), Some(StdDuration::from_secs(1)));
+        for _ in 1..MAX_THROTTLED {
+            assert!(planner.throttle(shard, None).is_some_and(|wait| wait <= MAX_BACKOFF));
+        }
+        assert_eq!(planner.throttle(shard, None), None);
+        assert_eq!(planner.concurrency(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+/// Receives progress from long running operations, such as downloading query results or
+/// uploading a file. Progress is counted in bytes.
+pub trait Progress: Send + Sync {
+    /// Starts a new operation, replacing any current one. `total` is the expected number of
+    /// bytes, when it's known.
+    fn start(&self, message: &str, total: Option<u64>);
+
+    /// Records that `bytes` more bytes were transferred.
+    fn advance(&self, bytes: u64);
+
+    /// Ends the current operation.
+    fn finish(&self);
+}
+
+/// Discards all progress, for quiet output and callers that aren't attached to a terminal.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn start(&self, _message: &str, _total: Option<u64>) {}
+
+    fn advance(&self, _bytes: u64) {}
+
+    fn finish(&self) {}
+}
+
+/// Reports every byte read through it. Owns its progress so it can be used as a request body.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<dyn Progress>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, progress: Arc<dyn Progress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.advance(read as u64);
+        Ok(read)
+    }
+}
+
+/// Reports every byte written through it.
+pub struct ProgressWriter<'p, W> {
+    inner: W,
+    progress: &'p dyn Progress,
+}
+
+impl<'p, W: Write> ProgressWriter<'p, W> {
+    pub fn new(inner: W, progress: &'p dyn Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.progress.advance(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
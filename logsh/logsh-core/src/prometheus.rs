@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use crate::{metrics, query::QueryResult};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Writes a query result as Prometheus gauges in the text exposition format. Each numeric or
+/// boolean column is a gauge named `{prefix}_{column}`, with a sample per row, labelled by the
+/// row's text columns. Rows should differ in their text columns, or their samples collide.
+pub fn gauges(result: &QueryResult, prefix: &str) -> String {
+    let samples = metrics::samples(result);
+    let mut text = String::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let name = metric_name(&format!("{}_{}", prefix, sample.column));
+        if i == 0 || samples[i - 1].column != sample.column {
+            let _ = writeln!(text, "# HELP {} The {} column of the query.", name, escape_help(&sample.column));
+            let _ = writeln!(text, "# TYPE {} gauge", name);
+        }
+
+        let labels: Vec<String> = sample.labels.iter().map(|(k, v)| format!("{}=\"{}\"", label_name(k), escape_label(v))).collect();
+        match labels.is_empty() {
+            true => { let _ = writeln!(text, "{} {}", name, sample_value(sample.value)); }
+            false => { let _ = writeln!(text, "{}{{{}}} {}", name, labels.join(","), sample_value(sample.value)); }
+        }
+    }
+    text
+}
+
+/// Writes one unlabelled gauge.
+pub fn gauge(text: &mut String, name: &str, help: &str, value: f64) {
+    let name = metric_name(name);
+    let _ = writeln!(text, "# HELP {} {}", name, escape_help(help));
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+    let _ = writeln!(text, "{} {}", name, sample_value(value));
+}
+
+/// Writes an info metric: a gauge of 1, whose labels carry the information.
+pub fn info(text: &mut String, name: &str, help: &str, labels: &[(&str, &str)]) {
+    let name = metric_name(name);
+    let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", label_name(k), escape_label(v))).collect();
+    let _ = writeln!(text, "# HELP {} {}", name, escape_help(help));
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+    let _ = writeln!(text, "{}{{{}}} 1", name, labels.join(","));
+}
+
+/// A valid metric name, with anything but letters, digits, underscores and colons replaced by
+/// underscores, and an underscore in front of a leading digit.
+pub fn metric_name(name: &str) -> String {
+    let mut metric: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == ':' { c } else { '_' }).collect();
+    if metric.is_empty() || metric.starts_with(|c: char| c.is_ascii_digit()) {
+        metric.insert(0, '_');
+    }
+    metric
+}
+
+/// Label names are metric names without colons.
+fn label_name(name: &str) -> String {
+    metric_name(name).replace(':', "_")
+}
+
+fn sample_value(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".to_string(),
+        v if v == f64::INFINITY => "+Inf".to_string(),
+        v if v == f64::NEG_INFINITY => "-Inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label(text: &str) -> String {
+    escape_help(text).replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_a_gauge_per_numeric_column() {
+        let text = r#"{"header":["host","requests","up"],"results":[{"host":"a","requests":5,"up":true},{"host":"b\"1","requests":1.5,"up":false}]}"#;
+        let result = crate::query::result(text).unwrap();
+        assert_eq!(gauges(&result, "logsh"), "\
+# HELP logsh_requests The requests column of the query.
+# TYPE logsh_requests gauge
+logsh_requests{host=\"a\"} 5
+logsh_requests{host=\"b\\\"1\"} 1.5
+# HELP logsh_up The up column of the query.
+# TYPE logsh_up gauge
+logsh_up{host=\"a\"} 1
+logsh_up{host=\"b\\\"1\"} 0
+");
+    }
+
+    #[test]
+    fn carries_text_in_info_labels() {
+        let mut text = String::new();
+        info(&mut text, "logsh_query_info", "The query.", &[("query", "Logs\n| where m == \"x\"")]);
+        assert_eq!(text, "\
+# HELP logsh_query_info The query.
+# TYPE logsh_query_info gauge
+logsh_query_info{query=\"Logs\\n| where m == \\\"x\\\"\"} 1
+");
+    }
+
+    #[test]
+    fn names_are_made_valid() {
+        assert_eq!(metric_name("logsh_5xx rate"), "logsh_5xx_rate");
+        assert_eq!(metric_name("5xx"), "_5xx");
+        assert_eq!(label_name("a:b"), "a_b");
+        assert_eq!(sample_value(f64::NEG_INFINITY), "-Inf");
+    }
+}
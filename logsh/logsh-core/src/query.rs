@@ -1,8 +1,9 @@
 
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde_json::value::RawValue;
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, fmt, io::Read};
 
-use crate::error::QueryError;
+use crate::{error::QueryError, kusto};
 
 #[derive(Clone, Copy, Debug, serde::Serialize)]
 pub struct QueryRequest<'a, 'b> {
@@ -28,6 +29,62 @@ pub struct QueryResult<'a> {
     #[serde(alias = "Results")]
     #[serde(alias = "results")]
     pub results: Vec<HashMap<&'a str, &'a RawValue>>,
+
+    #[serde(alias = "Statistics")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<QueryStatistics>,
+}
+
+/// Execution statistics reported by the server alongside a query result.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryStatistics {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned_rows: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned_bytes: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_hits: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_time_ms: Option<f64>,
+
+    /// Any statistics this client doesn't know about yet.
+    #[serde(flatten)]
+    pub other: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+/// How the server would execute a query, as returned by its explain endpoint.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPlan {
+    #[serde(alias = "Stages")]
+    pub stages: Vec<PlanStage>,
+}
+
+/// One step of a query plan. Children are the stages that feed it.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanStage {
+    pub operator: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_rows: Option<u64>,
+
+    /// Number of shards the stage fans out to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PlanStage>,
 }
 
 #[derive(serde::Serialize)]
@@ -41,10 +98,181 @@ pub struct QueryResultFmt {
     pub results: Vec<HashMap<String, serde_json::Value>>,
 }
 
+pub type Row<'a> = HashMap<&'a str, &'a RawValue>;
+
+/// Row level differences between two query results.
+#[derive(serde::Serialize, Debug, Default)]
+pub struct ResultDiff<'r> {
+    pub added: Vec<&'r Row<'r>>,
+    pub removed: Vec<&'r Row<'r>>,
+    pub changed: Vec<ChangedRow<'r>>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ChangedRow<'r> {
+    pub before: &'r Row<'r>,
+    pub after: &'r Row<'r>,
+}
+
+impl ResultDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares `current` against `baseline`, matching rows on the given key columns. With no
+/// key columns the whole row is the key, so rows are only ever added or removed. Rows sharing
+/// a key are paired up in result order.
+pub fn diff<'r>(
+    baseline: &'r QueryResult<'_>,
+    current: &'r QueryResult<'_>,
+    keys: &[String],
+) -> Result<ResultDiff<'r>, QueryError> {
+    if let Some(unknown) = keys
+        .iter()
+        .find(|k| !baseline.header.contains(k) || !current.header.contains(k))
+    {
+        return Err(QueryError::UnknownColumn(unknown.to_string()));
+    }
+
+    let mut columns: Vec<&str> = baseline.header.iter().map(String::as_str).collect();
+    for h in current.header.iter() {
+        if !columns.contains(&h.as_str()) {
+            columns.push(h);
+        }
+    }
+    let keys: Vec<&str> = if keys.is_empty() {
+        columns.clone()
+    } else {
+        keys.iter().map(String::as_str).collect()
+    };
+    let values = |row: &Row<'r>, columns: &[&str]| -> Vec<Option<&'r str>> {
+        columns.iter().map(|c| row.get(c).map(|v| v.get())).collect()
+    };
+
+    let before: &'r [Row<'r>] = &baseline.results;
+    let after: &'r [Row<'r>] = &current.results;
+    let mut pending: HashMap<Vec<Option<&str>>, std::collections::VecDeque<&Row>> = HashMap::new();
+    for row in before.iter() {
+        pending.entry(values(row, &keys)).or_default().push_back(row);
+    }
+
+    let mut diff = ResultDiff::default();
+    for row in after.iter() {
+        match pending.get_mut(&values(row, &keys)).and_then(|rows| rows.pop_front()) {
+            Some(old) if values(old, &columns) != values(row, &columns) => {
+                diff.changed.push(ChangedRow { before: old, after: row })
+            }
+            Some(_) => {}
+            None => diff.added.push(row),
+        }
+    }
+
+    // Whatever is left in the baseline had no counterpart, keep it in baseline order.
+    diff.removed = before
+        .iter()
+        .filter(|row| {
+            pending
+                .get(&values(row, &keys))
+                .is_some_and(|rows| rows.iter().any(|r| std::ptr::eq(*r, *row)))
+        })
+        .collect();
+
+    Ok(diff)
+}
+
 pub fn result<'a>(result: &'a str) -> Result<QueryResult<'a>, QueryError> {
     result.try_into()
 }
 
+impl<'a> QueryResult<'a> {
+    /// Removes duplicate rows, keeping the first occurrence. Rows are compared on the
+    /// given columns, or on every column when `columns` is empty. Returns the number of rows removed.
+    pub fn distinct(&mut self, columns: &[String]) -> Result<usize, QueryError> {
+        if let Some(unknown) = columns.iter().find(|c| !self.header.contains(c)) {
+            return Err(QueryError::UnknownColumn(unknown.to_string()));
+        }
+
+        let keys = if columns.is_empty() { &self.header } else { columns };
+        let before = self.results.len();
+        let mut seen = HashSet::new();
+        self.results.retain(|row| {
+            let key: Vec<Option<&'a str>> = keys
+                .iter()
+                .map(|k| row.get(k.as_str()).copied().map(RawValue::get))
+                .collect();
+            seen.insert(key)
+        });
+
+        Ok(before - self.results.len())
+    }
+
+    /// Prepends a column numbering the rows from 1. The numbers are borrowed from `numbers`,
+    /// built with [row_numbers] for at least as many rows.
+    pub fn number_rows(&mut self, column: &'a str, numbers: &'a [Box<RawValue>]) {
+        self.header.retain(|h| h != column);
+        self.header.insert(0, column.to_string());
+        for (row, number) in self.results.iter_mut().zip(numbers.iter()) {
+            row.insert(column, number);
+        }
+    }
+}
+
+/// One query of a batch, named by the `--- NAME` line before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchQuery {
+    pub name: Option<String>,
+    pub query: String,
+}
+
+/// Splits several queries, separated by `;;` or by lines starting with `---`. Text after the
+/// dashes names the query that follows. Separators in strings and comments don't count, and
+/// blank queries are dropped.
+pub fn split_batch(text: &str) -> Vec<BatchQuery> {
+    let mut queries = vec![];
+    let mut name = None;
+    let mut finish = |name: &mut Option<String>, query: &str| {
+        let name = name.take();
+        if !query.trim().is_empty() {
+            queries.push(BatchQuery { name, query: query.trim().to_string() });
+        }
+    };
+
+    let tokens = kusto::tokenize(text);
+    let mut start = 0;
+    let mut i = 0;
+    while let Some(token) = tokens.get(i) {
+        let line = text[..token.start].rfind('\n').map_or(0, |n| n + 1);
+        if text[token.start..].starts_with("---") && text[line..token.start].trim().is_empty() {
+            let end = text[token.start..].find('\n').map_or(text.len(), |n| token.start + n);
+            finish(&mut name, &text[start..line]);
+            name = Some(text[token.start..end].trim_start_matches('-').trim().to_string()).filter(|n| !n.is_empty());
+            start = end;
+            while tokens.get(i).is_some_and(|t| t.start < end) {
+                i += 1;
+            }
+            continue;
+        }
+
+        if token.text == ";" && tokens.get(i + 1).is_some_and(|t| t.text == ";") {
+            finish(&mut name, &text[start..token.start]);
+            start = token.start + 2;
+            i += 2;
+            continue;
+        }
+        i += 1;
+    }
+    finish(&mut name, &text[start..]);
+    queries
+}
+
+/// Row numbers from 1 to `count`, for [QueryResult::number_rows].
+pub fn row_numbers(count: usize) -> Vec<Box<RawValue>> {
+    (1..=count)
+        .map(|i| RawValue::from_string(i.to_string()).expect("integers are valid JSON"))
+        .collect()
+}
+
 impl<'a> TryFrom<&'a str> for QueryResult<'a> {
     type Error = QueryError;
 
@@ -52,3 +280,169 @@ impl<'a> TryFrom<&'a str> for QueryResult<'a> {
         serde_json::from_str(value).map_err(QueryError::Json)
     }
 }
+
+/// Receives a query result one row at a time from [`stream_result`].
+pub trait RowSink {
+    type Error: From<serde_json::Error>;
+
+    fn header(&mut self, header: &[String]) -> Result<(), Self::Error>;
+
+    fn row(&mut self, header: &[String], row: &HashMap<String, Box<RawValue>>) -> Result<(), Self::Error>;
+}
+
+/// Reads a query result from `reader` without holding the full result in memory.
+/// The header must precede the results, which is how the server writes them.
+pub fn stream_result<R: Read, S: RowSink>(reader: R, sink: &mut S) -> Result<(), S::Error> {
+    let mut error = None;
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let visitor = ResultVisitor {
+        sink,
+        error: &mut error,
+    };
+
+    match serde::Deserializer::deserialize_map(&mut de, visitor).and_then(|_| de.end()) {
+        Ok(()) => Ok(()),
+        Err(err) => match error {
+            Some(sink_err) => Err(sink_err),
+            None => Err(err.into()),
+        },
+    }
+}
+
+struct ResultVisitor<'s, S: RowSink> {
+    sink: &'s mut S,
+    error: &'s mut Option<S::Error>,
+}
+
+impl<'de, 's, S: RowSink> Visitor<'de> for ResultVisitor<'s, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a query result object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut header: Option<Vec<String>> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "header" | "Header" => {
+                    let h: Vec<String> = map.next_value()?;
+                    if let Err(err) = self.sink.header(&h) {
+                        *self.error = Some(err);
+                        return Err(de::Error::custom("query result sink failed"));
+                    }
+                    header = Some(h);
+                }
+                "results" | "Results" => {
+                    let header = header
+                        .as_deref()
+                        .ok_or_else(|| de::Error::custom("query result header must precede results"))?;
+                    map.next_value_seed(RowsSeed {
+                        sink: &mut *self.sink,
+                        error: &mut *self.error,
+                        header,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct RowsSeed<'s, 'h, S: RowSink> {
+    sink: &'s mut S,
+    error: &'s mut Option<S::Error>,
+    header: &'h [String],
+}
+
+impl<'de, 's, 'h, S: RowSink> DeserializeSeed<'de> for RowsSeed<'s, 'h, S> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 's, 'h, S: RowSink> Visitor<'de> for RowsSeed<'s, 'h, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of query result rows")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element::<HashMap<String, Box<RawValue>>>()? {
+            if let Err(err) = self.sink.row(self.header, &row) {
+                *self.error = Some(err);
+                return Err(de::Error::custom("query result sink failed"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<'r>(rows: &[&'r Row<'r>]) -> Vec<&'r str> {
+        rows.iter().map(|row| row["name"].get()).collect()
+    }
+
+    #[test]
+    fn diff_on_key_columns_finds_added_removed_and_changed_rows() {
+        let baseline = result(r#"{"header":["name","count"],"results":[{"name":"a","count":1},{"name":"b","count":2}]}"#).unwrap();
+        let current = result(r#"{"header":["name","count"],"results":[{"name":"b","count":3},{"name":"c","count":4}]}"#).unwrap();
+        let diff = diff(&baseline, &current, &["name".to_string()]).unwrap();
+
+        assert_eq!(names(&diff.added), [r#""c""#]);
+        assert_eq!(names(&diff.removed), [r#""a""#]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].before["count"].get(), "2");
+        assert_eq!(diff.changed[0].after["count"].get(), "3");
+    }
+
+    #[test]
+    fn diff_without_keys_matches_whole_rows() {
+        let baseline = result(r#"{"header":["name"],"results":[{"name":"a"},{"name":"a"}]}"#).unwrap();
+        let current = result(r#"{"header":["name"],"results":[{"name":"a"}]}"#).unwrap();
+        let diff = diff(&baseline, &current, &[]).unwrap();
+
+        assert!(diff.added.is_empty());
+        assert!(diff.changed.is_empty());
+        assert_eq!(names(&diff.removed), [r#""a""#]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn diff_rejects_unknown_key_columns() {
+        let baseline = result(r#"{"header":["name"],"results":[]}"#).unwrap();
+        assert!(matches!(diff(&baseline, &baseline, &["missing".to_string()]), Err(QueryError::UnknownColumn(c)) if c == "missing"));
+    }
+
+    fn queries(batch: &[BatchQuery]) -> Vec<(Option<&str>, &str)> {
+        batch.iter().map(|b| (b.name.as_deref(), b.query.as_str())).collect()
+    }
+
+    #[test]
+    fn split_batch_on_semicolons_and_named_dashes() {
+        let batch = split_batch("A | take 1;; B\n--- errors\nC\n| count\n---\n\n;;D");
+
+        assert_eq!(queries(&batch), [(None, "A | take 1"), (None, "B"), (Some("errors"), "C\n| count"), (None, "D")]);
+    }
+
+    #[test]
+    fn split_batch_skips_separators_in_strings_and_comments() {
+        let batch = split_batch("A | where m == 'a;;b' // c;;d\n| where n == \"---\";;B | where x == ```\n--- not a name\n```");
+
+        assert_eq!(queries(&batch), [
+            (None, "A | where m == 'a;;b' // c;;d\n| where n == \"---\""),
+            (None, "B | where x == ```\n--- not a name\n```"),
+        ]);
+    }
+}
@@ -0,0 +1,194 @@
+use std::{borrow::Cow, sync::OnceLock};
+
+use regex::{Captures, Regex};
+use serde_json::{Map, Value};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Replaces the values of redacted columns.
+pub const MASK: &str = "***";
+
+/// Shortest bearer or basic value masked outside an `Authorization` header, so prose such as
+/// "basic authentication" isn't.
+const MIN_CREDENTIAL: usize = 16;
+
+/// Masks credentials in text bound for logs or error messages: `Authorization` header values,
+/// other bearer and basic values and JWTs, and the values of password, secret, and token fields
+/// in JSON, headers, query strings and Kusto comparisons. Returns the text unchanged when
+/// there's nothing to mask.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    static AUTHORIZATION: OnceLock<Regex> = OnceLock::new();
+    static JWT: OnceLock<Regex> = OnceLock::new();
+    static FIELD: OnceLock<Regex> = OnceLock::new();
+
+    let header = HEADER.get_or_init(|| {
+        Regex::new(r#"(?i)(\bauthorization\\?"?\s*[:=]\s*\\?"?(?:bearer|basic)\s+)[A-Za-z0-9\-._~+/]+=*"#).unwrap()
+    });
+    let authorization = AUTHORIZATION.get_or_init(|| {
+        Regex::new(r"(?i)\b((?:bearer|basic)\s+)([A-Za-z0-9\-._~+/]+=*)").unwrap()
+    });
+    let jwt = JWT.get_or_init(|| {
+        Regex::new(r"\beyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+").unwrap()
+    });
+    let field = FIELD.get_or_init(|| {
+        Regex::new(concat!(
+            r#"(?i)(\b(?:password|passwd|secret|client_secret|token|access_token|refresh_token|id_token|api_key|apikey)\\?"?\s*(==|[:=])\s*)"#,
+            r#"("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|[^\s,&;}]+)"#,
+        ))
+        .unwrap()
+    });
+
+    let mut text = Cow::Borrowed(text);
+    if let Cow::Owned(t) = header.replace_all(&text, format!("${{1}}{}", REDACTED)) {
+        text = Cow::Owned(t);
+    }
+    if let Cow::Owned(t) = authorization.replace_all(&text, |c: &Captures| match is_credential(&c[2], MIN_CREDENTIAL) {
+        true => format!("{}{}", &c[1], REDACTED),
+        false => c[0].to_string(),
+    }) {
+        text = Cow::Owned(t);
+    }
+    if let Cow::Owned(t) = jwt.replace_all(&text, REDACTED) {
+        text = Cow::Owned(t);
+    }
+    if let Cow::Owned(t) = field.replace_all(&text, |c: &Captures| {
+        // Keep quoted values quoted, so redacted JSON and queries stay readable.
+        match c[3].chars().next() {
+            Some(quote @ ('"' | '\'')) => format!("{}{}{}{}", &c[1], quote, REDACTED, quote),
+            // After a colon, only values that look like credentials, so "Invalid token: expired
+            // signature" keeps its words.
+            _ if &c[2] == ":" && !is_credential(&c[3], 1) => c[0].to_string(),
+            _ => format!("{}{}", &c[1], REDACTED),
+        }
+    }) {
+        text = Cow::Owned(t);
+    }
+
+    text
+}
+
+/// Whether a word looks like a credential rather than prose: at least `min` characters, with a
+/// digit or one of the symbols tokens and base64 are written with.
+fn is_credential(value: &str, min: usize) -> bool {
+    value.len() >= min && value.chars().any(|c| c.is_ascii_digit() || matches!(c, '-' | '.' | '_' | '~' | '+' | '/' | '='))
+}
+
+/// Whether a column name matches a redaction pattern. Patterns ignore case, and `*` matches any
+/// run of characters, so `*password*` matches `UserPassword`.
+pub fn matches_column(pattern: &str, column: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let column = column.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = column.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Masks the values of columns matching any of `patterns` in a query response, so results can
+/// be shared without the data in those columns. Nulls stay null. Returns the response unchanged
+/// when no column matches.
+pub fn redact_columns<'a>(response: &'a str, patterns: &[String]) -> Result<Cow<'a, str>, serde_json::Error> {
+    if patterns.is_empty() {
+        return Ok(Cow::Borrowed(response));
+    }
+
+    let mut doc: Map<String, Value> = serde_json::from_str(response)?;
+    let header: Vec<String> = match doc.get("header").or_else(|| doc.get("Header")) {
+        Some(header) => serde_json::from_value(header.clone())?,
+        None => vec![],
+    };
+    let columns: Vec<&String> = header
+        .iter()
+        .filter(|column| patterns.iter().any(|p| matches_column(p, column)))
+        .collect();
+    if columns.is_empty() {
+        return Ok(Cow::Borrowed(response));
+    }
+
+    log::debug!("Redacting columns: {:?}", columns);
+    let results_key = if doc.contains_key("Results") { "Results" } else { "results" };
+    if let Some(Value::Array(rows)) = doc.get_mut(results_key) {
+        for row in rows.iter_mut().filter_map(Value::as_object_mut) {
+            for column in columns.iter() {
+                if let Some(value) = row.get_mut(column.as_str()).filter(|v| !v.is_null()) {
+                    *value = Value::from(MASK);
+                }
+            }
+        }
+    }
+
+    Ok(Cow::Owned(serde_json::to_string(&doc)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_authorization_values_and_jwts() {
+        assert_eq!(redact("Authorization: Bearer abc.def-123=="), "Authorization: Bearer [REDACTED]");
+        assert_eq!(redact(r#"{"Authorization":"Basic dXNlcjpwYXNz"}"#), r#"{"Authorization":"Basic [REDACTED]"}"#);
+        assert_eq!(redact("sent bearer 9f8e7d6c5b4a3928170 again"), "sent bearer [REDACTED] again");
+        assert_eq!(redact("got eyJhbGciOi.eyJzdWIiOi.c2lnbmF0dXJl back"), "got [REDACTED] back");
+    }
+
+    #[test]
+    fn masks_secret_fields_in_json_and_query_strings() {
+        assert_eq!(redact(r#"{"user":"a","password":"p\"w"}"#), r#"{"user":"a","password":"[REDACTED]"}"#);
+        assert_eq!(redact("?client_secret=s3cr3t&scope=x"), "?client_secret=[REDACTED]&scope=x");
+    }
+
+    #[test]
+    fn masks_secrets_compared_in_queries() {
+        assert_eq!(redact("T | where password == 'hunter 2'"), "T | where password == '[REDACTED]'");
+        assert_eq!(redact("T | where token == s3cr3t | take 1"), "T | where token == [REDACTED] | take 1");
+    }
+
+    #[test]
+    fn keeps_prose_about_credentials() {
+        for text in ["Basic Auth Error: bad header", "use basic authentication", "Bearer tokens expire", "Invalid token: expired signature", "password: required"] {
+            assert_eq!(redact(text), text);
+        }
+        assert_eq!(redact("token: s3cr3t"), "token: [REDACTED]");
+    }
+
+    #[test]
+    fn borrows_text_with_nothing_to_mask() {
+        assert!(matches!(redact("select * from T"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn column_patterns_ignore_case_and_match_wildcards() {
+        assert!(matches_column("*password*", "UserPassword"));
+        assert!(matches_column("ssn", "SSN"));
+        assert!(matches_column("card_*_number", "card_visa_number"));
+        assert!(!matches_column("ssn", "ssn_hash"));
+        assert!(!matches_column("*_token", "token"));
+        assert!(!matches_column("a*b*b", "ab"));
+    }
+
+    #[test]
+    fn masks_matching_columns_but_keeps_nulls() {
+        let response = r#"{"header":["name","Secret"],"results":[{"name":"a","Secret":"x"},{"name":"b","Secret":null}]}"#;
+        let redacted = redact_columns(response, &["secret".to_string()]).unwrap();
+        let redacted: Value = serde_json::from_str(&redacted).unwrap();
+        assert_eq!(redacted["results"][0]["Secret"], "***");
+        assert_eq!(redacted["results"][0]["name"], "a");
+        assert_eq!(redacted["results"][1]["Secret"], Value::Null);
+        assert!(matches!(redact_columns(response, &["other".to_string()]).unwrap(), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use serde_json::value::RawValue;
+use thiserror::Error;
+
+use crate::query::QueryResult;
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("Output format \"{0}\" is already registered.")]
+    Duplicate(String),
+
+    #[error("Failed to write output: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// An output format for query results. Formats are looked up by name in the registry, so a
+/// crate that depends on logsh-core can add its own with [register] before parsing arguments.
+pub trait ResultRenderer: Send + Sync {
+    /// The name `-o` takes. Names are matched ignoring case.
+    fn name(&self) -> &'static str;
+
+    /// Other names accepted for the format.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// File extensions, without the dot, that imply the format for output files.
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// A line saying what the format is, for listings.
+    fn description(&self) -> &'static str;
+
+    /// Whether the output is binary, and so isn't written to terminals or the clipboard.
+    fn is_binary(&self) -> bool {
+        false
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError>;
+}
+
+/// Whether the renderer goes by `name`, or has it as an alias, ignoring case.
+fn answers_to(renderer: &dyn ResultRenderer, name: &str) -> bool {
+    renderer.name().eq_ignore_ascii_case(name) || renderer.aliases().iter().any(|a| a.eq_ignore_ascii_case(name))
+}
+
+fn registry() -> &'static RwLock<Vec<Arc<dyn ResultRenderer>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn ResultRenderer>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(vec![
+            Arc::new(Json),
+            Arc::new(JsonPretty),
+            Arc::new(JsonLines),
+            Arc::new(Csv),
+            Arc::new(Tsv),
+            Arc::new(Arrow),
+            Arc::new(Html),
+        ])
+    })
+}
+
+/// Adds an output format. Fails when its name or an alias is taken by a registered format.
+pub fn register<R: ResultRenderer + 'static>(renderer: R) -> Result<(), RenderError> {
+    let mut renderers = registry().write().unwrap_or_else(|p| p.into_inner());
+    let names = std::iter::once(renderer.name()).chain(renderer.aliases().iter().copied());
+    for name in names {
+        if renderers.iter().any(|r| answers_to(r.as_ref(), name)) {
+            return Err(RenderError::Duplicate(name.to_string()));
+        }
+    }
+
+    log::debug!("Registered output format \"{}\"", renderer.name());
+    renderers.push(Arc::new(renderer));
+    Ok(())
+}
+
+/// The format with this name or alias, ignoring case.
+pub fn find(name: &str) -> Option<Arc<dyn ResultRenderer>> {
+    let name = name.trim();
+    registry().read().unwrap_or_else(|p| p.into_inner()).iter().find(|r| answers_to(r.as_ref(), name)).cloned()
+}
+
+/// The format files with this extension are written in, ignoring case.
+pub fn for_extension(extension: &str) -> Option<Arc<dyn ResultRenderer>> {
+    registry().read().unwrap_or_else(|p| p.into_inner()).iter()
+        .find(|r| r.extensions().iter().any(|e| e.eq_ignore_ascii_case(extension)))
+        .cloned()
+}
+
+/// Every registered format, built in ones first, then in the order they were registered.
+pub fn formats() -> Vec<Arc<dyn ResultRenderer>> {
+    registry().read().unwrap_or_else(|p| p.into_inner()).clone()
+}
+
+/// Unformatted JSON, as the server sends it.
+struct Json;
+
+impl ResultRenderer for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn description(&self) -> &'static str {
+        "The query response as unformatted JSON."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, mut write: &mut dyn Write) -> Result<(), RenderError> {
+        serde_json::to_writer(&mut write, result).map_err(|e| RenderError::Failed(Box::new(e)))?;
+        writeln!(write)?;
+        Ok(())
+    }
+}
+
+struct JsonPretty;
+
+impl ResultRenderer for JsonPretty {
+    fn name(&self) -> &'static str {
+        "json-pretty"
+    }
+
+    fn description(&self) -> &'static str {
+        "The query response as indented JSON."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        serde_json::to_writer_pretty(write, result).map_err(|e| RenderError::Failed(Box::new(e)))
+    }
+}
+
+struct JsonLines;
+
+impl ResultRenderer for JsonLines {
+    fn name(&self) -> &'static str {
+        "json-lines"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["jsonl", "ndjson"]
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["jsonl", "ndjson"]
+    }
+
+    fn description(&self) -> &'static str {
+        "A JSON object per row, on its own line."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        for row in result.results.iter() {
+            write_json_line(&mut *write, &result.header, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a row as a JSON object on its own line, with its fields in header order.
+fn write_json_line(write: &mut dyn Write, header: &[String], row: &HashMap<&str, &RawValue>) -> Result<(), RenderError> {
+    write!(write, "{{")?;
+    let fields = header.iter().filter_map(|name| row.get(name.as_str()).map(|value| (name, value)));
+    for (i, (name, value)) in fields.enumerate() {
+        if i > 0 {
+            write!(write, ",")?;
+        }
+        let name = serde_json::to_string(name).map_err(|e| RenderError::Failed(Box::new(e)))?;
+        write!(write, "{}:{}", name, value.get())?;
+    }
+    writeln!(write, "}}")?;
+    Ok(())
+}
+
+struct Csv;
+
+impl ResultRenderer for Csv {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["csv"]
+    }
+
+    fn description(&self) -> &'static str {
+        "Comma separated values, with a header row."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        crate::csv::write_csv(result, write).map_err(|e| RenderError::Failed(Box::new(e)))
+    }
+}
+
+struct Tsv;
+
+impl ResultRenderer for Tsv {
+    fn name(&self) -> &'static str {
+        "tsv"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tsv"]
+    }
+
+    fn description(&self) -> &'static str {
+        "Tab separated values, with a header row."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        crate::csv::write_tsv(result, write).map_err(|e| RenderError::Failed(Box::new(e)))
+    }
+}
+
+struct Arrow;
+
+impl ResultRenderer for Arrow {
+    fn name(&self) -> &'static str {
+        "arrow"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["arrow", "arrows"]
+    }
+
+    fn description(&self) -> &'static str {
+        "An Arrow IPC stream, for pandas, Polars and DuckDB."
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        crate::arrow::write_arrow(result, write).map_err(|e| RenderError::Failed(Box::new(e)))
+    }
+}
+
+struct Html;
+
+impl ResultRenderer for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm"]
+    }
+
+    fn description(&self) -> &'static str {
+        "A standalone HTML page with a sortable table."
+    }
+
+    fn render(&self, result: &QueryResult<'_>, write: &mut dyn Write) -> Result<(), RenderError> {
+        crate::html::write_html(result, write).map_err(RenderError::from)
+    }
+}
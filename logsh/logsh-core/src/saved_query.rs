@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    capabilities::Capability,
+    config,
+    error::{self, ConfigError, SavedQueryError},
+    logship_client::LogshClientHandler,
+};
+
+/// A named query stored locally, and optionally shared through the server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQuery {
+    pub name: String,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub updated: DateTime<Utc>,
+}
+
+impl SavedQuery {
+    pub fn new(name: &str, query: &str, description: Option<String>) -> Self {
+        Self {
+            name: name.trim().to_string(),
+            query: query.to_string(),
+            description,
+            updated: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SavedQueries {
+    pub queries: BTreeMap<String, SavedQuery>,
+}
+
+impl SavedQueries {
+    pub fn get(&self, name: &str) -> Result<&SavedQuery, SavedQueryError> {
+        self.queries
+            .get(name.trim())
+            .ok_or_else(|| SavedQueryError::NotFound(name.to_string()))
+    }
+
+    /// Adds a query under its trimmed name, returning the query it replaced.
+    pub fn insert(&mut self, query: SavedQuery) -> Option<SavedQuery> {
+        self.queries.insert(query.name.clone(), query)
+    }
+
+    /// Merges queries pulled from the server. Remote queries replace local ones that are
+    /// older, or every local query when `force` is set. Returns the names that changed.
+    pub fn merge(&mut self, remote: Vec<SavedQuery>, force: bool) -> Vec<String> {
+        let mut changed = Vec::new();
+        for query in remote {
+            let replace = match self.queries.get(&query.name) {
+                Some(local) => force || (local.updated < query.updated && *local != query),
+                None => true,
+            };
+
+            if replace {
+                changed.push(query.name.clone());
+                self.queries.insert(query.name.clone(), query);
+            }
+        }
+
+        changed
+    }
+}
+
+/// Saved queries live next to the logsh configuration file.
+pub fn get_saved_queries_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("queries.json"))
+}
+
+pub fn load() -> Result<SavedQueries, ConfigError> {
+    let path = get_saved_queries_path()?;
+    if !path.exists() {
+        return Ok(SavedQueries::default());
+    }
+
+    let queries = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&queries).map_err(ConfigError::FailedDeserialize)
+}
+
+pub fn save(queries: SavedQueries) -> Result<SavedQueries, ConfigError> {
+    let path = get_saved_queries_path()?;
+    let serialized = serde_json::to_string_pretty(&queries).map_err(ConfigError::FailedSerialize)?;
+    fs::write(path, serialized).map_err(ConfigError::FailedWrite)?;
+    Ok(queries)
+}
+
+/// Publishes a saved query to the default subscription's shared query library.
+pub fn publish(
+    connection: &LogshClientHandler,
+    query: &SavedQuery,
+) -> Result<SavedQuery, SavedQueryError> {
+    connection.require(Capability::SharedQueries)?;
+    let subscription = connection.get_subscription()?;
+    let query_url = format!("search/{}/queries", subscription);
+
+    let result = connection.execute_func(&|client| -> Result<SavedQuery, error::ClientError> {
+        let result = client.post_json(&query_url, query)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
+
+/// Fetches the default subscription's shared query library.
+pub fn pull(connection: &LogshClientHandler) -> Result<Vec<SavedQuery>, SavedQueryError> {
+    connection.require(Capability::SharedQueries)?;
+    let subscription = connection.get_subscription()?;
+    let query_url = format!("search/{}/queries", subscription);
+
+    let result = connection.execute_func(&|client| -> Result<Vec<SavedQuery>, error::ClientError> {
+        let result = client.get_json(&query_url)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
@@ -0,0 +1,145 @@
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{config, error::ConfigError, kusto};
+
+/// The time range exported by one run of a schedule, from `start` up to but not including `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Window {
+    /// Restricts `query` to rows whose `column` falls in the window. The rows are filtered
+    /// before the rest of the query runs, so `column` is a column of its source table.
+    pub fn apply(&self, query: &str, column: &str) -> String {
+        let column = column.replace('\'', "\\'");
+        kusto::filter_source(query, &format!(
+            "['{}'] >= datetime({}) and ['{}'] < datetime({})",
+            column,
+            self.start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            column,
+            self.end.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ))
+    }
+
+    /// Fills the partition placeholders of a destination: `{date}` as 2024-03-01, `{year}`,
+    /// `{month}`, `{day}`, `{hour}` and `{minute}` of the window start, and `{start}` and
+    /// `{end}` as compact timestamps like 20240301T000000Z.
+    pub fn partition(&self, template: &str) -> String {
+        let compact = |t: &DateTime<Utc>| t.format("%Y%m%dT%H%M%SZ").to_string();
+        template
+            .replace("{date}", &self.start.format("%Y-%m-%d").to_string())
+            .replace("{year}", &self.start.format("%Y").to_string())
+            .replace("{month}", &self.start.format("%m").to_string())
+            .replace("{day}", &self.start.format("%d").to_string())
+            .replace("{hour}", &self.start.format("%H").to_string())
+            .replace("{minute}", &self.start.format("%M").to_string())
+            .replace("{start}", &compact(&self.start))
+            .replace("{end}", &compact(&self.end))
+    }
+}
+
+/// The windows of length `every`, aligned to midnight UTC on 1970-01-01, from the one holding
+/// `from` through the last one that ended by `now`. Empty when none has ended yet.
+pub fn due(every: Duration, from: DateTime<Utc>, now: DateTime<Utc>) -> Vec<Window> {
+    let mut windows = Vec::new();
+    let Ok(mut start) = from.duration_trunc(every) else {
+        return windows;
+    };
+    while start + every <= now {
+        windows.push(Window { start, end: start + every });
+        start += every;
+    }
+    windows
+}
+
+/// The most recent window of length `every` that ended by `now`.
+pub fn latest(every: Duration, now: DateTime<Utc>) -> Option<Window> {
+    let end = now.duration_trunc(every).ok()?;
+    Some(Window { start: end - every, end })
+}
+
+/// Where each schedule got to: the end of the last window it exported, by schedule name.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ScheduleState {
+    pub exported: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// The schedule state lives next to the logsh configuration file.
+pub fn get_state_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("agent_state.json"))
+}
+
+pub fn load() -> Result<ScheduleState, ConfigError> {
+    let path = get_state_path()?;
+    if !path.exists() {
+        return Ok(ScheduleState::default());
+    }
+
+    let state = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&state).map_err(ConfigError::FailedDeserialize)
+}
+
+/// Saves the state, unless logsh is in read-only mode, where it lasts until the agent exits.
+pub fn save(state: &ScheduleState) -> Result<(), ConfigError> {
+    if config::read_only() {
+        log::debug!("Read-only mode. Not saving the schedule state.");
+        return Ok(());
+    }
+
+    let path = get_state_path()?;
+    let serialized = serde_json::to_string_pretty(state).map_err(ConfigError::FailedSerialize)?;
+    fs::write(path, serialized).map_err(ConfigError::FailedWrite)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn due_catches_up_on_ended_windows() {
+        let windows = due(Duration::hours(1), at(9, 30), at(12, 15));
+        let starts: Vec<_> = windows.iter().map(|w| w.start).collect();
+        assert_eq!(starts, vec![at(9, 0), at(10, 0), at(11, 0)]);
+        assert_eq!(windows.last().unwrap().end, at(12, 0));
+    }
+
+    #[test]
+    fn due_waits_for_windows_to_end() {
+        assert!(due(Duration::hours(1), at(12, 0), at(12, 59)).is_empty());
+        assert_eq!(due(Duration::hours(1), at(12, 0), at(13, 0)), vec![Window { start: at(12, 0), end: at(13, 0) }]);
+    }
+
+    #[test]
+    fn latest_is_the_last_ended_window() {
+        assert_eq!(latest(Duration::minutes(15), at(10, 20)), Some(Window { start: at(10, 0), end: at(10, 15) }));
+    }
+
+    #[test]
+    fn applies_windows_to_queries_and_destinations() {
+        let window = Window { start: at(9, 0), end: at(10, 0) };
+        assert_eq!(
+            window.apply("Logs;", "time"),
+            "Logs\n| where ['time'] >= datetime(2024-03-01T09:00:00Z) and ['time'] < datetime(2024-03-01T10:00:00Z)"
+        );
+        assert_eq!(
+            window.apply("Logs | take 5", "time"),
+            "Logs\n| where ['time'] >= datetime(2024-03-01T09:00:00Z) and ['time'] < datetime(2024-03-01T10:00:00Z)\n| take 5"
+        );
+        assert_eq!(window.partition("logs/{date}/{hour}/{start}-{end}.csv"), "logs/2024-03-01/09/20240301T090000Z-20240301T100000Z.csv");
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{CommonError, ConfigError, SessionError};
+
+/// Response headers that describe the connection rather than the response, so aren't kept.
+const HOP_HEADERS: [&str; 5] = ["connection", "keep-alive", "transfer-encoding", "content-length", "set-cookie"];
+
+/// Paths of the endpoints that take and return credentials.
+const CREDENTIAL_PATHS: [&str; 1] = ["/auth/token"];
+
+/// Stands in for the hash of a request body that holds credentials.
+const UNHASHED: &str = "credentials";
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+/// A file of the requests a command made to servers and the responses it got.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub recorded: Option<DateTime<Utc>>,
+    pub exchanges: Vec<Exchange>,
+}
+
+/// One request and its response. Requests are matched by their server, method, path and a
+/// hash of their body, so headers and signatures are never written down. Bodies holding
+/// credentials aren't hashed, as a hash of a password can be guessed, and the tokens responses
+/// to them hold are redacted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exchange {
+    pub server: String,
+    pub method: String,
+    pub path: String,
+    pub request_sha256: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The response body, when it's text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// The response body, when it isn't text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+enum Mode {
+    Record(PathBuf),
+    Replay,
+}
+
+struct Session {
+    mode: Mode,
+    recording: Mutex<Recording>,
+    /// How many times each exchange has been replayed.
+    replayed: Mutex<Vec<usize>>,
+    /// The loopback address requests to each server are sent to instead.
+    routes: Mutex<HashMap<String, SocketAddr>>,
+}
+
+/// Records every request made to a server for the rest of the process in a file at `path`,
+/// which `replay` serves them back from. The file is rewritten after each response, so it's
+/// complete however the command ends.
+pub fn record(path: &Path) -> Result<(), SessionError> {
+    let session = Session {
+        mode: Mode::Record(path.to_path_buf()),
+        recording: Mutex::new(Recording { recorded: Some(Utc::now()), exchanges: Vec::new() }),
+        replayed: Mutex::new(Vec::new()),
+        routes: Mutex::new(HashMap::new()),
+    };
+    session.save()?;
+    SESSION.set(session).map_err(|_| SessionError::AlreadyStarted)?;
+    log::info!("Recording requests to {}", path.display());
+    Ok(())
+}
+
+/// Answers every request made to a server for the rest of the process from a file written by
+/// `record`, without contacting the server. Requests that weren't recorded are answered with
+/// an error. A request recorded once is answered the same way every time it's repeated, and
+/// one recorded several times gets its responses in the order they were recorded.
+pub fn replay(path: &Path) -> Result<(), SessionError> {
+    let text = std::fs::read_to_string(path).map_err(|err| SessionError::Read(path.display().to_string(), err))?;
+    let recording: Recording = serde_json::from_str(&text).map_err(|err| SessionError::Invalid(path.display().to_string(), err))?;
+    log::info!("Replaying {} recorded requests from {}", recording.exchanges.len(), path.display());
+    let session = Session {
+        mode: Mode::Replay,
+        replayed: Mutex::new(vec![0; recording.exchanges.len()]),
+        recording: Mutex::new(recording),
+        routes: Mutex::new(HashMap::new()),
+    };
+    SESSION.set(session).map_err(|_| SessionError::AlreadyStarted)
+}
+
+/// The base URL requests to `server` go to while recording or replaying, which is a loopback
+/// listener started the first time the server is used. None otherwise.
+///
+/// The listener keeps the server's base path, so the paths requests are signed with don't
+/// change on the way through.
+pub(crate) fn route(server: &str) -> Result<Option<String>, CommonError> {
+    let Some(session) = SESSION.get() else {
+        return Ok(None);
+    };
+    let server = server.trim().trim_end_matches('/').to_string();
+    let base_path = reqwest::Url::parse(&server).map(|url| url.path().trim_end_matches('/').to_string()).unwrap_or_default();
+
+    let mut routes = session.routes.lock().unwrap_or_else(|p| p.into_inner());
+    if let Some(addr) = routes.get(&server) {
+        return Ok(Some(format!("http://{}{}", addr, base_path)));
+    }
+
+    // Recorded requests are forwarded to the server's origin, reached as it would be otherwise.
+    let upstream = match session.mode {
+        Mode::Record(_) => Some(reqwest::Url::parse(&server).map(|url| url.origin().ascii_serialization()).unwrap_or_else(|_| server.clone())),
+        Mode::Replay => None,
+    };
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let addr = listener.local_addr()?;
+    log::debug!("Routing requests to {} through {}", server, addr);
+
+    let name = server.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let (name, upstream) = (name.clone(), upstream.clone());
+            thread::spawn(move || {
+                if let Err(err) = session.handle(stream, &name, upstream.as_deref()) {
+                    log::debug!("Recorded request to {} failed: {}", name, err);
+                }
+            });
+        }
+    });
+
+    routes.insert(server, addr);
+    Ok(Some(format!("http://{}{}", addr, base_path)))
+}
+
+/// A request as read off the loopback listener.
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Session {
+    fn handle(&self, mut stream: TcpStream, server: &str, upstream: Option<&str>) -> io::Result<()> {
+        let request = read_request(&mut BufReader::new(stream.try_clone()?))?;
+        let path = request.path.split('?').next().unwrap_or_default();
+        let credentials = CREDENTIAL_PATHS.iter().any(|p| path.ends_with(p));
+        let request_sha256: String = match credentials {
+            true => UNHASHED.to_string(),
+            false => Sha256::digest(&request.body).iter().map(|b| format!("{:02x}", b)).collect(),
+        };
+        let exchange = match upstream {
+            Some(upstream) => match forward(server, upstream, &request, request_sha256) {
+                Ok(exchange) => {
+                    // The client gets the real response, and the recording a redacted one.
+                    let mut recorded = exchange.clone();
+                    if credentials {
+                        recorded.body = recorded.body.as_deref().map(|body| crate::redact::redact(body).into_owned());
+                        recorded.body_base64 = None;
+                    }
+                    self.recording.lock().unwrap_or_else(|p| p.into_inner()).exchanges.push(recorded);
+                    if let Err(err) = self.save() {
+                        log::error!("{}", err);
+                    }
+                    exchange
+                }
+                Err(err) => failure(server, &request, 502, &format!("Failed to reach {}: {}", server, err)),
+            },
+            None => self.find(server, &request, &request_sha256).unwrap_or_else(|| {
+                log::error!("No recorded response for {} {}{}", request.method, server, request.path);
+                failure(server, &request, 501, &format!("No recorded response for {} {}.", request.method, request.path))
+            }),
+        };
+
+        let body = match (&exchange.body, &exchange.body_base64) {
+            (Some(text), _) => text.as_bytes().to_vec(),
+            (None, Some(encoded)) => STANDARD.decode(encoded).unwrap_or_default(),
+            (None, None) => Vec::new(),
+        };
+        let reason = reqwest::StatusCode::from_u16(exchange.status).ok().and_then(|s| s.canonical_reason()).unwrap_or("");
+        write!(stream, "HTTP/1.1 {} {}\r\n", exchange.status, reason)?;
+        for (name, value) in exchange.headers.iter() {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+        write!(stream, "Content-Length: {}\r\nConnection: close\r\n\r\n", body.len())?;
+        stream.write_all(&body)?;
+        stream.flush()
+    }
+
+    /// The recorded response to a request: the first one not yet replayed, or the last if
+    /// they all have been.
+    fn find(&self, server: &str, request: &Request, request_sha256: &str) -> Option<Exchange> {
+        let recording = self.recording.lock().unwrap_or_else(|p| p.into_inner());
+        let mut replayed = self.replayed.lock().unwrap_or_else(|p| p.into_inner());
+        let matching: Vec<usize> = recording.exchanges.iter().enumerate()
+            .filter(|(_, e)| e.server == server && e.method.eq_ignore_ascii_case(&request.method) && e.path == request.path && e.request_sha256 == request_sha256)
+            .map(|(i, _)| i)
+            .collect();
+        let i = matching.iter().copied().find(|i| replayed[*i] == 0).or(matching.last().copied())?;
+        replayed[i] += 1;
+        Some(recording.exchanges[i].clone())
+    }
+
+    fn save(&self) -> Result<(), SessionError> {
+        let Mode::Record(path) = &self.mode else {
+            return Ok(());
+        };
+        let recording = self.recording.lock().unwrap_or_else(|p| p.into_inner());
+        let text = serde_json::to_string_pretty(&*recording).map_err(|err| SessionError::Invalid(path.display().to_string(), err))?;
+        // Results can be as sensitive as the credentials, so the file is private.
+        crate::config::replace(path, &text, true).map_err(|err| match err {
+            ConfigError::FailedWrite(err) => SessionError::Write(path.display().to_string(), err),
+            err => SessionError::Write(path.display().to_string(), io::Error::other(err)),
+        })
+    }
+}
+
+/// Sends a request on to the server, returning the exchange to record.
+fn forward(server: &str, upstream: &str, request: &Request, request_sha256: String) -> Result<Exchange, reqwest::Error> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let method = reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::GET);
+    let mut builder = client.request(method, format!("{}{}", upstream, request.path));
+    for (name, value) in request.headers.iter() {
+        if !matches!(name.to_ascii_lowercase().as_str(), "host" | "connection" | "content-length" | "transfer-encoding") {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+
+    let mut response = builder.body(request.body.clone()).send()?;
+    let mut headers = BTreeMap::new();
+    for (name, value) in response.headers().iter() {
+        if HOP_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let value = value.to_str().unwrap_or_default().to_string();
+        headers.entry(name.to_string())
+            .and_modify(|existing: &mut String| *existing = format!("{}, {}", existing, value))
+            .or_insert(value);
+    }
+    let mut body = Vec::new();
+    response.copy_to(&mut body)?;
+    Ok(exchange(server, request, request_sha256, response.status().as_u16(), headers, body))
+}
+
+fn exchange(server: &str, request: &Request, request_sha256: String, status: u16, headers: BTreeMap<String, String>, body: Vec<u8>) -> Exchange {
+    let (body, body_base64) = match String::from_utf8(body) {
+        Ok(text) => (Some(text), None),
+        Err(err) => (None, Some(STANDARD.encode(err.into_bytes()))),
+    };
+    Exchange { server: server.to_string(), method: request.method.clone(), path: request.path.clone(), request_sha256, status, headers, body, body_base64 }
+}
+
+/// A response made up in place of the server's, with an error message the client reports.
+fn failure(server: &str, request: &Request, status: u16, message: &str) -> Exchange {
+    let headers = BTreeMap::from([("content-type".to_string(), "application/json".to_string())]);
+    let body = serde_json::json!({ "message": message, "errors": [] }).to_string();
+    exchange(server, request, String::new(), status, headers, body.into_bytes())
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default().to_string(), parts.next().unwrap_or("/").to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.trim_end().split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let header = |name: &str| headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str());
+    let mut body = Vec::new();
+    if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = usize::from_str_radix(size.trim().split(';').next().unwrap_or_default(), 16)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            if size == 0 {
+                reader.read_line(&mut String::new())?;
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            reader.read_line(&mut String::new())?;
+        }
+    } else if let Some(length) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    }
+
+    Ok(Request { method, path, headers, body })
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, ops::Range, path::PathBuf};
+
+use crate::{
+    config,
+    error::{ConfigError, SnippetError},
+};
+
+/// Start of a snippet reference in a query, such as `{{snippet:errors}}`.
+const REFERENCE: &str = "{{snippet:";
+
+/// A small reusable piece of a query, such as a filter or a summarize clause.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    pub name: String,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub updated: DateTime<Utc>,
+}
+
+impl Snippet {
+    pub fn new(name: &str, text: &str, tags: Vec<String>, description: Option<String>) -> Self {
+        let mut tags: Vec<String> = tags
+            .iter()
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        Self {
+            name: name.trim().to_string(),
+            text: text.trim().to_string(),
+            tags,
+            description,
+            updated: Utc::now(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Snippets {
+    pub snippets: BTreeMap<String, Snippet>,
+}
+
+impl Snippets {
+    pub fn get(&self, name: &str) -> Result<&Snippet, SnippetError> {
+        self.snippets
+            .get(name)
+            .ok_or_else(|| SnippetError::NotFound(name.to_string()))
+    }
+
+    /// Replaces every `{{snippet:NAME}}` in the query with the snippet's text.
+    pub fn expand(&self, query: &str) -> Result<String, SnippetError> {
+        Ok(self.expand_mapped(query)?.text)
+    }
+
+    /// Like `expand`, also recording where each snippet was inserted.
+    pub fn expand_mapped(&self, query: &str) -> Result<Expanded, SnippetError> {
+        let mut expanded = String::with_capacity(query.len());
+        let mut replacements = Vec::new();
+        let mut rest = query;
+        while let Some(start) = rest.find(REFERENCE) {
+            let offset = query.len() - rest.len() + start;
+            expanded.push_str(&rest[..start]);
+            let reference = &rest[start + REFERENCE.len()..];
+            let end = reference
+                .find("}}")
+                .ok_or(SnippetError::Unterminated(offset))?;
+            let text = &self.get(reference[..end].trim())?.text;
+            replacements.push(Replacement {
+                expanded: expanded.len()..expanded.len() + text.len(),
+                original: offset..offset + REFERENCE.len() + end + 2,
+            });
+            expanded.push_str(text);
+            rest = &reference[end + 2..];
+        }
+
+        expanded.push_str(rest);
+        Ok(Expanded { text: expanded, replacements })
+    }
+}
+
+/// A query with its snippet references replaced, and where each one was, so a position in the
+/// expanded query can be traced back to the query as written.
+#[derive(Clone, Debug, Default)]
+pub struct Expanded {
+    pub text: String,
+    replacements: Vec<Replacement>,
+}
+
+#[derive(Clone, Debug)]
+struct Replacement {
+    expanded: Range<usize>,
+    original: Range<usize>,
+}
+
+impl Expanded {
+    /// A query without snippet references, whose positions are the same as written.
+    pub fn unexpanded(query: &str) -> Self {
+        Self { text: query.to_string(), replacements: vec![] }
+    }
+
+    /// The byte range in the written query of a byte range in the expanded one. Positions
+    /// within a snippet point at the whole reference.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.original_offset(range.start, |r| r.original.start);
+        let end = self.original_offset(range.end, |r| r.original.end);
+        start..end.max(start)
+    }
+
+    fn original_offset(&self, offset: usize, within: impl Fn(&Replacement) -> usize) -> usize {
+        let mut shift = 0isize;
+        for r in &self.replacements {
+            if offset < r.expanded.start {
+                break;
+            }
+            if offset < r.expanded.end {
+                return within(r);
+            }
+            shift = r.original.end as isize - r.expanded.end as isize;
+        }
+        offset.saturating_add_signed(shift)
+    }
+}
+
+/// Expands snippet references in a query. Snippets are only loaded when the query has any.
+pub fn expand(query: &str) -> Result<String, SnippetError> {
+    Ok(expand_mapped(query)?.text)
+}
+
+/// Like `expand`, keeping track of where snippets were inserted.
+pub fn expand_mapped(query: &str) -> Result<Expanded, SnippetError> {
+    if !query.contains(REFERENCE) {
+        return Ok(Expanded::unexpanded(query));
+    }
+
+    load()?.expand_mapped(query)
+}
+
+/// Snippets live next to the logsh configuration file.
+pub fn get_snippets_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("snippets.json"))
+}
+
+pub fn load() -> Result<Snippets, ConfigError> {
+    let path = get_snippets_path()?;
+    if !path.exists() {
+        return Ok(Snippets::default());
+    }
+
+    let snippets = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&snippets).map_err(ConfigError::FailedDeserialize)
+}
+
+pub fn save(snippets: Snippets) -> Result<Snippets, ConfigError> {
+    let path = get_snippets_path()?;
+    let serialized =
+        serde_json::to_string_pretty(&snippets).map_err(ConfigError::FailedSerialize)?;
+    fs::write(path, serialized).map_err(ConfigError::FailedWrite)?;
+    Ok(snippets)
+}
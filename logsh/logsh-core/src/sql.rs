@@ -0,0 +1,150 @@
+use std::{
+    fs,
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use rusqlite::{types::Value as SqlValue, types::ValueRef, Connection, ErrorCode};
+use serde_json::{Map, Value};
+
+use crate::{
+    query,
+    sqlite::{self, SqliteError},
+};
+
+/// An in-memory SQLite database that query results and local files are loaded into as tables,
+/// so they can be joined with SQL.
+pub struct SqlContext {
+    conn: Connection,
+}
+
+impl SqlContext {
+    pub fn new() -> Result<Self, SqliteError> {
+        Ok(Self {
+            conn: Connection::open_in_memory()?,
+        })
+    }
+
+    /// Loads a query response body, as returned by the server or saved with `-o json`.
+    pub fn load_result(&self, table: &str, response: &str) -> Result<usize, SqliteError> {
+        let result = query::result(response).map_err(|err| SqliteError::InvalidSource(err.to_string()))?;
+        let rows = sqlite::result_rows(&result)?;
+        sqlite::insert_rows(&self.conn, table, &result.header, &rows)
+    }
+
+    /// Loads a CSV file with a header row. Values that parse as numbers are stored as numbers.
+    pub fn load_csv(&self, table: &str, path: &Path) -> Result<usize, SqliteError> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let header: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+        let rows = reader
+            .records()
+            .map(|record| Ok(record?.iter().map(csv_value).collect()))
+            .collect::<Result<Vec<Vec<SqlValue>>, csv::Error>>()?;
+        sqlite::insert_rows(&self.conn, table, &header, &rows)
+    }
+
+    /// Loads a file by its extension: `.csv` as CSV, anything else as a JSON query response.
+    pub fn load_file(&self, table: &str, path: &Path) -> Result<usize, SqliteError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => self.load_csv(table, path),
+            _ => {
+                let response = fs::read_to_string(path).map_err(SqliteError::FailedFile)?;
+                self.load_result(table, &response)
+            }
+        }
+    }
+
+    /// Runs a SQL statement and returns its rows as a query response body, in the shape the
+    /// server returns so it renders like any other result. The statement is interrupted when it
+    /// runs longer than `timeout`.
+    pub fn query(&self, sql: &str, timeout: Option<Duration>) -> Result<String, SqliteError> {
+        let (done, finished) = mpsc::channel::<()>();
+        let watchdog = timeout.map(|timeout| {
+            let handle = self.conn.get_interrupt_handle();
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                    handle.interrupt();
+                }
+            })
+        });
+        let result = self.rows(sql);
+        drop(done);
+        if let Some(watchdog) = watchdog {
+            let _ = watchdog.join();
+        }
+
+        match (result, timeout) {
+            (Err(SqliteError::Sqlite(rusqlite::Error::SqliteFailure(err, _))), Some(timeout))
+                if err.code == ErrorCode::OperationInterrupted =>
+            {
+                Err(SqliteError::Timeout(timeout))
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn rows(&self, sql: &str) -> Result<String, SqliteError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let header: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query([])?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut object = Map::new();
+            for (i, name) in header.iter().enumerate() {
+                object.insert(name.to_string(), json_value(row.get_ref(i)?));
+            }
+            results.push(Value::Object(object));
+        }
+
+        Ok(serde_json::json!({ "header": header, "results": results }).to_string())
+    }
+}
+
+fn csv_value(field: &str) -> SqlValue {
+    if field.is_empty() {
+        SqlValue::Null
+    } else if let Ok(i) = field.parse::<i64>() {
+        SqlValue::Integer(i)
+    } else if let Ok(f) = field.parse::<f64>() {
+        SqlValue::Real(f)
+    } else {
+        SqlValue::Text(field.to_string())
+    }
+}
+
+fn json_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(t) | ValueRef::Blob(t) => Value::String(String::from_utf8_lossy(t).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_loaded_tables() {
+        let context = SqlContext::new().unwrap();
+        context
+            .load_result("a", r#"{"header":["id","name"],"results":[{"id":1,"name":"x"},{"id":2,"name":"y"}]}"#)
+            .unwrap();
+        context.load_result("b", r#"{"header":["id","hits"],"results":[{"id":2,"hits":5}]}"#).unwrap();
+
+        let text = context.query("SELECT a.name, b.hits FROM a JOIN b ON a.id = b.id", None).unwrap();
+        let result: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(result["results"], serde_json::json!([{ "name": "y", "hits": 5 }]));
+    }
+
+    #[test]
+    fn interrupts_sql_past_the_timeout() {
+        let context = SqlContext::new().unwrap();
+        let forever = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT count(*) FROM n";
+        let err = context.query(forever, Some(Duration::from_millis(50))).unwrap_err();
+        assert!(matches!(err, SqliteError::Timeout(_)), "{}", err);
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use rusqlite::{types::Value as SqlValue, Connection};
+use serde_json::{value::RawValue, Value};
+use thiserror::Error;
+
+use crate::query::QueryResult;
+
+#[derive(Debug, Error)]
+pub enum SqliteError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Failed to read query result value: {0}")]
+    FailedRead(#[from] serde_json::Error),
+
+    #[error("Invalid table name: \"{0}\"")]
+    InvalidTable(String),
+
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("Failed to read file: {0}")]
+    FailedFile(std::io::Error),
+
+    #[error("Invalid query result: {0}")]
+    InvalidSource(String),
+
+    #[error("SQL took longer than the timeout of {0:?}.")]
+    Timeout(std::time::Duration),
+}
+
+/// Appends a query result to a SQLite table, creating the database and table when missing.
+///
+/// Column types are inferred from the result values: integer and boolean columns become
+/// INTEGER, other numeric columns REAL, and everything else TEXT. Objects and arrays are
+/// stored as JSON text. Columns missing from an existing table are added. Returns the number
+/// of rows written.
+pub fn write_sqlite(query: &QueryResult<'_>, path: &Path, table: &str) -> Result<usize, SqliteError> {
+    if table.trim().is_empty() {
+        return Err(SqliteError::InvalidTable(table.to_string()));
+    }
+
+    let rows = result_rows(query)?;
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+    let written = insert_rows(&tx, table, &query.header, &rows)?;
+    tx.commit()?;
+    Ok(written)
+}
+
+/// The values of each row of a query result, in header order.
+pub(crate) fn result_rows(query: &QueryResult<'_>) -> Result<Vec<Vec<SqlValue>>, serde_json::Error> {
+    query
+        .results
+        .iter()
+        .map(|row| {
+            query
+                .header
+                .iter()
+                .map(|h| row.get(h.as_str()).map_or(Ok(SqlValue::Null), |v| sql_value(v)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Appends rows to a table of an open database, creating the table or adding missing columns
+/// first. Each row holds one value per header column.
+pub(crate) fn insert_rows(conn: &Connection, table: &str, header: &[String], rows: &[Vec<SqlValue>]) -> Result<usize, SqliteError> {
+    let existing: Vec<String> = {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+        let names = stmt.query_map([], |r| r.get::<_, String>(1))?;
+        names.collect::<Result<_, _>>()?
+    };
+
+    let columns: Vec<String> = header
+        .iter()
+        .enumerate()
+        .map(|(i, h)| format!("{} {}", quote(h), column_type(rows.iter().map(|r| &r[i]))))
+        .collect();
+    if existing.is_empty() {
+        log::debug!("Creating SQLite table {}.", table);
+        conn.execute(&format!("CREATE TABLE {} ({})", quote(table), columns.join(", ")), [])?;
+    } else {
+        for (h, column) in header.iter().zip(columns.iter()) {
+            if !existing.iter().any(|e| e.eq_ignore_ascii_case(h)) {
+                log::debug!("Adding column {} to SQLite table {}.", h, table);
+                conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}", quote(table), column), [])?;
+            }
+        }
+    }
+
+    let names: Vec<String> = header.iter().map(|h| quote(h)).collect();
+    let params = vec!["?"; names.len()].join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote(table),
+        names.join(", "),
+        params
+    ))?;
+    for row in rows.iter() {
+        stmt.execute(rusqlite::params_from_iter(row.iter()))?;
+    }
+
+    Ok(rows.len())
+}
+
+fn sql_value(raw: &RawValue) -> Result<SqlValue, serde_json::Error> {
+    Ok(match serde_json::from_str::<Value>(raw.get())? {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b.into()),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        other => SqlValue::Text(other.to_string()),
+    })
+}
+
+/// Picks the narrowest column type that fits every non-null value.
+fn column_type<'v>(values: impl Iterator<Item = &'v SqlValue>) -> &'static str {
+    let mut typ = None;
+    for value in values {
+        typ = match (typ, value) {
+            (_, SqlValue::Null) => typ,
+            (None | Some("INTEGER"), SqlValue::Integer(_)) => Some("INTEGER"),
+            (None | Some("INTEGER") | Some("REAL"), SqlValue::Real(_) | SqlValue::Integer(_)) => Some("REAL"),
+            _ => return "TEXT",
+        };
+    }
+
+    typ.unwrap_or("TEXT")
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
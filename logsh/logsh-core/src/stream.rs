@@ -0,0 +1,183 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// How a streamed request body is buffered between the code producing it and the request
+/// sending it.
+#[derive(Clone, Copy, Debug)]
+pub struct StreamOptions {
+    /// Bytes gathered before they're sent.
+    pub chunk_size: usize,
+    /// Bytes held before the producer waits for the request to catch up, so memory stays flat
+    /// however large the body is.
+    pub max_buffered: usize,
+    /// Longest time gathered bytes wait for a full chunk, so a slow source such as a pipe
+    /// still reaches the server.
+    pub flush_interval: Duration,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024 * 1024,
+            max_buffered: 8 * 1024 * 1024,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+struct State {
+    buffer: VecDeque<u8>,
+    /// When the oldest byte in the buffer was written.
+    pending_since: Option<Instant>,
+    /// The writer is done. The reader ends once the buffer is empty.
+    closed: bool,
+    /// The writer failed, so the body must not end as though it were complete.
+    failed: Option<String>,
+    /// The reader was dropped, such as when the request failed. Writes fail from then on.
+    abandoned: bool,
+}
+
+struct Shared {
+    options: StreamOptions,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Creates a bounded pipe: bytes written to the writer, typically from another thread, are
+/// read from the reader, which can be used as a request body.
+pub fn channel(options: StreamOptions) -> (StreamWriter, StreamReader) {
+    let options = StreamOptions {
+        chunk_size: options.chunk_size.max(1),
+        max_buffered: options.max_buffered.max(options.chunk_size).max(1),
+        ..options
+    };
+    let shared = Arc::new(Shared {
+        options,
+        state: Mutex::new(State {
+            buffer: VecDeque::new(),
+            pending_since: None,
+            closed: false,
+            failed: None,
+            abandoned: false,
+        }),
+        changed: Condvar::new(),
+    });
+    (StreamWriter { shared: shared.clone() }, StreamReader { shared })
+}
+
+/// The producing end of a [channel]. Writes wait while the buffer is full. Dropping it ends
+/// the body.
+pub struct StreamWriter {
+    shared: Arc<Shared>,
+}
+
+impl StreamWriter {
+    /// Whether the reader was dropped, such as when the request failed.
+    pub fn is_abandoned(&self) -> bool {
+        self.shared.lock().abandoned
+    }
+
+    /// Ends the body with an error, so the request fails instead of sending partial data.
+    pub fn fail(self, message: &str) {
+        self.shared.lock().failed = Some(message.to_string());
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Write for StreamWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let max_buffered = self.shared.options.max_buffered;
+        let mut state = self.shared.lock();
+        while state.buffer.len() >= max_buffered && !state.abandoned {
+            state = self.shared.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        if state.abandoned {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the upload stopped reading"));
+        }
+
+        let written = buf.len().min(max_buffered - state.buffer.len());
+        state.buffer.extend(&buf[..written]);
+        state.pending_since.get_or_insert_with(Instant::now);
+        self.shared.changed.notify_all();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// The consuming end of a [channel]. Reads return a chunk once enough is buffered, the flush
+/// interval passes, or the writer is done.
+pub struct StreamReader {
+    shared: Arc<Shared>,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let options = self.shared.options;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(message) = state.failed.as_ref() {
+                return Err(io::Error::other(message.to_string()));
+            }
+
+            let waited = state.pending_since.map(|since| since.elapsed());
+            let ready = state.buffer.len() >= options.chunk_size
+                || state.closed
+                || waited.is_some_and(|waited| waited >= options.flush_interval);
+            if ready {
+                let read = buf.len().min(state.buffer.len());
+                for (to, from) in buf.iter_mut().zip(state.buffer.drain(..read)) {
+                    *to = from;
+                }
+                state.pending_since = match state.buffer.is_empty() {
+                    true => None,
+                    false => state.pending_since,
+                };
+                self.shared.changed.notify_all();
+                return Ok(read);
+            }
+
+            state = match waited {
+                Some(waited) => {
+                    let timeout = options.flush_interval.saturating_sub(waited);
+                    self.shared.changed.wait_timeout(state, timeout).unwrap_or_else(|poisoned| poisoned.into_inner()).0
+                }
+                None => self.shared.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        self.shared.lock().abandoned = true;
+        self.shared.changed.notify_all();
+    }
+}
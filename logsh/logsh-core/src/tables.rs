@@ -0,0 +1,68 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    endpoint,
+    error::{self, TableError},
+    logship_client::LogshClientHandler,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableModel {
+    pub schema: String,
+    pub name: String,
+    pub row_count: Option<u64>,
+    pub size_bytes: Option<u64>,
+    pub last_ingest: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnModel {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDetailsModel {
+    #[serde(flatten)]
+    pub table: TableModel,
+    pub columns: Vec<ColumnModel>,
+}
+
+pub fn list_tables(
+    connection: &LogshClientHandler,
+    schema: Option<&str>,
+) -> Result<Vec<TableModel>, TableError> {
+    let subscription = connection.get_subscription()?.to_string();
+    let query = match schema {
+        Some(schema) => vec![("schema", schema)],
+        None => vec![],
+    };
+    let query_url = endpoint::path(&["search", &subscription, "tables"], &query);
+
+    let result = connection.execute_func(&|client| -> Result<Vec<TableModel>, error::ClientError> {
+        let result = client.get_json(&query_url)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
+
+pub fn describe_table(
+    connection: &LogshClientHandler,
+    name: &str,
+) -> Result<TableDetailsModel, TableError> {
+    let subscription = connection.get_subscription()?;
+    let query_url = endpoint::path(&["search", &subscription.to_string(), "tables", name], &[]);
+
+    let result = connection.execute_func(&|client| -> Result<TableDetailsModel, error::ClientError> {
+        let result = client.get_json(&query_url)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use handlebars::{handlebars_helper, no_escape, Handlebars};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::query::QueryResult;
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("Invalid template: {0}")]
+    Invalid(#[from] Box<handlebars::TemplateError>),
+
+    #[error("Failed to render row: {0}")]
+    Render(#[from] handlebars::RenderError),
+
+    #[error("Failed to read query result value: {0}")]
+    FailedRead(#[from] serde_json::Error),
+
+    #[error("Failed to write output: {0}")]
+    FailedWrite(#[from] std::io::Error),
+}
+
+handlebars_helper!(json: |v: Json| v.to_string());
+
+/// Renders each row of a query result through a handlebars template, one line per row.
+///
+/// Columns are referenced by name, such as `{{time}} [{{level}}] {{message}}`, or as
+/// `{{[column name]}}` when the name isn't a plain identifier. Values aren't HTML escaped.
+/// Nested values can be walked with the usual `{{a.b}}` and `{{#each}}` syntax, or written
+/// as JSON with `{{json a}}`.
+pub fn write_template<W: Write>(query: &QueryResult<'_>, template: &str, mut to: W) -> Result<(), TemplateError> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(no_escape);
+    handlebars.register_helper("json", Box::new(json));
+    handlebars.register_template_string("row", template).map_err(Box::new)?;
+
+    for row in query.results.iter() {
+        let mut data = Map::with_capacity(query.header.len());
+        for h in query.header.iter() {
+            let value = match row.get(h.as_str()) {
+                Some(v) => serde_json::from_str(v.get())?,
+                None => Value::Null,
+            };
+            data.insert(h.to_string(), value);
+        }
+
+        handlebars.render_to_write("row", &data, &mut to)?;
+        writeln!(to)?;
+    }
+
+    to.flush()?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::{error::{TenantError, self}, fuzzy, logship_client::LogshClientHandler};
+use serde::{Deserialize, Serialize};
+
+/// A logship account, called a subscription or tenant depending on where you look.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantModel {
+    pub permissions: Vec<String>,
+    pub account_id: uuid::Uuid,
+    pub account_name: String,
+}
+
+pub use crate::fuzzy::MatchKind;
+
+/// Finds the one subscription `query` names: by ID, then by whole name, then by the start of
+/// its name, then by any part of it, ignoring case. Fails when nothing matches, or when the
+/// first kind of match that finds anything finds more than one.
+pub fn resolve<'a>(tenants: &'a [TenantModel], query: &str) -> Result<(&'a TenantModel, MatchKind), TenantError> {
+    let query = query.trim();
+    if let Ok(id) = uuid::Uuid::parse_str(query) {
+        return tenants.iter().find(|t| t.account_id == id).map(|t| (t, MatchKind::Exact)).ok_or(TenantError::NotFound(query.to_string()));
+    }
+
+    match fuzzy::find(tenants, |t| t.account_name.as_str(), query) {
+        Some((matches, kind)) => match matches.as_slice() {
+            [one] => Ok((one, kind)),
+            many => {
+                let names: Vec<String> = many.iter().map(|t| format!("{} ({})", t.account_name, t.account_id)).collect();
+                Err(TenantError::Ambiguous(query.to_string(), names.join(", ")))
+            }
+        },
+        None => Err(TenantError::NotFound(query.to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TenantRequest<'a> {
+    account_name: &'a str,
+}
+
+pub fn list_tenants(
+    connection : &LogshClientHandler,
+    user_id : uuid::Uuid,
+    include_all_if_admin : bool) -> Result<Vec<TenantModel>, TenantError> {
+    let query_url = format!("users/{}/accounts?allIfAdmin={}", user_id, include_all_if_admin);
+
+    let result = connection.execute_func(&|client| -> Result<Vec<TenantModel>, error::ClientError> {
+        let result = client.get_json(&query_url)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
+
+pub fn create_tenant(
+    connection : &LogshClientHandler,
+    name : &str) -> Result<TenantModel, TenantError> {
+    let request = TenantRequest { account_name: name };
+
+    let result = connection.execute_func(&|client| -> Result<TenantModel, error::ClientError> {
+        let result = client.post_json("accounts", &request)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
+
+pub fn rename_tenant(
+    connection : &LogshClientHandler,
+    tenant_id : uuid::Uuid,
+    name : &str) -> Result<TenantModel, TenantError> {
+    let query_url = format!("accounts/{}", tenant_id);
+    let request = TenantRequest { account_name: name };
+
+    let result = connection.execute_func(&|client| -> Result<TenantModel, error::ClientError> {
+        let result = client.put_json(&query_url, &request)?;
+        Ok(result)
+    })?;
+
+    Ok(result)
+}
+
+pub fn delete_tenant(
+    connection : &LogshClientHandler,
+    tenant_id : uuid::Uuid) -> Result<(), TenantError> {
+    let query_url = format!("accounts/{}", tenant_id);
+
+    connection.execute_func(&|client| -> Result<(), error::ClientError> {
+        client.delete(&query_url)?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenants() -> Vec<TenantModel> {
+        ["Production", "prod-eu", "Staging", "Development"].iter().enumerate()
+            .map(|(i, name)| TenantModel { account_id: uuid::Uuid::from_u128(i as u128 + 1), account_name: name.to_string(), ..Default::default() })
+            .collect()
+    }
+
+    fn resolved(query: &str) -> Result<(String, MatchKind), TenantError> {
+        let tenants = tenants();
+        resolve(&tenants, query).map(|(t, kind)| (t.account_name.to_string(), kind))
+    }
+
+    #[test]
+    fn resolves_ids_and_whole_names_exactly() {
+        let (name, kind) = resolved("00000000-0000-0000-0000-000000000003").unwrap();
+        assert_eq!((name.as_str(), kind), ("Staging", MatchKind::Exact));
+        let (name, kind) = resolved(" production ").unwrap();
+        assert_eq!((name.as_str(), kind), ("Production", MatchKind::Exact));
+        assert!(matches!(resolved("00000000-0000-0000-0000-000000000009"), Err(TenantError::NotFound(_))));
+    }
+
+    #[test]
+    fn prefers_tighter_matches() {
+        assert_eq!(resolved("stag").unwrap(), ("Staging".to_string(), MatchKind::Prefix));
+        assert_eq!(resolved("velop").unwrap(), ("Development".to_string(), MatchKind::Fuzzy));
+        assert_eq!(resolved("dvlp").unwrap(), ("Development".to_string(), MatchKind::Fuzzy));
+        assert_eq!(resolved("prod-eu").unwrap(), ("prod-eu".to_string(), MatchKind::Exact));
+    }
+
+    #[test]
+    fn refuses_ambiguous_and_unknown_names() {
+        assert!(matches!(resolved("prod"), Err(TenantError::Ambiguous(_, _))));
+        assert!(matches!(resolved("qa"), Err(TenantError::NotFound(_))));
+    }
+}
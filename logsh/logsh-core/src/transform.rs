@@ -0,0 +1,727 @@
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Timelike, Utc,
+};
+use serde_json::{Map, Number, Value};
+
+use crate::error::TransformError;
+
+/// A computed column, written as `NAME = EXPRESSION`, evaluated for every row of a result after
+/// it's retrieved.
+///
+/// Expressions support numbers, strings, `true`, `false` and `null`, column names (or
+/// `['column name']`), arithmetic with `+ - * / %`, comparisons, `and`, `or`, and the functions
+/// in [FUNCTIONS]. `+` joins strings. Division is always floating point. Anything evaluated
+/// against a missing value or the wrong type is null, like in the query language.
+#[derive(Clone, Debug)]
+pub struct Derive {
+    pub name: String,
+    expr: Expr,
+}
+
+/// Functions usable in a derived column, with their minimum and maximum argument counts.
+pub const FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("abs", 1, 1),
+    ("ceiling", 1, 1),
+    ("datetime_diff", 3, 3),
+    ("datetime_part", 2, 2),
+    ("floor", 1, 1),
+    ("iff", 3, 3),
+    ("now", 0, 0),
+    ("replace_string", 3, 3),
+    ("round", 1, 2),
+    ("startofday", 1, 1),
+    ("strcat", 1, usize::MAX),
+    ("strlen", 1, 1),
+    ("substring", 2, 3),
+    ("todatetime", 1, 1),
+    ("todouble", 1, 1),
+    ("toint", 1, 1),
+    ("tolower", 1, 1),
+    ("tostring", 1, 1),
+    ("toupper", 1, 1),
+    ("trim", 1, 1),
+];
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Value),
+    Column(String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+    Call(&'static str, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl FromStr for Derive {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| TransformError::Parse(s.to_string(), reason.to_string());
+        let (name, expr) = s
+            .split_once('=')
+            .ok_or_else(|| invalid("expected NAME = EXPRESSION"))?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(invalid(
+                "the column name must be letters, digits and underscores",
+            ));
+        }
+
+        let tokens = lex(expr).map_err(|reason| invalid(&reason))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr().map_err(|reason| invalid(&reason))?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(invalid(&format!("unexpected {:?}", token)));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            expr,
+        })
+    }
+}
+
+impl Derive {
+    /// Columns the expression reads.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.expr.columns(&mut columns);
+        columns
+    }
+
+    pub fn evaluate(&self, row: &Map<String, Value>) -> Value {
+        self.expr.evaluate(row)
+    }
+}
+
+/// Adds derived columns to a query response body, in the given order so later columns can use
+/// earlier ones. A derived column replaces a returned column of the same name. Returns the new
+/// response body.
+pub fn derive(response: &str, derives: &[Derive]) -> Result<String, TransformError> {
+    let mut doc: Map<String, Value> = serde_json::from_str(response)?;
+    let header_key = if doc.contains_key("Header") {
+        "Header"
+    } else {
+        "header"
+    };
+    let results_key = if doc.contains_key("Results") {
+        "Results"
+    } else {
+        "results"
+    };
+
+    let mut header: Vec<String> = match doc.remove(header_key) {
+        Some(header) => serde_json::from_value(header)?,
+        None => vec![],
+    };
+    for derive in derives {
+        if let Some(unknown) = derive
+            .columns()
+            .into_iter()
+            .find(|c| !header.iter().any(|h| h == c))
+        {
+            return Err(TransformError::UnknownColumn(unknown.to_string()));
+        }
+        if !header.contains(&derive.name) {
+            header.push(derive.name.clone());
+        }
+    }
+
+    if let Some(Value::Array(rows)) = doc.get_mut(results_key) {
+        for row in rows.iter_mut() {
+            if let Value::Object(row) = row {
+                for derive in derives {
+                    let value = derive.evaluate(row);
+                    row.insert(derive.name.clone(), value);
+                }
+            }
+        }
+    }
+
+    doc.insert(header_key.to_string(), Value::from(header));
+    Ok(serde_json::to_string(&doc)?)
+}
+
+impl Expr {
+    fn columns<'e>(&'e self, columns: &mut Vec<&'e str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Column(name) => columns.push(name),
+            Expr::Negate(expr) => expr.columns(columns),
+            Expr::Binary(left, _, right) => {
+                left.columns(columns);
+                right.columns(columns);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.columns(columns)),
+        }
+    }
+
+    fn evaluate(&self, row: &Map<String, Value>) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Column(name) => row.get(name).cloned().unwrap_or(Value::Null),
+            Expr::Negate(expr) => match expr.evaluate(row) {
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => i
+                        .checked_neg()
+                        .map_or_else(|| float(-(i as f64)), Value::from),
+                    None => float(-n.as_f64().unwrap_or(f64::NAN)),
+                },
+                _ => Value::Null,
+            },
+            Expr::Binary(left, Op::And, right) => match left.evaluate(row) {
+                Value::Bool(false) => Value::Bool(false),
+                Value::Bool(true) => match right.evaluate(row) {
+                    Value::Bool(b) => Value::Bool(b),
+                    _ => Value::Null,
+                },
+                _ => Value::Null,
+            },
+            Expr::Binary(left, Op::Or, right) => match left.evaluate(row) {
+                Value::Bool(true) => Value::Bool(true),
+                Value::Bool(false) => match right.evaluate(row) {
+                    Value::Bool(b) => Value::Bool(b),
+                    _ => Value::Null,
+                },
+                _ => Value::Null,
+            },
+            Expr::Binary(left, op, right) => binary(left.evaluate(row), *op, right.evaluate(row)),
+            Expr::Call(name, args) => {
+                let args: Vec<Value> = args.iter().map(|a| a.evaluate(row)).collect();
+                call(name, &args).unwrap_or(Value::Null)
+            }
+        }
+    }
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+fn binary(left: Value, op: Op, right: Value) -> Value {
+    match (op, &left, &right) {
+        (Op::Equal, _, _) => Value::Bool(compare(&left, &right) == Some(std::cmp::Ordering::Equal)),
+        (Op::NotEqual, _, _) => {
+            Value::Bool(compare(&left, &right) != Some(std::cmp::Ordering::Equal))
+        }
+        (Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual, _, _) => {
+            match compare(&left, &right) {
+                Some(ordering) => Value::Bool(match op {
+                    Op::Less => ordering.is_lt(),
+                    Op::LessEqual => ordering.is_le(),
+                    Op::Greater => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }),
+                None => Value::Null,
+            }
+        }
+        (Op::Add, Value::String(l), r) => {
+            Value::String(format!("{}{}", l, text(r).unwrap_or_default()))
+        }
+        (Op::Add, l, Value::String(r)) => {
+            Value::String(format!("{}{}", text(l).unwrap_or_default(), r))
+        }
+        (_, Value::Number(l), Value::Number(r)) => arithmetic(l, op, r),
+        _ => Value::Null,
+    }
+}
+
+fn arithmetic(left: &Number, op: Op, right: &Number) -> Value {
+    if let (Some(l), Some(r), false) = (left.as_i64(), right.as_i64(), op == Op::Divide) {
+        let result = match op {
+            Op::Add => l.checked_add(r),
+            Op::Subtract => l.checked_sub(r),
+            Op::Multiply => l.checked_mul(r),
+            _ => l.checked_rem(r),
+        };
+        if let Some(result) = result {
+            return Value::from(result);
+        }
+    }
+
+    let (l, r) = (
+        left.as_f64().unwrap_or(f64::NAN),
+        right.as_f64().unwrap_or(f64::NAN),
+    );
+    match op {
+        Op::Add => float(l + r),
+        Op::Subtract => float(l - r),
+        Op::Multiply => float(l * r),
+        Op::Divide if r == 0.0 => Value::Null,
+        Op::Divide => float(l / r),
+        _ if r == 0.0 => Value::Null,
+        _ => float(l % r),
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<std::cmp::Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => match (l.as_i64(), r.as_i64()) {
+            (Some(l), Some(r)) => Some(l.cmp(&r)),
+            _ => l.as_f64()?.partial_cmp(&r.as_f64()?),
+        },
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        (Value::Bool(l), Value::Bool(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
+
+/// A value as text, the way `tostring` and string concatenation see it.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.to_string()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        _ => None,
+    }
+}
+
+fn datetime(value: &Value) -> Option<DateTime<Utc>> {
+    let s = value.as_str()?.trim();
+    if let Ok(d) = DateTime::parse_from_rfc3339(s) {
+        return Some(d.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(d) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(Utc.from_utc_datetime(&d));
+        }
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+}
+
+fn from_datetime(d: DateTime<Utc>) -> Value {
+    Value::String(d.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn call(name: &str, args: &[Value]) -> Option<Value> {
+    let arg = |i: usize| args.get(i).unwrap_or(&Value::Null);
+    Some(match name {
+        "abs" => match arg(0) {
+            Value::Number(n) if n.is_i64() => Value::from(n.as_i64()?.checked_abs()?),
+            v => float(number_only(v)?.abs()),
+        },
+        "ceiling" => float(number_only(arg(0))?.ceil()),
+        "floor" => float(number_only(arg(0))?.floor()),
+        "round" => {
+            let digits = match args.get(1) {
+                Some(digits) => number_only(digits)? as i32,
+                None => 0,
+            };
+            let scale = 10f64.powi(digits);
+            float((number_only(arg(0))? * scale).round() / scale)
+        }
+        "toint" => Value::from(number(arg(0))?.trunc() as i64),
+        "todouble" => float(number(arg(0))?),
+        "tostring" => Value::String(text(arg(0))?),
+        "strcat" => Value::String(args.iter().filter_map(text).collect()),
+        "strlen" => Value::from(arg(0).as_str()?.chars().count()),
+        "tolower" => Value::String(arg(0).as_str()?.to_lowercase()),
+        "toupper" => Value::String(arg(0).as_str()?.to_uppercase()),
+        "trim" => Value::String(arg(0).as_str()?.trim().to_string()),
+        "substring" => {
+            let s = arg(0).as_str()?;
+            let start = number_only(arg(1))?.max(0.0) as usize;
+            let chars = s.chars().skip(start);
+            Value::String(match args.get(2) {
+                Some(len) => chars.take(number_only(len)?.max(0.0) as usize).collect(),
+                None => chars.collect(),
+            })
+        }
+        "replace_string" => {
+            Value::String(arg(0).as_str()?.replace(arg(1).as_str()?, arg(2).as_str()?))
+        }
+        "iff" => match arg(0) {
+            Value::Bool(true) => arg(1).clone(),
+            _ => arg(2).clone(),
+        },
+        "now" => from_datetime(Utc::now()),
+        "todatetime" => from_datetime(datetime(arg(0))?),
+        "startofday" => {
+            let d = datetime(arg(0))?;
+            from_datetime(Utc.from_utc_datetime(&d.date_naive().and_hms_opt(0, 0, 0)?))
+        }
+        "datetime_part" => {
+            let d = datetime(arg(1))?;
+            Value::from(match arg(0).as_str()?.to_lowercase().as_str() {
+                "year" => d.year() as i64,
+                "month" => d.month() as i64,
+                "day" => d.day() as i64,
+                "dayofweek" => d.weekday().num_days_from_sunday() as i64,
+                "dayofyear" => d.ordinal() as i64,
+                "hour" => d.hour() as i64,
+                "minute" => d.minute() as i64,
+                "second" => d.second() as i64,
+                "millisecond" => d.timestamp_subsec_millis() as i64,
+                _ => return None,
+            })
+        }
+        "datetime_diff" => {
+            let diff = datetime(arg(1))? - datetime(arg(2))?;
+            Value::from(match arg(0).as_str()?.to_lowercase().as_str() {
+                "week" => diff.num_weeks(),
+                "day" => diff.num_days(),
+                "hour" => diff.num_hours(),
+                "minute" => diff.num_minutes(),
+                "second" => diff.num_seconds(),
+                "millisecond" => diff.num_milliseconds(),
+                _ => return None,
+            })
+        }
+        _ => return None,
+    })
+}
+
+/// A number argument. Unlike [number], strings aren't converted.
+fn number_only(value: &Value) -> Option<f64> {
+    value.as_f64()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Value),
+    String(String),
+    Identifier(String),
+    Column(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+fn lex(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let literal = &rest[..len];
+            let value = match literal.parse::<i64>() {
+                Ok(i) => Value::from(i),
+                Err(_) => float(
+                    literal
+                        .parse()
+                        .map_err(|_| format!("invalid number {}", literal))?,
+                ),
+            };
+            tokens.push(Token::Number(value));
+            len
+        } else if c == '"' || c == '\'' {
+            let (value, len) = string(rest)?;
+            tokens.push(Token::String(value));
+            len
+        } else if let Some(quoted) = rest.strip_prefix('[') {
+            let (name, len) = string(quoted.trim_start())?;
+            let after = quoted.trim_start()[len..].trim_start();
+            let after = after
+                .strip_prefix(']')
+                .ok_or("expected ] after a quoted column name")?;
+            tokens.push(Token::Column(name));
+            rest.len() - after.len()
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..len].to_string()));
+            len
+        } else if let Some(symbol) = SYMBOLS.iter().find(|sym| rest.starts_with(**sym)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a quoted string with backslash escapes. Returns its value and length in `s`.
+fn string(s: &str) -> Result<(String, usize), String> {
+    let mut chars = s.char_indices();
+    let quote = match chars.next() {
+        Some((_, q @ ('"' | '\''))) => q,
+        _ => return Err("expected a quoted string".to_string()),
+    };
+
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c if c == quote => return Ok((value, i + c.len_utf8())),
+            c => value.push(c),
+        }
+    }
+
+    Err("unterminated string".to_string())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(w)) if w == word => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat_word("or") {
+            left = Expr::Binary(Box::new(left), Op::Or, Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        while self.eat_word("and") {
+            left = Expr::Binary(Box::new(left), Op::And, Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let ops = [
+            ("==", Op::Equal),
+            ("!=", Op::NotEqual),
+            ("<=", Op::LessEqual),
+            (">=", Op::GreaterEqual),
+            ("<", Op::Less),
+            (">", Op::Greater),
+        ];
+        for (symbol, op) in ops {
+            if self.eat(symbol) {
+                return Ok(Expr::Binary(Box::new(left), op, Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat("+") {
+                Op::Add
+            } else if self.eat("-") {
+                Op::Subtract
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(Box::new(left), op, Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                Op::Multiply
+            } else if self.eat("/") {
+                Op::Divide
+            } else if self.eat("%") {
+                Op::Remainder
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(Box::new(left), op, Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(n)),
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Column(name)) => Ok(Expr::Column(name)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.expr()?;
+                if !self.eat(")") {
+                    return Err("expected )".to_string());
+                }
+                Ok(expr)
+            }
+            Some(Token::Identifier(word)) if self.eat("(") => self.call(&word),
+            Some(Token::Identifier(word)) => Ok(match word.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ => Expr::Column(word),
+            }),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn call(&mut self, word: &str) -> Result<Expr, String> {
+        let (name, min, max) = FUNCTIONS
+            .iter()
+            .find(|(name, _, _)| *name == word)
+            .ok_or_else(|| format!("unknown function {}", word))?;
+
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(")") {
+                    break;
+                }
+                if !self.eat(",") {
+                    return Err(format!("expected , or ) in {}()", name));
+                }
+            }
+        }
+
+        if args.len() < *min || args.len() > *max {
+            return Err(format!("wrong number of arguments to {}()", name));
+        }
+        Ok(Expr::Call(name, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn eval(expr: &str, row: Value) -> Value {
+        let derive: Derive = format!("x = {}", expr).parse().unwrap();
+        derive.evaluate(row.as_object().unwrap())
+    }
+
+    #[test]
+    fn evaluates_arithmetic_like_the_query_language() {
+        assert_eq!(eval("a * 2 + 1", json!({"a": 3})), json!(7));
+        assert_eq!(eval("a / 2", json!({"a": 3})), json!(1.5));
+        assert_eq!(eval("-a % 4", json!({"a": 10})), json!(-2));
+        assert_eq!(eval("a + 1", json!({"a": null})), Value::Null);
+        assert_eq!(eval("a + 1", json!({"a": "x"})), json!("x1"));
+    }
+
+    #[test]
+    fn evaluates_comparisons_and_logic() {
+        assert_eq!(
+            eval("a > 1 and b == 'x'", json!({"a": 2, "b": "x"})),
+            json!(true)
+        );
+        assert_eq!(
+            eval("a < 1 or ['b c'] != 'x'", json!({"a": 2, "b c": "x"})),
+            json!(false)
+        );
+    }
+
+    #[test]
+    fn calls_functions() {
+        assert_eq!(
+            eval("strcat(toupper(a), '-', strlen(a))", json!({"a": "ab"})),
+            json!("AB-2")
+        );
+        assert_eq!(
+            eval("iff(a > 1, 'big', 'small')", json!({"a": 2})),
+            json!("big")
+        );
+        assert_eq!(
+            eval("substring(a, 1, 2)", json!({"a": "hello"})),
+            json!("el")
+        );
+        assert_eq!(eval("floor(a)", json!({"a": 2.7})), json!(2.0));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!("no equals sign".parse::<Derive>().is_err());
+        assert!("x = (a + ".parse::<Derive>().is_err());
+        assert!("x = nosuchfunction(a)".parse::<Derive>().is_err());
+    }
+
+    #[test]
+    fn derives_columns_in_order_and_replaces_returned_ones() {
+        let derives: Vec<Derive> = ["b = a * 10", "c = b + 1", "a = 0"]
+            .iter()
+            .map(|d| d.parse().unwrap())
+            .collect();
+        let response = derive(r#"{"header":["a"],"results":[{"a":1},{"a":2}]}"#, &derives).unwrap();
+        let doc: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(doc["header"], json!(["a", "b", "c"]));
+        assert_eq!(doc["results"][1], json!({"a": 0, "b": 20, "c": 21}));
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        let derives = ["b = missing + 1".parse().unwrap()];
+        assert!(
+            matches!(derive(r#"{"header":["a"],"results":[]}"#, &derives), Err(TransformError::UnknownColumn(c)) if c == "missing")
+        );
+    }
+}
@@ -1,19 +1,175 @@
 use std::{
+    fmt,
     fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
     path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
+use serde::{
+    de::{Error as _, SeqAccess, Visitor},
+    Serialize,
+};
+use serde_json::{Map, Value};
+
 use crate::{
-    connect::Connection,
+    connect::{Connection, Timeouts},
     error::{CommonError, UploadError, ClientError}, logship_client::LogshClientHandler,
+    progress::{Progress, ProgressReader},
+    stream::{self, StreamOptions},
+    upload_state::{self, PendingUpload},
 };
 
+/// How to read a CSV file before it's uploaded. The server expects comma delimited files with a
+/// header row, so files read with anything but the defaults are rewritten that way first.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: Delimiter,
+    /// Whether the first row names the columns. Without one, columns are named `Column1`,
+    /// `Column2` and so on unless `columns` names them.
+    pub has_header: bool,
+    /// Lines to drop before the header, such as a title or export notes.
+    pub skip_rows: usize,
+    /// The columns to upload, in order. Unlisted columns are dropped.
+    pub columns: Option<Vec<ColumnMapping>>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: Delimiter(b','),
+            has_header: true,
+            skip_rows: 0,
+            columns: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Whether the file can be uploaded as-is.
+    pub fn is_default(&self) -> bool {
+        self.delimiter.0 == b','
+            && self.has_header
+            && self.skip_rows == 0
+            && self.columns.is_none()
+    }
+}
+
+/// How to rewrite JSON before it's uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct JsonOptions {
+    /// Expand nested objects into dotted columns, like `request.headers.host`, up to this many
+    /// levels deep. Objects nested deeper are sent as JSON strings. None sends records as-is.
+    pub flatten: Option<usize>,
+}
+
+/// File extensions read as JSON, an array or a single record, or as newline delimited records.
+const JSON_EXTENSIONS: &[&str] = &["json", "ndjson", "jsonl"];
+
+/// A single byte field delimiter. Parses from one ASCII character, or `tab` or `\t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Delimiter(pub u8);
+
+impl FromStr for Delimiter {
+    type Err = UploadError;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        match arg {
+            "tab" | "\\t" | "\t" => Ok(Delimiter(b'\t')),
+            _ if arg.len() == 1 && arg.is_ascii() => Ok(Delimiter(arg.as_bytes()[0])),
+            _ => Err(UploadError::InvalidDelimiter(arg.to_string())),
+        }
+    }
+}
+
+/// Where an uploaded column's values come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnSource {
+    /// The column at the mapping's own position in the list.
+    Position,
+    /// A 1-based column index.
+    Index(usize),
+    /// A column named in the header row.
+    Name(String),
+}
+
+/// An uploaded column, written as `NAME` to name the column at the same position, or as
+/// `SOURCE=NAME` to take it from a header name or 1-based index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub source: ColumnSource,
+    pub name: String,
+}
+
+impl FromStr for ColumnMapping {
+    type Err = UploadError;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (source, name) = match arg.split_once('=') {
+            Some((source, name)) => {
+                let source = source.trim();
+                let source = match source.parse::<usize>() {
+                    Ok(0) => return Err(UploadError::InvalidColumns(format!("column indexes start at 1, in \"{}\"", arg))),
+                    Ok(index) => ColumnSource::Index(index),
+                    Err(_) => ColumnSource::Name(source.to_string()),
+                };
+                (source, name.trim())
+            }
+            None => (ColumnSource::Position, arg.trim()),
+        };
+
+        if name.is_empty() || matches!(&source, ColumnSource::Name(n) if n.is_empty()) {
+            return Err(UploadError::InvalidColumns(format!("expected NAME or SOURCE=NAME, got \"{}\"", arg)));
+        }
+        Ok(Self { source, name: name.to_string() })
+    }
+}
+
+/// Everything that decides how a file is read and sent.
+#[derive(Clone, Debug, Default)]
+pub struct UploadOptions {
+    pub csv: CsvOptions,
+    pub json: JsonOptions,
+    /// Format of the body, named the way a file extension would. Taken from the file's
+    /// extension by default, and required to read stdin.
+    pub format: Option<String>,
+    /// Buffering of bodies that are streamed as they're produced.
+    pub stream: StreamOptions,
+}
+
+/// The path that uploads stdin.
+pub const STDIN: &str = "-";
+
+/// What an upload sent, and how the server answered.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadReport {
+    /// Bytes of the request body, after any rewriting.
+    pub bytes: u64,
+    /// Records sent. Only known when the file was rewritten, which reads every record.
+    pub rows: Option<usize>,
+    /// HTTP status of the server's answer.
+    pub status: u16,
+    /// The server's answer, as JSON when it is, otherwise as text. None when it's empty.
+    pub response: Option<Value>,
+}
+
+/// Uploads a file, or stdin for [STDIN], reporting the bytes sent to `progress`. The progress
+/// is owned by the request body while it's sent. Files sent as they are have a known length.
+/// Rewritten files and stdin are streamed in chunks as they're produced, so memory stays flat
+/// however large they are.
 pub fn execute<'a>(
     schema_str: &'a str,
     path_str: &'a str,
+    options: &UploadOptions,
     connection: &Connection,
-    timeout: Option<std::time::Duration>,
-) -> Result<(), UploadError> {
+    timeouts: Timeouts,
+    progress: Arc<dyn Progress>,
+) -> Result<UploadReport, UploadError> {
     if path_str.trim().is_empty() {
         log::debug!("Uploading file: {:?}", path_str);
         return Err(UploadError::Common(CommonError::EmptyArgument(
@@ -21,38 +177,435 @@ pub fn execute<'a>(
         )));
     }
 
+    let stdin = path_str == STDIN;
     let path = Path::new(path_str);
-    if !path.exists() {
+    if !stdin && !path.exists() {
         return Err(UploadError::Common(CommonError::FileNotFound(
             path_str.to_string(),
         )));
     }
 
-    let ext = path.extension()
-        .ok_or(UploadError::UnsupportedFileExtension("".to_string()))
-        .map(|e| e.to_string_lossy())?;
+    let ext = match options.format.as_deref() {
+        Some(format) => format.trim_start_matches('.').to_string(),
+        None if stdin => return Err(UploadError::StdinFormat),
+        None => path.extension()
+            .ok_or(UploadError::UnsupportedFileExtension("".to_string()))
+            .map(|e| e.to_string_lossy().to_string())?,
+    };
+
+    let reshape_csv = !options.csv.is_default();
+    if reshape_csv && !ext.eq_ignore_ascii_case("csv") {
+        return Err(UploadError::UnsupportedCsvOptions(ext.to_string()));
+    }
+    let flatten_depth = options.json.flatten;
+    if flatten_depth.is_some() && !JSON_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)) {
+        return Err(UploadError::UnsupportedFlatten(ext.to_string()));
+    }
+
+    if !stdin && !reshape_csv && flatten_depth.is_none() {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        progress.start(&format!("Uploading {}", path_str), Some(len));
+        let body = reqwest::blocking::Body::sized(ProgressReader::new(file, progress.clone()), len);
+        let result = post(schema_str, &ext, body, connection, timeouts);
+        progress.finish();
+        return result.map(|(status, response)| UploadReport { bytes: len, rows: None, status, response });
+    }
+
+    // Rewritten files and stdin are sent as they're produced, in a chunked body.
+    crate::capabilities::require(connection, crate::capabilities::Capability::StreamingUpload).map_err(UploadError::Common)?;
+    let mut input: Box<dyn Read + Send> = match stdin {
+        true => Box::new(std::io::stdin()),
+        false => Box::new(File::open(path)?),
+    };
+    let (mut writer, reader) = stream::channel(options.stream);
+    let csv = options.csv.clone();
+    let producer = std::thread::spawn(move || {
+        let result = if reshape_csv {
+            reshape(input, &csv, &mut writer).map(Some)
+        } else if let Some(max_depth) = flatten_depth {
+            flatten(input, max_depth, &mut writer).map(Some)
+        } else {
+            std::io::copy(&mut input, &mut writer).map(|_| None).map_err(UploadError::from)
+        };
+
+        match result {
+            // The request stopped reading, and its error explains why.
+            Err(_) if writer.is_abandoned() => Ok(None),
+            Err(err) => {
+                writer.fail(&err.to_string());
+                Err(err)
+            }
+            Ok(rows) => Ok(rows),
+        }
+    });
+
+    let name = if stdin { "stdin" } else { path_str };
+    progress.start(&format!("Uploading {}", name), None);
+    let sent = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader { inner: ProgressReader::new(reader, progress.clone()), count: sent.clone() };
+    let body = reqwest::blocking::Body::new(reader);
+    let result = post(schema_str, &ext, body, connection, timeouts);
+    progress.finish();
+
+    // A file that couldn't be rewritten explains a failed request better than the request does.
+    match producer.join() {
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err(UploadError::FileIO(std::io::Error::other("the upload body thread panicked"))),
+        Ok(Ok(rows)) => result.map(|(status, response)| UploadReport { bytes: sent.load(Ordering::Relaxed), rows, status, response }),
+    }
+}
+
+/// Uploads a file in chunks of whole records, recording in the [upload state](upload_state)
+/// how far the server got after each. When an earlier upload of the same unchanged file to the
+/// same schema stopped partway, carries on after the last chunk the server accepted. CSV
+/// chunks each start with the file's header.
+pub fn execute_resumable(
+    schema_str: &str,
+    path_str: &str,
+    options: &UploadOptions,
+    connection: &Connection,
+    timeouts: Timeouts,
+    progress: Arc<dyn Progress>,
+) -> Result<UploadReport, UploadError> {
+    let path = Path::new(path_str);
+    if path_str == STDIN {
+        return Err(UploadError::NotResumable("stdin".to_string()));
+    }
+    if !path.exists() {
+        return Err(UploadError::Common(CommonError::FileNotFound(path_str.to_string())));
+    }
+
+    let ext = match options.format.as_deref() {
+        Some(format) => format.trim_start_matches('.').to_string(),
+        None => path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default(),
+    };
+    let csv = ext.eq_ignore_ascii_case("csv");
+    let lines = ["ndjson", "jsonl"].iter().any(|e| ext.eq_ignore_ascii_case(e));
+    if !(csv && options.csv.is_default() || lines && options.json.flatten.is_none()) {
+        return Err(UploadError::NotResumable(format!(".{} files with these options", ext)));
+    }
+
+    let subscription = connection.default_subscription()
+        .ok_or(UploadError::Config(crate::error::ConfigError::NoDefaultSubscription))?;
+    let (size, modified) = upload_state::file_version(path)?;
+    let now = chrono::Utc::now();
+    let mut upload = PendingUpload {
+        path: std::fs::canonicalize(path)?.to_string_lossy().to_string(),
+        schema: schema_str.to_string(),
+        server: connection.server.to_string(),
+        subscription,
+        size,
+        modified,
+        offset: 0,
+        chunks: 0,
+        started: now,
+        updated: now,
+        error: None,
+    };
+    let state = upload_state::load().map_err(UploadError::Config)?;
+    if let Some(previous) = state.uploads.into_iter().find(|u| u.is_same(&upload)) {
+        match previous.size == size && previous.modified == modified {
+            true => {
+                log::info!("Resuming the upload of {} after {} of {} bytes.", path_str, previous.offset, size);
+                upload = PendingUpload { error: None, updated: now, ..previous };
+            }
+            false => log::warn!("{} changed since its last upload stopped, so it's uploaded from the start.", path_str),
+        }
+    }
+    upload_state::record(&upload).map_err(UploadError::Config)?;
+
+    let mut input = BufReader::new(File::open(path)?);
+    let mut header = Vec::new();
+    if csv {
+        read_records(&mut input, 0, true, &mut header)?;
+        upload.offset = upload.offset.max(header.len() as u64);
+    }
+    input.seek(SeekFrom::Start(upload.offset))?;
+
+    progress.start(&format!("Uploading {}", path_str), Some(size));
+    progress.advance(upload.offset);
+    let mut report = UploadReport::default();
+    let result = loop {
+        let mut chunk = header.clone();
+        let read = read_records(&mut input, options.stream.chunk_size, csv, &mut chunk)?;
+        if read == 0 {
+            break Ok(());
+        }
+
+        let bytes = chunk.len() as u64;
+        match post(schema_str, &ext, chunk, connection, timeouts) {
+            Ok((status, response)) => {
+                report = UploadReport { bytes: report.bytes + bytes, rows: None, status, response };
+                upload.offset += read as u64;
+                upload.chunks += 1;
+                upload.updated = chrono::Utc::now();
+                progress.advance(read as u64);
+                upload_state::record(&upload).map_err(UploadError::Config)?;
+            }
+            Err(err) => break Err(err),
+        }
+    };
+    progress.finish();
+
+    match result {
+        Ok(()) => {
+            upload_state::finish(&upload).map_err(UploadError::Config)?;
+            Ok(report)
+        }
+        Err(err) => {
+            upload.error = Some(err.to_string());
+            upload.updated = chrono::Utc::now();
+            if let Err(save) = upload_state::record(&upload) {
+                log::warn!("Unable to record how far the upload of {} got: {}", path_str, save);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Reads whole records into `chunk` until at least `size` bytes are read, or the input ends.
+/// NDJSON records end at each newline, CSV ones only at newlines outside quoted fields. Returns
+/// the bytes read.
+fn read_records<R: BufRead>(input: &mut R, size: usize, csv: bool, chunk: &mut Vec<u8>) -> std::io::Result<usize> {
+    let mut read = 0;
+    let mut quoted = false;
+    loop {
+        let start = chunk.len();
+        let line = input.read_until(b'\n', chunk)?;
+        if line == 0 {
+            return Ok(read);
+        }
+
+        read += line;
+        // Escaped quotes come in pairs, so only an odd count opens or closes a field.
+        if csv && chunk[start..].iter().filter(|&&b| b == b'"').count() % 2 == 1 {
+            quoted = !quoted;
+        }
+        if !quoted && read >= size {
+            return Ok(read);
+        }
+    }
+}
 
+/// Counts the bytes read through it, for the size of a streamed body.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Rewrites CSV read with `options` as comma delimited CSV with a header row. Returns the
+/// number of records.
+pub fn reshape<R: Read, W: Write>(input: R, options: &CsvOptions, output: W) -> Result<usize, UploadError> {
+    let mut input = BufReader::new(input);
+    let mut line = Vec::new();
+    for _ in 0..options.skip_rows {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter.0)
+        .has_headers(options.has_header)
+        .from_reader(input);
+    let header = reader.headers()?.clone();
+
+    // Source index and uploaded name of each column.
+    let columns: Vec<(usize, String)> = match options.columns.as_ref() {
+        None if options.has_header => header.iter().enumerate().map(|(i, name)| (i, name.to_string())).collect(),
+        None => (0..header.len()).map(|i| (i, format!("Column{}", i + 1))).collect(),
+        Some(mappings) => mappings
+            .iter()
+            .enumerate()
+            .map(|(position, mapping)| {
+                let index = match &mapping.source {
+                    ColumnSource::Position => position,
+                    ColumnSource::Index(index) => index - 1,
+                    ColumnSource::Name(name) if options.has_header => header
+                        .iter()
+                        .position(|h| h == name)
+                        .ok_or_else(|| UploadError::InvalidColumns(format!("no column named \"{}\" in the header", name)))?,
+                    ColumnSource::Name(name) => {
+                        return Err(UploadError::InvalidColumns(format!("\"{}\" names a header column, but the file has no header", name)))
+                    }
+                };
+                if index >= header.len() {
+                    return Err(UploadError::InvalidColumns(format!(
+                        "column {} of \"{}\" is past the {} columns in the file",
+                        index + 1,
+                        mapping.name,
+                        header.len()
+                    )));
+                }
+                Ok((index, mapping.name.to_string()))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(columns.iter().map(|(_, name)| name))?;
+    let mut records = 0;
+    for record in reader.records() {
+        let record = record?;
+        writer.write_record(columns.iter().map(|(index, _)| record.get(*index).unwrap_or("")))?;
+        records += 1;
+    }
+    writer.flush()?;
+    Ok(records)
+}
+
+/// Rewrites JSON records with nested objects expanded into dotted keys, up to `max_depth` keys
+/// deep. Reads an array of records, a single record, or records one after another such as
+/// NDJSON, and writes them back the same way. Returns the number of records.
+pub fn flatten<R: Read, W: Write>(input: R, max_depth: usize, mut output: W) -> Result<usize, UploadError> {
+    let max_depth = max_depth.max(1);
+    let mut input = BufReader::new(input);
+
+    // Look past leading whitespace to tell an array of records from records one after another.
+    let array = loop {
+        let buffer = input.fill_buf()?;
+        match buffer.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(start) => {
+                let array = buffer[start] == b'[';
+                input.consume(start);
+                break array;
+            }
+            None if buffer.is_empty() => break false,
+            None => {
+                let len = buffer.len();
+                input.consume(len);
+            }
+        }
+    };
+
+    let mut deserializer = serde_json::Deserializer::from_reader(input);
+    if array {
+        output.write_all(b"[")?;
+        let records = serde::Deserializer::deserialize_seq(&mut deserializer, FlattenRecords { output: &mut output, max_depth })
+            .and_then(|records| deserializer.end().map(|_| records))
+            .map_err(UploadError::FailedToReadFileContent)?;
+        output.write_all(b"]")?;
+        output.flush()?;
+        return Ok(records);
+    }
+
+    let mut records = 0;
+    for value in deserializer.into_iter::<Value>() {
+        let value = value.map_err(UploadError::FailedToReadFileContent)?;
+        serde_json::to_writer(&mut output, &flatten_record(&value, max_depth)).map_err(UploadError::FailedToReadFileContent)?;
+        output.write_all(b"\n")?;
+        records += 1;
+    }
+    output.flush()?;
+    Ok(records)
+}
+
+/// Writes each record of an array as it's read, so only one is held in memory at a time.
+struct FlattenRecords<W> {
+    output: W,
+    max_depth: usize,
+}
+
+impl<'de, W: Write> Visitor<'de> for FlattenRecords<W> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<usize, A::Error> {
+        let mut records = 0;
+        while let Some(record) = seq.next_element::<Value>()? {
+            if records > 0 {
+                self.output.write_all(b",").map_err(A::Error::custom)?;
+            }
+            serde_json::to_writer(&mut self.output, &flatten_record(&record, self.max_depth)).map_err(A::Error::custom)?;
+            records += 1;
+        }
+        Ok(records)
+    }
+}
+
+fn flatten_record(record: &Value, max_depth: usize) -> Value {
+    let Value::Object(fields) = record else {
+        return record.clone();
+    };
+
+    let mut flat = Map::new();
+    for (key, value) in fields {
+        flatten_field(key.to_string(), value, 1, max_depth, &mut flat);
+    }
+    Value::Object(flat)
+}
+
+fn flatten_field(key: String, value: &Value, depth: usize, max_depth: usize, flat: &mut Map<String, Value>) {
+    match value {
+        Value::Object(fields) if depth < max_depth && !fields.is_empty() => {
+            for (name, value) in fields {
+                flatten_field(format!("{}.{}", key, name), value, depth + 1, max_depth, flat);
+            }
+        }
+        // The schema is columnar, so anything still nested is sent as text.
+        Value::Object(_) => {
+            flat.insert(key, Value::String(value.to_string()));
+        }
+        _ => {
+            flat.insert(key, value.clone());
+        }
+    }
+}
+
+/// Uploads an in-memory file body, such as generated CSV rows. `ext` names the body format the
+/// way a file extension would.
+pub fn execute_bytes(
+    schema_str: &str,
+    ext: &str,
+    body: Vec<u8>,
+    connection: &Connection,
+    timeouts: Timeouts,
+) -> Result<(), UploadError> {
+    post(schema_str, ext, body, connection, timeouts).map(|_| ())
+}
+
+fn post<B: Into<reqwest::blocking::Body>>(
+    schema_str: &str,
+    ext: &str,
+    body: B,
+    connection: &Connection,
+    timeouts: Timeouts,
+) -> Result<(u16, Option<Value>), UploadError> {
     let sub = &connection.default_subscription()
         .ok_or(UploadError::Config(crate::error::ConfigError::NoDefaultConnection))?;
 
-    let client = crate::connect::client_builder()
-        .timeout(timeout)
-        .build()?;
-    let req = client.post(format!(
-        "{}/inflow/{}/{}/{}",
-        &connection.server.trim_end_matches("/"),
-        sub,
-        schema_str,
-        ext,
-    ));
-    let file = File::open(path)?;
-    let _response = connection
-        .authenticate_request(req)
-        .body(file)
-        .header("content-type", "application/oxtet-stream")
+    // The body is sent within a single wait on the client, so a read limit would bound the
+    // whole upload. Only the total limit applies.
+    let timeouts = timeouts.or(connection.timeouts);
+    let client = connection.client_builder(&Timeouts { read: None, ..timeouts }).build()?;
+    let req = client.post(crate::endpoint::url(
+        &connection.server,
+        &crate::endpoint::path(&["inflow", &sub.to_string(), schema_str, ext], &[]),
+    ).map_err(UploadError::Common)?);
+    let response = connection
+        .authenticate_request(req.body(body).header("content-type", "application/oxtet-stream"))
         .send()?
         .error_for_status()?;
-    return Ok(());
+    let status = response.status().as_u16();
+    let text = response.text()?;
+    let response = match text.trim() {
+        "" => None,
+        trimmed => Some(serde_json::from_str(trimmed).unwrap_or(Value::String(text))),
+    };
+    Ok((status, response))
 }
 
 pub fn execute_upload<'a>(
@@ -97,4 +650,97 @@ pub fn execute_upload<'a>(
     })?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reshaped(input: &str, options: &CsvOptions) -> Result<String, UploadError> {
+        let mut output = Vec::new();
+        reshape(input.as_bytes(), options, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn reshape_rewrites_delimiters_and_skips_leading_rows() {
+        let options = CsvOptions { delimiter: "tab".parse().unwrap(), skip_rows: 2, ..Default::default() };
+        let output = reshaped("Exported\nby someone\na\tb\n1\tx,y\n", &options).unwrap();
+        assert_eq!(output, "a,b\n1,\"x,y\"\n");
+    }
+
+    #[test]
+    fn reshape_names_columns_of_files_without_a_header() {
+        let options = CsvOptions { has_header: false, ..Default::default() };
+        assert_eq!(reshaped("1,2\n3,4\n", &options).unwrap(), "Column1,Column2\n1,2\n3,4\n");
+    }
+
+    #[test]
+    fn reshape_picks_columns_by_name_index_and_position() {
+        let columns = ["when", "c=level", "2=message"].iter().map(|c| c.parse().unwrap()).collect();
+        let options = CsvOptions { columns: Some(columns), ..Default::default() };
+        assert_eq!(reshaped("t,m,c,x\n1,hi,warn,0\n", &options).unwrap(), "when,level,message\n1,warn,hi\n");
+    }
+
+    #[test]
+    fn reshape_refuses_columns_the_file_doesnt_have() {
+        let columns = Some(vec!["missing=a".parse().unwrap()]);
+        let options = CsvOptions { columns, ..Default::default() };
+        assert!(matches!(reshaped("t,m\n1,2\n", &options), Err(UploadError::InvalidColumns(_))));
+
+        let columns = Some(vec!["3=a".parse().unwrap()]);
+        let options = CsvOptions { columns, ..Default::default() };
+        assert!(matches!(reshaped("t,m\n1,2\n", &options), Err(UploadError::InvalidColumns(_))));
+        assert!(matches!("0=a".parse::<ColumnMapping>(), Err(UploadError::InvalidColumns(_))));
+    }
+
+    #[test]
+    fn read_records_keeps_quoted_newlines_in_csv_records() {
+        let mut input = "a,\"b\nc\"\nd,e\nf,g\n".as_bytes();
+        let mut chunk = vec![];
+        assert_eq!(read_records(&mut input, 1, true, &mut chunk).unwrap(), 8);
+        assert_eq!(chunk, b"a,\"b\nc\"\n");
+
+        let mut chunk = vec![];
+        assert_eq!(read_records(&mut input, 100, true, &mut chunk).unwrap(), 8);
+        assert_eq!(chunk, b"d,e\nf,g\n");
+        assert_eq!(read_records(&mut input, 100, true, &mut chunk).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_records_splits_ndjson_at_every_newline() {
+        let mut input = "{\"a\":\"\\\"\"}\n{\"b\":1}\n".as_bytes();
+        let mut chunk = vec![];
+        assert_eq!(read_records(&mut input, 1, false, &mut chunk).unwrap(), 11);
+        assert_eq!(chunk, b"{\"a\":\"\\\"\"}\n");
+    }
+
+    fn flattened(input: &str, max_depth: usize) -> (usize, String) {
+        let mut output = Vec::new();
+        let records = flatten(input.as_bytes(), max_depth, &mut output).unwrap();
+        (records, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn flatten_expands_nested_objects_into_dotted_keys() {
+        let (records, output) = flattened(r#"[{"a":{"b":{"c":1}},"d":[1,{"e":2}]}, {"f":{}}]"#, 5);
+        assert_eq!(records, 2);
+        assert_eq!(output, r#"[{"a.b.c":1,"d":[1,{"e":2}]},{"f":"{}"}]"#);
+    }
+
+    #[test]
+    fn flatten_sends_objects_past_the_depth_as_text() {
+        let (_, output) = flattened(r#"{"a":{"b":{"c":1}}}"#, 2);
+        assert_eq!(output, "{\"a.b\":\"{\\\"c\\\":1}\"}\n");
+        let (_, output) = flattened(r#"{"a":{"b":1}}"#, 0);
+        assert_eq!(output, "{\"a\":\"{\\\"b\\\":1}\"}\n");
+    }
+
+    #[test]
+    fn flatten_keeps_records_one_after_another() {
+        let (records, output) = flattened("  {\"a\":{\"b\":1}}\n{\"c\":2}\n", 5);
+        assert_eq!(records, 2);
+        assert_eq!(output, "{\"a.b\":1}\n{\"c\":2}\n");
+        assert_eq!(flattened("", 5), (0, String::new()));
+    }
+}
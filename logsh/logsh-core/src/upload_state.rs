@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{config, error::ConfigError};
+
+/// A resumable upload that hasn't finished: how far through its file the server has accepted,
+/// and why it stopped.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingUpload {
+    /// Absolute path of the file.
+    pub path: String,
+    pub schema: String,
+    pub server: String,
+    pub subscription: uuid::Uuid,
+    /// Size and modification time of the file when the upload started. A file changed since
+    /// can't be resumed, and is uploaded from the start again.
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    /// Bytes of the file the server has accepted, always at the end of a record.
+    pub offset: u64,
+    /// Chunks the server has accepted.
+    pub chunks: usize,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Why the last attempt stopped. None while it's still running.
+    pub error: Option<String>,
+}
+
+impl PendingUpload {
+    pub fn status(&self) -> &'static str {
+        match (self.is_stale(), &self.error) {
+            (true, _) => "stale",
+            (false, Some(_)) => "failed",
+            (false, None) => "pending",
+        }
+    }
+
+    /// Whether the upload is of the same file to the same place, so one can carry on the other.
+    pub fn is_same(&self, other: &PendingUpload) -> bool {
+        self.path == other.path
+            && self.schema == other.schema
+            && self.server == other.server
+            && self.subscription == other.subscription
+    }
+
+    /// Whether the file was deleted or changed since the upload started, so it can't resume.
+    pub fn is_stale(&self) -> bool {
+        match file_version(Path::new(&self.path)) {
+            Ok((size, modified)) => size != self.size || modified != self.modified,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Size and modification time of a file, which tell whether it changed.
+pub fn file_version(path: &Path) -> std::io::Result<(u64, DateTime<Utc>)> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.len(), metadata.modified()?.into()))
+}
+
+/// Every unfinished resumable upload.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UploadState {
+    pub uploads: Vec<PendingUpload>,
+}
+
+/// The upload state lives next to the logsh configuration file.
+pub fn get_state_path() -> Result<PathBuf, ConfigError> {
+    let config = config::get_configuration_path()?;
+    let parent = config.parent().ok_or(ConfigError::InvalidConfigPath(format!(
+        "{} has no parent directory.",
+        config.display()
+    )))?;
+    Ok(parent.join("upload_state.json"))
+}
+
+pub fn load() -> Result<UploadState, ConfigError> {
+    let path = get_state_path()?;
+    if !path.exists() {
+        return Ok(UploadState::default());
+    }
+
+    let state = fs::read_to_string(path).map_err(ConfigError::FailedRead)?;
+    serde_json::from_str(&state).map_err(ConfigError::FailedDeserialize)
+}
+
+/// Saves the state, unless logsh is in read-only mode.
+pub fn save(state: &UploadState) -> Result<(), ConfigError> {
+    if config::read_only() {
+        return Ok(());
+    }
+
+    let path = get_state_path()?;
+    let serialized = serde_json::to_string_pretty(state).map_err(ConfigError::FailedSerialize)?;
+    config::replace(&path, &serialized, false)
+}
+
+/// Records an upload's progress, replacing what was saved for the same upload before.
+pub fn record(upload: &PendingUpload) -> Result<(), ConfigError> {
+    let mut state = load()?;
+    state.uploads.retain(|u| !u.is_same(upload));
+    state.uploads.push(upload.clone());
+    save(&state)
+}
+
+/// Forgets an upload, once it's finished.
+pub fn finish(upload: &PendingUpload) -> Result<(), ConfigError> {
+    let mut state = load()?;
+    state.uploads.retain(|u| !u.is_same(upload));
+    save(&state)
+}
+
+/// Forgets the uploads `remove` picks. Returns how many were forgotten.
+pub fn clear<F: Fn(&PendingUpload) -> bool>(remove: F) -> Result<usize, ConfigError> {
+    let mut state = load()?;
+    let before = state.uploads.len();
+    state.uploads.retain(|u| !remove(u));
+    let removed = before - state.uploads.len();
+    if removed > 0 {
+        save(&state)?;
+    }
+    Ok(removed)
+}
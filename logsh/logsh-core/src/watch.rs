@@ -0,0 +1,115 @@
+use crate::{
+    connect::{Connection, Timeouts},
+    error::QueryError,
+    query::{self, QueryResult},
+};
+
+/// Runs a query over and over for watch modes, keeping the previous response so an unchanged
+/// result is neither downloaded again, when the server tags results with an ETag, nor
+/// rendered again.
+#[derive(Debug, Clone)]
+pub struct Watch {
+    query: String,
+    etag: Option<String>,
+    body: Option<String>,
+}
+
+/// What a poll found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poll {
+    /// The result is the same as the previous poll's.
+    Unchanged,
+    /// The result changed. Holds the positions of the rows that differ from the row in the same
+    /// position last time, which is every row on the first poll or when the columns changed.
+    Changed(Vec<usize>),
+}
+
+impl Watch {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            etag: None,
+            body: None,
+        }
+    }
+
+    /// The latest response body, once a poll has returned one.
+    pub fn body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    /// Runs the query again, sending the previous result's ETag so the server can skip sending
+    /// a result that hasn't changed.
+    pub fn poll(&mut self, connection: &Connection, timeouts: Timeouts) -> Result<Poll, QueryError> {
+        let etag = self.body.as_ref().and(self.etag.as_deref());
+        let Some(response) = connection.query_if_changed(&self.query, timeouts, etag)? else {
+            log::debug!("Server reports the result is unchanged.");
+            return Ok(Poll::Unchanged);
+        };
+
+        self.etag = response.etag;
+        if self.body.as_deref() == Some(response.body.as_str()) {
+            return Ok(Poll::Unchanged);
+        }
+
+        let current = query::result(&response.body)?;
+        let previous = match self.body.as_deref() {
+            Some(body) => Some(query::result(body)?),
+            None => None,
+        };
+        let changed = changed_rows(previous.as_ref(), &current);
+        self.body = Some(response.body);
+        Ok(Poll::Changed(changed))
+    }
+}
+
+/// Positions of the rows in `current` that differ from the row in the same position of
+/// `previous`. Every row when there's no previous result or its columns differ.
+pub fn changed_rows(previous: Option<&QueryResult<'_>>, current: &QueryResult<'_>) -> Vec<usize> {
+    let previous = match previous {
+        Some(previous) if previous.header == current.header => previous,
+        _ => return (0..current.results.len()).collect(),
+    };
+
+    current
+        .results
+        .iter()
+        .enumerate()
+        .filter(|(i, row)| {
+            let Some(before) = previous.results.get(*i) else {
+                return true;
+            };
+            current
+                .header
+                .iter()
+                .any(|h| row.get(h.as_str()).map(|v| v.get()) != before.get(h.as_str()).map(|v| v.get()))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_row_changed_without_a_previous_result() {
+        let current = query::result(r#"{"header":["n"],"results":[{"n":1},{"n":2}]}"#).unwrap();
+        assert_eq!(changed_rows(None, &current), vec![0, 1]);
+    }
+
+    #[test]
+    fn finds_rows_that_changed_position() {
+        let previous = query::result(r#"{"header":["n","tick"],"results":[{"n":1,"tick":"a"},{"n":2,"tick":"b"}]}"#).unwrap();
+        let current = query::result(r#"{"header":["n","tick"],"results":[{"n":1,"tick":"a"},{"n":2,"tick":"c"},{"n":3,"tick":"d"}]}"#).unwrap();
+        assert_eq!(changed_rows(Some(&previous), &current), vec![1, 2]);
+        assert!(changed_rows(Some(&current), &current).is_empty());
+    }
+
+    #[test]
+    fn every_row_changed_when_the_columns_did() {
+        let previous = query::result(r#"{"header":["n"],"results":[{"n":1}]}"#).unwrap();
+        let current = query::result(r#"{"header":["n","tick"],"results":[{"n":1,"tick":"a"}]}"#).unwrap();
+        assert_eq!(changed_rows(Some(&previous), &current), vec![0]);
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    notify::{Condition, NotifyFormat},
+    schedule::{self, ScheduleState, Window},
+};
+use serde::Deserialize;
+
+use crate::{
+    fmt::parse::{OptionalDurationArg, OutputFileArg},
+    OutputMode,
+};
+
+/// Delay before retrying an export whose last window failed.
+const RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Run scheduled exports of query results or whole tables, such as a recurring sync to a data lake. Windows missed while the agent was stopped are exported when it starts again."
+)]
+pub struct AgentCommand {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "agent.toml",
+        help = "Agent file listing the export schedules."
+    )]
+    config: PathBuf,
+
+    #[arg(
+        long,
+        help = "Export the windows that are due, then exit instead of waiting for the next ones. For running from cron."
+    )]
+    once: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Timeout of each window's query. Use \"none\" to disable timeout.",
+        default_value = "5m"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+/// An agent file, for example:
+///
+/// ```toml
+/// [[export]]
+/// name = "errors"
+/// query = "Logs | where level == 'error'"
+/// destination = "s3://lake/logs/errors/{date}.csv"
+/// every = "1d"
+/// start = "2024-03-01"
+///
+/// [[export]]
+/// name = "error-rate"
+/// query = "Logs | where level == 'error' | summarize errors = count() by service"
+/// every = "5m"
+/// metrics = { statsd = "127.0.0.1:8125", otlp = "http://localhost:4318" }
+///
+/// [[export]]
+/// name = "failed-logins"
+/// query = "Logins | where result == 'failure'"
+/// every = "15m"
+/// notify = { slack = ["https://hooks.slack.com/services/..."], when = "rows > 10" }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AgentConfig {
+    #[serde(default, rename = "export")]
+    exports: Vec<ExportConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportConfig {
+    /// Names the export in logs and in the saved state, so renaming it starts over.
+    name: String,
+    query: Option<String>,
+    /// Exports every row of a table, instead of a query.
+    table: Option<String>,
+    /// File path or object storage URL, with placeholders such as `{date}` filled in for each window.
+    destination: Option<String>,
+    /// Where to send the result's numeric columns as gauges, as well as or instead of a file.
+    metrics: Option<MetricsConfig>,
+    /// Webhooks to post each window's result to, when it meets a condition.
+    notify: Option<NotifyConfig>,
+    /// Output format. Inferred from the destination's extension by default.
+    format: Option<String>,
+    #[serde(default = "default_every")]
+    every: String,
+    /// Column of the queried table holding each row's time, which decides the window it's
+    /// exported in.
+    #[serde(default = "default_time_column")]
+    time_column: String,
+    /// How long after a window ends to wait before exporting it, for late arriving rows.
+    #[serde(default = "default_lag")]
+    lag: String,
+    /// First window to export, as a date or RFC 3339 time. Defaults to the latest window, as
+    /// does turning off catch_up.
+    start: Option<String>,
+    /// Export the windows missed while the agent wasn't running. Otherwise only the latest
+    /// window is exported and the missed ones are skipped.
+    #[serde(default = "default_catch_up")]
+    catch_up: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetricsConfig {
+    /// StatsD server, as `host:port`.
+    statsd: Option<String>,
+    /// OpenTelemetry collector's OTLP/HTTP endpoint, such as `http://localhost:4318`.
+    otlp: Option<String>,
+    /// Start of each gauge's name, followed by a dot and the column. Defaults to the export's name.
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NotifyConfig {
+    /// Webhook URLs sent the query, row count, and rows as JSON.
+    #[serde(default)]
+    webhook: Vec<String>,
+    /// Slack incoming webhook URLs.
+    #[serde(default)]
+    slack: Vec<String>,
+    /// Condition on the row count, such as `rows > 10`. Defaults to `rows > 0`.
+    when: Option<String>,
+}
+
+fn default_every() -> String {
+    "1d".to_string()
+}
+
+fn default_time_column() -> String {
+    "timestamp".to_string()
+}
+
+fn default_lag() -> String {
+    "0s".to_string()
+}
+
+fn default_catch_up() -> bool {
+    true
+}
+
+struct Export {
+    name: String,
+    query: String,
+    /// Where the result is written, and in what format.
+    destination: Option<(String, OutputMode)>,
+    metrics: Option<MetricsConfig>,
+    /// Webhooks with their formats, and when to notify them.
+    notify: Option<(Vec<(String, NotifyFormat)>, Condition)>,
+    every: chrono::Duration,
+    lag: chrono::Duration,
+    time_column: String,
+    start: Option<DateTime<Utc>>,
+    catch_up: bool,
+}
+
+pub fn execute_agent<W: Write>(command: AgentCommand, mut write: W) -> Result<(), Error> {
+    log::debug!("Entering agent: {:?}", &command);
+    let text = std::fs::read_to_string(&command.config).map_err(|err| {
+        anyhow!(
+            "Failed to read agent file {}: {}",
+            command.config.display(),
+            err
+        )
+    })?;
+    let agent: AgentConfig = toml::from_str(&text).map_err(|err| {
+        anyhow!(
+            "Failed to parse agent file {}: {}",
+            command.config.display(),
+            err
+        )
+    })?;
+    if agent.exports.is_empty() {
+        return Err(anyhow!(
+            "Agent file {} has no [[export]] entries.",
+            command.config.display()
+        ));
+    }
+    let exports = agent
+        .exports
+        .into_iter()
+        .map(Export::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cfg = config::load()?;
+    let mut connection: config::ConnectionConfig = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let mut state = schedule::load()?;
+
+    loop {
+        let mut failed = 0;
+        let mut next: Option<std::time::Duration> = None;
+        for export in exports.iter() {
+            let wait = match export.run(&command, &cfg, &mut connection, &mut state, &mut write) {
+                Ok(()) => export.until_due(&state),
+                Err(err) => {
+                    failed += 1;
+                    log::error!("Export {} failed: {:#}", export.name, err);
+                    RETRY
+                }
+            };
+            next = Some(next.map_or(wait, |next| next.min(wait)));
+        }
+
+        if command.once {
+            return match failed {
+                0 => Ok(()),
+                n => Err(anyhow!("{} of {} exports failed.", n, exports.len())),
+            };
+        }
+
+        let wait = next.unwrap_or(RETRY);
+        log::info!("Next export in {}.", humantime::format_duration(wait));
+        std::thread::sleep(wait);
+    }
+}
+
+impl Export {
+    fn new(config: ExportConfig) -> Result<Self, Error> {
+        let invalid = |err: String| anyhow!("Invalid export \"{}\": {}", config.name, err);
+        let query = match (config.query.as_ref(), config.table.as_ref()) {
+            (Some(query), None) => logsh_core::snippet::expand(query)?,
+            (None, Some(table)) => table.to_string(),
+            _ => return Err(invalid("set one of query or table.".to_string())),
+        };
+
+        let destination = match (config.destination, config.format.as_ref()) {
+            (Some(destination), format) => {
+                let sink = match format {
+                    Some(format) => format!("{}:{}", destination, format),
+                    None => destination.clone(),
+                };
+                let mode = OutputFileArg::from_str(&sink)
+                    .map_err(|err| invalid(err.to_string()))?
+                    .mode;
+                if matches!(mode, OutputMode::Template) {
+                    return Err(invalid("template output isn't supported.".to_string()));
+                }
+                Some((destination, mode))
+            }
+            (None, Some(_)) => return Err(invalid("format needs a destination.".to_string())),
+            (None, None) => None,
+        };
+        if let Some(m) = config.metrics.as_ref() {
+            if m.statsd.is_none() && m.otlp.is_none() {
+                return Err(invalid("metrics needs statsd or otlp.".to_string()));
+            }
+        }
+        let notify = match config.notify {
+            Some(notify) => {
+                let when = match notify.when.as_deref() {
+                    Some(when) => {
+                        Condition::from_str(when).map_err(|err| invalid(err.to_string()))?
+                    }
+                    None => Condition::default(),
+                };
+                let targets: Vec<_> = notify
+                    .webhook
+                    .into_iter()
+                    .map(|url| (url, NotifyFormat::Webhook))
+                    .chain(
+                        notify
+                            .slack
+                            .into_iter()
+                            .map(|url| (url, NotifyFormat::Slack)),
+                    )
+                    .collect();
+                if targets.is_empty() {
+                    return Err(invalid("notify needs a webhook or slack URL.".to_string()));
+                }
+                Some((targets, when))
+            }
+            None => None,
+        };
+        if destination.is_none() && config.metrics.is_none() && notify.is_none() {
+            return Err(invalid(
+                "set a destination, metrics, notify, or a combination.".to_string(),
+            ));
+        }
+
+        let duration = |value: &str| {
+            humantime::parse_duration(value)
+                .map_err(|err| invalid(format!("\"{}\": {}", value, err)))
+                .and_then(|d| chrono::Duration::from_std(d).map_err(|err| invalid(err.to_string())))
+        };
+        let every = duration(&config.every)?;
+        if every.is_zero() {
+            return Err(invalid("every must not be zero.".to_string()));
+        }
+        let start = config
+            .start
+            .as_deref()
+            .map(|start| parse_time(start).map_err(|err| invalid(format!("start {}", err))))
+            .transpose()?;
+
+        Ok(Self {
+            lag: duration(&config.lag)?,
+            name: config.name,
+            query,
+            destination,
+            metrics: config.metrics,
+            notify,
+            every,
+            time_column: config.time_column,
+            start,
+            catch_up: config.catch_up,
+        })
+    }
+
+    /// Exports each window that's due, oldest first, saving the state after each so a failure or
+    /// restart carries on from the window that didn't finish.
+    fn run<W: Write>(
+        &self,
+        command: &AgentCommand,
+        cfg: &config::Configuration,
+        connection: &mut config::ConnectionConfig,
+        state: &mut ScheduleState,
+        mut write: W,
+    ) -> Result<(), Error> {
+        let now = Utc::now() - self.lag;
+        let windows = match (state.exported.get(&self.name), self.start) {
+            (Some(end), _) if self.catch_up => schedule::due(self.every, *end, now),
+            (None, Some(start)) if self.catch_up => schedule::due(self.every, start, now),
+            (exported, _) => schedule::latest(self.every, now)
+                .filter(|latest| Some(&latest.end) > exported)
+                .into_iter()
+                .collect(),
+        };
+        if windows.len() > 1 {
+            log::info!(
+                "Export {} is catching up on {} windows.",
+                self.name,
+                windows.len()
+            );
+        }
+
+        for window in windows {
+            let destination = self
+                .destination
+                .as_ref()
+                .map(|(destination, mode)| (window.partition(destination), *mode));
+            let rows = self.export(&window, destination.as_ref(), command, cfg, connection)?;
+            let mut sinks: Vec<String> = destination.into_iter().map(|(d, _)| d).collect();
+            if let Some(metrics) = self.metrics.as_ref() {
+                sinks.extend(metrics.statsd.iter().map(|a| format!("StatsD at {}", a)));
+                sinks.extend(metrics.otlp.iter().map(|e| format!("OTLP at {}", e)));
+            }
+            match self.notify.as_ref() {
+                Some((targets, when)) if when.matches(rows) => {
+                    sinks.push(format!("{} notification webhooks", targets.len()))
+                }
+                Some((_, when)) if sinks.is_empty() => {
+                    sinks.push(format!("no notification webhooks, as {} is false", when))
+                }
+                _ => {}
+            }
+            writeln!(
+                write,
+                "Exported {} rows of {} from {} to {} to {}.",
+                rows,
+                self.name.bright_blue(),
+                window.start.to_rfc3339(),
+                window.end.to_rfc3339(),
+                sinks.join(" and ")
+            )?;
+            state.exported.insert(self.name.clone(), window.end);
+            schedule::save(state)?;
+        }
+        Ok(())
+    }
+
+    fn export(
+        &self,
+        window: &Window,
+        destination: Option<&(String, OutputMode)>,
+        command: &AgentCommand,
+        cfg: &config::Configuration,
+        connection: &mut config::ConnectionConfig,
+    ) -> Result<usize, Error> {
+        let query = window.apply(&self.query, &self.time_column);
+        log::debug!("Export {} query:\n{}", self.name, query);
+        let text = crate::connect::retry_unauthorized(connection, |c| {
+            c.connection.query_raw(&query, command.timeout.into())
+        })?;
+        let text = logsh_core::redact::redact_columns(&text, &cfg.redact_columns)
+            .map_err(|err| anyhow!("Failed to redact columns: {}", err))?;
+        let result = logsh_core::query::result(&text)?;
+
+        if let Some(metrics) = self.metrics.as_ref() {
+            let samples = logsh_core::metrics::samples(&result);
+            let prefix = metrics.prefix.as_deref().unwrap_or(&self.name);
+            if let Some(address) = metrics.statsd.as_deref() {
+                logsh_core::metrics::send_statsd(address, prefix, &samples)?;
+            }
+            if let Some(endpoint) = metrics.otlp.as_deref() {
+                logsh_core::metrics::send_otlp(endpoint, prefix, &samples, window.end)?;
+            }
+        }
+
+        if let Some((targets, when)) = self.notify.as_ref() {
+            if when.matches(result.results.len()) {
+                for (url, format) in targets {
+                    logsh_core::notify::notify(
+                        url,
+                        *format,
+                        &query,
+                        when,
+                        &result,
+                        command.timeout.into(),
+                    )?;
+                    log::info!("Sent {:?} notification for export {}.", format, self.name);
+                }
+            }
+        }
+
+        let Some((destination, mode)) = destination else {
+            return Ok(result.results.len());
+        };
+        let contents = match mode {
+            OutputMode::Sqlite => {
+                let temp = tempfile::Builder::new().suffix(".db").tempfile()?;
+                logsh_core::sqlite::write_sqlite(&result, temp.path(), "results")?;
+                std::fs::read(temp.path())?
+            }
+            mode => {
+                let mut contents = Vec::new();
+                crate::fmt::render_result(&result, None, *mode, None, &mut contents)?;
+                contents
+            }
+        };
+
+        write_destination(destination, contents)
+            .map_err(|err| anyhow!("Failed to write {}: {}", destination, err))?;
+        Ok(result.results.len())
+    }
+
+    /// Time until the next window ends and its lag has passed.
+    fn until_due(&self, state: &ScheduleState) -> std::time::Duration {
+        let now = Utc::now();
+        let end = match state.exported.get(&self.name) {
+            Some(end) => *end + self.every,
+            None => {
+                schedule::latest(self.every, now - self.lag).map_or(now, |w| w.end + self.every)
+            }
+        };
+        (end + self.lag - now).to_std().unwrap_or_default()
+    }
+}
+
+/// Writes to object storage for `s3://` and `az://` URLs, otherwise to a file, creating the
+/// directories of date partitions as needed.
+fn write_destination(destination: &str, contents: Vec<u8>) -> Result<(), Error> {
+    if logsh_core::cloud::is_cloud_url(destination) {
+        return Ok(logsh_core::cloud::put(destination, contents)?);
+    }
+
+    let path = Path::new(destination);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&contents)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a time written as a date, meaning midnight UTC, or as an RFC 3339 time.
+pub(crate) fn parse_time(time: &str) -> Result<DateTime<Utc>, Error> {
+    if let Ok(date) = NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        return Ok(DateTime::from_naive_utc_and_offset(midnight, Utc));
+    }
+    DateTime::parse_from_rfc3339(time)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|err| anyhow!("\"{}\" is not a date or RFC 3339 time: {}", time, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(text: &str) -> Result<Export, Error> {
+        let config: AgentConfig = toml::from_str(text)?;
+        Export::new(config.exports.into_iter().next().expect("an export"))
+    }
+
+    #[test]
+    fn notify_is_a_sink() {
+        let export = export(
+            "[[export]]\nname = \"logins\"\ntable = \"Logins\"\nnotify = { slack = [\"https://hooks.example.com/a\"], when = \"rows > 10\" }\n",
+        )
+        .expect("valid export");
+        let (targets, when) = export.notify.expect("notify");
+        assert_eq!(
+            targets,
+            vec![(
+                "https://hooks.example.com/a".to_string(),
+                NotifyFormat::Slack
+            )]
+        );
+        assert!(!when.matches(10));
+        assert!(when.matches(11));
+    }
+
+    #[test]
+    fn rejects_notify_without_urls() {
+        let err = export("[[export]]\nname = \"logins\"\ntable = \"Logins\"\nnotify = {}\n")
+            .err()
+            .expect("invalid export");
+        assert!(err.to_string().contains("notify needs"));
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Error};
+use clap::{Subcommand, ValueEnum};
+use logsh_core::{
+    audit::{list_audit_events, AuditEventModel},
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+};
+use serde_json::Value;
+use std::{collections::HashMap, io::Write};
+
+use crate::OutputMode;
+
+#[derive(Subcommand)]
+#[clap(about = "Review the server audit log.")]
+pub enum AuditCommand {
+    #[clap(about = "List audit events", visible_alias = "ls")]
+    List {
+        #[arg(
+            long,
+            help = "Only list events newer than this duration ago.",
+            default_value = "24h"
+        )]
+        since: humantime::Duration,
+        #[arg(short, long, help = "Only list events of this category.")]
+        category: Option<AuditCategory>,
+        #[arg(short, long, help = "Maximum number of events to list.")]
+        limit: Option<u32>,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum AuditCategory {
+    Login,
+    Query,
+    Admin,
+}
+
+pub fn execute_audit<W: Write>(command: AuditCommand, write: W) -> Result<(), Error> {
+    let conn_handler = LogshClientHandler::new();
+    match command {
+        AuditCommand::List {
+            since,
+            category,
+            limit,
+            output,
+        } => {
+            let since = chrono::Duration::from_std(since.into())
+                .ok()
+                .and_then(|since| chrono::Utc::now().checked_sub_signed(since))
+                .ok_or_else(|| anyhow!("Invalid --since duration: {}", since))?;
+            let category = category.and_then(|c| c.to_possible_value());
+            let mut events = list_audit_events(
+                &conn_handler,
+                since,
+                category.as_ref().map(|c| c.get_name()),
+                limit,
+            )?;
+            events.sort_by_key(|e| e.timestamp);
+            let mode = output.unwrap_or_else(OutputMode::configured);
+            crate::fmt::render(write, mode, &events, |events| event_rows(events, mode))
+        }
+    }
+}
+
+fn event_rows(events: &[AuditEventModel], mode: OutputMode) -> QueryResultFmt {
+    let human = matches!(mode, OutputMode::Table | OutputMode::Markdown);
+    let results = events
+        .iter()
+        .map(|e| {
+            let timestamp = if human {
+                e.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+            } else {
+                e.timestamp.to_rfc3339()
+            };
+            let user = e
+                .username
+                .clone()
+                .or_else(|| e.user_id.map(|id| id.to_string()));
+            let details = match &e.details {
+                Value::Null => Value::Null,
+                Value::String(s) => Value::String(s.to_string()),
+                other => Value::String(other.to_string()),
+            };
+            HashMap::from([
+                ("Timestamp".to_string(), Value::String(timestamp)),
+                (
+                    "Category".to_string(),
+                    Value::String(e.category.to_string()),
+                ),
+                ("Action".to_string(), Value::String(e.action.to_string())),
+                ("User".to_string(), user.map_or(Value::Null, Value::String)),
+                (
+                    "Target".to_string(),
+                    e.target.clone().map_or(Value::Null, Value::String),
+                ),
+                (
+                    "Source".to_string(),
+                    e.source_address.clone().map_or(Value::Null, Value::String),
+                ),
+                ("Details".to_string(), details),
+            ])
+        })
+        .collect();
+
+    QueryResultFmt {
+        header: [
+            "Timestamp",
+            "Category",
+            "Action",
+            "User",
+            "Target",
+            "Source",
+            "Details",
+        ]
+        .map(String::from)
+        .to_vec(),
+        results,
+    }
+}
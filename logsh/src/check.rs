@@ -0,0 +1,193 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Error};
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    notify::Condition,
+};
+
+use crate::fmt::parse::OptionalDurationArg;
+
+/// Exit statuses understood by Nagios and compatible monitoring systems.
+const OK: i32 = 0;
+const WARNING: i32 = 1;
+const CRITICAL: i32 = 2;
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Run a query as a health check, printing one status line and exiting 0 (OK), 1 (WARNING) or 2 (CRITICAL)."
+)]
+pub struct CheckCommand {
+    #[arg(short, long, help = "Query to run.")]
+    query: Option<String>,
+
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "NAME",
+        conflicts_with = "query",
+        add = crate::complete::saved_queries(),
+        help = "Run a saved query."
+    )]
+    saved: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "expect_rows",
+        help = "Pass when the query returns no rows, such as a query for errors. This is the default."
+    )]
+    expect_empty: bool,
+
+    #[arg(long, help = "Pass when the query returns at least one row.")]
+    expect_rows: bool,
+
+    #[arg(
+        long,
+        value_name = "CONDITION",
+        conflicts_with_all = ["expect_empty", "expect_rows"],
+        help = "Pass when the row count meets this condition, written as \"count <op> <n>\" with op one of >, >=, <, <=, ==, !=."
+    )]
+    assert: Option<Condition>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help = "Attempts that must fail in a row before the check is critical. Failed attempts are retried after --interval, and a check that passes after failing is a warning."
+    )]
+    max_failures: u32,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "60s",
+        help = "Delay between attempts."
+    )]
+    interval: humantime::Duration,
+
+    #[arg(
+        short,
+        long,
+        help = "Timeout of each attempt. Use \"none\" to disable timeout.",
+        default_value = "60s"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+impl CheckCommand {
+    fn condition(&self) -> Condition {
+        match (self.assert, self.expect_rows) {
+            (Some(condition), _) => condition,
+            (None, true) => Condition::has_rows(),
+            (None, false) => Condition::no_rows(),
+        }
+    }
+}
+
+/// The outcome of one attempt: the row count, or why the query failed.
+type Attempt = Result<usize, Error>;
+
+pub fn execute_check<W: Write>(command: CheckCommand, mut write: W) -> Result<(), Error> {
+    // Anything that stops the check from running is critical too, rather than the usual exit
+    // status 1, which monitoring would read as a warning.
+    let (query, mut connection) = match prepare(&command) {
+        Ok(prepared) => prepared,
+        Err(err) => {
+            writeln!(
+                write,
+                "CRITICAL - {}",
+                logsh_core::redact::redact(&err.to_string()).replace('\n', " ")
+            )?;
+            write.flush()?;
+            std::process::exit(CRITICAL);
+        }
+    };
+
+    let condition = command.condition();
+    let max_failures = command.max_failures.max(1);
+    let mut failures = 0;
+    let last = loop {
+        let attempt: Attempt = crate::connect::retry_unauthorized(&mut connection, |c| {
+            c.connection.query_raw(&query, command.timeout.into())
+        })
+        .map_err(Error::from)
+        .and_then(|text| Ok(logsh_core::query::result(&text)?.results.len()));
+
+        match &attempt {
+            Ok(rows) if condition.matches(*rows) => break attempt,
+            Ok(rows) => log::info!("Check failed: {} rows, expected {}.", rows, condition),
+            Err(err) => log::info!("Check failed: {}", err),
+        }
+        failures += 1;
+        if failures >= max_failures {
+            break attempt;
+        }
+        std::thread::sleep(command.interval.into());
+    };
+
+    let (status, line) = summary(&last, &condition, failures, max_failures);
+    writeln!(write, "{}", line)?;
+    write.flush()?;
+    std::process::exit(status);
+}
+
+/// Reads the query and the connection to run it on.
+fn prepare(command: &CheckCommand) -> Result<(String, config::ConnectionConfig), Error> {
+    let query = if let Some(q) = command.query.as_ref() {
+        q.to_string()
+    } else if let Some(name) = command.saved.as_ref() {
+        logsh_core::saved_query::load()?
+            .get(name)?
+            .query
+            .to_string()
+    } else {
+        return Err(anyhow!("A check needs --query or --saved."));
+    };
+    let query = logsh_core::snippet::expand(&query)?;
+    logsh_core::kusto::validate(&query)?;
+
+    let connection = config::load()?
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    Ok((query, connection))
+}
+
+/// The exit status and Nagios style status line for the final attempt, with the row count as
+/// performance data.
+fn summary(
+    last: &Attempt,
+    condition: &Condition,
+    failures: u32,
+    max_failures: u32,
+) -> (i32, String) {
+    match last {
+        Ok(rows) if condition.matches(*rows) && failures == 0 => (
+            OK,
+            format!("OK - {} rows, expected {} | rows={}", rows, condition, rows),
+        ),
+        Ok(rows) if condition.matches(*rows) => (
+            WARNING,
+            format!(
+                "WARNING - {} rows, expected {}, passed after {} failed attempts | rows={}",
+                rows, condition, failures, rows
+            ),
+        ),
+        Ok(rows) => (
+            CRITICAL,
+            format!(
+                "CRITICAL - {} rows, expected {} ({} of {} attempts failed) | rows={}",
+                rows, condition, failures, max_failures, rows
+            ),
+        ),
+        Err(err) => (
+            CRITICAL,
+            format!(
+                "CRITICAL - query failed: {} ({} of {} attempts failed)",
+                logsh_core::redact::redact(&err.to_string()).replace('\n', " "),
+                failures,
+                max_failures
+            ),
+        ),
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, Error};
+
+/// Set on the copy of logsh started to serve the clipboard on Linux.
+#[cfg(target_os = "linux")]
+const HELPER_ENV: &str = "LOGSH_CLIPBOARD_HELPER";
+
+/// Puts text on the system clipboard.
+///
+/// X11 and Wayland clipboards are served by the process that set them, and empty when it exits.
+/// On Linux, a copy of logsh is left running in the background to serve the text until
+/// something else is copied.
+pub fn copy(text: &str) -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::{
+            io::Write,
+            os::unix::process::CommandExt,
+            process::{Command, Stdio},
+        };
+
+        // Reports a missing display here, as the helper can't.
+        arboard::Clipboard::new().map_err(|err| anyhow!("{}", err))?;
+        let mut helper = Command::new(std::env::current_exe()?)
+            .env(HELPER_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Its own process group, so closing the terminal doesn't take the clipboard with it.
+            .process_group(0)
+            .spawn()
+            .map_err(|err| anyhow!("Failed to start the clipboard helper: {}", err))?;
+        let mut stdin = helper.stdin.take().expect("the helper's stdin is piped");
+        stdin.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|err| anyhow!("{}", err))
+}
+
+/// When started as the clipboard helper, serves the text piped to it until something else is
+/// copied, then exits. Otherwise returns straight away.
+pub fn serve() {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os(HELPER_ENV).is_some() {
+        use arboard::SetExtLinux;
+        use std::io::Read;
+
+        let mut text = String::new();
+        if std::io::stdin().read_to_string(&mut text).is_err() {
+            std::process::exit(1);
+        }
+        let served =
+            arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set().wait().text(text));
+        std::process::exit(i32::from(served.is_err()));
+    }
+}
@@ -0,0 +1,82 @@
+use clap::builder::StyledStr;
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use logsh_core::{config, logship_client::LogshClientHandler, metadata, saved_query};
+
+/// Shell setup printed by `logsh --help`. The shell calls logsh back with `COMPLETE` set for
+/// each completion, so names are read from the configuration and caches as they are then.
+pub const SETUP_HELP: &str = "Shell completion, including connection, subscription, schema and saved query names:
+  bash:       source <(COMPLETE=bash logsh)
+  zsh:        source <(COMPLETE=zsh logsh)
+  fish:       COMPLETE=fish logsh | source
+  powershell: $env:COMPLETE = \"powershell\"; logsh | Out-String | Invoke-Expression; Remove-Item Env:\\COMPLETE";
+
+// Completion runs without a terminal to report errors on, so anything that can't be read
+// completes nothing.
+
+/// Names of the configured connections.
+pub fn connections() -> ArgValueCandidates {
+    ArgValueCandidates::new(|| {
+        let Ok(cfg) = config::load() else {
+            return vec![];
+        };
+        let mut connections: Vec<_> = cfg.connections.into_iter().collect();
+        connections.sort_by(|a, b| a.0.cmp(&b.0));
+        connections
+            .into_iter()
+            .map(|(name, c)| candidate(name, c.server))
+            .collect()
+    })
+}
+
+/// Names of the subscriptions in the metadata cache. Refreshing metadata fills it in.
+pub fn subscriptions() -> ArgValueCandidates {
+    ArgValueCandidates::new(|| {
+        metadata::cached_subscriptions()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| candidate(s.account_name, s.account_id.to_string()))
+            .collect()
+    })
+}
+
+/// Schemas of the default subscription in the metadata cache.
+pub fn schemas() -> ArgValueCandidates {
+    ArgValueCandidates::new(|| {
+        let Ok(Some(metadata)) = metadata::cached(&LogshClientHandler::new()) else {
+            return vec![];
+        };
+        metadata
+            .schemas()
+            .into_iter()
+            .map(|schema| {
+                let tables = metadata
+                    .tables
+                    .iter()
+                    .filter(|t| t.schema == schema)
+                    .count();
+                candidate(schema.to_string(), format!("{} tables", tables))
+            })
+            .collect()
+    })
+}
+
+/// Names of the saved queries.
+pub fn saved_queries() -> ArgValueCandidates {
+    ArgValueCandidates::new(|| {
+        let Ok(saved) = saved_query::load() else {
+            return vec![];
+        };
+        saved
+            .queries
+            .into_iter()
+            .map(|(name, q)| match q.description {
+                Some(description) => candidate(name, description),
+                None => CompletionCandidate::new(name),
+            })
+            .collect()
+    })
+}
+
+fn candidate(value: String, help: String) -> CompletionCandidate {
+    CompletionCandidate::new(value).help(Some(StyledStr::from(help)))
+}
@@ -1,9 +1,16 @@
 use anyhow::anyhow;
 use clap::{Subcommand, ValueEnum};
 use colored::Colorize;
-use logsh_core::config;
+use logsh_core::{
+    config,
+    connect::{Group, Resolve},
+    query::QueryResultFmt,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
 
-use crate::{connect, OutputMode};
+use crate::{connect, fmt::parse::OptionalDurationArg, OutputMode};
 
 #[derive(Subcommand)]
 #[clap(visible_alias = "cfg", about = "Configure the logsh CLI.")]
@@ -19,6 +26,39 @@ pub enum ConfigCommand {
     },
     #[clap(subcommand)]
     Connection(ConfigConnectionCommand),
+    #[clap(about = "List the output formats -o and the default_output setting take.")]
+    Formats {
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+}
+
+/// An output format, as `logsh config formats` lists it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Format {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    extensions: Vec<&'static str>,
+    binary: bool,
+    description: &'static str,
+}
+
+/// Where a password is read from instead of a prompt, so scripts don't pass it as an argument.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct PasswordSource {
+    #[arg(
+        long,
+        conflicts_with = "password_file",
+        help = "Read the password, or HMAC secret, from stdin instead of prompting for it, such as from a secret store in a script."
+    )]
+    pub password_stdin: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read the password, or HMAC secret, from a file, such as a mounted secret or /dev/fd/3 for an open descriptor."
+    )]
+    pub password_file: Option<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -32,8 +72,15 @@ pub enum AddConnectionCommand {
         server: Option<String>,
         #[arg(short, long, help = "Username.")]
         username: Option<String>,
-        #[arg(short, long, help = "Password.")]
+        #[arg(
+            short,
+            long,
+            conflicts_with_all = ["password_stdin", "password_file"],
+            help = "Password. Visible to other users in the process list, prefer --password-stdin or --password-file."
+        )]
         password: Option<String>,
+        #[command(flatten)]
+        source: PasswordSource,
         #[arg(help = "Set the new connection as default.", default_value = "true")]
         default: Option<bool>,
     },
@@ -51,6 +98,80 @@ pub enum AddConnectionCommand {
         default: Option<bool>,
         #[arg(long, help = "Specify an OAuth flow.", default_value = "device")]
         flow: OAuthFlow,
+        #[arg(
+            long,
+            requires = "client_id",
+            help = "Log in through a known identity provider instead of the server's OAuth configuration."
+        )]
+        idp: Option<IdentityProvider>,
+        #[arg(
+            long,
+            requires = "idp",
+            help = "Identity provider tenant: the Azure directory, or the Okta domain."
+        )]
+        tenant: Option<String>,
+        #[arg(
+            long,
+            help = "Client id of the application registered with the identity provider."
+        )]
+        client_id: Option<String>,
+        #[arg(
+            long = "scope",
+            requires = "idp",
+            help = "OAuth scope to request, replacing the provider's defaults. May be repeated."
+        )]
+        scopes: Vec<String>,
+    },
+    #[clap(
+        name = "hmac",
+        about = "Add a connection that signs each request with a shared secret"
+    )]
+    Hmac {
+        #[arg(help = "Connection name.")]
+        name: String,
+        #[arg(help = "Server Endpoint.")]
+        server: Option<String>,
+        #[arg(short, long, help = "Key id the gateway knows the secret by.")]
+        key_id: Option<String>,
+        #[arg(
+            short,
+            long,
+            help = "Shared secret. Prompted for when not given, which keeps it out of shell history."
+        )]
+        secret: Option<String>,
+        #[arg(
+            long,
+            help = "Set the new connection as default.",
+            default_value = "true"
+        )]
+        default: Option<bool>,
+    },
+    #[clap(
+        name = "negotiate",
+        visible_alias = "kerberos",
+        about = "Add a Kerberos single sign-on connection, using the current domain login"
+    )]
+    Negotiate {
+        #[arg(help = "Connection name.")]
+        name: String,
+        #[arg(help = "Server Endpoint.")]
+        server: Option<String>,
+        #[arg(
+            long,
+            help = "Server service principal. Defaults to HTTP@host, or HTTP/host on Windows."
+        )]
+        spn: Option<String>,
+        #[arg(
+            long,
+            help = "Client principal to use when the credential cache holds several."
+        )]
+        principal: Option<String>,
+        #[arg(
+            long,
+            help = "Set the new connection as default.",
+            default_value = "true"
+        )]
+        default: Option<bool>,
     },
 }
 
@@ -61,6 +182,33 @@ pub enum OAuthFlow {
     // Browser,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum IdentityProvider {
+    Azure,
+    Okta,
+    Google,
+}
+
+impl From<IdentityProvider> for logsh_core::auth::oauth::IdentityProvider {
+    fn from(value: IdentityProvider) -> Self {
+        match value {
+            IdentityProvider::Azure => Self::Azure,
+            IdentityProvider::Okta => Self::Okta,
+            IdentityProvider::Google => Self::Google,
+        }
+    }
+}
+
+impl From<logsh_core::auth::oauth::IdentityProvider> for IdentityProvider {
+    fn from(value: logsh_core::auth::oauth::IdentityProvider) -> Self {
+        match value {
+            logsh_core::auth::oauth::IdentityProvider::Azure => Self::Azure,
+            logsh_core::auth::oauth::IdentityProvider::Okta => Self::Okta,
+            logsh_core::auth::oauth::IdentityProvider::Google => Self::Google,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 #[clap(visible_aliases = ["c", "conn"], about = "Configure logsh connections.")]
 pub enum ConfigConnectionCommand {
@@ -68,24 +216,151 @@ pub enum ConfigConnectionCommand {
     Add(AddConnectionCommand),
     #[clap(about = "Authenticate an existing connection")]
     Login {
-        #[arg(help = "Connection name.")]
+        #[arg(add = crate::complete::connections(), help = "Connection name.")]
         name: Option<String>,
+        #[arg(
+            long,
+            conflicts_with_all = ["name", "password_stdin", "password_file"],
+            help = "Log in to every connection, renewing tokens where possible and only prompting where needed, then print a summary."
+        )]
+        all: bool,
+        #[arg(
+            short,
+            long,
+            requires = "all",
+            help = "Output format of the --all summary"
+        )]
+        output: Option<OutputMode>,
+        #[arg(
+            short,
+            long,
+            value_name = "KEY=VALUE",
+            requires = "all",
+            help = "Only log in to connections in this group, such as env=prod. Repeat to require several."
+        )]
+        group: Vec<Group>,
+        #[command(flatten)]
+        source: PasswordSource,
     },
     #[clap(visible_alias = "ls", about = "List connections")]
     List {
         #[arg(short, long, help = "Output result format")]
         output: Option<OutputMode>,
+        #[arg(
+            long,
+            help = "Ping each server's health endpoint and add Latency and Status columns."
+        )]
+        ping: bool,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            default_value = "5s",
+            requires = "ping",
+            help = "How long to wait for each server to answer a ping."
+        )]
+        ping_timeout: humantime::Duration,
+        #[arg(
+            short,
+            long,
+            value_name = "KEY=VALUE",
+            help = "Only list connections in this group, such as env=prod. Repeat to require several."
+        )]
+        group: Vec<Group>,
+    },
+    #[clap(
+        about = "Ping each connection's server and show whether it's up, along with its login. The same as \"ls --ping\"."
+    )]
+    Status {
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            default_value = "5s",
+            help = "How long to wait for each server to answer."
+        )]
+        timeout: humantime::Duration,
+        #[arg(
+            short,
+            long,
+            value_name = "KEY=VALUE",
+            help = "Only check connections in this group, such as env=prod. Repeat to require several."
+        )]
+        group: Vec<Group>,
     },
     #[clap(visible_alias = "rm", about = "Remove connections")]
     Remove {
-        #[arg(help = "Connection name.")]
+        #[arg(add = crate::complete::connections(), help = "Connection name.")]
         name: String,
     },
     #[clap(visible_alias = "d", about = "Set the default logsh connection")]
     Default {
-        #[arg(help = "Connection name.")]
+        #[arg(add = crate::complete::connections(), help = "Connection name.")]
         name: String,
     },
+    #[clap(
+        about = "Show or set a connection's request timeouts. Commands may override them, and \"none\" clears a limit."
+    )]
+    Timeouts {
+        #[arg(
+            add = crate::complete::connections(),
+            help = "Connection name. Defaults to the default connection."
+        )]
+        name: Option<String>,
+        #[arg(long, help = "Time allowed to connect to the server.")]
+        connect: Option<OptionalDurationArg>,
+        #[arg(
+            long,
+            help = "Time a response may stall, waiting for it to start or between reads of the body."
+        )]
+        read: Option<OptionalDurationArg>,
+        #[arg(long, help = "Time allowed for a whole request.")]
+        total: Option<OptionalDurationArg>,
+    },
+    #[clap(
+        about = "Show or set a connection's DNS overrides, which send requests for HOST:PORT to ADDR."
+    )]
+    Resolve {
+        #[arg(
+            value_name = "HOST:PORT:ADDR",
+            help = "Overrides to add. Each replaces any override for the same host and port."
+        )]
+        entries: Vec<Resolve>,
+        #[arg(
+            short,
+            long,
+            add = crate::complete::connections(),
+            help = "Connection name. Defaults to the default connection."
+        )]
+        name: Option<String>,
+        #[arg(long, help = "Remove the existing overrides first.")]
+        clear: bool,
+    },
+    #[clap(
+        about = "Show or set the groups a connection is tagged with, such as env=prod, which --group picks connections by."
+    )]
+    Group {
+        #[arg(
+            value_name = "KEY=VALUE",
+            help = "Groups to add. Each replaces the connection's value for the same key."
+        )]
+        entries: Vec<Group>,
+        #[arg(
+            short,
+            long,
+            add = crate::complete::connections(),
+            help = "Connection name. Defaults to the default connection."
+        )]
+        name: Option<String>,
+        #[arg(
+            long,
+            value_name = "KEY",
+            help = "Take the connection out of the group with this key."
+        )]
+        remove: Vec<String>,
+        #[arg(long, help = "Take the connection out of every group first.")]
+        clear: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -98,7 +373,7 @@ pub enum ConfigSubscriptionCommand {
     },
     #[clap(visible_alias = "d", about = "Set the default user subscription.")]
     Default {
-        #[arg(help = "Subscription name.")]
+        #[arg(add = crate::complete::subscriptions(), help = "Subscription name.")]
         name: String,
     },
 }
@@ -150,5 +425,52 @@ pub(crate) fn execute_config(command: ConfigCommand) -> Result<(), anyhow::Error
         }
 
         ConfigCommand::Connection(command) => connect::execute_connect(command),
+
+        ConfigCommand::Formats { output } => {
+            let formats: Vec<Format> = OutputMode::available()
+                .into_iter()
+                .map(|mode| Format {
+                    name: mode.name(),
+                    aliases: mode.aliases(),
+                    extensions: match mode {
+                        OutputMode::Table => vec!["txt"],
+                        OutputMode::Markdown => vec!["md", "markdown"],
+                        OutputMode::Sqlite => vec!["db", "sqlite", "sqlite3"],
+                        mode => logsh_core::render::find(mode.name())
+                            .map_or(vec![], |r| r.extensions().to_vec()),
+                    },
+                    binary: mode.is_binary(),
+                    description: mode.description(),
+                })
+                .collect();
+
+            crate::fmt::render(
+                std::io::stdout(),
+                output.unwrap_or_else(OutputMode::configured),
+                &formats,
+                |formats| {
+                    let results = formats
+                        .iter()
+                        .map(|f| {
+                            HashMap::from([
+                                ("Name".to_string(), Value::from(f.name)),
+                                ("Aliases".to_string(), Value::from(f.aliases.join(", "))),
+                                (
+                                    "Extensions".to_string(),
+                                    Value::from(f.extensions.join(", ")),
+                                ),
+                                ("Description".to_string(), Value::from(f.description)),
+                            ])
+                        })
+                        .collect();
+                    QueryResultFmt {
+                        header: ["Name", "Aliases", "Extensions", "Description"]
+                            .map(String::from)
+                            .to_vec(),
+                        results,
+                    }
+                },
+            )
+        }
     }
 }
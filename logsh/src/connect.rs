@@ -1,22 +1,23 @@
 use anyhow::{anyhow, Error};
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use logsh_core::{
+    auth::AuthRequest,
     config,
-    connect::Connection,
+    connect::{Connection, Group},
     error::{AuthError, BasicAuthError, ConnectError},
     query::QueryResultFmt,
 };
-use std::{collections::HashMap, io::Write};
-use term_table::{
-    row::Row,
-    table_cell::{Alignment, TableCell},
-    Table, TableStyle,
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{IsTerminal, Read, Write},
+    sync::Mutex,
 };
 
 use crate::{
-    config::{AddConnectionCommand, ConfigConnectionCommand, OAuthFlow},
-    query::markdown_style,
+    config::{AddConnectionCommand, ConfigConnectionCommand, OAuthFlow, PasswordSource},
     OutputMode,
 };
 
@@ -29,8 +30,12 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
             default,
             username,
             password,
+            source,
         }) => {
             log::trace!("Entering {}.", "add user connection".bright_black().bold());
+            if password.is_some() {
+                log::warn!("Passing --password leaves it in the process list and shell history. Use --password-stdin or --password-file instead.");
+            }
             let default = default.unwrap_or(true);
             let server = server
                 .or_else(|| cfg.connections.get(&name).map(|s| s.server.to_owned()))
@@ -40,17 +45,12 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
 
             let username = match username {
                 Some(username) => username,
-                None => {
-                    println!(
-                        "{} {}{}",
-                        "Please enter your logship".cyan(),
-                        "username".cyan().bold(),
-                        ":".cyan(),
-                    );
-                    let mut username = String::new();
-                    let _ = std::io::stdin().read_line(&mut username)?;
-                    username.trim().to_string()
+                None if source.password_stdin => {
+                    return Err(anyhow!(
+                        "--password-stdin needs --username, as stdin holds the password."
+                    ))
                 }
+                None => crate::prompt::text("Logship username")?,
             };
 
             log::debug!(
@@ -58,28 +58,32 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
                 username.clone().yellow()
             );
 
-            let connection = Connection::new(&server);
+            let mut connection = Connection::new(&server);
+            connection.default_subscription = previous_subscription(&cfg, &name, &server);
+            connection.groups = previous_groups(&cfg, &name);
             let auth = Some(logsh_core::auth::AuthRequest::Jwt {
                 username: username.clone(),
                 password: || {
                     if let Some(password) = password {
                         return Result::<String, ConnectError>::Ok(password);
                     }
+                    if let Some(password) = read_password(&source) {
+                        return password;
+                    }
 
-                    rpassword::prompt_password(format!(
-                        "{} {}{}{} ",
-                        "Please enter".cyan(),
-                        username.bright_blue().bold(),
-                        "'s password".cyan().bold(),
-                        ":".cyan(),
-                    ))
-                    .map_err(BasicAuthError::IOError)
-                    .map_err(AuthError::BasicAuth)
-                    .map_err(ConnectError::Auth)
+                    crate::prompt::password(&format!("Password for {}", username))
+                        .map_err(BasicAuthError::IOError)
+                        .map_err(AuthError::BasicAuth)
+                        .map_err(ConnectError::Auth)
                 },
             });
 
-            let c = logsh_core::connect::add_connect(name.clone(), Some(connection), auth);
+            let c = logsh_core::connect::add_connect(
+                name.clone(),
+                Some(connection),
+                auth,
+                crate::tenant::pick_tenant,
+            );
             match c {
                 Ok(connection) => {
                     log::debug!(
@@ -88,17 +92,8 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
                         default.to_string().blue()
                     );
 
-                    if default || cfg.connections.is_empty() {
-                        cfg.default_connection = name.clone();
-                    }
-
-                    cfg.connections.insert(name, connection);
                     log::info!("Saving new connection.");
-                    logsh_core::config::save(cfg).map_err(|err| {
-                        crate::fmt::print_config_error(&err);
-                        err
-                    })?;
-                    Ok(())
+                    save_connection(name, connection, default)
                 }
                 Err(err) => {
                     crate::fmt::print_connect_error(&cfg, &err);
@@ -111,59 +106,163 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
             server,
             default,
             flow,
+            idp,
+            tenant,
+            client_id,
+            scopes,
         }) => {
-            let mut cfg = config::load()?;
+            let cfg = config::load()?;
             let server = server
                 .or_else(|| cfg.connections.get(&name).map(|s| s.server.to_owned()))
                 .ok_or(anyhow!(
                     "Missing required argument \"server\" for new connection."
                 ))?;
 
-            let c = Connection::new(&server);
+            let mut c = Connection::new(&server);
+            c.default_subscription = previous_subscription(&cfg, &name, &server);
+            c.groups = previous_groups(&cfg, &name);
             let c = logsh_core::connect::add_connect::<
                 Box<dyn FnOnce() -> Result<String, ConnectError>>,
+                _,
             >(
                 name.clone(),
                 Some(c),
                 Some(logsh_core::auth::AuthRequest::OAuth {
-                    client_id: String::default(),
+                    client_id: client_id.unwrap_or_default(),
                     device_endpoint: None,
-                    scopes: vec![],
+                    scopes,
                     authorize_endpoint: String::default(),
                     token_endpoint: String::default(),
                     flow: match flow {
                         OAuthFlow::Device => logsh_core::auth::oauth::OAuthFlow::Device,
                         // OAuthFlow::Browser => logsh_core::auth::oauth::OAuthFlow::Code,
                     },
+                    provider: idp.map(Into::into),
+                    tenant,
                 }),
+                crate::tenant::pick_tenant,
             )
             .map_err(|err| {
                 crate::fmt::print_connect_error(&cfg, &err);
                 err
             })?;
 
-            if let Some(_old) = cfg.connections.insert(name.clone(), c) {
+            if cfg.connections.contains_key(&name) {
                 log::info!(
                     "New OAuth connection \"{}\" replacing existing connection.",
                     name.yellow().dimmed()
                 )
             }
-
-            if default.unwrap_or(true) || cfg.connections.is_empty() {
+            if default.unwrap_or(true) {
                 log::info!(
                     "Setting OAuth connection \"{}\" as default connection.",
                     name.yellow().dimmed()
                 );
-                cfg.default_connection = name.clone();
             }
+            save_connection(name, c, default.unwrap_or(true))
+        }
+        ConfigConnectionCommand::Add(AddConnectionCommand::Hmac {
+            name,
+            server,
+            key_id,
+            secret,
+            default,
+        }) => {
+            let server = server
+                .or_else(|| cfg.connections.get(&name).map(|s| s.server.to_owned()))
+                .ok_or(anyhow!(
+                    "Missing required argument \"server\" for new connection."
+                ))?;
+            let key_id = key_id
+                .or_else(|| {
+                    cfg.connections
+                        .get(&name)
+                        .and_then(|c| c.hmac_key_id().map(str::to_string))
+                })
+                .ok_or(anyhow!(
+                    "Missing required argument \"--key-id\" for new connection."
+                ))?;
 
-            config::save(cfg).map_err(|err| {
-                crate::fmt::print_config_error(&err);
-                err
+            let mut connection = Connection::new(&server);
+            connection.default_subscription = previous_subscription(&cfg, &name, &server);
+            connection.groups = previous_groups(&cfg, &name);
+            let auth = Some(logsh_core::auth::AuthRequest::Hmac {
+                key_id: key_id.clone(),
+                secret: || {
+                    if let Some(secret) = secret {
+                        return Result::<String, ConnectError>::Ok(secret);
+                    }
+
+                    crate::prompt::password(&format!("Shared secret for key {}", key_id))
+                        .map_err(BasicAuthError::IOError)
+                        .map_err(AuthError::BasicAuth)
+                        .map_err(ConnectError::Auth)
+                },
+            });
+
+            let connection = logsh_core::connect::add_connect(
+                name.clone(),
+                Some(connection),
+                auth,
+                crate::tenant::pick_tenant,
+            )
+            .map_err(|err| {
+                crate::fmt::print_connect_error(&cfg, &err);
+                anyhow!("Error adding connection: {err}")
             })?;
-            Ok(())
+
+            log::info!("Saving new connection.");
+            save_connection(name, connection, default.unwrap_or(true))
+        }
+        ConfigConnectionCommand::Add(AddConnectionCommand::Negotiate {
+            name,
+            server,
+            spn,
+            principal,
+            default,
+        }) => {
+            let server = server
+                .or_else(|| cfg.connections.get(&name).map(|s| s.server.to_owned()))
+                .ok_or(anyhow!(
+                    "Missing required argument \"server\" for new connection."
+                ))?;
+
+            let mut connection = Connection::new(&server);
+            connection.default_subscription = previous_subscription(&cfg, &name, &server);
+            connection.groups = previous_groups(&cfg, &name);
+            let connection = logsh_core::connect::add_connect::<
+                Box<dyn FnOnce() -> Result<String, ConnectError>>,
+                _,
+            >(
+                name.clone(),
+                Some(connection),
+                Some(logsh_core::auth::AuthRequest::Negotiate { spn, principal }),
+                crate::tenant::pick_tenant,
+            )
+            .map_err(|err| {
+                crate::fmt::print_connect_error(&cfg, &err);
+                anyhow!("Error adding connection: {err}")
+            })?;
+
+            log::info!("Saving new connection.");
+            save_connection(name, connection, default.unwrap_or(true))
         }
-        ConfigConnectionCommand::List { output } => list(std::io::stdout(), output),
+        ConfigConnectionCommand::List {
+            output,
+            ping,
+            ping_timeout,
+            group,
+        } => list(
+            std::io::stdout(),
+            output,
+            ping.then(|| ping_timeout.into()),
+            &group,
+        ),
+        ConfigConnectionCommand::Status {
+            output,
+            timeout,
+            group,
+        } => list(std::io::stdout(), output, Some(timeout.into()), &group),
         ConfigConnectionCommand::Remove { name } => {
             let mut cfg = config::load()?;
             if let Some(_conn) = cfg.connections.remove(&name) {
@@ -182,6 +281,114 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
             })?;
             Ok(())
         }
+        ConfigConnectionCommand::Timeouts {
+            name,
+            connect,
+            read,
+            total,
+        } => {
+            let name = name.unwrap_or_else(|| cfg.default_connection.clone());
+            let Some(connection) = cfg.connections.get_mut(&name) else {
+                let err = ConnectError::NoConnection(name);
+                crate::fmt::print_connect_error(&cfg, &err);
+                return Err(anyhow!("Invalid Input: {}", err));
+            };
+
+            if connect.is_none() && read.is_none() && total.is_none() {
+                println!("{}", connection.timeouts);
+                return Ok(());
+            }
+
+            let timeouts = &mut connection.timeouts;
+            if let Some(connect) = connect {
+                timeouts.connect = connect.into();
+            }
+            if let Some(read) = read {
+                timeouts.read = read.into();
+            }
+            if let Some(total) = total {
+                timeouts.total = total.into();
+            }
+            log::info!("Set timeouts for connection {}: {}", name, timeouts);
+            config::save(cfg).inspect_err(|err| {
+                crate::fmt::print_config_error(err);
+            })?;
+            Ok(())
+        }
+        ConfigConnectionCommand::Resolve {
+            entries,
+            name,
+            clear,
+        } => {
+            let name = name.unwrap_or_else(|| cfg.default_connection.clone());
+            let Some(connection) = cfg.connections.get_mut(&name) else {
+                let err = ConnectError::NoConnection(name);
+                crate::fmt::print_connect_error(&cfg, &err);
+                return Err(anyhow!("Invalid Input: {}", err));
+            };
+
+            if entries.is_empty() && !clear {
+                for r in connection.resolve.iter() {
+                    println!("{}", r);
+                }
+                return Ok(());
+            }
+
+            if clear {
+                connection.resolve.clear();
+            }
+            for entry in entries {
+                connection.resolve.retain(|r| {
+                    !(r.host.eq_ignore_ascii_case(&entry.host) && r.port == entry.port)
+                });
+                log::info!("Resolving {} for connection {}", entry, name);
+                connection.resolve.push(entry);
+            }
+            config::save(cfg).inspect_err(|err| {
+                crate::fmt::print_config_error(err);
+            })?;
+            Ok(())
+        }
+        ConfigConnectionCommand::Group {
+            entries,
+            name,
+            remove,
+            clear,
+        } => {
+            let name = name.unwrap_or_else(|| cfg.default_connection.clone());
+            let Some(connection) = cfg.connections.get_mut(&name) else {
+                let err = ConnectError::NoConnection(name);
+                crate::fmt::print_connect_error(&cfg, &err);
+                return Err(anyhow!("Invalid Input: {}", err));
+            };
+
+            if entries.is_empty() && remove.is_empty() && !clear {
+                for (key, value) in connection.groups.iter() {
+                    println!("{}={}", key, value);
+                }
+                return Ok(());
+            }
+
+            if clear {
+                connection.groups.clear();
+            }
+            for key in remove.iter() {
+                connection
+                    .groups
+                    .retain(|k, _| !k.eq_ignore_ascii_case(key));
+            }
+            for entry in entries {
+                connection
+                    .groups
+                    .retain(|k, _| !k.eq_ignore_ascii_case(&entry.key));
+                log::info!("Adding connection {} to group {}", name, entry);
+                connection.groups.insert(entry.key, entry.value);
+            }
+            config::save(cfg).inspect_err(|err| {
+                crate::fmt::print_config_error(err);
+            })?;
+            Ok(())
+        }
         ConfigConnectionCommand::Default { name } => {
             if !cfg.connections.contains_key(&name) {
                 let err = ConnectError::NoConnection(name.clone());
@@ -196,7 +403,14 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
             })?;
             Ok(())
         }
-        ConfigConnectionCommand::Login { name } => {
+        ConfigConnectionCommand::Login {
+            name: _,
+            all: true,
+            output,
+            group,
+            ..
+        } => login_all(std::io::stdout(), output, &group),
+        ConfigConnectionCommand::Login { name, source, .. } => {
             let cfg = logsh_core::config::load()?;
             let conn = if let Some(name) = name.as_ref() {
                 cfg.connections.get(name).map(|c| config::ConnectionConfig {
@@ -208,32 +422,7 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
             };
 
             match conn {
-                Some(connection_config) => {
-                    if connection_config.connection.is_jwt_auth() {
-                        execute_connect(ConfigConnectionCommand::Add(AddConnectionCommand::Basic {
-                            name: connection_config.name.to_owned(),
-                            server: Some(connection_config.connection.server.to_owned()),
-                            username: Some(connection_config.connection.username.to_owned()),
-                            password: None,
-                            default: None,
-                        }))
-                    } else if connection_config.connection.is_oauth_auth() {
-                        return execute_connect(ConfigConnectionCommand::Add(
-                            AddConnectionCommand::OAuth {
-                                name: connection_config.name.to_owned(),
-                                server: None,
-                                default: None,
-                                flow: OAuthFlow::Device,
-                            },
-                        ));
-                    } else {
-                        let err = ConnectError::InvalidConfigError(
-                            "No authentication defined for this connection.".to_string(),
-                        );
-                        crate::fmt::print_connect_error(&cfg, &err);
-                        Err(anyhow!("Invalid Auth Configuration: {}", err))
-                    }
-                }
+                Some(connection_config) => login(&cfg, &connection_config, &source),
                 None => {
                     let err = ConnectError::NoConnection(name.unwrap_or_default().to_string());
                     crate::fmt::print_connect_error(&cfg, &err);
@@ -244,106 +433,620 @@ pub fn execute_connect(command: ConfigConnectionCommand) -> Result<(), Error> {
     }
 }
 
-fn list<W: Write>(mut write: W, mode: Option<OutputMode>) -> Result<(), Error> {
-    let config = logsh_core::config::load()?;
-    let mut list: Vec<_> = Vec::from_iter(config.connections);
-    list.sort_by_key(|c| c.0.to_owned());
-    let list: Vec<_> = list
-        .iter()
-        .map(|c| crate::fmt::Connection {
-            name: c.0.to_string(),
-            server: c.1.server.to_string(),
-            is_default: c.0 == config.default_connection,
-            username: c.1.username.to_string(),
+/// Logs a connection in again the way it was set up. Only its credentials change, so its server,
+/// default subscription and whether it's the default connection stay as they are.
+fn login(
+    cfg: &config::Configuration,
+    target: &config::ConnectionConfig,
+    source: &PasswordSource,
+) -> Result<(), Error> {
+    let connection = &target.connection;
+    let secret = |prompt: String| -> Box<dyn FnOnce() -> Result<String, ConnectError>> {
+        let source = source.clone();
+        Box::new(move || match read_password(&source) {
+            Some(password) => password,
+            None => crate::prompt::password(&prompt)
+                .map_err(BasicAuthError::IOError)
+                .map_err(AuthError::BasicAuth)
+                .map_err(ConnectError::Auth),
         })
-        .collect();
-
-    match mode.unwrap_or_default() {
-        OutputMode::Table | OutputMode::Markdown => {
-            let mut table = Table::new();
-            table.style = match mode.unwrap_or_default() {
-                OutputMode::Table => TableStyle::thin(),
-                OutputMode::Markdown => markdown_style(),
-                _ => unreachable!(),
-            };
-            table.add_row(Row::new(vec![
-                TableCell::new_with_alignment("Name".bright_white().bold(), 1, Alignment::Left),
-                TableCell::new_with_alignment("Server".bright_white().bold(), 1, Alignment::Center),
-                TableCell::new_with_alignment("Default".bright_white().bold(), 1, Alignment::Left),
-                TableCell::new_with_alignment(
-                    "Logged in User".bright_white().bold(),
-                    1,
-                    Alignment::Right,
-                ),
-            ]));
-
-            list.iter().for_each(|f| {
-                table.add_row(Row::new(vec![
-                    TableCell::new_with_alignment(&f.name.white(), 1, Alignment::Left),
-                    TableCell::new_with_alignment(&f.server.blue(), 1, Alignment::Center),
-                    TableCell::new_with_alignment(
-                        if f.is_default {
-                            "true".green()
-                        } else {
-                            "false".red()
-                        },
-                        1,
-                        Alignment::Left,
-                    ),
-                    TableCell::new_with_alignment(f.username.bright_black(), 1, Alignment::Right),
-                ]));
-            });
+    };
 
-            log::trace!("Rendering output table.");
-            let render = table.render();
-            writeln!(write, "{}", render).map_err(|e| anyhow!("Failed to write output: {}", e))
+    let auth = if connection.is_jwt_auth() {
+        AuthRequest::Jwt {
+            username: connection.username.clone(),
+            password: secret(format!("Password for {}", connection.username)),
+        }
+    } else if let Some(key_id) = connection.hmac_key_id() {
+        AuthRequest::Hmac {
+            key_id: key_id.to_string(),
+            secret: secret(format!("Shared secret for key {}", key_id)),
+        }
+    } else if connection.is_token_auth() {
+        return Err(anyhow!(
+            "{} uses a token issued elsewhere, which logsh can't renew. Set LOGSH_TOKEN to a new token and run \"logsh init --from-env\".",
+            target.name
+        ));
+    } else if source.password_stdin || source.password_file.is_some() {
+        return Err(anyhow!(
+            "--password-stdin and --password-file only apply to password and HMAC connections."
+        ));
+    } else if connection.is_negotiate_auth() {
+        // A new ticket is acquired for every request, so there's nothing to refresh beyond
+        // checking the login works.
+        connection.who_am_i().map_err(|err| {
+            crate::fmt::print_connect_error(cfg, &err);
+            anyhow!("Kerberos login failed: {err}. Run \"kinit\" and try again.")
+        })?;
+        return Ok(());
+    } else if let Some(data) = connection.oauth_data() {
+        // Connections set up through an identity provider log in through it again, the rest
+        // rediscover the server's OAuth configuration.
+        let provider = data.provider;
+        AuthRequest::OAuth {
+            client_id: provider.map(|_| data.client_id.clone()).unwrap_or_default(),
+            device_endpoint: None,
+            scopes: provider.map(|_| data.scopes.clone()).unwrap_or_default(),
+            authorize_endpoint: String::default(),
+            token_endpoint: String::default(),
+            flow: logsh_core::auth::oauth::OAuthFlow::Device,
+            provider,
+            tenant: provider.and(data.tenant.clone()),
         }
-        OutputMode::Json => {
-            let json = serde_json::to_string(&list)?;
-            writeln!(write, "{}", json).map_err(|e| anyhow!("Failed to write json output: {}", e))
+    } else {
+        let err = ConnectError::InvalidConfigError(
+            "No authentication defined for this connection.".to_string(),
+        );
+        crate::fmt::print_connect_error(cfg, &err);
+        return Err(anyhow!("Invalid Auth Configuration: {}", err));
+    };
+
+    let connection = logsh_core::connect::login(&target.name, auth).map_err(|err| {
+        crate::fmt::print_connect_error(cfg, &err);
+        anyhow!("Login failed: {err}")
+    })?;
+    log::info!(
+        "Logged in to {} as {}.",
+        target.name.blue(),
+        connection.username.blue()
+    );
+    Ok(())
+}
+
+/// Reads the password from stdin or a file, when `source` says to, without the trailing line
+/// break. None when it should be prompted for. Piping it in, such as `echo $PASSWORD | logsh
+/// conn login --password-stdin`, keeps it out of the process list.
+fn read_password(source: &PasswordSource) -> Option<Result<String, ConnectError>> {
+    let (read, from) = match (&source.password_file, source.password_stdin) {
+        (Some(path), _) => (std::fs::read_to_string(path), path.display().to_string()),
+        (None, true) => {
+            let mut password = String::new();
+            let read = std::io::stdin().read_to_string(&mut password);
+            (read.map(|_| password), "stdin".to_string())
         }
-        OutputMode::JsonPretty => {
-            let json = serde_json::to_string_pretty(&list)?;
-            writeln!(write, "{}", json)
-                .map_err(|e| anyhow!("Failed to write pretty json output: {}", e))
+        (None, false) => return None,
+    };
+
+    let password = read.map_err(BasicAuthError::IOError).and_then(|password| {
+        match password.trim_end_matches(['\r', '\n']) {
+            "" => Err(BasicAuthError::Empty(from)),
+            password => Ok(password.to_string()),
         }
-        OutputMode::Csv => {
-            let results = list
+    });
+    Some(password.map_err(|err| AuthError::BasicAuth(err).into()))
+}
+
+/// One connection's row of the `conn login --all` summary.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Login {
+    name: String,
+    auth_type: Option<&'static str>,
+    token_expires: Option<chrono::DateTime<chrono::Utc>>,
+    status: String,
+    #[serde(skip)]
+    failed: bool,
+}
+
+/// Logs in to every connection in the groups in turn. Saved credentials are checked first, and
+/// expired OAuth logins renewed with their refresh token, so only connections that can't be
+/// renewed prompt, and only when attached to a terminal.
+fn login_all<W: Write>(
+    mut write: W,
+    mode: Option<OutputMode>,
+    groups: &[Group],
+) -> Result<(), Error> {
+    let cfg = config::load()?;
+    let names: Vec<_> = cfg
+        .connections_in(groups)
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    if names.is_empty() {
+        return Err(no_connections(&cfg, groups));
+    }
+
+    let mut logins = Vec::with_capacity(names.len());
+    for name in names {
+        let status = login_one(&name).map_err(|err| {
+            log::warn!("Login to {} failed: {}", name, err);
+            err.to_string()
+        });
+        let connection = config::load()?.connections.remove(&name);
+        logins.push(Login {
+            auth_type: connection.as_ref().and_then(|c| c.auth_type()),
+            token_expires: connection.as_ref().and_then(|c| c.token_expires()),
+            failed: status.is_err(),
+            status: status.map_or_else(|err| format!("failed: {}", err), String::from),
+            name,
+        });
+    }
+
+    crate::fmt::render(
+        &mut write,
+        mode.unwrap_or_else(OutputMode::configured),
+        &logins,
+        |logins| {
+            let results = logins
                 .iter()
-                .map(|c| {
+                .map(|l| {
                     HashMap::from([
+                        ("Name".to_string(), Value::from(l.name.clone())),
+                        ("AuthType".to_string(), Value::from(l.auth_type)),
                         (
-                            "Name".to_string(),
-                            serde_json::Value::String(c.name.to_string()),
-                        ),
-                        (
-                            "Server".to_string(),
-                            serde_json::Value::String(c.server.to_string()),
-                        ),
-                        (
-                            "Default".to_string(),
-                            serde_json::Value::String(c.is_default.to_string()),
+                            "Expires".to_string(),
+                            Value::from(l.token_expires.map(|e| e.to_rfc3339())),
                         ),
+                        ("Status".to_string(), Value::from(l.status.clone())),
                     ])
                 })
                 .collect();
-            let result = QueryResultFmt {
-                header: vec![
-                    "Name".to_string(),
-                    "Server".to_string(),
-                    "Default".to_string(),
-                ],
+            QueryResultFmt {
+                header: ["Name", "AuthType", "Expires", "Status"]
+                    .map(String::from)
+                    .to_vec(),
                 results,
+            }
+        },
+    )?;
+
+    let failures = logins.iter().filter(|l| l.failed).count();
+    match failures {
+        0 => Ok(()),
+        failures => Err(anyhow!(
+            "{} of {} connections couldn't log in.",
+            failures,
+            logins.len()
+        )),
+    }
+}
+
+/// Brings one connection's login up to date, returning what was done.
+fn login_one(name: &str) -> Result<&'static str, Error> {
+    let mut cfg = config::load()?;
+    let mut connection = cfg
+        .connections
+        .get(name)
+        .cloned()
+        .ok_or_else(|| ConnectError::NoConnection(name.to_string()))?;
+
+    // Only expired OAuth tokens are renewed. Valid ones are left as they are.
+    let mut status = "valid";
+    let mut renew_error = None;
+    if connection.oauth_data().is_some() && connection.get_token().is_none() {
+        match connection.refresh_token() {
+            Ok(true) => {
+                cfg.connections.insert(name.to_string(), connection.clone());
+                config::save(cfg)?;
+                status = "renewed";
+            }
+            Ok(false) => {}
+            Err(err) => {
+                log::warn!("Unable to renew the token of {}: {}", name, err);
+                renew_error = Some(err);
+            }
+        }
+    }
+
+    if connection.has_credentials() {
+        match connection.who_am_i() {
+            Ok(_) => return Ok(status),
+            Err(err) if err.is_unreachable() => return Err(err.into()),
+            Err(err) => log::debug!("Saved credentials for {} were rejected: {}", name, err),
+        }
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(match renew_error {
+            Some(err) => anyhow!(
+                "renewing the token failed ({}), and there's no terminal to prompt",
+                err
+            ),
+            None => anyhow!("needs login, and there's no terminal to prompt"),
+        });
+    }
+    eprintln!("Logging in to {}.", name.blue());
+    execute_connect(ConfigConnectionCommand::Login {
+        name: Some(name.to_string()),
+        all: false,
+        output: None,
+        group: Vec::new(),
+        source: PasswordSource::default(),
+    })?;
+    Ok("logged in")
+}
+
+/// Prints a dim reminder when the default connection's token expires soon, so it can be renewed
+/// before it runs out in the middle of a session.
+pub fn warn_expiring_token() {
+    let Ok(cfg) = config::load() else {
+        return;
+    };
+    let Some(conn) = cfg.get_default_connection() else {
+        return;
+    };
+    let Some(remaining) = conn
+        .connection
+        .token_expires()
+        .and_then(|expires| (expires - chrono::Utc::now()).to_std().ok())
+    else {
+        return;
+    };
+
+    if remaining.as_secs() < cfg.expiry_warning_minutes * 60 {
+        let remaining = std::time::Duration::from_secs((remaining.as_secs() / 60).max(1) * 60);
+        eprintln!(
+            "{}",
+            format!(
+                "token for {} expires in {}, run `logsh conn login`",
+                conn.name,
+                humantime::format_duration(remaining)
+            )
+            .dimmed()
+        );
+    }
+}
+
+/// The default subscription of an existing connection to the same server, kept when logging in again.
+fn previous_subscription(
+    cfg: &config::Configuration,
+    name: &str,
+    server: &str,
+) -> Option<uuid::Uuid> {
+    cfg.connections
+        .get(name)
+        .filter(|c| c.server == server.trim())
+        .and_then(|c| c.default_subscription)
+}
+
+/// The groups of an existing connection, kept when logging in again.
+fn previous_groups(
+    cfg: &config::Configuration,
+    name: &str,
+) -> std::collections::BTreeMap<String, String> {
+    cfg.connections
+        .get(name)
+        .map(|c| c.groups.clone())
+        .unwrap_or_default()
+}
+
+/// Saves a newly added connection onto the latest configuration, so connections other logsh
+/// processes added meanwhile aren't lost. The first connection is always the default.
+fn save_connection(name: String, connection: Connection, default: bool) -> Result<(), Error> {
+    config::update(|cfg| {
+        if default || cfg.connections.is_empty() {
+            cfg.default_connection = name.clone();
+        }
+        cfg.connections.insert(name, connection);
+    })
+    .inspect_err(|err| {
+        crate::fmt::print_config_error(err);
+    })?;
+    Ok(())
+}
+
+/// The error for groups no connection is in, or for having no connections at all.
+pub(crate) fn no_connections(cfg: &config::Configuration, groups: &[Group]) -> Error {
+    if cfg.connections.is_empty() || groups.is_empty() {
+        return anyhow!("No connections configured. Add one with \"logsh conn add\".");
+    }
+
+    let groups: Vec<String> = groups.iter().map(Group::to_string).collect();
+    anyhow!(
+        "No connections are in group {}. Tag them with \"logsh conn group\".",
+        groups.join(" and ")
+    )
+}
+
+fn list<W: Write>(
+    write: W,
+    mode: Option<OutputMode>,
+    ping: Option<std::time::Duration>,
+    groups: &[Group],
+) -> Result<(), Error> {
+    let config = logsh_core::config::load()?;
+    let list = config.connections_in(groups);
+    if list.is_empty() && !groups.is_empty() {
+        return Err(no_connections(&config, groups));
+    }
+    let pings = match ping {
+        Some(timeout) => ping_all(list.iter().map(|c| &c.connection), timeout),
+        None => vec![(None, None); list.len()],
+    };
+    let list: Vec<_> = list
+        .into_iter()
+        .zip(pings)
+        .map(|(c, (latency_ms, status))| crate::fmt::Connection {
+            is_default: c.name == config.default_connection,
+            server: c.connection.server.to_string(),
+            username: c.connection.username.to_string(),
+            auth_type: c.connection.auth_type(),
+            token_expires: c.connection.token_expires(),
+            groups: c.connection.groups,
+            name: c.name,
+            latency_ms,
+            status,
+        })
+        .collect();
+
+    let warning_minutes = config.expiry_warning_minutes;
+    let mode = mode.unwrap_or_else(OutputMode::configured);
+    crate::fmt::render(write, mode, &list, |list| {
+        connection_rows(list, mode, ping.is_some(), warning_minutes)
+    })
+}
+
+/// Rows of `conn ls`. The Groups column is only shown once a connection is in a group.
+fn connection_rows(
+    list: &[crate::fmt::Connection],
+    mode: OutputMode,
+    ping: bool,
+    warning_minutes: u64,
+) -> QueryResultFmt {
+    let grouped = list.iter().any(|c| !c.groups.is_empty());
+    let human = matches!(mode, OutputMode::Table | OutputMode::Markdown);
+    let results = list
+        .iter()
+        .map(|c| {
+            let expires = match human {
+                true => Value::String(expires_in(c, warning_minutes).to_string()),
+                false => Value::from(
+                    c.token_expires
+                        .map(|e| (e - chrono::Utc::now()).num_seconds()),
+                ),
             };
-            let result = serde_json::to_string(&result).map_err(|e| {
-                anyhow::anyhow!("Error converting connections to query response json: {}", e)
-            })?;
-            let query = result
-                .as_str()
-                .try_into()
-                .map_err(|e| anyhow::anyhow!("Error converting connection json to csv: {}", e))?;
-            logsh_core::csv::write_csv(&query, write)
-                .map_err(|e| anyhow!("Failed to write csv output: {}", e))
+            let mut row = HashMap::from([
+                ("Name".to_string(), Value::String(c.name.to_string())),
+                ("Server".to_string(), Value::String(c.server.to_string())),
+                ("Default".to_string(), Value::Bool(c.is_default)),
+                (
+                    "Logged in User".to_string(),
+                    Value::String(c.username.to_string()),
+                ),
+                (
+                    "AuthType".to_string(),
+                    match human {
+                        true => Value::from(c.auth_type.unwrap_or("none")),
+                        false => Value::from(c.auth_type),
+                    },
+                ),
+                ("ExpiresIn".to_string(), expires),
+            ]);
+            if grouped {
+                let groups: Vec<String> = c
+                    .groups
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                row.insert("Groups".to_string(), Value::String(groups.join(", ")));
+            }
+            if ping {
+                let latency = match human {
+                    true => Value::String(
+                        c.latency_ms
+                            .map_or("-".to_string(), |ms| format!("{} ms", ms)),
+                    ),
+                    false => Value::from(c.latency_ms),
+                };
+                let status = c.status.clone().unwrap_or_default();
+                let status = match (human, c.latency_ms) {
+                    (true, Some(_)) => status.green().to_string(),
+                    (true, None) => status.red().to_string(),
+                    (false, _) => status,
+                };
+                row.insert("Latency".to_string(), latency);
+                row.insert("Status".to_string(), Value::String(status));
+            }
+            row
+        })
+        .collect();
+
+    let mut header = vec![
+        "Name".to_string(),
+        "Server".to_string(),
+        "Default".to_string(),
+        "Logged in User".to_string(),
+        "AuthType".to_string(),
+        "ExpiresIn".to_string(),
+    ];
+    if grouped {
+        header.push("Groups".to_string());
+    }
+    if ping {
+        header.push("Latency".to_string());
+        header.push("Status".to_string());
+    }
+    QueryResultFmt { header, results }
+}
+
+/// Time left on a connection's token, coloured once it's within the expiry warning or has run
+/// out. Credentials that don't expire show "never".
+fn expires_in(connection: &crate::fmt::Connection, warning_minutes: u64) -> ColoredString {
+    let Some(expires) = connection.token_expires else {
+        return match connection.auth_type {
+            Some(_) => "never".normal(),
+            None => "-".normal(),
+        };
+    };
+    let Ok(remaining) = (expires - chrono::Utc::now()).to_std() else {
+        return "expired".red();
+    };
+
+    let remaining = std::time::Duration::from_secs((remaining.as_secs() / 60).max(1) * 60);
+    let text = humantime::format_duration(remaining).to_string();
+    match remaining.as_secs() < warning_minutes * 60 {
+        true => text.yellow(),
+        false => text.normal(),
+    }
+}
+
+/// Pings every connection's server at once, returning the latency in milliseconds of those
+/// that answered, and a status for each.
+fn ping_all<'a>(
+    connections: impl Iterator<Item = &'a Connection>,
+    timeout: std::time::Duration,
+) -> Vec<(Option<u64>, Option<String>)> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = connections
+            .map(|c| scope.spawn(move || c.ping(timeout)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                let (latency, status) = ping_status(result);
+                (latency, Some(status))
+            })
+            .collect()
+    })
+}
+
+/// The latency in milliseconds of a health check that answered, and a short status.
+pub(crate) fn ping_status(
+    result: Result<std::time::Duration, ConnectError>,
+) -> (Option<u64>, String) {
+    match result {
+        Ok(latency) => (Some(latency.as_millis() as u64), "ok".to_string()),
+        Err(ConnectError::HttpResponseFailed(status)) => (None, status.to_string()),
+        Err(ConnectError::Network(err)) if err.is_timeout() => (None, "timed out".to_string()),
+        Err(ConnectError::Network(err)) if err.is_connect() => (None, "unreachable".to_string()),
+        Err(err) => {
+            log::debug!("Health check failed: {}", err);
+            (None, "failed".to_string())
+        }
+    }
+}
+
+/// The last time each connection was logged in to again: the connection it logged in to, or
+/// why it couldn't. Threads rejected with the same credentials share it, rather than each
+/// prompting on the same terminal.
+static RELOGINS: Mutex<BTreeMap<String, Result<config::ConnectionConfig, String>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Runs `request`, and when the server rejects the stored credentials, logs in again and
+/// retries once. Returns the original error if logging in again isn't possible.
+pub fn retry_unauthorized<T, E, F>(
+    connection: &mut config::ConnectionConfig,
+    mut request: F,
+) -> Result<T, E>
+where
+    E: std::error::Error + 'static,
+    F: FnMut(&config::ConnectionConfig) -> Result<T, E>,
+{
+    match request(connection) {
+        Err(err) if logsh_core::error::is_unauthorized(&err) => match relogin(connection) {
+            Ok(refreshed) => {
+                *connection = refreshed;
+                request(connection)
+            }
+            Err(reauth) => {
+                log::warn!("Unable to log in again: {}", reauth);
+                Err(err)
+            }
+        },
+        result => result,
+    }
+}
+
+/// Logs in to the connection again, one thread at a time. When another thread already did
+/// since the connection's credentials were loaded, its login is reused, and once logging in
+/// has failed or been declined it isn't tried again.
+fn relogin(connection: &config::ConnectionConfig) -> Result<config::ConnectionConfig, Error> {
+    let mut relogins = RELOGINS.lock().unwrap_or_else(|err| err.into_inner());
+    match relogins.get(&connection.name) {
+        Some(Ok(refreshed)) if !same_login(refreshed, connection) => {
+            log::debug!("Reusing the login to {} made meanwhile.", connection.name);
+            return Ok(refreshed.clone());
         }
+        Some(Err(failure)) => return Err(anyhow!("{}", failure)),
+        _ => {}
     }
+
+    let result = reauthenticate(&connection.name);
+    relogins.insert(
+        connection.name.to_string(),
+        result.as_ref().cloned().map_err(ToString::to_string),
+    );
+    result
+}
+
+/// Whether two copies of a connection hold the same login.
+fn same_login(a: &config::ConnectionConfig, b: &config::ConnectionConfig) -> bool {
+    a.connection.auth_type() == b.connection.auth_type()
+        && a.connection.token_expires() == b.connection.token_expires()
+}
+
+/// Logs in to the named connection again. Only prompts when attached to a terminal.
+fn reauthenticate(name: &str) -> Result<config::ConnectionConfig, Error> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "no terminal to prompt for credentials. Run \"logsh conn login\""
+        ));
+    }
+
+    // Password logins can't be renewed without the user, so ask before prompting for it.
+    let cfg = config::load()?;
+    match cfg.connections.get(name) {
+        Some(connection) if connection.is_jwt_auth() => {
+            let relogin = dialoguer::Confirm::with_theme(&*crate::prompt::theme())
+                .with_prompt(format!(
+                    "{} Re-enter password for {}@{}?",
+                    "Token expired.".yellow(),
+                    connection.username,
+                    name
+                ))
+                .default(true)
+                .interact()?;
+            if !relogin {
+                return Err(anyhow!("declined to log in again"));
+            }
+        }
+        _ => eprintln!(
+            "{} Logging in to {} again.",
+            "Session expired.".yellow(),
+            name.blue()
+        ),
+    }
+    execute_connect(ConfigConnectionCommand::Login {
+        name: Some(name.to_string()),
+        all: false,
+        output: None,
+        group: Vec::new(),
+        source: PasswordSource::default(),
+    })?;
+    stored_connection(name)
+}
+
+/// The named connection as last saved.
+fn stored_connection(name: &str) -> Result<config::ConnectionConfig, Error> {
+    let cfg = config::load()?;
+    cfg.connections
+        .get(name)
+        .map(|c| config::ConnectionConfig {
+            name: name.to_string(),
+            connection: c.clone(),
+        })
+        .ok_or_else(|| anyhow!("Connection {} no longer exists.", name))
 }
@@ -0,0 +1,420 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Local};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    watch::{Poll, Watch},
+};
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Sparkline, Table, Wrap},
+    Frame,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{fmt::parse::OptionalDurationArg, tui::Screen};
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Show a live terminal dashboard of query tiles.")]
+pub struct DashCommand {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "dash.toml",
+        help = "Dashboard file listing the tiles to show."
+    )]
+    config: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Query timeout. Use \"none\" to disable timeout.",
+        default_value = "60s"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+/// A dashboard file, for example:
+///
+/// ```toml
+/// title = "Ingest"
+/// columns = 2
+/// interval = "30s"
+///
+/// [[tile]]
+/// title = "Errors in the last hour"
+/// kind = "stat"
+/// query = "logs | where level == 'error' and timestamp > ago(1h) | count"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DashConfig {
+    title: Option<String>,
+    #[serde(default = "default_columns")]
+    columns: usize,
+    #[serde(default = "default_interval")]
+    interval: String,
+    #[serde(default, rename = "tile")]
+    tiles: Vec<TileConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TileConfig {
+    title: String,
+    query: String,
+    #[serde(default)]
+    kind: TileKind,
+    /// Column shown by stat and sparkline tiles. Stats default to the first column and
+    /// sparklines to the last.
+    column: Option<String>,
+    interval: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TileKind {
+    #[default]
+    Table,
+    Stat,
+    Sparkline,
+}
+
+fn default_columns() -> usize {
+    2
+}
+
+fn default_interval() -> String {
+    "30s".to_string()
+}
+
+enum TileState {
+    Loading,
+    Ready(TileData),
+    Failed(String),
+}
+
+struct TileData {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    /// Numeric values of the tile column, in row order.
+    values: Vec<f64>,
+    /// Text of the tile column in the first row.
+    first: Option<String>,
+}
+
+struct Tile {
+    config: TileConfig,
+    interval: Duration,
+    due: Instant,
+    updated: Option<DateTime<Local>>,
+    state: TileState,
+    /// The tile's last result, so an unchanged one isn't parsed and laid out again.
+    watch: Watch,
+}
+
+pub fn execute_dash(command: DashCommand) -> Result<(), Error> {
+    log::debug!("Entering dashboard: {:?}", &command);
+    let text = std::fs::read_to_string(&command.config).map_err(|err| {
+        anyhow!(
+            "Failed to read dashboard {}: {}",
+            command.config.display(),
+            err
+        )
+    })?;
+    let dash: DashConfig = toml::from_str(&text).map_err(|err| {
+        anyhow!(
+            "Failed to parse dashboard {}: {}",
+            command.config.display(),
+            err
+        )
+    })?;
+    if dash.tiles.is_empty() {
+        return Err(anyhow!(
+            "Dashboard {} has no [[tile]] entries.",
+            command.config.display()
+        ));
+    }
+    if dash.columns == 0 {
+        return Err(anyhow!("Dashboard columns must be at least 1."));
+    }
+
+    let default_interval = parse_interval(&dash.interval)?;
+    let now = Instant::now();
+    let mut tiles = Vec::with_capacity(dash.tiles.len());
+    let (title, columns) = (dash.title, dash.columns);
+    for config in dash.tiles {
+        let interval = match config.interval.as_deref() {
+            Some(interval) => parse_interval(interval)?,
+            None => default_interval,
+        };
+        tiles.push(Tile {
+            watch: Watch::new(&config.query),
+            config,
+            interval,
+            due: now,
+            updated: None,
+            state: TileState::Loading,
+        });
+    }
+
+    let cfg = config::load()?;
+    let connection: config::ConnectionConfig = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let title = title.unwrap_or_else(|| command.config.display().to_string());
+
+    let mut screen = Screen::enter()?;
+    loop {
+        screen
+            .terminal()
+            .draw(|frame| draw(frame, &title, columns, &tiles))?;
+        for i in 0..tiles.len() {
+            if tiles[i].due <= Instant::now() {
+                tiles[i].refresh(&connection, command.timeout.into());
+                screen
+                    .terminal()
+                    .draw(|frame| draw(frame, &title, columns, &tiles))?;
+            }
+        }
+
+        let next = tiles
+            .iter()
+            .map(|t| t.due)
+            .min()
+            .unwrap_or_else(Instant::now);
+        if event::poll(next.saturating_duration_since(Instant::now()))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('r') => {
+                        let now = Instant::now();
+                        tiles.iter_mut().for_each(|t| t.due = now);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn parse_interval(interval: &str) -> Result<Duration, Error> {
+    let duration = humantime::parse_duration(interval)
+        .map_err(|err| anyhow!("Invalid interval \"{}\": {}", interval, err))?;
+    if duration.is_zero() {
+        return Err(anyhow!(
+            "Invalid interval \"{}\": must not be zero.",
+            interval
+        ));
+    }
+
+    Ok(duration)
+}
+
+impl Tile {
+    fn refresh(&mut self, connection: &config::ConnectionConfig, timeout: Option<Duration>) {
+        log::debug!("Refreshing tile: {}", self.config.title);
+        match self.load(connection, timeout) {
+            Ok(Some(data)) => self.state = TileState::Ready(data),
+            Ok(None) => log::debug!("Tile unchanged: {}", self.config.title),
+            Err(err) => {
+                // Start over so the next good result is shown even if it's the last one again.
+                self.watch = Watch::new(&self.config.query);
+                self.state = TileState::Failed(logsh_core::redact::redact(&err.to_string()).into());
+            }
+        }
+        self.updated = Some(Local::now());
+        self.due = Instant::now() + self.interval;
+    }
+
+    /// The tile's new data, or None when the result hasn't changed since the last load.
+    fn load(
+        &mut self,
+        connection: &config::ConnectionConfig,
+        timeout: Option<Duration>,
+    ) -> Result<Option<TileData>, Error> {
+        let changed = match self.watch.poll(&connection.connection, timeout.into())? {
+            Poll::Unchanged => return Ok(None),
+            Poll::Changed(changed) => changed,
+        };
+        let result = logsh_core::query::result(self.watch.body().unwrap_or_default())?;
+        let column = match (self.config.column.as_ref(), self.config.kind) {
+            (Some(column), _) => Some(
+                result
+                    .header
+                    .iter()
+                    .position(|h| h == column)
+                    .ok_or_else(|| anyhow!("Column \"{}\" is not in the result.", column))?,
+            ),
+            (None, TileKind::Stat) => (!result.header.is_empty()).then_some(0),
+            (None, TileKind::Sparkline) => result.header.len().checked_sub(1),
+            (None, TileKind::Table) => None,
+        };
+
+        // Rows that didn't change keep the cells laid out for them last time.
+        let previous = match &self.state {
+            TileState::Ready(data) if data.header == result.header => data.rows.as_slice(),
+            _ => &[],
+        };
+        let rows: Vec<Vec<String>> = result
+            .results
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                if let (Err(_), Some(cells)) = (changed.binary_search(&i), previous.get(i)) {
+                    return cells.clone();
+                }
+
+                result
+                    .header
+                    .iter()
+                    .map(|h| {
+                        row.get(h.as_str())
+                            .map_or(String::new(), |v| crate::tui::cell(v.get()))
+                    })
+                    .collect()
+            })
+            .collect();
+        let values = column.map_or(Vec::new(), |c| {
+            let name = result.header[c].as_str();
+            result
+                .results
+                .iter()
+                .filter_map(|row| row.get(name))
+                .filter_map(|v| match Value::from_str(v.get()) {
+                    Ok(Value::Number(n)) => n.as_f64(),
+                    Ok(Value::String(s)) => s.parse().ok(),
+                    _ => None,
+                })
+                .collect()
+        });
+        let first = column.and_then(|c| rows.first().map(|r| r[c].to_string()));
+
+        Ok(Some(TileData {
+            header: result.header.clone(),
+            rows,
+            values,
+            first,
+        }))
+    }
+}
+
+fn draw(frame: &mut Frame, title: &str, columns: usize, tiles: &[Tile]) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(1)])
+        .split(frame.size());
+    let header = Line::from(vec![
+        Span::styled(title, Style::default().add_modifier(Modifier::BOLD)),
+        Span::styled("  r refresh  q quit", Style::default().fg(Color::DarkGray)),
+    ]);
+    frame.render_widget(Paragraph::new(header), chunks[0]);
+
+    let grid_rows = tiles.len().div_ceil(columns);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, grid_rows as u32); grid_rows])
+        .split(chunks[1]);
+    for (row, tiles) in rows.iter().zip(tiles.chunks(columns)) {
+        let cells = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, columns as u32); columns])
+            .split(*row);
+        for (area, tile) in cells.iter().zip(tiles) {
+            draw_tile(frame, *area, tile);
+        }
+    }
+}
+
+fn draw_tile(frame: &mut Frame, area: Rect, tile: &Tile) {
+    let updated = tile
+        .updated
+        .map_or(String::new(), |u| format!(" · {}", u.format("%H:%M:%S")));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" {}{} ", tile.config.title, updated));
+    let data = match &tile.state {
+        TileState::Loading => {
+            let loading = Paragraph::new("Loading…")
+                .style(Style::default().fg(Color::DarkGray))
+                .block(block);
+            frame.render_widget(loading, area);
+            return;
+        }
+        TileState::Failed(err) => {
+            let failed = Paragraph::new(err.as_str())
+                .style(Style::default().fg(Color::Red))
+                .wrap(Wrap { trim: false })
+                .block(block);
+            frame.render_widget(failed, area);
+            return;
+        }
+        TileState::Ready(data) => data,
+    };
+
+    match tile.config.kind {
+        TileKind::Stat => {
+            let value = data.first.as_deref().unwrap_or("-");
+            // Center the value vertically inside the borders.
+            let padding = (area.height.saturating_sub(2) / 2) as usize;
+            let mut lines = vec![Line::default(); padding];
+            lines.push(Line::from(Span::styled(
+                value,
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            let stat = Paragraph::new(lines)
+                .alignment(Alignment::Center)
+                .block(block);
+            frame.render_widget(stat, area);
+        }
+        TileKind::Sparkline => {
+            let last = data.values.last().copied().unwrap_or_default();
+            let max = data.values.iter().copied().fold(0f64, f64::max);
+            // Sparklines take integers, so scale the series to keep fractional values visible.
+            let scale = if max > 0.0 { 1000.0 / max } else { 0.0 };
+            let points: Vec<u64> = data
+                .values
+                .iter()
+                .map(|v| (v.max(0.0) * scale).round() as u64)
+                .collect();
+            let block = block.title(format!(" last {}  max {} ", last, max));
+            let sparkline = Sparkline::default()
+                .data(&points)
+                .style(Style::default().fg(Color::Green))
+                .block(block);
+            frame.render_widget(sparkline, area);
+        }
+        TileKind::Table => {
+            let count = data.header.len().max(1);
+            let widths = vec![Constraint::Ratio(1, count as u32); count];
+            let header = Row::new(data.header.iter().map(|h| Cell::from(h.as_str())))
+                .style(Style::default().add_modifier(Modifier::BOLD));
+            let rows = data
+                .rows
+                .iter()
+                .map(|r| Row::new(r.iter().map(|c| Cell::from(c.as_str()))));
+            let table = Table::new(rows)
+                .header(header)
+                .widths(&widths)
+                .column_spacing(1)
+                .block(block);
+            frame.render_widget(table, area);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    generate::Template,
+};
+
+use crate::fmt::parse::OptionalDurationArg;
+
+/// Sample web request logs, spread over the last day so time based queries have data.
+const DEMO_TEMPLATE: &str = r#"{
+    "timestamp": "{{timestamp:24h}}",
+    "level": "{{one_of:info,info,info,info,info,warn,warn,error}}",
+    "service": "{{one_of:api,api,auth,billing,search}}",
+    "region": "{{one_of:us-east,us-east,us-west,eu-west}}",
+    "method": "{{one_of:GET,GET,GET,POST,PUT,DELETE}}",
+    "path": "{{one_of:/,/login,/search,/cart,/checkout,/orders,/profile}}",
+    "status": "{{one_of:200,200,200,200,200,201,204,301,400,401,404,500,503}}",
+    "duration_ms": "{{int:2..1500}}",
+    "user_id": "{{one_of:u-1001,u-1002,u-1003,u-1004,u-1005,u-1006,u-1007,u-1008}}",
+    "client_ip": "{{ipv4}}",
+    "message": "{{words:5}}",
+    "request_id": "{{uuid}}"
+}"#;
+
+const BATCH_SIZE: usize = 5000;
+
+#[derive(Subcommand)]
+#[clap(about = "Try logsh with sample data.")]
+pub enum DemoCommand {
+    #[clap(about = "Upload sample request logs to a demo schema and print queries to try.")]
+    Setup {
+        #[arg(
+            short,
+            long,
+            default_value = "logsh_demo",
+            help = "Schema to upload the sample data to."
+        )]
+        schema: String,
+
+        #[arg(
+            short,
+            long,
+            default_value = "5000",
+            help = "Number of sample rows to upload."
+        )]
+        rows: usize,
+
+        #[arg(
+            short,
+            long,
+            help = "Upload timeout for each batch. Use \"none\" to disable timeout.",
+            default_value = "60s"
+        )]
+        timeout: OptionalDurationArg,
+    },
+}
+
+pub fn execute_demo(command: DemoCommand) -> Result<(), Error> {
+    match command {
+        DemoCommand::Setup {
+            schema,
+            rows,
+            timeout,
+        } => {
+            if rows == 0 {
+                return Err(anyhow!("The number of rows must be greater than zero."));
+            }
+
+            let cfg = config::load()?;
+            let mut connection = cfg
+                .get_default_connection()
+                .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+            let mut template = Template::parse(DEMO_TEMPLATE)?;
+            crate::ingest::upload_generated(
+                &mut connection,
+                &schema,
+                &mut template,
+                rows,
+                BATCH_SIZE,
+                timeout.into(),
+            )?;
+
+            println!(
+                "Uploaded {} sample rows to schema {}.",
+                rows,
+                schema.bright_blue()
+            );
+            println!();
+            println!("Try these queries:");
+            for (about, query) in example_queries(&schema) {
+                println!();
+                println!("  {}", about.bright_black());
+                println!("  logsh query -q \"{}\"", query);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn example_queries(schema: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("A few rows", format!("{} | take 10", schema)),
+        (
+            "Requests per service",
+            format!("{} | summarize count() by service", schema),
+        ),
+        (
+            "Server errors in the last hour",
+            format!(
+                "{} | where status >= 500 and timestamp > ago(1h) | summarize count() by service, path",
+                schema
+            ),
+        ),
+        (
+            "Average latency per hour",
+            format!(
+                "{} | summarize avg(duration_ms) by bin(timestamp, 1h) | order by timestamp asc",
+                schema
+            ),
+        ),
+        (
+            "Slowest requests",
+            format!(
+                "{} | project timestamp, method, path, duration_ms | order by duration_ms desc | take 10",
+                schema
+            ),
+        ),
+    ]
+}
@@ -0,0 +1,185 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    query::{QueryResult, Row},
+};
+
+use crate::{fmt::parse::OptionalDurationArg, OutputMode};
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Compare a query result against a baseline and show added, removed and changed rows."
+)]
+#[command(group(clap::ArgGroup::new("baseline_source").required(true).args(["baseline", "against"])))]
+pub struct DiffCommand {
+    #[arg(
+        short,
+        long,
+        help = "Query to execute. If not provided, will read from stdin."
+    )]
+    query: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "JSON query result to compare against, such as one written with \"--output json\"."
+    )]
+    baseline: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "QUERY",
+        help = "Query whose result to compare against."
+    )]
+    against: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        value_name = "COLUMNS",
+        help = "Comma separated columns identifying a row. Rows with the same key and different values are reported as changed. Defaults to every column."
+    )]
+    key: Vec<String>,
+
+    #[arg(short, long, help = "Output result format")]
+    output: Option<OutputMode>,
+
+    #[arg(
+        short,
+        long,
+        help = "Query timeout. Use \"none\" to disable timeout.",
+        default_value = "60s"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+pub fn execute_diff<W: Write>(command: DiffCommand, write: W) -> Result<(), Error> {
+    log::debug!("Entering query diff: {:?}", &command);
+    let query = if let Some(q) = command.query.as_ref() {
+        q.to_string()
+    } else {
+        crate::edit::read_stdin("query", "--query")?
+    };
+
+    let cfg = config::load()?;
+    let mut connection: config::ConnectionConfig = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+
+    let baseline_text = match (command.baseline.as_ref(), command.against.as_ref()) {
+        (Some(path), _) => std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read baseline {}: {}", path.display(), err))?,
+        (None, Some(against)) => run_query(&cfg, &mut connection, against, &command)?,
+        (None, None) => return Err(anyhow!("A baseline file or query is required.")),
+    };
+    let baseline = logsh_core::query::result(&baseline_text)
+        .map_err(|err| anyhow!("Failed to read baseline result: {}", err))?;
+
+    let current_text = run_query(&cfg, &mut connection, &query, &command)?;
+    let current = parse(&cfg, &query, &current_text)?;
+
+    let diff = logsh_core::query::diff(&baseline, &current, &command.key)?;
+    let mode = command.output.unwrap_or_else(OutputMode::configured);
+    crate::fmt::render_with(write, mode, &diff, |write, _, diff| {
+        let columns = columns(&baseline, &current);
+        for row in diff.removed.iter() {
+            writeln!(write, "{}", format_row('-', &columns, row).red())?;
+        }
+        for row in diff.added.iter() {
+            writeln!(write, "{}", format_row('+', &columns, row).green())?;
+        }
+        for changed in diff.changed.iter() {
+            writeln!(write, "{}", format_row('-', &columns, changed.before).red())?;
+            writeln!(
+                write,
+                "{}",
+                format_row('+', &columns, changed.after).green()
+            )?;
+        }
+
+        let summary = format!(
+            "{} added, {} removed, {} changed.",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
+        );
+        writeln!(write, "{}", summary.bright_black())?;
+        Ok(())
+    })
+}
+
+fn run_query(
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    command: &DiffCommand,
+) -> Result<String, Error> {
+    log::info!("Starting query. Timeout = {}", &command.timeout);
+    let r = crate::connect::retry_unauthorized(connection, |c| {
+        c.connection.query_raw(query, command.timeout.into())
+    })
+    .inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, query, err);
+    })?;
+    log::debug!("Response text: {:?}", r);
+    Ok(r)
+}
+
+fn parse<'a>(
+    cfg: &config::Configuration,
+    query: &str,
+    text: &'a str,
+) -> Result<QueryResult<'a>, Error> {
+    logsh_core::query::result(text).map_err(|err| {
+        crate::fmt::print_query_error(cfg, query, &err);
+        err.into()
+    })
+}
+
+/// Columns of both results, baseline columns first.
+fn columns<'a>(baseline: &'a QueryResult<'_>, current: &'a QueryResult<'_>) -> Vec<&'a str> {
+    let mut columns: Vec<&str> = baseline.header.iter().map(String::as_str).collect();
+    for h in current.header.iter() {
+        if !columns.contains(&h.as_str()) {
+            columns.push(h);
+        }
+    }
+
+    columns
+}
+
+fn format_row(marker: char, columns: &[&str], row: &Row<'_>) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .filter_map(|c| row.get(c).map(|v| format!("{}={}", c, v.get())))
+        .collect();
+    format!("{} {}", marker, fields.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_keep_baseline_order_then_add_new_ones() {
+        let baseline = logsh_core::query::result(r#"{"header":["b","a"],"results":[]}"#).unwrap();
+        let current = logsh_core::query::result(r#"{"header":["a","c"],"results":[]}"#).unwrap();
+        assert_eq!(columns(&baseline, &current), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn rows_show_the_columns_they_have() {
+        let result =
+            logsh_core::query::result(r#"{"header":["a","b"],"results":[{"a":"x","b":2}]}"#)
+                .unwrap();
+        assert_eq!(
+            format_row('+', &["a", "missing", "b"], &result.results[0]),
+            r#"+ a="x" b=2"#
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::{IsTerminal, Read};
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use dialoguer::{Confirm, Input};
+use logsh_core::saved_query::{self, SavedQuery};
+
+/// Reads a query, or another `what`, from stdin. At a terminal it says it's waiting for input
+/// rather than appearing to hang, and empty input fails with a pointer to `option`, the usual
+/// way to pass it instead.
+pub fn read_stdin(what: &str, option: &str) -> Result<String, Error> {
+    log::debug!("Reading {} from STDIN", what);
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        let finish = if cfg!(windows) {
+            "Ctrl-Z then Enter"
+        } else {
+            "Ctrl-D"
+        };
+        eprintln!(
+            "{}",
+            format!(
+                "Reading the {} from stdin. Finish with {}, or pass it with {}.",
+                what, finish, option
+            )
+            .bright_black()
+        );
+    }
+
+    let mut s = String::new();
+    stdin
+        .lock()
+        .read_to_string(&mut s)
+        .map_err(|err| anyhow!("Failed to read STDIN: {}", err))?;
+    if s.trim().is_empty() {
+        return Err(anyhow!(
+            "No {} given. Pass it with {}, or pipe it to stdin.",
+            what,
+            option
+        ));
+    }
+    Ok(s)
+}
+
+/// Opens the query in the user's editor, `$VISUAL` or `$EDITOR`, as a `.kql` file and returns
+/// what they saved. Fails when the editor is closed without saving, or the file is left empty.
+pub fn compose(initial: &str) -> Result<String, Error> {
+    let edited = dialoguer::Editor::new()
+        .extension(".kql")
+        .edit(initial)
+        .map_err(|err| anyhow!("Failed to run the editor: {}", err))?
+        .ok_or(anyhow!(
+            "The editor was closed without saving. Nothing to run."
+        ))?;
+
+    if edited.trim().is_empty() {
+        return Err(anyhow!("The query is empty. Nothing to run."));
+    }
+    Ok(edited)
+}
+
+/// Asks whether to keep a composed query as a saved query, suggesting the name it was opened
+/// from. Does nothing without a terminal to ask on.
+pub fn offer_save(query: &str, name: Option<&str>) -> Result<(), Error> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Ok(());
+    }
+
+    let theme = crate::prompt::theme();
+    let name: String = Input::with_theme(&*theme)
+        .with_prompt("Save as a named query (empty to skip)")
+        .with_initial_text(name.unwrap_or_default())
+        .allow_empty(true)
+        .interact_text()?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(());
+    }
+
+    let mut saved = saved_query::load()?;
+    let description = match saved.queries.get(name) {
+        Some(existing) if existing.query == query => return Ok(()),
+        Some(existing) => {
+            let replace = Confirm::with_theme(&*theme)
+                .with_prompt(format!("Replace saved query \"{}\"?", name))
+                .default(false)
+                .interact()?;
+            if !replace {
+                return Ok(());
+            }
+            existing.description.clone()
+        }
+        None => None,
+    };
+    saved.insert(SavedQuery::new(name, query, description));
+    saved_query::save(saved)?;
+    log::info!("Saved query \"{}\".", name);
+    Ok(())
+}
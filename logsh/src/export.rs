@@ -0,0 +1,498 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, IsTerminal, Write},
+    sync::{mpsc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError, QueryError},
+    planner::Planner,
+    progress::Progress,
+    schedule::Window,
+};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::{
+    fmt::parse::{OptionalDurationArg, OutputFileArg},
+    OutputMode,
+};
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Export a time range of a query's rows. The range is split into shards sized by how quickly the server answers, which are queried concurrently and written out in order."
+)]
+pub struct ExportCommand {
+    #[arg(
+        short,
+        long,
+        required_unless_present = "table",
+        conflicts_with = "table",
+        help = "Query to export."
+    )]
+    query: Option<String>,
+
+    #[arg(long, help = "Export every row of a table, instead of a query.")]
+    table: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        help = "Start of the range, as a date meaning midnight UTC, or an RFC 3339 time."
+    )]
+    from: String,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        help = "End of the range, which isn't included. Defaults to now."
+    )]
+    to: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        default_value = "timestamp",
+        help = "Column of the queried table holding each row's time, which decides the shard it's exported in."
+    )]
+    time_column: String,
+
+    #[arg(
+        short = 'j',
+        long,
+        default_value_t = 4,
+        help = "Most shards queried at once. Lowered while the server throttles requests."
+    )]
+    concurrency: usize,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1h",
+        help = "Length of the first shards, before the server's latency is known."
+    )]
+    shard: humantime::Duration,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "10s",
+        help = "How long each shard's query should take. Later shards are lengthened or shortened towards it."
+    )]
+    target: humantime::Duration,
+
+    #[arg(
+        short,
+        long,
+        value_parser = crate::fmt::parse::OutputModeParser::query(),
+        help = "Output result format"
+    )]
+    output: Option<OutputMode>,
+
+    #[arg(
+        long,
+        value_name = "PATH[:FORMAT]",
+        help = "Write to a file or object storage URL instead of stdout. The format is inferred from the extension, or given after a colon."
+    )]
+    output_file: Option<OutputFileArg>,
+
+    #[arg(
+        short,
+        long,
+        help = "Timeout of each shard's query. Shards that time out are split in half and tried again. Use \"none\" to disable timeout.",
+        default_value = "5m"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+/// A shard, and its response and how long it took, or why it failed.
+type Queried = (Window, Result<(String, std::time::Duration), QueryError>);
+
+/// A shard's query response, with the rows left as the server sent them.
+#[derive(Deserialize)]
+struct ShardResult {
+    header: Vec<String>,
+    results: Vec<Box<RawValue>>,
+}
+
+pub fn execute_export<W: Write>(command: ExportCommand, mut write: W) -> Result<(), Error> {
+    let query = match (command.query.as_ref(), command.table.as_ref()) {
+        (Some(query), _) => logsh_core::snippet::expand(query)?,
+        (None, Some(table)) => table.to_string(),
+        (None, None) => return Err(anyhow!("Give a --query or a --table to export.")),
+    };
+    let from = crate::agent::parse_time(&command.from)
+        .map_err(|err| anyhow!("Invalid --from: {}", err))?;
+    let to = match command.to.as_deref() {
+        Some(to) => crate::agent::parse_time(to).map_err(|err| anyhow!("Invalid --to: {}", err))?,
+        None => Utc::now(),
+    };
+    if from >= to {
+        return Err(anyhow!("--from must be before --to."));
+    }
+
+    let mode = match command.output_file.as_ref() {
+        Some(sink) => sink.mode,
+        None => command.output.unwrap_or_else(OutputMode::configured),
+    };
+    match mode {
+        OutputMode::Template => return Err(anyhow!("Template output isn't supported.")),
+        OutputMode::Sqlite if command.output_file.is_none() => {
+            return Err(anyhow!(
+                "SQLite output is written to a database file. Use --output-file <PATH>.db."
+            ))
+        }
+        mode if mode.is_binary()
+            && command.output_file.is_none()
+            && std::io::stdout().is_terminal() =>
+        {
+            return Err(anyhow!(
+                "Refusing to write binary {} output to a terminal. Redirect stdout or use --output-file.",
+                mode.name()
+            ))
+        }
+        _ => {}
+    }
+
+    let cfg = config::load()?;
+    let connection = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let initial = chrono::Duration::from_std(command.shard.into())
+        .map_err(|err| anyhow!("Invalid --shard: {}", err))?;
+    let mut planner = Planner::new(
+        from,
+        to,
+        initial,
+        command.target.into(),
+        command.concurrency,
+    );
+
+    let progress = crate::progress::reporter();
+    let started = Instant::now();
+    let (jobs, queued) = mpsc::channel::<Window>();
+    let queued = Mutex::new(queued);
+    let (finished, done) = mpsc::channel();
+    let mut assembler =
+        Assembler::new(mode, Output::new(command.output_file.as_ref(), &mut write)?);
+    let shards = std::thread::scope(|scope| {
+        for _ in 0..planner.concurrency() {
+            let (finished, queued, query) = (finished.clone(), &queued, &query);
+            let mut connection = config::ConnectionConfig {
+                name: connection.name.clone(),
+                connection: connection.connection.clone(),
+            };
+            let (time_column, timeout) = (&command.time_column, command.timeout);
+            scope.spawn(move || loop {
+                let job = queued.lock().unwrap_or_else(|p| p.into_inner()).recv();
+                let Ok(window) = job else {
+                    break;
+                };
+                let query = window.apply(query, time_column);
+                let started = Instant::now();
+                let result = crate::connect::retry_unauthorized(&mut connection, |c| {
+                    c.connection.query_raw(&query, timeout.into())
+                });
+                if finished
+                    .send((window, result.map(|text| (text, started.elapsed()))))
+                    .is_err()
+                {
+                    break;
+                }
+            });
+        }
+        drop(finished);
+
+        progress.start("Exporting", None);
+        let shards = dispatch(
+            &mut planner,
+            &jobs,
+            &done,
+            &cfg,
+            from,
+            &mut assembler,
+            progress.as_ref(),
+        );
+        progress.finish();
+        // Workers stop once the queue closes, after the queries they're running.
+        drop(jobs);
+        shards
+    })?;
+
+    let rows = assembler.finish()?;
+    let elapsed =
+        humantime::format_duration(std::time::Duration::from_secs(started.elapsed().as_secs()));
+    match command.output_file.as_ref() {
+        Some(sink) => writeln!(
+            write,
+            "Exported {} rows in {} shards from {} to {} to {} in {}.",
+            rows,
+            shards,
+            from.to_rfc3339(),
+            to.to_rfc3339(),
+            sink.path.display().to_string().bright_blue(),
+            elapsed
+        )?,
+        None => log::info!(
+            "Exported {} rows in {} shards in {}.",
+            rows,
+            shards,
+            elapsed
+        ),
+    }
+    Ok(())
+}
+
+/// Hands shards to the workers as they free up, and writes the results in order as the shards
+/// before them complete. Returns the number of shards queried.
+fn dispatch(
+    planner: &mut Planner,
+    jobs: &mpsc::Sender<Window>,
+    done: &mpsc::Receiver<Queried>,
+    cfg: &config::Configuration,
+    from: DateTime<Utc>,
+    assembler: &mut Assembler,
+    progress: &dyn Progress,
+) -> Result<usize, Error> {
+    let mut running = 0;
+    let mut shards = 0;
+    let mut written = from;
+    let mut completed: BTreeMap<DateTime<Utc>, (Window, String)> = BTreeMap::new();
+    // No shards are handed out until then, after the server throttled one.
+    let mut resume = Instant::now();
+    loop {
+        let waiting = resume.saturating_duration_since(Instant::now());
+        while waiting.is_zero() && running < planner.concurrency() {
+            let Some(window) = planner.next_shard() else {
+                break;
+            };
+            log::debug!("Querying shard {} to {}", window.start, window.end);
+            jobs.send(window)
+                .map_err(|_| anyhow!("Export workers stopped."))?;
+            running += 1;
+        }
+        if running == 0 && waiting.is_zero() {
+            return Ok(shards);
+        }
+
+        let received = match running {
+            0 => Err(mpsc::RecvTimeoutError::Timeout),
+            _ if !waiting.is_zero() => done.recv_timeout(waiting),
+            _ => done
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        let (window, result) = match received {
+            Ok(received) => received,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                std::thread::sleep(resume.saturating_duration_since(Instant::now()));
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Export workers stopped."))
+            }
+        };
+        running -= 1;
+        match result {
+            Ok((text, elapsed)) => {
+                planner.observe(&window, elapsed);
+                progress.advance(text.len() as u64);
+                let text = logsh_core::redact::redact_columns(&text, &cfg.redact_columns)
+                    .map_err(|err| anyhow!("Failed to redact columns: {}", err))?;
+                completed.insert(window.start, (window, text.into_owned()));
+                shards += 1;
+            }
+            Err(QueryError::Throttled(status, retry_after)) => {
+                let Some(wait) = planner.throttle(window, retry_after) else {
+                    return Err(anyhow!(
+                        "The server kept throttling the export ({}), giving up.",
+                        status
+                    ));
+                };
+                log::warn!(
+                    "Server throttled the shard from {}, retrying in {} with {} at a time.",
+                    window.start,
+                    humantime::format_duration(wait),
+                    planner.concurrency()
+                );
+                resume = resume.max(Instant::now() + wait);
+            }
+            Err(err) if is_timeout(&err) && planner.split(window) => {
+                log::info!(
+                    "Shard from {} to {} timed out, splitting it.",
+                    window.start,
+                    window.end
+                );
+            }
+            Err(err) => {
+                crate::fmt::print_query_error(cfg, "", &err);
+                return Err(anyhow!(
+                    "Shard from {} to {} failed.",
+                    window.start.to_rfc3339(),
+                    window.end.to_rfc3339()
+                ));
+            }
+        }
+
+        while let Some((window, text)) = completed.remove(&written) {
+            assembler.shard(&text)?;
+            written = window.end;
+        }
+    }
+}
+
+fn is_timeout(err: &QueryError) -> bool {
+    match err {
+        QueryError::TimedOut(_) => true,
+        QueryError::Request(err) => {
+            err.is_timeout() || err.status() == Some(reqwest::StatusCode::GATEWAY_TIMEOUT)
+        }
+        _ => false,
+    }
+}
+
+/// Where an export goes.
+enum Output<'w> {
+    /// A file, or stdout.
+    Write(Box<dyn Write + 'w>),
+    /// Object storage, written in one piece at the end.
+    Cloud(String, Vec<u8>),
+    /// A SQLite database, as a file path or object storage URL.
+    Sqlite(String),
+}
+
+impl<'w> Output<'w> {
+    fn new<W: Write + 'w>(sink: Option<&OutputFileArg>, stdout: W) -> Result<Self, Error> {
+        let Some(sink) = sink else {
+            return Ok(Output::Write(Box::new(stdout)));
+        };
+        let url = sink.path.to_string_lossy().to_string();
+        if matches!(sink.mode, OutputMode::Sqlite) {
+            return Ok(Output::Sqlite(url));
+        }
+        if logsh_core::cloud::is_cloud_url(&url) {
+            return Ok(Output::Cloud(url, Vec::new()));
+        }
+
+        let file = File::create(&sink.path).map_err(|err| {
+            anyhow!(
+                "Failed to create output file {}: {}",
+                sink.path.display(),
+                err
+            )
+        })?;
+        Ok(Output::Write(Box::new(BufWriter::new(file))))
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        match self {
+            Output::Write(write) => write.write_all(bytes)?,
+            Output::Cloud(_, buffer) => buffer.extend_from_slice(bytes),
+            Output::Sqlite(_) => return Err(anyhow!("SQLite output is written at the end.")),
+        }
+        Ok(())
+    }
+}
+
+/// Puts shard results back together. Line formats are written as each shard arrives, with a
+/// CSV header only for the first, and the rest are rendered once from every row at the end.
+struct Assembler<'w> {
+    mode: OutputMode,
+    output: Output<'w>,
+    header: Option<Vec<String>>,
+    rows: Vec<Box<RawValue>>,
+    count: usize,
+}
+
+impl<'w> Assembler<'w> {
+    fn new(mode: OutputMode, output: Output<'w>) -> Self {
+        Self {
+            mode,
+            output,
+            header: None,
+            rows: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn streams(&self) -> bool {
+        matches!(
+            self.mode,
+            OutputMode::Csv | OutputMode::Tsv | OutputMode::JsonLines
+        )
+    }
+
+    fn shard(&mut self, text: &str) -> Result<(), Error> {
+        if !self.streams() {
+            let shard: ShardResult = serde_json::from_str(text)?;
+            self.count += shard.results.len();
+            self.header.get_or_insert(shard.header);
+            self.rows.extend(shard.results);
+            return Ok(());
+        }
+
+        let result = logsh_core::query::result(text)?;
+        let mut rendered = Vec::new();
+        crate::fmt::render_result(&result, None, self.mode, None, &mut rendered)?;
+        let first = self.header.is_none();
+        self.count += result.results.len();
+        self.header.get_or_insert_with(|| result.header.clone());
+        let rendered = match (first, self.mode) {
+            (false, OutputMode::Csv | OutputMode::Tsv) => {
+                let body = rendered
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .map_or(0, |i| i + 1);
+                &rendered[body..]
+            }
+            _ => &rendered[..],
+        };
+        self.output.write_all(rendered)
+    }
+
+    /// Writes whatever's left, returning the number of rows exported.
+    fn finish(mut self) -> Result<usize, Error> {
+        if !self.streams() {
+            let merged = serde_json::json!({
+                "header": self.header.take().unwrap_or_default(),
+                "results": std::mem::take(&mut self.rows),
+            })
+            .to_string();
+            let result = logsh_core::query::result(&merged)?;
+            match &self.output {
+                Output::Sqlite(url) if logsh_core::cloud::is_cloud_url(url) => {
+                    let temp = tempfile::Builder::new().suffix(".db").tempfile()?;
+                    logsh_core::sqlite::write_sqlite(&result, temp.path(), "results")?;
+                    logsh_core::cloud::put(url, std::fs::read(temp.path())?)
+                        .map_err(|err| anyhow!("Failed to write output file {}: {}", url, err))?;
+                }
+                Output::Sqlite(path) => {
+                    logsh_core::sqlite::write_sqlite(
+                        &result,
+                        std::path::Path::new(path),
+                        "results",
+                    )?;
+                }
+                _ => {
+                    let mut rendered = Vec::new();
+                    crate::fmt::render_result(&result, None, self.mode, None, &mut rendered)?;
+                    self.output.write_all(&rendered)?;
+                }
+            }
+        }
+
+        match self.output {
+            Output::Write(mut write) => write.flush()?,
+            Output::Cloud(url, buffer) => logsh_core::cloud::put(&url, buffer)
+                .map_err(|err| anyhow!("Failed to write output file {}: {}", url, err))?,
+            Output::Sqlite(_) => {}
+        }
+        Ok(self.count)
+    }
+}
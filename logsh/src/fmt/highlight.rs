@@ -0,0 +1,19 @@
+use colored::Colorize;
+use logsh_core::kusto::{tokenize, TokenKind};
+
+/// Colors a query for the terminal. Colors are left out when color output is off.
+pub fn highlight(query: &str) -> String {
+    tokenize(query)
+        .into_iter()
+        .map(|token| match token.kind {
+            TokenKind::Comment => token.text.bright_black().to_string(),
+            TokenKind::String => token.text.green().to_string(),
+            TokenKind::Number => token.text.yellow().to_string(),
+            TokenKind::Operator => token.text.bright_blue().bold().to_string(),
+            TokenKind::Keyword => token.text.blue().to_string(),
+            TokenKind::Function => token.text.cyan().to_string(),
+            TokenKind::Pipe => token.text.magenta().to_string(),
+            _ => token.text.to_string(),
+        })
+        .collect()
+}
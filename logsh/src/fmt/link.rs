@@ -0,0 +1,266 @@
+use std::{collections::HashMap, io::IsTerminal, sync::Mutex};
+
+use logsh_core::{
+    config::Links,
+    query::{QueryResult, Row as QueryRow},
+};
+
+/// What a column headed `ID` holds in the tables being rendered, as the label it's linked by.
+/// Set by commands that list one kind of thing, like `logsh sub ls`.
+static ID_COLUMN: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Sets what `ID` columns hold, returning what they held before.
+pub(crate) fn set_id_column(label: Option<&'static str>) -> Option<&'static str> {
+    let mut id = ID_COLUMN.lock().unwrap_or_else(|e| e.into_inner());
+    std::mem::replace(&mut id, label)
+}
+
+/// The web UI of the default connection's server, which IDs in terminal output link to.
+pub(crate) struct WebUi {
+    base: String,
+    subscription: Option<uuid::Uuid>,
+}
+
+impl WebUi {
+    /// None when links are off, the terminal can't show them, or the server isn't an HTTP URL.
+    pub(crate) fn for_default_connection() -> Option<WebUi> {
+        let cfg = logsh_core::config::load().ok()?;
+        if !enabled(cfg.links) {
+            return None;
+        }
+
+        let conn = cfg.get_default_connection()?;
+        let server = conn.connection.server.trim();
+        if !server.starts_with("http://") && !server.starts_with("https://") {
+            return None;
+        }
+
+        Some(WebUi {
+            base: server.trim_end_matches('/').to_string(),
+            subscription: conn.connection.default_subscription(),
+        })
+    }
+
+    pub(crate) fn subscription(&self, id: uuid::Uuid) -> String {
+        format!("{}/subscriptions/{}", self.base, id)
+    }
+
+    /// Trace pages belong to a subscription, so there's none without a default one.
+    fn trace(&self, id: &str) -> Option<String> {
+        self.subscription
+            .map(|s| format!("{}/subscriptions/{}/traces/{}", self.base, s, id))
+    }
+
+    /// Where a value links to, by the column or property it's shown under.
+    fn url(&self, label: &str, value: &str) -> Option<String> {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return Some(value.to_string());
+        }
+
+        let normalize = |label: &str| {
+            label
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        };
+        let mut label = normalize(label);
+        if label == "id" {
+            let id = *ID_COLUMN.lock().unwrap_or_else(|e| e.into_inner());
+            label = normalize(id?);
+        }
+        match label.as_str() {
+            "subscription" | "subscriptionid" | "defaultsubscription" | "accountid" => {
+                uuid::Uuid::parse_str(value)
+                    .ok()
+                    .map(|id| self.subscription(id))
+            }
+            "trace" | "traceid" => self.trace(value),
+            _ => None,
+        }
+    }
+}
+
+/// Whether to write hyperlinks with the `links` setting. Like colors, they're never written
+/// to files or pipes.
+fn enabled(links: Links) -> bool {
+    let colored = colored::control::SHOULD_COLORIZE.should_colorize();
+    match links {
+        Links::Never => false,
+        Links::Always => colored,
+        Links::Auto => colored && std::io::stdout().is_terminal() && terminal_supports_links(),
+    }
+}
+
+/// Recognizes terminals known to support OSC 8 hyperlinks. Others may print the escape codes.
+fn terminal_supports_links() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if std::env::var_os("CI").is_some() {
+        return false;
+    }
+
+    var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+        || [
+            "WT_SESSION",
+            "KONSOLE_VERSION",
+            "DOMTERM",
+            "KITTY_WINDOW_ID",
+        ]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+        || matches!(
+            var("TERM_PROGRAM").as_str(),
+            "iTerm.app" | "WezTerm" | "vscode" | "Hyper" | "ghostty" | "terminology"
+        )
+        || matches!(
+            var("TERM").as_str(),
+            "xterm-kitty" | "xterm-ghostty" | "alacritty" | "foot"
+        )
+}
+
+/// `text` as an OSC 8 hyperlink to `url`.
+pub(crate) fn hyperlink(text: &str, url: &str) -> String {
+    format!("\u{1b}]8;;{}\u{1b}\\{}\u{1b}]8;;\u{1b}\\", url, text)
+}
+
+/// A subscription ID, linked to its page when links are on.
+pub(crate) fn subscription_id(id: uuid::Uuid) -> String {
+    match WebUi::for_default_connection() {
+        Some(web) => hyperlink(&id.to_string(), &web.subscription(id)),
+        None => id.to_string(),
+    }
+}
+
+/// The cells of `result` that link somewhere, by row and column, with their text and where
+/// they link to. In a Property and Value table, like `logsh whoami`, a value is labelled by its
+/// property rather than its column.
+pub(crate) fn table_links(
+    result: &QueryResult<'_>,
+    web: &WebUi,
+) -> HashMap<(usize, usize), (String, String)> {
+    let properties = result.header == ["Property", "Value"];
+    let string = |row: &QueryRow<'_>, name: &str| match serde_json::from_str(row.get(name)?.get()) {
+        Ok(serde_json::Value::String(text)) => Some(text),
+        _ => None,
+    };
+
+    let mut links = HashMap::new();
+    for (r, row) in result.results.iter().enumerate() {
+        for (c, name) in result.header.iter().enumerate() {
+            let Some(text) = string(row, name) else {
+                continue;
+            };
+
+            let label = match properties && name == "Value" {
+                true => string(row, "Property").unwrap_or_default(),
+                false => name.to_string(),
+            };
+            if let Some(url) = web.url(&label, &text) {
+                links.insert((r, c), (text, url));
+            }
+        }
+    }
+    links
+}
+
+/// Whether a line of a laid out table is a border, rather than cells.
+fn is_border(line: &str) -> bool {
+    line.contains('\u{2500}')
+        && line
+            .chars()
+            .all(|c| c.is_whitespace() || ('\u{2500}'..='\u{257f}').contains(&c))
+}
+
+/// Turns the linked cells of an already laid out table into hyperlinks. Escape codes take no
+/// room on screen, so the layout is unchanged. Rows are told apart by the borders between
+/// them, and cells by the lines between those, so only the cell a link came from is linked. A
+/// cell a narrow column wrapped isn't linked.
+pub(crate) fn apply(rendered: String, links: HashMap<(usize, usize), (String, String)>) -> String {
+    if links.is_empty() {
+        return rendered;
+    }
+
+    let mut linked = String::with_capacity(rendered.len());
+    // Rows of cells seen so far, the first being the header.
+    let mut rows = 0;
+    let mut in_row = false;
+    for line in rendered.split_inclusive('\n') {
+        if is_border(line) {
+            in_row = false;
+            linked.push_str(line);
+            continue;
+        }
+        if !in_row {
+            rows += 1;
+            in_row = true;
+        }
+
+        // The text before the first line is the table's left border, and after the last its end.
+        let mut cells = line.split('\u{2502}');
+        linked.push_str(cells.next().unwrap_or_default());
+        for (c, cell) in cells.enumerate() {
+            linked.push('\u{2502}');
+            let link = match rows {
+                1 => None,
+                row => links.get(&(row - 2, c)),
+            };
+            match link {
+                Some((text, url)) if cell.trim() == text => {
+                    let start = cell.find(text.as_str()).unwrap_or_default();
+                    let end = start + text.len();
+                    linked.push_str(&cell[..start]);
+                    linked.push_str(&hyperlink(text, url));
+                    linked.push_str(&cell[end..]);
+                }
+                _ => linked.push_str(cell),
+            }
+        }
+    }
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "\
+┌──────┬──────┐
+│ Name │  ID  │
+├──────┼──────┤
+│  a1  │  a1  │
+├──────┼──────┤
+│  b2  │  a1  │
+└──────┴──────┘
+";
+
+    #[test]
+    fn links_only_the_cell_a_link_came_from() {
+        let links = HashMap::from([((0, 1), ("a1".to_string(), "https://x/a1".to_string()))]);
+        let linked = apply(TABLE.to_string(), links);
+        assert_eq!(linked.matches("https://x/a1").count(), 1);
+        let row = linked.lines().nth(3).unwrap();
+        assert!(row.starts_with("│  a1  │  \u{1b}]8;;https://x/a1\u{1b}\\a1"));
+    }
+
+    #[test]
+    fn wrapped_cells_arent_linked() {
+        let links = HashMap::from([((1, 1), ("a1-long".to_string(), "https://x".to_string()))]);
+        assert_eq!(apply(TABLE.to_string(), links), TABLE);
+    }
+
+    #[test]
+    fn id_columns_link_by_what_they_hold() {
+        let web = WebUi {
+            base: "https://x".to_string(),
+            subscription: None,
+        };
+        let id = "00000000-0000-0000-0000-000000000001";
+        assert_eq!(web.url("ID", id), None);
+        let previous = set_id_column(Some("subscription"));
+        assert_eq!(
+            web.url("ID", id).as_deref(),
+            Some("https://x/subscriptions/00000000-0000-0000-0000-000000000001")
+        );
+        set_id_column(previous);
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use annotate_snippets::{Annotation, AnnotationType, Renderer, Slice, Snippet, SourceAnnotation};
 
 use colored::Colorize;
@@ -7,12 +5,21 @@ use logsh_core::{
     common::{ErrorMessage, ErrorToken},
     config::Configuration,
     error::{ConfigError, ConnectError},
+    snippet::Expanded,
 };
 use reqwest::StatusCode;
 use serde::Serialize;
+use std::ops::Range;
 
+pub mod highlight;
+pub(crate) mod link;
+mod output;
 pub mod parse;
 
+pub(crate) use output::{
+    markdown_style, render, render_result, render_rows, render_with, set_fit_to_terminal,
+};
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Connection {
@@ -20,13 +27,34 @@ pub struct Connection {
     pub server: String,
     pub is_default: bool,
     pub username: String,
+    pub auth_type: Option<&'static str>,
+    /// When the saved token runs out. None for credentials that don't expire.
+    pub token_expires: Option<chrono::DateTime<chrono::Utc>>,
+    /// Round trip to the server's health endpoint, when pinged and it answered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// How the server answered the ping, when pinged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub groups: std::collections::BTreeMap<String, String>,
 }
 
-#[derive(Clone, Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct DataFrame {
-    pub headers: Vec<String>,
-    pub data: Vec<HashMap<String, serde_json::Value>>,
+/// Formats a byte count with binary units, e.g. "1.5 KiB".
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
 }
 
 pub fn print_connect_error(cfg: &Configuration, err: &ConnectError) {
@@ -42,7 +70,11 @@ pub fn print_connect_error(cfg: &Configuration, err: &ConnectError) {
         }
         ConnectError::Network(err) => print_reqwest_error(cfg, err),
         err => {
-            println!("{} {}", "Error:".red(), err.to_string().bright_red());
+            println!(
+                "{} {}",
+                "Error:".red(),
+                logsh_core::redact::redact(&err.to_string()).bright_red()
+            );
             print_add_connection_help();
         }
     }
@@ -85,7 +117,53 @@ pub fn print_add_connection_help() {
 }
 
 pub(crate) fn print_config_error(err: &ConfigError) {
-    println!("{} {}", "Error:".red(), err.to_string().red(),);
+    println!(
+        "{} {}",
+        "Error:".red(),
+        logsh_core::redact::redact(&err.to_string()).red(),
+    );
+}
+
+/// Where a query was written, so errors point at the file and line it came from.
+pub(crate) struct QuerySource {
+    /// The file or saved query the query was read from.
+    pub origin: Option<String>,
+    /// The query as written, before snippets were expanded.
+    pub written: String,
+    pub expanded: Expanded,
+}
+
+impl QuerySource {
+    /// A query given on the command line or typed in, with nothing to point back to.
+    pub fn inline(query: &str) -> Self {
+        Self {
+            origin: None,
+            written: query.to_string(),
+            expanded: Expanded::unexpanded(query),
+        }
+    }
+
+    /// The character range in the written query of a byte range in the expanded one.
+    fn written_chars(&self, range: Range<usize>) -> (usize, usize) {
+        let range = self.expanded.original_range(range);
+        let chars = |byte: usize| {
+            let mut byte = byte.min(self.written.len());
+            while !self.written.is_char_boundary(byte) {
+                byte -= 1;
+            }
+            self.written[..byte].chars().count()
+        };
+        (chars(range.start), chars(range.end))
+    }
+
+    /// The byte offset in the expanded query of a character offset.
+    fn expanded_byte(&self, char: usize) -> usize {
+        self.expanded
+            .text
+            .char_indices()
+            .nth(char)
+            .map_or(self.expanded.text.len(), |(byte, _)| byte)
+    }
 }
 
 pub(crate) fn print_query_error(
@@ -93,6 +171,21 @@ pub(crate) fn print_query_error(
     query: &str,
     err: &logsh_core::error::QueryError,
 ) {
+    print_query_error_from(cfg, &QuerySource::inline(query), err)
+}
+
+/// Like `print_query_error`, attributing the snippet to the file the query came from, with
+/// positions traced back through snippet expansion to the lines as written.
+pub(crate) fn print_query_error_from(
+    cfg: &Configuration,
+    source: &QuerySource,
+    err: &logsh_core::error::QueryError,
+) {
+    // Lines of a file are numbered from one, like editors do.
+    let line_start = match source.origin {
+        Some(_) => 1,
+        None => 0,
+    };
     match err {
         logsh_core::error::QueryError::Config(err) => print_config_error(err),
         logsh_core::error::QueryError::Request(err) => print_reqwest_error(cfg, err),
@@ -102,7 +195,7 @@ pub(crate) fn print_query_error(
             let mut annotations = Vec::new();
             for e in bad_request.errors.iter() {
                 for t in e.tokens.iter() {
-                    if let Some(annotation) = to_source_annotation(e, t) {
+                    if let Some(annotation) = to_source_annotation(source, e, t) {
                         annotations.push(annotation);
                     }
                 }
@@ -111,7 +204,7 @@ pub(crate) fn print_query_error(
             // This is stupid, but the library we're using is stupid.
             // You can't highlight an error which goes all the way tot he end of the line.
             // So add a tiny space to the end of the line.
-            let extended_source = query.to_string() + " ";
+            let extended_source = source.written.to_string() + " ";
             let snippy = Snippet {
                 title: Some(Annotation {
                     label: Some(bad_request.message.as_str()),
@@ -121,30 +214,77 @@ pub(crate) fn print_query_error(
                 footer: vec![],
                 slices: vec![Slice {
                     source: extended_source.as_str(),
-                    line_start: 0,
-                    origin: None,
+                    line_start,
+                    origin: source.origin.as_deref(),
                     fold: true,
                     annotations,
                 }],
             };
 
+            if snippy
+                .slices
+                .iter()
+                .all(|slice| slice.annotations.is_empty())
+            {
+                // Nothing to point at, so show the query colored instead of as a plain snippet.
+                println!("{} {}", "error:".red().bold(), bad_request.message.bold());
+                println!("{}", highlight::highlight(&source.written));
+                return;
+            }
+
             let renderer = Renderer::styled();
             println!("{}", renderer.render(snippy));
         }
+        logsh_core::error::QueryError::Syntax(findings) => {
+            // Findings hold byte offsets, the snippet wants characters.
+            let annotations = findings
+                .iter()
+                .map(|f| SourceAnnotation {
+                    label: f.message.as_str(),
+                    annotation_type: AnnotationType::Error,
+                    range: source.written_chars(f.start..f.end),
+                })
+                .collect();
+            let extended_source = source.written.to_string() + " ";
+            let snippy = Snippet {
+                title: Some(Annotation {
+                    label: Some("Invalid query, not sent to the server."),
+                    id: None,
+                    annotation_type: AnnotationType::Error,
+                }),
+                footer: vec![],
+                slices: vec![Slice {
+                    source: extended_source.as_str(),
+                    line_start,
+                    origin: source.origin.as_deref(),
+                    fold: true,
+                    annotations,
+                }],
+            };
+            println!("{}", Renderer::styled().render(snippy));
+        }
         logsh_core::error::QueryError::Connection(err) => print_connect_error(cfg, err),
         err => {
-            println!("{} {}", "Error:".red(), err.to_string().red(),);
+            println!(
+                "{} {}",
+                "Error:".red(),
+                logsh_core::redact::redact(&err.to_string()).red(),
+            );
         }
     }
 }
 
 fn to_source_annotation<'a>(
+    source: &QuerySource,
     msg: &'a ErrorMessage,
     e: &'a ErrorToken,
 ) -> Option<SourceAnnotation<'a>> {
+    let start = source.expanded_byte(e.start as usize);
+    let end = source.expanded_byte(e.end as usize);
+    let (start, end) = source.written_chars(start..end);
     msg.message.as_ref().map(|msg| SourceAnnotation {
         label: msg.as_str(),
         annotation_type: AnnotationType::Error,
-        range: (e.start as usize + 1, e.end as usize + 1),
+        range: (start + 1, end + 1),
     })
 }
@@ -0,0 +1,412 @@
+use std::{
+    io::{IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use logsh_core::query::{QueryResult, QueryResultFmt};
+use serde::Serialize;
+use serde_json::value::to_raw_value;
+use term_table::{
+    row::Row,
+    table_cell::{Alignment, TableCell},
+    Table, TableStyle,
+};
+
+use crate::OutputMode;
+
+pub(crate) fn markdown_style() -> TableStyle {
+    let mut style: TableStyle = TableStyle::simple();
+    style.top_left_corner = '│';
+    style.top_right_corner = '│';
+    style.bottom_left_corner = '│';
+    style.bottom_right_corner = '│';
+    style.outer_right_vertical = '|';
+    style.outer_left_vertical = '|';
+    style.intersection = '|';
+    style.vertical = '|';
+    style
+}
+
+/// Writes a list-style command's output in any output mode. JSON modes serialize `data` as is,
+/// so scripts get every field, and the other modes render the rows `rows` builds from it.
+pub(crate) fn render<W: Write, T: Serialize + ?Sized>(
+    write: W,
+    mode: OutputMode,
+    data: &T,
+    rows: impl FnOnce(&T) -> QueryResultFmt,
+) -> Result<(), Error> {
+    render_with(write, mode, data, |write, mode, data| {
+        render_rows(write, mode, rows(data))
+    })
+}
+
+/// Like [render], for commands whose output for people isn't a single table. JSON modes
+/// serialize `data` as is, and `human` writes the other modes.
+pub(crate) fn render_with<W: Write, T: Serialize + ?Sized>(
+    mut write: W,
+    mode: OutputMode,
+    data: &T,
+    human: impl FnOnce(&mut W, OutputMode, &T) -> Result<(), Error>,
+) -> Result<(), Error> {
+    // A default_output setting meant for query results, such as sqlite, doesn't apply here.
+    let mode = match mode.is_query_only() {
+        true => OutputMode::Table,
+        false => mode,
+    };
+    match mode {
+        OutputMode::Json => writeln!(write, "{}", serde_json::to_string(data)?)?,
+        OutputMode::JsonPretty => writeln!(write, "{}", serde_json::to_string_pretty(data)?)?,
+        _ => human(&mut write, mode, data)?,
+    }
+    Ok(())
+}
+
+/// Writes a query result in any output mode. `raw` is the response as the server sent it, which
+/// unformatted JSON writes as is.
+pub(crate) fn render_result<W: Write>(
+    result: &QueryResult<'_>,
+    raw: Option<&str>,
+    mode: OutputMode,
+    template: Option<&str>,
+    mut write: W,
+) -> Result<(), Error> {
+    if let (OutputMode::Json, Some(raw)) = (mode, raw) {
+        log::trace!("Outputting unformatted JSON as received");
+        writeln!(write, "{}", raw)?;
+        return Ok(());
+    }
+
+    match mode {
+        OutputMode::Table => {
+            log::trace!("Outputting table");
+            render_table(result, TableStyle::thin(), false, write)
+        }
+        OutputMode::Markdown => {
+            log::trace!("Outputting markdown table");
+            render_table(result, markdown_style(), true, write)
+        }
+        OutputMode::Template => {
+            log::trace!("Outputting template");
+            let template =
+                template.ok_or_else(|| anyhow!("Template output requires --template."))?;
+            logsh_core::template::write_template(result, template, write)
+                .map_err(|e| anyhow!("Failed to render template: {}", e))
+        }
+        OutputMode::Sqlite => Err(anyhow!(
+            "SQLite output is written to a database file. Use --output-file <PATH>.db."
+        )),
+        mode => {
+            log::trace!("Outputting {}", mode.name());
+            let renderer = logsh_core::render::find(mode.name())
+                .ok_or_else(|| anyhow!("Unknown output format \"{}\".", mode.name()))?;
+            renderer.render(result, &mut write)?;
+            Ok(())
+        }
+    }
+}
+
+/// Renders rows built by a command, rather than returned by a query, in any output mode.
+pub(crate) fn render_rows<W: Write>(
+    write: W,
+    mode: OutputMode,
+    rows: QueryResultFmt,
+) -> Result<(), Error> {
+    let values = rows
+        .results
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|(column, value)| Ok((column.as_str(), to_raw_value(value)?)))
+                .collect::<Result<Vec<_>, serde_json::Error>>()
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("Error converting rows to query result: {}", e))?;
+    let result = QueryResult {
+        header: rows.header,
+        results: values
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|(column, value)| (*column, &**value))
+                    .collect()
+            })
+            .collect(),
+        statistics: None,
+    };
+    if result.results.is_empty() {
+        log::info!("No rows to render.");
+    }
+
+    render_result(&result, None, mode, None, write)
+}
+
+/// Narrowest a column is squeezed to before columns are dropped to fit the terminal.
+const MIN_COLUMN_WIDTH: usize = 10;
+
+/// Whether tables are fitted to the terminal. Off while rendering for files and the clipboard.
+static FIT_TO_TERMINAL: AtomicBool = AtomicBool::new(true);
+
+/// Turns fitting tables to the terminal on or off, returning whether it was on.
+pub(crate) fn set_fit_to_terminal(fit: bool) -> bool {
+    FIT_TO_TERMINAL.swap(fit, Ordering::Relaxed)
+}
+
+/// Columns tables are fitted to: the terminal's width when stdout is one.
+fn terminal_width() -> Option<usize> {
+    if !FIT_TO_TERMINAL.load(Ordering::Relaxed) || !std::io::stdout().is_terminal() {
+        return None;
+    }
+
+    crossterm::terminal::size()
+        .ok()
+        .map(|(columns, _)| columns as usize)
+        .filter(|columns| *columns > 0)
+}
+
+/// Characters on the longest line of a cell, leaving out terminal color codes.
+fn display_width(text: &str) -> usize {
+    let mut longest = 0;
+    for line in text.lines() {
+        let mut width = 0;
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\u{1b}' {
+                // Skip the escape sequence up to and including its final letter.
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            width += 1;
+        }
+        longest = longest.max(width);
+    }
+    longest
+}
+
+/// Widths that fit columns of `natural` width into `width` terminal columns. Columns that
+/// don't fit even at [MIN_COLUMN_WIDTH] are left off from the right, so the result may be
+/// shorter than `natural`. Columns narrower than their share keep their width, and the
+/// rest split what's left, wrapping their cells.
+fn fit_columns(natural: &[usize], width: usize) -> Vec<usize> {
+    // Each column has a space either side and a border to its right, and the table a border
+    // on the left.
+    let overhead = |columns: usize| columns * 3 + 1;
+    if natural.iter().sum::<usize>() + overhead(natural.len()) <= width {
+        return natural.to_vec();
+    }
+
+    let mut kept = 0;
+    let mut minimum = 0;
+    for n in natural {
+        let next = minimum + n.min(&MIN_COLUMN_WIDTH);
+        if kept > 0 && next + overhead(kept + 1) > width {
+            break;
+        }
+        minimum = next;
+        kept += 1;
+    }
+
+    let mut widths = natural[..kept].to_vec();
+    let mut available = width.saturating_sub(overhead(kept));
+    let mut wide: Vec<usize> = (0..kept).collect();
+    loop {
+        let share = (available / wide.len().max(1)).max(1);
+        let (narrow, rest): (Vec<usize>, Vec<usize>) =
+            wide.iter().partition(|i| widths[**i] <= share);
+        if narrow.is_empty() || rest.is_empty() {
+            for i in wide.iter() {
+                widths[*i] = widths[*i].min(share);
+            }
+            return widths;
+        }
+        available = available.saturating_sub(narrow.iter().map(|i| widths[*i]).sum());
+        wide = rest;
+    }
+}
+
+fn render_table<W: Write>(
+    result: &QueryResult<'_>,
+    style: TableStyle,
+    is_markdown: bool,
+    mut write: W,
+) -> Result<(), Error> {
+    let header: Vec<TableCell> = result
+        .header
+        .iter()
+        .map(|s| {
+            if is_markdown {
+                s.to_string()
+            } else {
+                s.bright_white().bold().to_string()
+            }
+        })
+        .map(|f| TableCell::new_with_alignment(f, 1, Alignment::Center))
+        .collect();
+
+    let rows: Vec<Vec<TableCell>> = result
+        .results
+        .iter()
+        .map(|row| {
+            result
+                .header
+                .iter()
+                .map(|header| {
+                    let raw = row.get(header.as_str()).map_or("null", |v| v.get());
+                    table_cell(header, raw, is_markdown)
+                })
+                .collect()
+        })
+        .collect();
+
+    // Markdown is meant to be copied elsewhere, so it's never fitted to the terminal.
+    let widths = match (is_markdown, terminal_width()) {
+        (false, Some(width)) => {
+            let natural: Vec<usize> = (0..header.len())
+                .map(|i| {
+                    std::iter::once(&header)
+                        .chain(rows.iter())
+                        .filter_map(|row| row.get(i))
+                        .map(|cell| display_width(&cell.data))
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            Some(fit_columns(&natural, width))
+        }
+        _ => None,
+    };
+    let kept = widths.as_ref().map_or(header.len(), |w| w.len());
+
+    let mut table = Table::new();
+    table.style = style;
+    table.has_bottom_boarder = !is_markdown;
+    if let Some(widths) = widths.as_ref() {
+        table.set_max_column_widths(widths.iter().copied().enumerate().collect());
+    }
+
+    let mut header_row = Row::new(header.into_iter().take(kept));
+    header_row.has_separator = !is_markdown;
+    table.add_row(header_row);
+
+    let mut is_first = true;
+    for cells in rows {
+        let mut row = Row::new(cells.into_iter().take(kept));
+        row.has_separator = !is_markdown || is_first;
+        table.add_row(row);
+
+        is_first = false;
+    }
+
+    log::trace!("Render table.");
+
+    let table = match (is_markdown, super::link::WebUi::for_default_connection()) {
+        (false, Some(web)) => {
+            super::link::apply(table.render(), super::link::table_links(result, &web))
+        }
+        _ => table.render(),
+    };
+    writeln!(write, "{}", table).map_err(|e| anyhow!("Failed to write table: {}", e))?;
+    let dropped = result.header.len() - kept;
+    if dropped > 0 {
+        writeln!(
+            write,
+            "{}",
+            format!(
+                "(+{} more {})",
+                dropped,
+                if dropped == 1 { "column" } else { "columns" }
+            )
+            .bright_black()
+        )
+        .map_err(|e| anyhow!("Failed to write table: {}", e))?;
+    }
+    Ok(())
+}
+
+/// A cell shown as the server sent it, so numbers keep their precision and timestamps their
+/// format. The JSON type only picks the color and alignment.
+fn table_cell(header: &str, raw: &str, is_markdown: bool) -> TableCell<'static> {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(raw) else {
+        return TableCell::new_with_alignment(raw, 1, Alignment::Center);
+    };
+    if header == "json" {
+        let json = serde_json::to_string_pretty(&json).unwrap_or_else(|_| raw.to_string());
+        return TableCell::new_with_alignment(json, 1, Alignment::Center);
+    }
+
+    if !is_markdown {
+        match json {
+            serde_json::Value::Null => {
+                return TableCell::new_with_alignment("<null>".bright_black(), 1, Alignment::Center)
+            }
+            serde_json::Value::Bool(b) => {
+                return TableCell::new_with_alignment(
+                    if b { "true".green() } else { "false".red() },
+                    1,
+                    Alignment::Center,
+                )
+            }
+            serde_json::Value::Number(_) => {
+                return TableCell::new_with_alignment(raw, 1, Alignment::Left)
+            }
+            serde_json::Value::String(s) => {
+                return TableCell::new_with_alignment(s, 1, Alignment::Center)
+            }
+            _ => { /* noop */ }
+        }
+    }
+
+    let serialized = serde_json::to_string_pretty(&json).unwrap_or_else(|_| raw.to_string());
+    TableCell::new_with_alignment(serialized, 1, Alignment::Center)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_show_numbers_and_timestamps_as_sent() {
+        assert_eq!(table_cell("n", "5.0", false).data, "5.0");
+        assert_eq!(
+            table_cell("n", "18446744073709551615", false).data,
+            "18446744073709551615"
+        );
+        assert_eq!(
+            table_cell("t", r#""2024-03-01T10:30:00.000Z""#, false).data,
+            "2024-03-01T10:30:00.000Z"
+        );
+    }
+
+    #[test]
+    fn cells_that_arent_json_are_shown_raw() {
+        assert_eq!(table_cell("n", "not json", false).data, "not json");
+    }
+
+    #[test]
+    fn markdown_cells_stay_json() {
+        assert_eq!(table_cell("s", r#""a""#, true).data, r#""a""#);
+        assert_eq!(
+            table_cell("json", r#"{"a":1}"#, false).data,
+            "{\n  \"a\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn rows_render_in_header_order() {
+        let rows = QueryResultFmt {
+            header: vec!["name".to_string(), "rows".to_string()],
+            results: vec![std::collections::HashMap::from([
+                ("rows".to_string(), serde_json::json!(3)),
+                ("name".to_string(), serde_json::json!("Logs")),
+            ])],
+        };
+        let mut out = Vec::new();
+        render_rows(&mut out, OutputMode::Csv, rows).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "name,rows\nLogs,3\n");
+    }
+}
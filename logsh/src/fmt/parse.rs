@@ -1,5 +1,14 @@
 use core::fmt;
-use std::str::FromStr;
+use std::{ffi::OsStr, path::PathBuf, str::FromStr};
+
+use anyhow::anyhow;
+use clap::{
+    builder::{PossibleValue, TypedValueParser},
+    error::ErrorKind,
+};
+use logsh_core::connect::Timeouts;
+
+use crate::OutputMode;
 
 #[derive(Clone, Copy, Debug)]
 pub struct OptionalDurationArg {
@@ -28,6 +37,14 @@ impl From<OptionalDurationArg> for Option<std::time::Duration> {
     }
 }
 
+/// A total limit. "none" is a zero limit, which lifts the connection's rather than falling back
+/// to it.
+impl From<OptionalDurationArg> for Timeouts {
+    fn from(val: OptionalDurationArg) -> Self {
+        Timeouts::total(Some(val.duration.unwrap_or_default()))
+    }
+}
+
 impl AsRef<Option<std::time::Duration>> for OptionalDurationArg {
     fn as_ref(&self) -> &Option<std::time::Duration> {
         &self.duration
@@ -48,3 +65,114 @@ impl fmt::Display for OptionalDurationArg {
         }
     }
 }
+
+/// Parses `-o` with [OutputMode]'s `FromStr`, and offers the format names it accepts to help
+/// and completions. List commands don't accept the formats only query results are written in.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutputModeParser {
+    /// Whether the formats only query results can be written in are accepted too.
+    query: bool,
+}
+
+impl OutputModeParser {
+    /// Accepts every format, for commands that output query results.
+    pub fn query() -> Self {
+        Self { query: true }
+    }
+
+    fn accepts(&self, mode: &OutputMode) -> bool {
+        self.query || !mode.is_query_only()
+    }
+}
+
+impl TypedValueParser for OutputModeParser {
+    type Value = OutputMode;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        _arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = value
+            .to_str()
+            .ok_or_else(|| clap::Error::new(ErrorKind::InvalidUtf8).with_cmd(cmd))?;
+        let invalid =
+            |err| clap::Error::raw(ErrorKind::InvalidValue, format!("{}\n", err)).with_cmd(cmd);
+        let mode: OutputMode = value.parse().map_err(invalid)?;
+        if !self.accepts(&mode) {
+            let names: Vec<_> = OutputMode::available()
+                .into_iter()
+                .filter(|m| self.accepts(m))
+                .map(|m| m.name())
+                .collect();
+            return Err(invalid(anyhow!(
+                "{} output is only for query results. Use one of: {}.",
+                mode.name(),
+                names.join(", ")
+            )));
+        }
+        Ok(mode)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            OutputMode::available()
+                .into_iter()
+                .filter(|mode| self.accepts(mode))
+                .map(|mode| {
+                    PossibleValue::new(mode.name()).aliases(mode.aliases().iter().copied())
+                }),
+        ))
+    }
+}
+
+/// An output file given as `PATH[:FORMAT]`. Without a format suffix, the format is inferred
+/// from the file extension. The path may be an object storage URL, like `s3://bucket/key`,
+/// in builds with the `cloud` feature.
+#[derive(Clone, Debug)]
+pub struct OutputFileArg {
+    pub path: PathBuf,
+    pub mode: OutputMode,
+}
+
+impl FromStr for OutputFileArg {
+    type Err = anyhow::Error;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        if let Some((path, format)) = arg.rsplit_once(':') {
+            if let (false, Ok(mode)) = (path.is_empty(), format.parse::<OutputMode>()) {
+                return Ok(Self {
+                    path: PathBuf::from(path),
+                    mode,
+                });
+            }
+        }
+
+        let path = PathBuf::from(arg);
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        // Formats rendered by logsh_core, including registered ones, say which extensions are
+        // theirs.
+        let registered = extension
+            .as_deref()
+            .and_then(logsh_core::render::for_extension)
+            .and_then(|r| r.name().parse::<OutputMode>().ok());
+        let mode = match (extension.as_deref(), registered) {
+            (_, Some(mode)) => mode,
+            (Some("md") | Some("markdown"), _) => OutputMode::Markdown,
+            (Some("txt"), _) => OutputMode::Table,
+            (Some("db") | Some("sqlite") | Some("sqlite3"), _) => OutputMode::Sqlite,
+            _ => {
+                return Err(anyhow!(
+                "Unable to infer the output format of \"{}\". Use PATH:FORMAT, e.g. \"{}:csv\".",
+                arg,
+                arg
+            ))
+            }
+        };
+
+        Ok(Self { path, mode })
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use logsh_core::{
+    grafana::{self, LogQlTarget},
+    query::QueryResultFmt,
+    saved_query::{self, SavedQuery},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Debug, Subcommand)]
+#[clap(about = "Import queries from other tools as saved queries.")]
+pub enum ImportCommand {
+    #[clap(
+        about = "Save the panel queries of a Grafana dashboard. LogQL is converted where it can be, and everything else is saved as a TODO comment holding the original."
+    )]
+    Grafana {
+        #[arg(help = "Dashboard JSON, as exported from Grafana or returned by its API.")]
+        path: PathBuf,
+        #[arg(
+            long,
+            help = "Start of each saved query's name. Defaults to the dashboard's title."
+        )]
+        prefix: Option<String>,
+        #[arg(
+            long,
+            default_value = "Logs",
+            help = "Table that converted LogQL queries read. Stream labels are expected to be its columns."
+        )]
+        table: String,
+        #[arg(
+            long,
+            value_name = "COLUMN",
+            default_value = "message",
+            help = "Column holding the log line, which LogQL line filters match."
+        )]
+        message_column: String,
+        #[arg(
+            long,
+            value_name = "COLUMN",
+            default_value = "timestamp",
+            help = "Column that count_over_time and rate are binned by."
+        )]
+        timestamp_column: String,
+        #[arg(long, help = "Replace saved queries that already have the same name.")]
+        force: bool,
+        #[arg(long, help = "Show what would be saved without saving it.")]
+        dry_run: bool,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+}
+
+/// One panel query of an import, and what became of it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportedQuery {
+    name: String,
+    panel: String,
+    language: &'static str,
+    status: String,
+    query: String,
+}
+
+pub fn execute_import<W: Write>(command: ImportCommand, write: W) -> Result<(), Error> {
+    match command {
+        ImportCommand::Grafana {
+            path,
+            prefix,
+            table,
+            message_column,
+            timestamp_column,
+            force,
+            dry_run,
+            output,
+        } => {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+            let dashboard = grafana::parse(&text)?;
+            if dashboard.queries.is_empty() {
+                return Err(anyhow!(
+                    "Dashboard \"{}\" has no panel queries.",
+                    dashboard.title
+                ));
+            }
+
+            let prefix = prefix.unwrap_or_else(|| grafana::slug(&dashboard.title));
+            let target = LogQlTarget {
+                table: &table,
+                message: &message_column,
+                timestamp: &timestamp_column,
+            };
+            let mut saved = saved_query::load()?;
+            let mut queries = Vec::new();
+            for imported in grafana::import(&dashboard, &prefix, &target) {
+                let exists = saved.queries.contains_key(imported.name.trim());
+                let status = match (&imported.todo, exists && !force) {
+                    (_, true) => "skipped, a saved query has this name".to_string(),
+                    (None, false) => "converted".to_string(),
+                    (Some(reason), false) => format!("todo, {}", reason),
+                };
+                if !exists || force {
+                    log::debug!("Saving imported query \"{}\".", imported.name);
+                    saved.insert(SavedQuery::new(
+                        &imported.name,
+                        &imported.query,
+                        Some(format!("{} panel \"{}\"", dashboard.title, imported.panel)),
+                    ));
+                }
+                queries.push(ImportedQuery {
+                    name: imported.name,
+                    panel: imported.panel,
+                    language: imported.language.name(),
+                    status,
+                    query: imported.query,
+                });
+            }
+            if !dry_run {
+                saved_query::save(saved)?;
+            }
+
+            crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &queries,
+                |queries| {
+                    let results = queries
+                        .iter()
+                        .map(|q| {
+                            HashMap::from([
+                                ("Name".to_string(), Value::from(q.name.clone())),
+                                ("Panel".to_string(), Value::from(q.panel.clone())),
+                                ("Language".to_string(), Value::from(q.language)),
+                                ("Status".to_string(), Value::from(q.status.clone())),
+                            ])
+                        })
+                        .collect();
+                    QueryResultFmt {
+                        header: ["Name", "Panel", "Language", "Status"]
+                            .map(String::from)
+                            .to_vec(),
+                        results,
+                    }
+                },
+            )
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use logsh_core::{
+    inflow::{list_inflows, InflowModel},
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+};
+use serde_json::Value;
+use std::{collections::HashMap, io::Write};
+
+use crate::OutputMode;
+
+#[derive(Subcommand)]
+#[clap(about = "Discover the inflow endpoints that uploads are sent to.")]
+pub enum InflowCommand {
+    #[clap(
+        about = "List the schemas the server accepts uploads for, and their formats",
+        visible_alias = "ls"
+    )]
+    List {
+        #[arg(short, long, add = crate::complete::schemas(), help = "Only list this schema. Fails when the server has no inflow endpoint for it.")]
+        schema: Option<String>,
+        #[arg(
+            short,
+            long,
+            help = "Only list schemas accepting this format, such as csv."
+        )]
+        format: Option<String>,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+}
+
+pub fn execute_inflow<W: Write>(command: InflowCommand, write: W) -> Result<(), Error> {
+    let conn_handler = LogshClientHandler::new();
+    match command {
+        InflowCommand::List {
+            schema,
+            format,
+            output,
+        } => {
+            let mut inflows = list_inflows(&conn_handler)?;
+            if let Some(schema) = schema.as_ref() {
+                inflows.retain(|i| i.schema.eq_ignore_ascii_case(schema));
+                if inflows.is_empty() {
+                    return Err(anyhow!(
+                        "The server has no inflow endpoint for schema \"{}\". Run \"logsh inflow ls\" to list them.",
+                        schema
+                    ));
+                }
+            }
+            if let Some(format) = format.as_ref() {
+                inflows.retain(|i| i.accepts(format));
+            }
+            inflows.sort_by(|a, b| a.schema.cmp(&b.schema));
+
+            let connection = conn_handler.get_connection()?;
+            let subscription = conn_handler.get_subscription()?;
+            let base = format!(
+                "{}/inflow/{}",
+                connection.server.trim_end_matches('/'),
+                subscription
+            );
+            crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &inflows,
+                |inflows| inflow_rows(inflows, &base),
+            )
+        }
+    }
+}
+
+fn inflow_rows(inflows: &[InflowModel], base: &str) -> QueryResultFmt {
+    let results = inflows
+        .iter()
+        .map(|i| {
+            HashMap::from([
+                ("Schema".to_string(), Value::String(i.schema.to_string())),
+                ("Formats".to_string(), Value::String(i.formats.join(", "))),
+                (
+                    "Endpoint".to_string(),
+                    Value::String(format!("{}/{}/<format>", base, i.schema)),
+                ),
+                (
+                    "Description".to_string(),
+                    i.description
+                        .as_ref()
+                        .map_or(Value::Null, |d| Value::String(d.to_string())),
+                ),
+            ])
+        })
+        .collect();
+
+    QueryResultFmt {
+        header: ["Schema", "Formats", "Endpoint", "Description"]
+            .map(String::from)
+            .to_vec(),
+        results,
+    }
+}
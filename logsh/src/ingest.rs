@@ -0,0 +1,251 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    generate::Template,
+};
+
+use crate::fmt::parse::OptionalDurationArg;
+
+#[derive(Subcommand)]
+#[clap(about = "Ingestion tools.")]
+pub enum IngestCommand {
+    #[clap(
+        about = "Generate fake rows and upload them at a steady rate, for sizing and soak tests."
+    )]
+    Generate {
+        #[arg(short, long, help = "Schema to upload the generated rows to.")]
+        schema: String,
+
+        #[arg(
+            short,
+            long,
+            value_name = "COUNT/UNIT",
+            default_value = "100/s",
+            help = "Rows to generate, such as 5000/s, 300/m or 10000/h."
+        )]
+        rate: RateArg,
+
+        #[arg(
+            short,
+            long,
+            default_value = "1m",
+            help = "How long to keep generating rows."
+        )]
+        duration: humantime::Duration,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "JSON object describing a row. String values such as \"{{int:1..100}}\" are replaced with fake data. Defaults to an application log line."
+        )]
+        template: Option<PathBuf>,
+
+        #[arg(
+            short,
+            long,
+            default_value = "10000",
+            help = "Most rows uploaded in one request."
+        )]
+        batch_size: usize,
+
+        #[arg(
+            short,
+            long,
+            help = "Upload timeout for each batch. Use \"none\" to disable timeout.",
+            default_value = "60s"
+        )]
+        timeout: OptionalDurationArg,
+    },
+}
+
+/// A row rate given as `COUNT/UNIT`, where unit is s, m or h. A bare count is per second.
+#[derive(Clone, Copy, Debug)]
+pub struct RateArg {
+    per_second: f64,
+}
+
+impl FromStr for RateArg {
+    type Err = anyhow::Error;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (count, unit) = arg.split_once('/').unwrap_or((arg, "s"));
+        let count: f64 = count
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("\"{}\" is not a row count.", count))?;
+        let seconds = match unit.trim() {
+            "s" | "sec" | "second" => 1.0,
+            "m" | "min" | "minute" => 60.0,
+            "h" | "hour" => 3600.0,
+            other => return Err(anyhow!("Unknown rate unit \"{}\". Use s, m or h.", other)),
+        };
+        if !count.is_finite() || count <= 0.0 {
+            return Err(anyhow!("The rate must be greater than zero."));
+        }
+
+        Ok(Self {
+            per_second: count / seconds,
+        })
+    }
+}
+
+pub fn execute_ingest(command: IngestCommand) -> Result<(), Error> {
+    match command {
+        IngestCommand::Generate {
+            schema,
+            rate,
+            duration,
+            template,
+            batch_size,
+            timeout,
+        } => {
+            if batch_size == 0 {
+                return Err(anyhow!("The batch size must be greater than zero."));
+            }
+
+            let mut template = match template {
+                Some(path) => {
+                    let text = std::fs::read_to_string(&path).map_err(|err| {
+                        anyhow!("Failed to read template {}: {}", path.display(), err)
+                    })?;
+                    Template::parse(&text)?
+                }
+                None => Template::default(),
+            };
+
+            let cfg = config::load()?;
+            let mut connection = cfg
+                .get_default_connection()
+                .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+            generate(
+                &mut connection,
+                &schema,
+                &mut template,
+                rate,
+                duration.into(),
+                batch_size,
+                timeout.into(),
+            )
+        }
+    }
+}
+
+/// Uploads rows whenever the target count for the elapsed time runs ahead of what was sent,
+/// checking once a second until the duration is over.
+fn generate(
+    connection: &mut config::ConnectionConfig,
+    schema: &str,
+    template: &mut Template,
+    rate: RateArg,
+    duration: Duration,
+    batch_size: usize,
+    timeout: Option<Duration>,
+) -> Result<(), Error> {
+    const TICK: Duration = Duration::from_secs(1);
+
+    log::info!(
+        "Generating {} rows/s into {} for {}",
+        rate.per_second,
+        schema,
+        humantime::format_duration(duration)
+    );
+    let progress = crate::progress::enabled();
+    let start = Instant::now();
+    let mut sent = 0usize;
+    let mut batches = 0usize;
+    loop {
+        let elapsed = start.elapsed().min(duration);
+        let target = (rate.per_second * elapsed.as_secs_f64()) as usize;
+        if sent < target {
+            batches += upload_generated(
+                connection,
+                schema,
+                template,
+                target - sent,
+                batch_size,
+                timeout,
+            )?;
+            sent = target;
+        }
+
+        if progress {
+            eprint!(
+                "\rUploaded {} rows in {} batches, {:.0} rows/s ",
+                sent,
+                batches,
+                sent as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON)
+            );
+            std::io::stderr().flush()?;
+        }
+        if elapsed >= duration {
+            break;
+        }
+
+        // Time spent uploading comes out of the wait for the next tick.
+        let busy = start.elapsed().saturating_sub(elapsed);
+        if busy > TICK {
+            log::warn!(
+                "Uploads took {:.1}s, longer than a tick. The target rate can't be kept.",
+                busy.as_secs_f64()
+            );
+        }
+        std::thread::sleep(TICK.saturating_sub(busy).min(duration - elapsed));
+    }
+
+    if progress {
+        eprintln!();
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    let summary = format!(
+        "Uploaded {} rows in {} batches over {:.1}s ({:.0} rows/s).",
+        sent,
+        batches,
+        seconds,
+        sent as f64 / seconds.max(f64::EPSILON)
+    );
+    println!("{}", summary.bright_black());
+    Ok(())
+}
+
+/// Generates rows from the template and uploads them as CSV, at most `batch_size` rows per
+/// request. Returns the number of requests made.
+pub(crate) fn upload_generated(
+    connection: &mut config::ConnectionConfig,
+    schema: &str,
+    template: &mut Template,
+    rows: usize,
+    batch_size: usize,
+    timeout: Option<Duration>,
+) -> Result<usize, Error> {
+    let mut remaining = rows;
+    let mut batches = 0;
+    while remaining > 0 {
+        let rows = remaining.min(batch_size);
+        let mut body = Vec::new();
+        template.write_csv(rows, &mut body)?;
+        crate::connect::retry_unauthorized(connection, |c| {
+            logsh_core::upload::execute_bytes(
+                schema,
+                "csv",
+                body.clone(),
+                &c.connection,
+                timeout.into(),
+            )
+        })?;
+        remaining -= rows;
+        batches += 1;
+        log::debug!("Uploaded batch {} of {} rows", batches, rows);
+    }
+
+    Ok(batches)
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use logsh_core::{
+    auth::AuthRequest, config, connect::Connection, error::ConnectError, tenant::TenantModel,
+};
+
+/// Variables read by `logsh init --from-env`, in the order they're listed in the help.
+const VARIABLES: &[(&str, &str)] = &[
+    ("LOGSH_SERVER", "Server endpoint. Required."),
+    (
+        "LOGSH_CONNECTION",
+        "Connection name. Defaults to \"default\".",
+    ),
+    ("LOGSH_TOKEN", "Bearer token issued for the connection."),
+    (
+        "LOGSH_HMAC_KEY_ID",
+        "Key id of a shared secret, with LOGSH_HMAC_SECRET.",
+    ),
+    ("LOGSH_HMAC_SECRET", "Shared secret for LOGSH_HMAC_KEY_ID."),
+    ("LOGSH_USERNAME", "User to log in as, with LOGSH_PASSWORD."),
+    ("LOGSH_PASSWORD", "Password of LOGSH_USERNAME."),
+    ("LOGSH_SUBSCRIPTION", "Default subscription, by id or name."),
+];
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Create a connection from environment variables, without prompting. For container entrypoints and scheduled jobs.",
+    after_help = variables_help()
+)]
+pub struct InitCommand {
+    #[arg(
+        long,
+        required = true,
+        help = "Read the connection from LOGSH_* environment variables."
+    )]
+    from_env: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File of KEY=VALUE lines or a JSON object with the same variables, such as a mounted secret. Defaults to LOGSH_SECRETS_FILE. Environment variables take precedence."
+    )]
+    secrets_file: Option<PathBuf>,
+}
+
+fn variables_help() -> String {
+    let mut help = "Environment variables:\n".to_string();
+    for (name, description) in VARIABLES {
+        help.push_str(&format!("  {:<20}{}\n", name, description));
+    }
+    help.push_str("\nAuthentication uses the first of LOGSH_TOKEN, LOGSH_HMAC_KEY_ID or LOGSH_USERNAME that's set.");
+    help
+}
+
+pub fn execute_init<W: Write>(command: InitCommand, mut write: W) -> Result<(), Error> {
+    let secrets_file = command
+        .secrets_file
+        .or_else(|| std::env::var_os("LOGSH_SECRETS_FILE").map(PathBuf::from));
+    let file = match secrets_file.as_ref() {
+        Some(path) => read_secrets(path)
+            .map_err(|err| anyhow!("Failed to read secrets file {}: {}", path.display(), err))?,
+        None => HashMap::new(),
+    };
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .or_else(|| file.get(name).cloned())
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    let server = var("LOGSH_SERVER").ok_or_else(|| anyhow!("LOGSH_SERVER is not set."))?;
+    let name = var("LOGSH_CONNECTION").unwrap_or_else(|| "default".to_string());
+    let subscription = var("LOGSH_SUBSCRIPTION");
+
+    let auth: AuthRequest<Box<dyn FnOnce() -> Result<String, ConnectError>>> = if let Some(token) =
+        var("LOGSH_TOKEN")
+    {
+        AuthRequest::Token {
+            token: Box::new(move || Ok(token)),
+        }
+    } else if let Some(key_id) = var("LOGSH_HMAC_KEY_ID") {
+        let secret = var("LOGSH_HMAC_SECRET")
+            .ok_or_else(|| anyhow!("LOGSH_HMAC_KEY_ID is set, but LOGSH_HMAC_SECRET is not."))?;
+        AuthRequest::Hmac {
+            key_id,
+            secret: Box::new(move || Ok(secret)),
+        }
+    } else if let Some(username) = var("LOGSH_USERNAME") {
+        let password = var("LOGSH_PASSWORD")
+            .ok_or_else(|| anyhow!("LOGSH_USERNAME is set, but LOGSH_PASSWORD is not."))?;
+        AuthRequest::Jwt {
+            username,
+            password: Box::new(move || Ok(password)),
+        }
+    } else {
+        return Err(anyhow!(
+                "No credentials found. Set LOGSH_TOKEN, LOGSH_HMAC_KEY_ID and LOGSH_HMAC_SECRET, or LOGSH_USERNAME and LOGSH_PASSWORD."
+            ));
+    };
+
+    let mut connection = Connection::new(&server);
+    connection.default_subscription = subscription.as_ref().and_then(|s| s.parse().ok());
+    let connection = logsh_core::connect::add_connect(
+        name.clone(),
+        Some(connection),
+        Some(auth),
+        |subs: &[TenantModel]| {
+            let chosen = subscription.as_ref().and_then(|wanted| {
+                subs.iter()
+                    .find(|s| s.account_name.eq_ignore_ascii_case(wanted))
+                    .map(|s| s.account_id)
+            });
+            if chosen.is_none() {
+                log::warn!(
+                    "The user has {} subscriptions and LOGSH_SUBSCRIPTION doesn't name one of them. No default subscription is set.",
+                    subs.len()
+                );
+            }
+            chosen
+        },
+    )
+    .map_err(|err| anyhow!("Failed to connect to {}: {}", server, err))?;
+
+    config::update(|cfg| {
+        cfg.connections.insert(name.clone(), connection.clone());
+        cfg.default_connection = name.clone();
+    })?;
+
+    let subscription = connection
+        .default_subscription
+        .map_or("none".to_string(), |s| s.to_string());
+    writeln!(
+        write,
+        "Initialized connection {} to {} as {}, default subscription {}.",
+        name.bright_blue(),
+        connection.server,
+        connection.username.yellow(),
+        subscription
+    )?;
+    Ok(())
+}
+
+/// Reads variables from a JSON object, or from KEY=VALUE lines where blank lines and lines
+/// starting with `#` are ignored and values may be quoted.
+fn read_secrets(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let text = std::fs::read_to_string(path)?;
+    if text.trim_start().starts_with('{') {
+        let values: HashMap<String, serde_json::Value> = serde_json::from_str(&text)?;
+        return Ok(values
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect());
+    }
+
+    let mut values = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {} is not KEY=VALUE", number + 1))?;
+        let value = value.trim();
+        let value = [('"', '"'), ('\'', '\'')]
+            .iter()
+            .find_map(|(open, close)| value.strip_prefix(*open)?.strip_suffix(*close))
+            .unwrap_or(value);
+        values.insert(key.trim().to_string(), value.to_string());
+    }
+    Ok(values)
+}
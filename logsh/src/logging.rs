@@ -0,0 +1,201 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use chrono::{SecondsFormat, Utc};
+use logsh_core::config::DiagnosticsConfig;
+use serde::Serialize;
+
+const LOG_FILE_NAME: &str = "logsh";
+
+#[derive(Copy, Clone, Debug, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Colored, human readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+/// Writes log records to stderr as line delimited JSON, for callers that parse diagnostics.
+pub struct JsonLogger {
+    level: log::LevelFilter,
+}
+
+impl JsonLogger {
+    pub fn new(level: log::LevelFilter) -> Self {
+        Self { level }
+    }
+}
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = JsonRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            level: record.level().as_str(),
+            target: record.target(),
+            message: record.args().to_string(),
+        };
+        if let Ok(line) = serde_json::to_string(&line) {
+            // Write the line in one call so concurrent records don't interleave.
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Appends log records to a file, rotating it once it grows past a size limit.
+/// The current file is `logsh.log`, and older ones are `logsh.1.log`, `logsh.2.log` and so on.
+pub struct FileLogger {
+    level: log::LevelFilter,
+    dir: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Mutex<(File, u64)>,
+}
+
+impl FileLogger {
+    pub fn open(dir: &Path, diagnostics: &DiagnosticsConfig) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(dir)?;
+        let file = open_log(dir)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            level: log::LevelFilter::from_str(&diagnostics.log_level)
+                .unwrap_or(log::LevelFilter::Debug),
+            dir: dir.to_path_buf(),
+            max_bytes: diagnostics.max_file_bytes,
+            max_files: diagnostics.max_files,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    pub fn level(&self) -> log::LevelFilter {
+        self.level
+    }
+
+    /// Shifts every log file up one number, dropping the oldest, and starts a new file.
+    fn rotate(&self) -> Result<File, std::io::Error> {
+        let path = |n: usize| match n {
+            0 => self.dir.join(format!("{}.log", LOG_FILE_NAME)),
+            n => self.dir.join(format!("{}.{}.log", LOG_FILE_NAME, n)),
+        };
+        let _ = std::fs::remove_file(path(self.max_files));
+        for n in (0..self.max_files).rev() {
+            match std::fs::rename(path(n), path(n + 1)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+
+        open_log(&self.dir)
+    }
+}
+
+fn open_log(dir: &Path) -> Result<File, std::io::Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(format!("{}.log", LOG_FILE_NAME)))
+}
+
+impl log::Log for FileLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // The process id tells apart runs that overlap, such as scheduled jobs.
+        let line = format!(
+            "{} [{}] {:<5} {}: {}\n",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            std::process::id(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let Ok(mut state) = self.file.lock() else {
+            return;
+        };
+        if state.1 > 0 && state.1 + line.len() as u64 > self.max_bytes {
+            match self.rotate() {
+                Ok(file) => *state = (file, 0),
+                Err(err) => eprintln!("Failed to rotate diagnostic log: {}", err),
+            }
+        }
+        if state.0.write_all(line.as_bytes()).is_ok() {
+            state.1 += line.len() as u64;
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut state) = self.file.lock() {
+            let _ = state.0.flush();
+        }
+    }
+}
+
+/// Sends every record to each logger, which apply their own level filters.
+pub struct TeeLogger(pub Vec<Box<dyn log::Log>>);
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.iter().any(|l| l.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        for logger in self.0.iter() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        for logger in self.0.iter() {
+            logger.flush();
+        }
+    }
+}
+
+/// Opens the diagnostic log file when it's enabled in the configuration.
+pub fn diagnostic_logger() -> Result<Option<FileLogger>, anyhow::Error> {
+    let cfg = logsh_core::config::load()?;
+    if !cfg.diagnostics.log_file {
+        return Ok(None);
+    }
+
+    let dir = logsh_core::config::get_logs_path()?;
+    let logger = FileLogger::open(&dir, &cfg.diagnostics).map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to open diagnostic log in {}: {}",
+            dir.display(),
+            err
+        )
+    })?;
+    Ok(Some(logger))
+}
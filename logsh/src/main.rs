@@ -7,20 +7,56 @@ use clap::{
         styling::{AnsiColor, Effects},
         Styles,
     },
-    command, Parser, Subcommand, ValueEnum,
+    command, CommandFactory, Parser, Subcommand,
 };
-use colored::Colorize;
 
+mod agent;
+mod audit;
+mod check;
+mod clipboard;
+mod complete;
 mod config;
 mod connect;
+mod dash;
+mod demo;
+mod diff;
+mod edit;
+mod export;
 mod fmt;
+mod import;
+mod inflow;
+mod ingest;
+mod init;
+mod logging;
+mod metadata;
+mod metrics;
+mod pick;
+mod progress;
+mod prompt;
 mod query;
-mod subscription;
+mod query_fmt;
+mod repl;
+mod saved_query;
+mod server;
+mod snippet;
+mod sql;
+mod stats;
+mod status;
+mod switch;
+mod tables;
+mod tenant;
+mod tui;
 mod upload;
 mod version;
+mod whoami;
 
 #[derive(Parser)]
-#[clap(name = "logsh", author = "logship.llc", styles = styles())]
+#[clap(
+    name = "logsh",
+    author = "logship.llc",
+    styles = styles(),
+    after_help = crate::complete::SETUP_HELP
+)]
 #[command(arg_required_else_help = false)]
 struct Args {
     #[command(subcommand)]
@@ -31,6 +67,59 @@ struct Args {
 
     #[arg(long, global = true, help = "Disable global color output.")]
     no_color: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        help = "Format of diagnostic log lines written to stderr."
+    )]
+    log_format: logging::LogFormat,
+
+    #[arg(long, global = true, help = "Hide progress bars.")]
+    quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "HOST:PORT:ADDR",
+        help = "Send requests for HOST:PORT to ADDR instead of resolving HOST, like curl's --resolve. May be repeated."
+    )]
+    resolve: Vec<logsh_core::connect::Resolve>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Never write the configuration file, such as for a read-only home directory. Refreshed tokens and other changes last until logsh exits. Also set by LOGSH_READONLY=1."
+    )]
+    no_save: bool,
+
+    #[arg(
+        long,
+        help = "Show the status saved in the configuration without contacting the server."
+    )]
+    offline: bool,
+
+    #[arg(short, long, help = "Status output format")]
+    output: Option<OutputMode>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        conflicts_with = "replay",
+        help = "Save every request made to the server, and its response, to a file --replay answers them from. Passwords, tokens and signatures aren't saved, but results are, so the file is only readable by you."
+    )]
+    record: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Answer requests from a file saved with --record instead of contacting the server, for offline demos and tests."
+    )]
+    replay: Option<std::path::PathBuf>,
 }
 
 fn styles() -> Styles {
@@ -59,14 +148,63 @@ enum Commands {
     Config(crate::config::ConfigCommand),
 
     #[command(subcommand)]
-    Subscription(crate::subscription::SubscriptionCommand),
+    Subscription(crate::tenant::TenantCommand),
+
+    #[command(subcommand)]
+    Tables(crate::tables::TablesCommand),
+
+    #[command(subcommand)]
+    Audit(crate::audit::AuditCommand),
+
+    #[command(subcommand)]
+    Inflow(crate::inflow::InflowCommand),
+
+    #[command(subcommand)]
+    Ingest(crate::ingest::IngestCommand),
+
+    #[command(subcommand)]
+    Demo(crate::demo::DemoCommand),
+
+    #[command(subcommand)]
+    Metadata(crate::metadata::MetadataCommand),
+
+    #[command(subcommand)]
+    Server(crate::server::ServerCommand),
+
+    #[command(subcommand)]
+    Import(crate::import::ImportCommand),
+
+    #[command(subcommand)]
+    Metrics(crate::metrics::MetricsCommand),
 
-    Query(crate::query::QueryCommand),
+    #[command(subcommand)]
+    Snippet(crate::snippet::SnippetCommand),
+
+    Use(crate::switch::UseCommand),
+    Agent(crate::agent::AgentCommand),
+    Check(crate::check::CheckCommand),
+    Dash(crate::dash::DashCommand),
+    Export(crate::export::ExportCommand),
+    Init(crate::init::InitCommand),
+    Pick(crate::pick::PickCommand),
+    Query(Box<crate::query::QueryCommand>),
+    Repl(crate::repl::ReplCommand),
+    Sql(crate::sql::SqlCommand),
+    Stats(crate::stats::StatsCommand),
     Upload(crate::upload::UploadCommand),
+    #[command(subcommand)]
+    Uploads(crate::upload::UploadsCommand),
     Version(crate::version::VersionCommand),
+    #[command(name = "whoami")]
+    WhoAmI(crate::whoami::WhoAmICommand),
 }
 
 fn main() -> Result<(), Error> {
+    // Answers completion requests from the shell and exits, when COMPLETE is set.
+    clap_complete::CompleteEnv::with_factory(Args::command).complete();
+    // Serves the clipboard for an earlier --copy and exits, when started to.
+    clipboard::serve();
+
     let cli = Args::parse();
     let log_level = match cli.verbose {
         0 => log::LevelFilter::Error,
@@ -80,93 +218,324 @@ fn main() -> Result<(), Error> {
         .unwrap_or_default()
         .trim()
         .is_empty();
-    if no_color || cli.no_color {
-        colored::control::set_override(false);
+    prompt::setup(no_color || cli.no_color);
+    progress::set_quiet(cli.quiet);
+    if cli.no_save {
+        logsh_core::config::set_read_only(true);
     }
+    logsh_core::connect::set_resolve_overrides(cli.resolve);
 
-    pretty_env_logger::formatted_builder()
-        .filter_level(log_level)
-        .init();
+    let terminal: Box<dyn log::Log> = match cli.log_format {
+        logging::LogFormat::Pretty => Box::new(
+            pretty_env_logger::formatted_builder()
+                .filter_level(log_level)
+                .build(),
+        ),
+        logging::LogFormat::Json => Box::new(logging::JsonLogger::new(log_level)),
+    };
+    let (logger, max_level, diagnostics_err): (Box<dyn log::Log>, _, _) =
+        match logging::diagnostic_logger() {
+            Ok(Some(file)) => {
+                let max_level = log_level.max(file.level());
+                (
+                    Box::new(logging::TeeLogger(vec![terminal, Box::new(file)])),
+                    max_level,
+                    None,
+                )
+            }
+            Ok(None) => (terminal, log_level, None),
+            Err(err) => (terminal, log_level, Some(err)),
+        };
+    log::set_max_level(max_level);
+    log::set_boxed_logger(Box::new(RedactingLogger(logger)))?;
+    if let Some(err) = diagnostics_err {
+        log::warn!("{}", err);
+    }
+    if let Some(path) = cli.record.as_deref() {
+        logsh_core::session::record(path)?;
+    }
+    if let Some(path) = cli.replay.as_deref() {
+        logsh_core::session::replay(path)?;
+    }
 
     match cli.command {
+        Some(
+            Commands::Connection(_)
+            | Commands::Config(_)
+            | Commands::Init(_)
+            | Commands::Version(_),
+        ) => {}
+        _ => crate::connect::warn_expiring_token(),
+    }
+
+    let result = match cli.command {
         Some(Commands::Connection(command)) => crate::connect::execute_connect(command),
-        Some(Commands::Query(command)) => crate::query::execute_query(command, std::io::stdout()),
+        Some(Commands::Use(command)) => crate::switch::execute_use(command),
+        Some(Commands::Agent(command)) => crate::agent::execute_agent(command, std::io::stdout()),
+        Some(Commands::Export(command)) => {
+            crate::export::execute_export(command, std::io::stdout())
+        }
+        Some(Commands::Check(command)) => crate::check::execute_check(command, std::io::stdout()),
+        Some(Commands::Dash(command)) => crate::dash::execute_dash(command),
+        Some(Commands::Init(command)) => crate::init::execute_init(command, std::io::stdout()),
+        Some(Commands::Pick(command)) => crate::pick::execute_pick(command, std::io::stdout()),
+        Some(Commands::Query(command)) => crate::query::execute_query(*command, std::io::stdout()),
+        Some(Commands::Repl(command)) => crate::repl::execute_repl(command),
+        Some(Commands::Sql(command)) => crate::sql::execute_sql(command, std::io::stdout()),
+        Some(Commands::Stats(command)) => crate::stats::execute_stats(command, std::io::stdout()),
         Some(Commands::Upload(command)) => crate::upload::execute_upload(command),
+        Some(Commands::Uploads(command)) => {
+            crate::upload::execute_uploads(command, std::io::stdout())
+        }
+        Some(Commands::Inflow(command)) => {
+            crate::inflow::execute_inflow(command, std::io::stdout())
+        }
+        Some(Commands::Ingest(command)) => crate::ingest::execute_ingest(command),
+        Some(Commands::Demo(command)) => crate::demo::execute_demo(command),
         Some(Commands::Version(command)) => {
             crate::version::version(std::io::stdout(), command, cli.verbose)
         }
-        Some(Commands::Subscription(command)) => crate::subscription::execute_subscription(command),
+        Some(Commands::Subscription(command)) => {
+            crate::tenant::execute_tenant(command, std::io::stdout())
+        }
+        Some(Commands::Tables(command)) => {
+            crate::tables::execute_tables(command, std::io::stdout())
+        }
+        Some(Commands::WhoAmI(command)) => {
+            crate::whoami::execute_whoami(command, std::io::stdout())
+        }
+        Some(Commands::Audit(command)) => crate::audit::execute_audit(command, std::io::stdout()),
+        Some(Commands::Metadata(command)) => {
+            crate::metadata::execute_metadata(command, std::io::stdout())
+        }
+        Some(Commands::Server(command)) => {
+            crate::server::execute_server(command, std::io::stdout())
+        }
+        Some(Commands::Snippet(command)) => {
+            crate::snippet::execute_snippet(command, std::io::stdout())
+        }
+        Some(Commands::Metrics(command)) => crate::metrics::execute_metrics(command),
+        Some(Commands::Import(command)) => {
+            crate::import::execute_import(command, std::io::stdout())
+        }
         Some(Commands::Config(command)) => crate::config::execute_config(command),
         None => {
             log::debug!("No arguments provided. Output status.");
-            let cfg = logsh_core::config::load()?;
-            let conn = cfg.get_default_connection();
-            let result = match conn {
-                Some(conn) => match conn.connection.who_am_i() {
-                    Ok(user) => {
-                        let sub = conn
-                            .connection
-                            .default_subscription()
-                            .map_or("None".to_string(), |s| s.to_string());
-                        println!("Status: {}", "Connected".green());
-                        println!(
-                            "Logged into connection {} as user {} with subscription: {}",
-                            &conn.name.blue(),
-                            &user.user_name.blue(),
-                            sub.blue()
-                        );
-                        Ok(())
-                    }
-                    Err(err) => {
-                        println!("Status: {}", "Not Connected".red());
-                        fmt::print_connect_error(&cfg, &err);
-                        Err(err)
-                    }
-                },
-                None => {
-                    println!(
-                        "Status: {} {}",
-                        "You don't have any connections configured yet!".red(),
-                        "Configuration Required.".red()
-                    );
-
-                    fmt::print_add_connection_help();
-                    Ok(())
-                }
-            };
-
-            println!(
-                "{} {} {}",
-                "# Execute".bright_black(),
-                "logsh --help".blue(),
-                "to view available commands.".bright_black()
-            );
-
-            result.map_err(|err| anyhow!("Status check failed: {err}"))
+            crate::status::execute_status(cli.offline, cli.output, std::io::stdout())
         }
+    };
+
+    if let Some(ExitStatus(code)) = result
+        .as_ref()
+        .err()
+        .and_then(|err| err.downcast_ref::<ExitStatus>())
+    {
+        log::logger().flush();
+        std::process::exit(*code);
+    }
+
+    match (result, cli.log_format) {
+        // Keep stderr parseable: report the failure as a log line rather than a backtrace.
+        (Err(err), logging::LogFormat::Json) => {
+            log::error!("{:#}", err);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+        (result, _) => result.map_err(|err| {
+            // Below the default verbosity, so it reaches the diagnostic log without repeating
+            // the error on the terminal.
+            log::debug!("Command failed: {:#}", err);
+            log::logger().flush();
+            anyhow!("{}", logsh_core::redact::redact(&format!("{:?}", err)))
+        }),
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+/// Ends the command with this exit status rather than the usual 1. Returned as an error, so
+/// what the command wrote is flushed and dropped before logsh exits.
+#[derive(Debug)]
+pub struct ExitStatus(pub i32);
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+/// Masks credentials in every log line, so verbose output is safe to share.
+struct RedactingLogger<L: log::Log>(L);
+
+impl<L: log::Log> log::Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        self.0.log(
+            &log::Record::builder()
+                .metadata(record.metadata().clone())
+                .args(format_args!("{}", logsh_core::redact::redact(&message)))
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
 pub enum OutputMode {
     #[default]
     Table,
     Json,
     JsonPretty,
+    JsonLines,
     Csv,
+    Tsv,
     Markdown,
+    Sqlite,
+    Arrow,
+    Html,
+    Template,
+    /// A format added to logsh_core's registry, by name.
+    Custom(&'static str),
+}
+
+impl OutputMode {
+    pub const ALL: [OutputMode; 11] = [
+        OutputMode::Table,
+        OutputMode::Json,
+        OutputMode::JsonPretty,
+        OutputMode::JsonLines,
+        OutputMode::Csv,
+        OutputMode::Tsv,
+        OutputMode::Markdown,
+        OutputMode::Sqlite,
+        OutputMode::Arrow,
+        OutputMode::Html,
+        OutputMode::Template,
+    ];
+
+    /// The name `-o` and the default_output setting take.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputMode::Table => "table",
+            OutputMode::Json => "json",
+            OutputMode::JsonPretty => "json-pretty",
+            OutputMode::JsonLines => "json-lines",
+            OutputMode::Csv => "csv",
+            OutputMode::Tsv => "tsv",
+            OutputMode::Markdown => "markdown",
+            OutputMode::Sqlite => "sqlite",
+            OutputMode::Arrow => "arrow",
+            OutputMode::Html => "html",
+            OutputMode::Template => "template",
+            OutputMode::Custom(name) => name,
+        }
+    }
+
+    /// Other names accepted for the format.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            OutputMode::Markdown => &["md"],
+            OutputMode::Table | OutputMode::Sqlite | OutputMode::Template => &[],
+            mode => logsh_core::render::find(mode.name()).map_or(&[], |r| r.aliases()),
+        }
+    }
+
+    /// What the format is, for `logsh config formats`.
+    pub fn description(&self) -> &'static str {
+        match self {
+            OutputMode::Table => "A table fitted to the terminal.",
+            OutputMode::Markdown => "A Markdown table.",
+            OutputMode::Sqlite => "A SQLite database, written with --output-file.",
+            OutputMode::Template => "Each row through the --template text.",
+            mode => logsh_core::render::find(mode.name()).map_or("", |r| r.description()),
+        }
+    }
+
+    /// Whether the format is binary, so isn't written to terminals or the clipboard.
+    pub fn is_binary(&self) -> bool {
+        match self {
+            OutputMode::Sqlite => true,
+            OutputMode::Table | OutputMode::Markdown | OutputMode::Template => false,
+            mode => logsh_core::render::find(mode.name()).is_some_and(|r| r.is_binary()),
+        }
+    }
+
+    /// Whether only query results can be written in the format, not the rows of list commands
+    /// such as `conn ls` and `tables ls`.
+    pub fn is_query_only(&self) -> bool {
+        matches!(
+            self,
+            OutputMode::Sqlite | OutputMode::Arrow | OutputMode::Html | OutputMode::Template
+        )
+    }
+
+    /// The built in formats, followed by any others registered with logsh_core.
+    pub fn available() -> Vec<OutputMode> {
+        let custom = logsh_core::render::formats()
+            .into_iter()
+            .map(|r| r.name())
+            .filter(|name| !OutputMode::ALL.iter().any(|m| m.name() == *name))
+            .map(OutputMode::Custom);
+        OutputMode::ALL.into_iter().chain(custom).collect()
+    }
+
+    /// The format of commands run without `-o`: the default_output setting, or a table.
+    pub fn configured() -> OutputMode {
+        let Some(name) = logsh_core::config::load()
+            .ok()
+            .and_then(|cfg| cfg.default_output)
+        else {
+            return OutputMode::default();
+        };
+        name.parse().unwrap_or_else(|err| {
+            log::warn!("Ignoring the default_output setting. {}", err);
+            OutputMode::default()
+        })
+    }
 }
 
+/// Parses every output format name, ignoring case, for `-o`, `PATH:FORMAT` and the
+/// default_output setting alike.
 impl FromStr for OutputMode {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "json" => Ok(OutputMode::Json),
-            "json-pretty" => Ok(OutputMode::JsonPretty),
-            "csv" => Ok(OutputMode::Csv),
-            "markdown" => Ok(OutputMode::Markdown),
-            _ => Err(anyhow!("Failed to read output format: \"{}\"", s)),
-        }
+        let s = s.trim();
+        OutputMode::available()
+            .into_iter()
+            .find(|mode| {
+                mode.name().eq_ignore_ascii_case(s)
+                    || mode.aliases().iter().any(|a| a.eq_ignore_ascii_case(s))
+            })
+            .ok_or_else(|| {
+                let names: Vec<_> = OutputMode::available().iter().map(|m| m.name()).collect();
+                anyhow!(
+                    "Unknown output format \"{}\". Use one of: {}.",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl clap::builder::ValueParserFactory for OutputMode {
+    type Parser = crate::fmt::parse::OutputModeParser;
+
+    fn value_parser() -> Self::Parser {
+        crate::fmt::parse::OutputModeParser::default()
     }
 }
@@ -0,0 +1,81 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use colored::Colorize;
+use logsh_core::{logship_client::LogshClientHandler, metadata, query::QueryResultFmt};
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Subcommand)]
+#[clap(about = "Manage the local cache of schema, table and column names.")]
+pub enum MetadataCommand {
+    #[clap(about = "Fetch the default subscription's tables and columns again.")]
+    Refresh,
+    #[clap(about = "Show the cached tables and columns.")]
+    Show {
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+    #[clap(about = "Remove the cached metadata of every connection.")]
+    Clear,
+}
+
+pub fn execute_metadata<W: Write>(command: MetadataCommand, mut write: W) -> Result<(), Error> {
+    let conn_handler = LogshClientHandler::new();
+    match command {
+        MetadataCommand::Refresh => {
+            let metadata = metadata::refresh(&conn_handler)?;
+            println!(
+                "Cached {} tables in {} schemas.",
+                metadata.tables.len().to_string().blue(),
+                metadata.schemas().len().to_string().blue()
+            );
+            Ok(())
+        }
+        MetadataCommand::Show { output } => {
+            let metadata = metadata::cached(&conn_handler)?.ok_or(anyhow!(
+                "No cached metadata. Run \"logsh metadata refresh\" first."
+            ))?;
+            crate::fmt::render(
+                &mut write,
+                output.unwrap_or_else(OutputMode::configured),
+                &metadata,
+                |metadata| {
+                    let results = metadata
+                        .tables
+                        .iter()
+                        .map(|table| {
+                            let columns = table
+                                .columns
+                                .iter()
+                                .map(|c| format!("{}: {}", c.name, c.typ))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            HashMap::from([
+                                ("Schema".to_string(), Value::String(table.schema.clone())),
+                                ("Table".to_string(), Value::String(table.name.clone())),
+                                ("Columns".to_string(), Value::String(columns)),
+                            ])
+                        })
+                        .collect();
+                    QueryResultFmt {
+                        header: vec![
+                            "Schema".to_string(),
+                            "Table".to_string(),
+                            "Columns".to_string(),
+                        ],
+                        results,
+                    }
+                },
+            )?;
+            log::info!("Metadata refreshed {}.", metadata.refreshed.to_rfc3339());
+            Ok(())
+        }
+        MetadataCommand::Clear => {
+            metadata::clear()?;
+            Ok(())
+        }
+    }
+}
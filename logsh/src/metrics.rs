@@ -0,0 +1,201 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use logsh_core::{
+    config,
+    connect::Timeouts,
+    error::{ConfigError, ConnectError},
+    prometheus,
+};
+
+use crate::fmt::parse::OptionalDurationArg;
+
+#[derive(Debug, Subcommand)]
+#[clap(about = "Serve query results to other monitoring tools.")]
+pub enum MetricsCommand {
+    #[clap(
+        about = "Run a query periodically and expose its numeric columns as Prometheus gauges, labelled by its text columns, on /metrics."
+    )]
+    Expose {
+        #[arg(short, long, help = "Query to run.")]
+        query: String,
+        #[arg(
+            long,
+            value_name = "[HOST]:PORT",
+            default_value = ":9101",
+            help = "Address to serve /metrics on. Without a host, every interface is listened on, and anyone who can reach the port can read the metrics, as they're served without authentication."
+        )]
+        listen: String,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            default_value = "30s",
+            help = "Delay between runs of the query."
+        )]
+        interval: humantime::Duration,
+        #[arg(
+            long,
+            default_value = "logsh",
+            help = "Start of every metric's name, followed by an underscore and the column."
+        )]
+        prefix: String,
+        #[arg(
+            short,
+            long,
+            help = "Query timeout. Use \"none\" to disable timeout.",
+            default_value = "1m"
+        )]
+        timeout: OptionalDurationArg,
+    },
+}
+
+pub fn execute_metrics(command: MetricsCommand) -> Result<(), Error> {
+    match command {
+        MetricsCommand::Expose {
+            query,
+            listen,
+            interval,
+            prefix,
+            timeout,
+        } => {
+            let cfg = config::load()?;
+            let mut connection = cfg
+                .get_default_connection()
+                .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+
+            let addr = match listen.starts_with(':') {
+                true => format!("0.0.0.0{}", listen),
+                false => listen,
+            };
+            let listener = TcpListener::bind(&addr)
+                .map_err(|err| anyhow!("Failed to listen on {}: {}", addr, err))?;
+            log::info!("Serving metrics on http://{}/metrics", addr);
+
+            let metrics = Arc::new(Mutex::new(String::new()));
+            let served = metrics.clone();
+            std::thread::spawn(move || {
+                // Each scrape gets its own thread, so a slow or idle client doesn't hold up the rest.
+                for stream in listener.incoming().flatten() {
+                    let served = served.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = serve(stream, &served) {
+                            log::debug!("Metrics request failed: {}", err);
+                        }
+                    });
+                }
+            });
+
+            let mut last_success = None;
+            let mut previous = None;
+            loop {
+                let start = Instant::now();
+                let response = crate::connect::retry_unauthorized(&mut connection, |c| {
+                    c.connection
+                        .query_raw(&query, Timeouts::total(timeout.into()))
+                });
+                let rendered = response.map_err(Error::from).and_then(|text| {
+                    let result = logsh_core::query::result(&text)?;
+                    Ok(prometheus::gauges(&result, &prefix))
+                });
+                let elapsed = start.elapsed().as_secs_f64();
+
+                let success = match rendered {
+                    Ok(rendered) => {
+                        previous = Some(rendered);
+                        last_success = Some(chrono::Utc::now());
+                        true
+                    }
+                    // Keep serving the last good values, and say they're stale.
+                    Err(err) => {
+                        log::warn!("Query failed, serving the previous result: {}", err);
+                        false
+                    }
+                };
+
+                let mut text = previous.clone().unwrap_or_default();
+                prometheus::info(
+                    &mut text,
+                    &format!("{}_query_info", prefix),
+                    "The query the metrics come from.",
+                    &[("query", &query)],
+                );
+                prometheus::gauge(
+                    &mut text,
+                    &format!("{}_query_success", prefix),
+                    "Whether the latest run of the query succeeded.",
+                    if success { 1.0 } else { 0.0 },
+                );
+                prometheus::gauge(
+                    &mut text,
+                    &format!("{}_query_duration_seconds", prefix),
+                    "How long the latest run of the query took.",
+                    elapsed,
+                );
+                if let Some(last) = last_success {
+                    prometheus::gauge(
+                        &mut text,
+                        &format!("{}_query_last_success_timestamp_seconds", prefix),
+                        "When the query last succeeded, as a Unix time.",
+                        last.timestamp_millis() as f64 / 1000.0,
+                    );
+                }
+                *metrics.lock().unwrap_or_else(|p| p.into_inner()) = text;
+
+                std::thread::sleep(interval.into());
+            }
+        }
+    }
+}
+
+/// Answers one scrape. Only `GET /metrics` is served, and the connection is closed after it.
+fn serve(mut stream: TcpStream, metrics: &Mutex<String>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers aren't needed, but are read so the client isn't reset before the response.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("/"),
+    );
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            prometheus::CONTENT_TYPE,
+            metrics.lock().unwrap_or_else(|p| p.into_inner()).clone(),
+        ),
+        ("GET", _) => (
+            "404 Not Found",
+            "text/plain",
+            "Metrics are served on /metrics.\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported.\n".to_string(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,103 @@
+use std::io::{IsTerminal, Write};
+
+use anyhow::{anyhow, Error};
+use clap::ValueEnum;
+use dialoguer::FuzzySelect;
+use logsh_core::{config, logship_client::LogshClientHandler, saved_query, tables::list_tables};
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Fuzzy find a saved query, table, or connection and print its name.")]
+pub struct PickCommand {
+    #[arg(value_enum, help = "What to pick from.")]
+    kind: PickKind,
+
+    #[arg(
+        short,
+        long,
+        help = "Act on the selection instead of printing it: run the saved query, describe the table, or use the connection."
+    )]
+    run: bool,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum PickKind {
+    #[value(alias = "query")]
+    Queries,
+    #[value(alias = "table")]
+    Tables,
+    #[value(alias = "connection", alias = "conn")]
+    Connections,
+}
+
+pub fn execute_pick<W: Write>(command: PickCommand, mut write: W) -> Result<(), Error> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Err(anyhow!("Picking needs an interactive terminal."));
+    }
+
+    // Each item is the name to act on, and the label shown while picking.
+    let items: Vec<(String, String)> = match command.kind {
+        PickKind::Queries => saved_query::load()?
+            .queries
+            .into_values()
+            .map(|q| {
+                let label = match q.description.as_deref() {
+                    Some(d) => format!("{} - {}", q.name, d),
+                    None => q.name.to_string(),
+                };
+                (q.name, label)
+            })
+            .collect(),
+        PickKind::Tables => {
+            let mut tables = list_tables(&LogshClientHandler::new(), None)?;
+            tables.sort_by(|a, b| (&a.schema, &a.name).cmp(&(&b.schema, &b.name)));
+            tables
+                .into_iter()
+                .map(|t| (t.name.to_string(), format!("{}.{}", t.schema, t.name)))
+                .collect()
+        }
+        PickKind::Connections => {
+            let cfg = config::load()?;
+            let mut names: Vec<(String, String)> = cfg
+                .connections
+                .iter()
+                .map(|(name, c)| (name.to_string(), format!("{} ({})", name, c.server)))
+                .collect();
+            names.sort();
+            names
+        }
+    };
+
+    if items.is_empty() {
+        return Err(anyhow!("Nothing to pick from."));
+    }
+
+    let labels: Vec<&str> = items.iter().map(|(_, label)| label.as_str()).collect();
+    let Some(selection) = FuzzySelect::with_theme(&*crate::prompt::theme())
+        .items(&labels)
+        .default(0)
+        .interact_opt()?
+    else {
+        return Ok(());
+    };
+
+    let name = items[selection].0.as_str();
+    if !command.run {
+        writeln!(write, "{}", name)?;
+        return Ok(());
+    }
+
+    match command.kind {
+        PickKind::Queries => crate::query::execute_query(
+            crate::query::QueryCommand::from_args(&["--saved", name])?,
+            write,
+        ),
+        PickKind::Tables => crate::tables::execute_tables(
+            crate::tables::TablesCommand::Describe {
+                name: name.to_string(),
+                output: None,
+            },
+            write,
+        ),
+        PickKind::Connections => crate::switch::execute_use(crate::switch::UseCommand::new(name)),
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    io::IsTerminal,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use logsh_core::progress::{NoProgress, Progress};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Turns off progress output for the rest of the process, for `--quiet`.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether progress should be drawn: stderr is a terminal and `--quiet` wasn't given.
+pub fn enabled() -> bool {
+    !QUIET.load(Ordering::Relaxed) && std::io::stderr().is_terminal()
+}
+
+/// Picks how long running operations report progress: bars on stderr, or nothing when
+/// progress isn't [enabled].
+pub fn reporter() -> Arc<dyn Progress> {
+    if enabled() {
+        Arc::new(BarProgress::default())
+    } else {
+        Arc::new(NoProgress)
+    }
+}
+
+/// Draws a progress bar on stderr, or a spinner when the total isn't known.
+#[derive(Default)]
+struct BarProgress {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl Progress for BarProgress {
+    fn start(&self, message: &str, total: Option<u64>) {
+        let style = match total {
+            Some(_) => ProgressStyle::with_template(
+                "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .map(|s| s.progress_chars("=> ")),
+            None => ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})"),
+        };
+
+        let bar = ProgressBar::with_draw_target(total, ProgressDrawTarget::stderr())
+            .with_style(style.unwrap_or_else(|_| ProgressStyle::default_bar()))
+            .with_message(message.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        if let Ok(mut current) = self.bar.lock() {
+            if let Some(previous) = current.replace(bar) {
+                previous.finish_and_clear();
+            }
+        }
+    }
+
+    fn advance(&self, bytes: u64) {
+        if let Ok(current) = self.bar.lock() {
+            if let Some(bar) = current.as_ref() {
+                bar.inc(bytes);
+            }
+        }
+    }
+
+    fn finish(&self) {
+        if let Ok(mut current) = self.bar.lock() {
+            if let Some(bar) = current.take() {
+                bar.finish_and_clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use dialoguer::{
+    theme::{ColorfulTheme, SimpleTheme, Theme},
+    Input, Password,
+};
+
+/// Whether the console shows ANSI escapes. Legacy Windows consoles only do once asked, and
+/// some never do.
+static ANSI: AtomicBool = AtomicBool::new(true);
+
+/// Prepares the console for colors and prompts. On Windows, turns on ANSI escapes, and where
+/// the console can't show them falls back to plain output rather than printing the escapes.
+/// `no_color` turns colors off everywhere, prompts included.
+pub fn setup(no_color: bool) {
+    #[cfg(windows)]
+    if colored::control::set_virtual_terminal(true).is_err() {
+        ANSI.store(false, Ordering::Relaxed);
+    }
+
+    if no_color || !ANSI.load(Ordering::Relaxed) {
+        colored::control::set_override(false);
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+/// The theme prompts are drawn with. Consoles without colors get plain ASCII, as the fonts of
+/// legacy Windows consoles often lack the colorful theme's symbols too.
+pub fn theme() -> Box<dyn Theme> {
+    match ANSI.load(Ordering::Relaxed) && console::colors_enabled_stderr() {
+        true => Box::new(ColorfulTheme::default()),
+        false => Box::new(SimpleTheme),
+    }
+}
+
+/// Asks for a password, or other secret, without echoing it. Keys are read from the console
+/// rather than a line from stdin, so masked input and wide characters work in Windows consoles.
+pub fn password(prompt: &str) -> io::Result<String> {
+    if !io::stderr().is_terminal() {
+        return Err(io::Error::other(
+            "no terminal to prompt for the password. Use --password-stdin or --password-file",
+        ));
+    }
+
+    Password::with_theme(&*theme())
+        .with_prompt(prompt)
+        .interact()
+        .map_err(io::Error::other)
+}
+
+/// Asks for a line of text. Without a terminal, reads it from stdin instead, so it can be piped
+/// in.
+pub fn text(prompt: &str) -> io::Result<String> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        return Ok(line.trim().to_string());
+    }
+
+    Input::<String>::with_theme(&*theme())
+        .with_prompt(prompt)
+        .interact_text()
+        .map(|text| text.trim().to_string())
+        .map_err(io::Error::other)
+}
@@ -1,7 +1,10 @@
 use std::{
-    io::{Read, Write},
-    str::FromStr,
-    time::Instant,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Error};
@@ -9,7 +12,15 @@ use clap::arg;
 use colored::Colorize;
 use logsh_core::{
     config,
-    error::{ConfigError, ConnectError},
+    connect::{Connection, Group, Timeouts},
+    cursor::Cursor,
+    error::{ConfigError, ConnectError, QueryError},
+    frame::{ColumnType, DataFrame},
+    history::HistoryEntry,
+    notify::{Condition, NotifyFormat},
+    query::{PlanStage, QueryResult, QueryResultFmt, QueryStatistics},
+    transform::Derive,
+    watch::{Poll, Watch},
 };
 use term_table::{
     row::Row,
@@ -17,24 +28,31 @@ use term_table::{
     Table, TableStyle,
 };
 
-use crate::{fmt::parse::OptionalDurationArg, OutputMode};
+use crate::{
+    fmt::parse::{OptionalDurationArg, OutputFileArg},
+    saved_query::SavedQueryCommand,
+    ExitStatus, OutputMode,
+};
 
-pub fn markdown_style() -> TableStyle {
-    let mut style: TableStyle = TableStyle::simple();
-    style.top_left_corner = '│';
-    style.top_right_corner = '│';
-    style.bottom_left_corner = '│';
-    style.bottom_right_corner = '│';
-    style.outer_right_vertical = '|';
-    style.outer_left_vertical = '|';
-    style.intersection = '|';
-    style.vertical = '|';
-    style
-}
+/// How many subscriptions `--all-subscriptions` queries at the same time.
+const PARALLEL_SUBSCRIPTIONS: usize = 8;
+
+/// Column naming the subscription each row came from in `--all-subscriptions` results.
+const SUBSCRIPTION_COLUMN: &str = "subscription";
+
+/// Column naming the connection each row came from in `--group` results.
+const CONNECTION_COLUMN: &str = "connection";
+
+/// Exit status when the result fails `--assert`, `--fail-if-empty` or `--fail-if-rows`.
+const ASSERTION_FAILED_EXIT_CODE: i32 = 3;
 
 #[derive(Debug, clap::Args)]
 #[clap(about = "Execute a query against a logship server.")]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct QueryCommand {
+    #[command(subcommand)]
+    command: Option<QuerySubcommand>,
+
     #[arg(
         short,
         long,
@@ -42,82 +60,1627 @@ pub struct QueryCommand {
     )]
     query: Option<String>,
 
-    #[arg(short, long, help = "Output result format")]
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "NAME",
+        conflicts_with = "query",
+        add = crate::complete::saved_queries(),
+        help = "Execute a saved query."
+    )]
+    saved: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["query", "saved", "edit"],
+        help = "Read the query from a file. Errors point at its lines."
+    )]
+    file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Compose the query in $VISUAL or $EDITOR, starting from --query or --saved if given, and run it once the editor closes. Offers to save it as a named query afterwards."
+    )]
+    edit: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["query", "file", "saved", "edit", "follow", "spill", "explain", "count", "schema_only", "all_subscriptions", "group", "output_file", "copy", "open", "tui", "row_numbers"],
+        help = "Read several queries from stdin, separated by ';;' or by lines starting with '---', and run them in turn on one connection. JSON output writes a document per query. CSV output writes a file per query, named by the text after the dashes, and prints its path."
+    )]
+    batch_stdin: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "batch_stdin",
+        help = "Directory for the CSV files written by --batch-stdin. Defaults to the current directory."
+    )]
+    batch_dir: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = crate::fmt::parse::OutputModeParser::query(),
+        help = "Output result format"
+    )]
     output: Option<OutputMode>,
 
     #[arg(
         short,
         long,
-        help = "Query timeout. Use \"none\" to disable timeout.",
-        default_value = "60s"
+        help = "Query timeout. Defaults to the connection's total timeout, or 60s. Use \"none\" to disable timeout."
+    )]
+    timeout: Option<OptionalDurationArg>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Time allowed to connect to the server. Defaults to the connection's connect timeout."
+    )]
+    connect_timeout: Option<humantime::Duration>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Time the response may stall before the query is abandoned. Defaults to the connection's read timeout."
+    )]
+    read_timeout: Option<humantime::Duration>,
+
+    #[arg(
+        long,
+        help = "Buffer the query response in a temporary file instead of memory. JSON and CSV output stream from disk."
+    )]
+    spill: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 0..,
+        value_name = "COLUMNS",
+        help = "Remove duplicate rows before rendering. Optionally compare only the given comma separated columns."
+    )]
+    distinct: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        value_name = "NAME = EXPRESSION",
+        help = "Add a column computed from each row, such as 'latency_ms = duration * 1000'. Supports arithmetic, comparisons, and string and date functions like strcat, substring, todatetime and datetime_diff. Repeat for more columns; later ones can use earlier ones."
+    )]
+    derive: Vec<Derive>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "COLUMNS",
+        help = "Replace the values of these comma separated columns with *** in every output. Patterns like '*password*' match column names ignoring case. Adds to the redact_columns configuration setting."
+    )]
+    redact: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "explain", "count", "schema_only"],
+        help = "Number the rows from 1 in a leading column, named \"#\" in table and markdown output and \"_row\" otherwise."
+    )]
+    row_numbers: bool,
+
+    #[arg(
+        long,
+        help = "Do not print the row count, timing, and transfer size footer after table output."
+    )]
+    no_summary: bool,
+
+    #[arg(
+        long,
+        help = "Send the query without checking it for unbalanced brackets, unterminated strings and unknown operators first."
+    )]
+    no_validate: bool,
+
+    #[arg(
+        long,
+        help = "Show execution statistics reported by the server, such as scanned rows and shards."
+    )]
+    stats: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "spill", "stats"],
+        help = "Show how the server would execute the query, without running it. Output is a tree of stages with estimated rows and shard fan-out."
+    )]
+    explain: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "spill", "stats", "explain"],
+        help = "Run the query against every subscription you can access, concurrently, and merge the results with a leading \"subscription\" column."
+    )]
+    all_subscriptions: bool,
+
+    #[arg(
+        short,
+        long,
+        value_name = "KEY=VALUE",
+        conflicts_with_all = ["follow", "spill", "stats", "explain", "all_subscriptions"],
+        help = "Run the query against every connection in this group, such as env=prod, concurrently, and merge the results with a leading \"connection\" column. Repeat to require several groups."
+    )]
+    group: Vec<Group>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "spill", "stats", "explain", "output", "output_file", "copy", "open", "tui", "template"],
+        help = "Print only the number of rows the query returns, and exit with status 1 when there are none. With --assert, --fail-if-empty or --fail-if-rows, those decide the exit status instead."
+    )]
+    count: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "spill", "stats", "explain", "count", "output_file", "copy", "open", "tui", "template"],
+        help = "Print the result's column names and types instead of its rows. Only the first row is fetched, so types are inferred from it; columns that are null there are reported as unknown."
+    )]
+    schema_only: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH[:FORMAT]",
+        help = "Also write the result to a file, or to an s3://bucket/key or az://container/path object in builds with the cloud feature. The format is inferred from the extension unless given. Can be repeated."
+    )]
+    output_file: Vec<OutputFileArg>,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "markdown",
+        value_parser = crate::fmt::parse::OutputModeParser::query(),
+        help = "Copy the result to the system clipboard. Copies a markdown table unless another format is given."
+    )]
+    copy: Option<OutputMode>,
+
+    #[arg(
+        long,
+        help = "Open an HTML view of the result, with sortable columns and a row filter, in the default browser."
+    )]
+    open: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["follow", "spill"],
+        help = "Browse the result in a full-screen grid that can be scrolled, searched and resized, with a detail view for long cells."
+    )]
+    tui: bool,
+
+    #[arg(long, help = "Exit with status 3 when the query returns no rows.")]
+    fail_if_empty: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "fail_if_empty",
+        help = "Exit with status 3 when the query returns any rows."
+    )]
+    fail_if_rows: bool,
+
+    #[arg(
+        long,
+        value_name = "CONDITION",
+        help = "Exit with status 3 unless the row count meets this condition, written as \"count <op> <n>\" with op one of >, >=, <, <=, ==, !=. Can be repeated."
+    )]
+    assert: Vec<Condition>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with = "follow",
+        help = "POST the query and its rows as JSON to this URL when --when matches. Can be repeated."
+    )]
+    notify_webhook: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with = "follow",
+        help = "Post a message to this Slack incoming webhook when --when matches. Can be repeated."
+    )]
+    notify_slack: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "CONDITION",
+        default_value = "rows > 0",
+        help = "When to send notifications, as \"rows <op> <count>\" with op one of >, >=, <, <=, ==, !=."
+    )]
+    when: Condition,
+
+    #[arg(
+        short,
+        long,
+        requires = "cursor_column",
+        conflicts_with_all = ["spill", "output_file", "fail_if_empty", "fail_if_rows", "assert"],
+        help = "Keep polling the query and print only rows newer than the previous poll."
+    )]
+    follow: bool,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["follow", "spill", "output_file", "copy", "open", "tui", "explain", "count", "schema_only", "all_subscriptions", "group", "fail_if_empty", "fail_if_rows", "assert"],
+        help = "Keep running the query every --interval and show the result again whenever it changes. On a terminal the screen is redrawn in place."
+    )]
+    watch: bool,
+
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        requires = "follow",
+        help = "Column of the queried table whose largest value marks the rows already printed in follow mode."
+    )]
+    cursor_column: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        help = "Delay between polls in follow and watch mode.",
+        default_value = "5s"
+    )]
+    interval: humantime::Duration,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Handlebars template rendered for each row, such as '{{time}} [{{level}}] {{message}}'. Implies \"--output template\"."
+    )]
+    template: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TABLE",
+        default_value = "results",
+        help = "Table that SQLite output files create or append to."
     )]
-    timeout: OptionalDurationArg,
+    table: String,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum QuerySubcommand {
+    #[command(flatten)]
+    Saved(SavedQueryCommand),
+    Diff(crate::diff::DiffCommand),
+    Fmt(crate::query_fmt::FmtCommand),
+}
+
+impl QueryCommand {
+    /// Builds a query command from arguments, as if they were given to "logsh query".
+    pub(crate) fn from_args(args: &[&str]) -> Result<Self, Error> {
+        use clap::{Args, FromArgMatches};
+
+        let matches = Self::augment_args(clap::Command::new("query"))
+            .try_get_matches_from(std::iter::once("query").chain(args.iter().copied()))?;
+        Ok(Self::from_arg_matches(&matches)?)
+    }
+
+    /// Per command timeouts. Limits not given here fall back to the connection's.
+    /// Whether the query runs against several subscriptions or connections at once.
+    fn fans_out(&self) -> bool {
+        self.all_subscriptions || !self.group.is_empty()
+    }
+
+    fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            connect: self.connect_timeout.map(Into::into),
+            read: self.read_timeout.map(Into::into),
+            total: self
+                .timeout
+                .and_then(|timeout| Timeouts::from(timeout).total),
+        }
+    }
+
+    /// Column patterns to mask, from the command line and the configuration.
+    fn redact_patterns(&self, cfg: &config::Configuration) -> Vec<String> {
+        cfg.redact_columns
+            .iter()
+            .chain(self.redact.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Conditions the row count must meet, from `--assert`, `--fail-if-empty` and `--fail-if-rows`.
+    fn assertions(&self) -> Vec<Condition> {
+        let mut assertions = self.assert.clone();
+        if self.fail_if_empty {
+            assertions.push(Condition::has_rows());
+        }
+        if self.fail_if_rows {
+            assertions.push(Condition::no_rows());
+        }
+        assertions
+    }
+
+    fn mode(&self) -> OutputMode {
+        match (self.output, self.template.as_ref()) {
+            (Some(mode), _) => mode,
+            (None, Some(_)) => OutputMode::Template,
+            (None, None) => OutputMode::configured(),
+        }
+    }
 }
 
-pub fn execute_query<W: Write>(command: QueryCommand, mut write: W) -> Result<(), Error> {
+/// Row and column counts of a rendered result.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Shape {
+    rows: usize,
+    columns: usize,
+}
+
+pub fn execute_query<W: Write>(mut command: QueryCommand, mut write: W) -> Result<(), Error> {
+    match command.command.take() {
+        Some(QuerySubcommand::Saved(saved)) => {
+            return crate::saved_query::execute_saved_query(saved, write)
+        }
+        Some(QuerySubcommand::Diff(diff)) => return crate::diff::execute_diff(diff, write),
+        Some(QuerySubcommand::Fmt(fmt)) => return crate::query_fmt::execute_fmt(fmt, write),
+        None => {}
+    }
+
+    if command.edit {
+        let initial = match (command.query.take(), command.saved.as_ref()) {
+            (Some(query), _) => query,
+            (None, Some(name)) => logsh_core::saved_query::load()?
+                .get(name)?
+                .query
+                .to_string(),
+            (None, None) => String::new(),
+        };
+        let query = crate::edit::compose(&initial)?;
+        let name = command.saved.take();
+        command.edit = false;
+        command.query = Some(query.clone());
+        // Offered even when the query failed, so a long composed query isn't lost to a typo.
+        let result = execute_query(command, write);
+        if let Err(err) = crate::edit::offer_save(&query, name.as_deref()) {
+            log::error!("Failed to save the query: {}", err);
+        }
+        return result;
+    }
+
+    if command.batch_stdin {
+        return batch_query(&command, write);
+    }
+
     log::debug!("Entering query execution: {:?}", &command);
     let start = Instant::now();
 
-    let query = if let Some(q) = command.query {
-        log::trace!("Provided query: {}", &q);
-        q
+    let (written, origin) = if let Some(q) = command.query.as_ref() {
+        log::trace!("Provided query: {}", q);
+        (q.to_string(), None)
+    } else if let Some(path) = command.file.as_ref() {
+        log::debug!("Reading query file: {}", path.display());
+        let query = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read query file {}: {}", path.display(), e))?;
+        (query, Some(path.display().to_string()))
+    } else if let Some(name) = command.saved.as_ref() {
+        log::debug!("Reading saved query: {}", name);
+        let saved = logsh_core::saved_query::load()?;
+        let query = saved.get(name)?.query.to_string();
+        (query, Some(format!("saved query \"{}\"", name)))
     } else {
-        log::debug!("Reading query from STDIN");
-        let mut s = String::new();
-        let _ = std::io::stdin()
-            .read_to_string(&mut s)
-            .map_err(|err| anyhow!("Failed to read STDIN: {}", err))?;
-        s
+        let query = crate::edit::read_stdin("query", "--query, --file, --saved or --edit")?;
+        (query, None)
     };
+    let expanded = logsh_core::snippet::expand_mapped(&written)?;
+    let query = expanded.text.clone();
+    let source = crate::fmt::QuerySource {
+        origin,
+        written,
+        expanded,
+    };
+
+    log::info!("Query:\n{}", crate::fmt::highlight::highlight(&query));
 
     let cfg = config::load()?;
-    let connection: config::ConnectionConfig = cfg
+    if !command.no_validate {
+        logsh_core::kusto::validate(&query).inspect_err(|err| {
+            crate::fmt::print_query_error_from(&cfg, &source, err);
+        })?;
+    }
+    let mut connection: config::ConnectionConfig = cfg
         .get_default_connection()
         .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
-    log::info!("Starting query. Timeout = {}", &command.timeout);
-    let r = connection
-        .connection
-        .query_raw(&query, command.timeout.into())
-        .map_err(|err| {
-            crate::fmt::print_query_error(&cfg, &query, &err);
-            err
+    if let (true, Some(column)) = (command.follow, command.cursor_column.as_ref()) {
+        return follow_query(&command, &cfg, &mut connection, &query, column, write);
+    }
+    if command.watch {
+        return watch_query(&command, &cfg, &mut connection, &query, write);
+    }
+    if command.explain {
+        return explain_query(&command, &cfg, &mut connection, &query, write);
+    }
+    if command.count {
+        return count_query(&command, &cfg, &mut connection, &query, write);
+    }
+    if command.schema_only {
+        return schema_query(&command, &cfg, &mut connection, &query, write);
+    }
+
+    log::info!("Starting query. Timeouts = {}", command.timeouts());
+    let mode = command.mode();
+    if matches!(mode, OutputMode::Sqlite)
+        && !command
+            .output_file
+            .iter()
+            .any(|sink| matches!(sink.mode, OutputMode::Sqlite))
+    {
+        return Err(anyhow!(
+            "SQLite output is written to a database file. Use --output-file <PATH>.db."
+        ));
+    }
+
+    if let Some(copy) = command.copy.filter(|copy| copy.is_binary()) {
+        return Err(anyhow!(
+            "{} output can't be copied to the clipboard.",
+            copy.name()
+        ));
+    }
+
+    if mode.is_binary() && std::io::stdout().is_terminal() {
+        return Err(anyhow!(
+            "Refusing to write binary {} output to a terminal. Redirect stdout or use --output-file <PATH>:{}.",
+            mode.name(),
+            mode.name()
+        ));
+    }
+
+    if command.tui && !std::io::stdout().is_terminal() {
+        return Err(anyhow!("--tui requires an interactive terminal."));
+    }
+
+    let summary =
+        !command.no_summary && matches!(mode, OutputMode::Table) && std::io::stdout().is_terminal();
+    let (text, spill, bytes) = if command.spill {
+        let (file, bytes) = spill_response(&cfg, &mut connection, &query, &command)?;
+        (None, Some(file), bytes)
+    } else if command.fans_out() {
+        let r = query_fan_out(&cfg, &mut connection, &query, &command)?;
+        let bytes = r.len() as u64;
+        (Some(r), None, bytes)
+    } else {
+        let progress = crate::progress::reporter();
+        let r = crate::connect::retry_unauthorized(&mut connection, |c| {
+            c.connection
+                .query_raw_with_progress(&query, command.timeouts(), &*progress)
+        })
+        .inspect_err(|err| {
+            let entry = HistoryEntry::failed(&connection.name, &query, start.elapsed());
+            record_history(&cfg, &entry);
+            crate::fmt::print_query_error_from(&cfg, &source, err);
         })?;
+        log::debug!("Response text: {:?}", r);
+        let bytes = r.len() as u64;
+        (Some(r), None, bytes)
+    };
 
-    log::debug!("Response text: {:?}", r);
-    let result = logsh_core::query::result(&r).map_err(|err| {
-        crate::fmt::print_query_error(&cfg, &query, &err);
-        err
-    })?;
     let query_duration = start.elapsed();
     let render_start = Instant::now();
     log::trace!("Finished query execution.");
     log::trace!("Processing result.");
-    match command.output.unwrap_or_default() {
-        OutputMode::Table => {
-            log::trace!("Outputting table");
-            render_table(result, TableStyle::thin(), false, write)
-        }
-        OutputMode::Markdown => {
-            log::trace!("Outputting markdown table");
-            render_table(result, markdown_style(), true, write)
-        }
-        OutputMode::Json => {
-            log::trace!("Outputting unformatted JSON");
-            writeln!(write, "{}", r)?;
-            Ok(())
+
+    // Spilled JSON and CSV can be streamed from disk, as long as nothing needs the loaded result.
+    let redact = command.redact_patterns(&cfg);
+    let streamable = spill.is_some()
+        && command.distinct.is_none()
+        && command.derive.is_empty()
+        && redact.is_empty()
+        && !command.row_numbers;
+    let can_stream = |mode: OutputMode, primary: bool| {
+        streamable
+            && !(primary && command.stats)
+            && matches!(mode, OutputMode::Json | OutputMode::Csv)
+    };
+    let needs_result = !can_stream(mode, true)
+        || command
+            .output_file
+            .iter()
+            .any(|sink| !can_stream(sink.mode, false))
+        || command.copy.is_some_and(|copy| !can_stream(copy, false))
+        || command.open
+        || command.tui
+        || !command.notify_webhook.is_empty()
+        || !command.notify_slack.is_empty()
+        || !command.assertions().is_empty();
+
+    let text = match (text, spill.as_ref()) {
+        (Some(text), _) => Some(text),
+        (None, Some(file)) if needs_result => {
+            log::warn!(
+                "Output options require the full result in memory. Reading spilled response."
+            );
+            let mut r = String::new();
+            BufReader::new(file)
+                .read_to_string(&mut r)
+                .map_err(|err| anyhow!("Failed to read query spill file: {}", err))?;
+            Some(r)
         }
-        OutputMode::JsonPretty => {
-            log::trace!("Outputting pretty JSON");
-            serde_json::to_writer_pretty(write, &result)?;
+        _ => None,
+    };
+
+    let text = text
+        .map(|text| post_fetch(&command, &cfg, text))
+        .transpose()?;
+
+    let numbers;
+    let mut result = match text.as_deref() {
+        Some(r) => Some(logsh_core::query::result(r).inspect_err(|err| {
+            crate::fmt::print_query_error_from(&cfg, &source, err);
+        })?),
+        None => None,
+    };
+    let rows = result.as_ref().map(|r| r.results.len());
+    let entry = HistoryEntry::new(&connection.name, &query, query_duration, rows, bytes);
+    record_history(&cfg, &entry);
+    let unchanged = match result.as_mut() {
+        Some(result) => post_process(&command, result).inspect_err(|err| {
+            crate::fmt::print_query_error_from(&cfg, &source, err);
+        })?,
+        None => true,
+    };
+    let unchanged = match (command.row_numbers, result.as_mut()) {
+        (true, Some(result)) => {
+            let column = match mode {
+                OutputMode::Table | OutputMode::Markdown => "#",
+                _ => "_row",
+            };
+            numbers = logsh_core::query::row_numbers(result.results.len());
+            result.number_rows(column, &numbers);
+            false
+        }
+        _ => unchanged,
+    };
+
+    let output = Output {
+        spill: spill.as_ref(),
+        result: result.as_ref(),
+        raw: text.as_deref().filter(|_| unchanged),
+        template: command.template.as_deref(),
+    };
+    let shape = match mode {
+        // Written by the SQLite file sinks below.
+        OutputMode::Sqlite => None,
+        // Shown in the browser once everything else is written.
+        _ if command.tui => None,
+        _ => output.render(mode, can_stream(mode, true), &mut write)?,
+    };
+    if let (true, Some(shape)) = (summary, shape) {
+        render_summary(&mut write, shape, query_duration, bytes)?;
+    }
+    if command.stats {
+        let statistics = result.as_ref().and_then(|r| r.statistics.as_ref());
+        render_statistics(&mut write, mode, statistics)?;
+    }
+
+    for sink in command.output_file.iter() {
+        log::info!("Writing {:?} output to {}", sink.mode, sink.path.display());
+        let url = sink.path.to_string_lossy();
+        if logsh_core::cloud::is_cloud_url(&url) {
+            let contents = match sink.mode {
+                OutputMode::Sqlite => {
+                    let result = result
+                        .as_ref()
+                        .ok_or_else(|| anyhow!("Query result was not loaded."))?;
+                    let temp = tempfile::Builder::new().suffix(".db").tempfile()?;
+                    logsh_core::sqlite::write_sqlite(result, temp.path(), &command.table)?;
+                    std::fs::read(temp.path())?
+                }
+                _ => {
+                    let mut contents = Vec::new();
+                    output.render_plain(sink.mode, can_stream(sink.mode, false), &mut contents)?;
+                    contents
+                }
+            };
+            logsh_core::cloud::put(&url, contents)
+                .map_err(|err| anyhow!("Failed to write output file {}: {}", url, err))?;
+            continue;
+        }
+
+        if let OutputMode::Sqlite = sink.mode {
+            let result = result
+                .as_ref()
+                .ok_or_else(|| anyhow!("Query result was not loaded."))?;
+            let rows = logsh_core::sqlite::write_sqlite(result, &sink.path, &command.table)
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to write output file {}: {}",
+                        sink.path.display(),
+                        err
+                    )
+                })?;
+            if matches!(mode, OutputMode::Sqlite) {
+                writeln!(
+                    write,
+                    "Wrote {} rows to table {} in {}.",
+                    rows,
+                    command.table.bright_blue(),
+                    sink.path.display()
+                )?;
+            }
+            continue;
+        }
+
+        let file = File::create(&sink.path).map_err(|err| {
+            anyhow!(
+                "Failed to create output file {}: {}",
+                sink.path.display(),
+                err
+            )
+        })?;
+
+        let mut writer = BufWriter::new(file);
+        output
+            .render_plain(sink.mode, can_stream(sink.mode, false), &mut writer)
+            .and_then(|_| writer.flush().map_err(Error::from))
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to write output file {}: {}",
+                    sink.path.display(),
+                    err
+                )
+            })?;
+    }
+
+    if let Some(copy) = command.copy {
+        let mut text = Vec::new();
+        output.render_plain(copy, can_stream(copy, false), &mut text)?;
+        crate::clipboard::copy(&String::from_utf8_lossy(&text))
+            .map_err(|err| anyhow!("Failed to copy result to the clipboard: {}", err))?;
+        log::info!("Copied {} output to the clipboard.", copy.name());
+    }
+
+    if command.open {
+        let (file, path) = create_view()?;
+        let mut writer = BufWriter::new(file);
+        output
+            .render_plain(OutputMode::Html, false, &mut writer)
+            .and_then(|_| writer.flush().map_err(Error::from))?;
+        log::info!("Opening HTML view {}", path.display());
+        webbrowser::open(&path.to_string_lossy())
+            .map_err(|err| anyhow!("Failed to open {} in a browser: {}", path.display(), err))?;
+    }
+
+    let targets = command
+        .notify_webhook
+        .iter()
+        .map(|url| (url, NotifyFormat::Webhook))
+        .chain(
+            command
+                .notify_slack
+                .iter()
+                .map(|url| (url, NotifyFormat::Slack)),
+        );
+    for (url, format) in targets {
+        let result = result
+            .as_ref()
+            .ok_or_else(|| anyhow!("Query result was not loaded."))?;
+        if !command.when.matches(result.results.len()) {
+            log::info!(
+                "Skipping {:?} notification: {} is false.",
+                format,
+                command.when
+            );
+            continue;
+        }
+        logsh_core::notify::notify(
+            url,
+            format,
+            &query,
+            &command.when,
+            result,
+            command.timeout.and_then(Into::into),
+        )?;
+        log::info!("Sent {:?} notification.", format);
+    }
+
+    if let Some(result) = result.as_ref() {
+        check_assertions(&command, result.results.len(), &mut write)?;
+    }
+
+    if command.tui {
+        let result = result
+            .as_ref()
+            .ok_or_else(|| anyhow!("Query result was not loaded."))?;
+        log_timings(start, query_duration, render_start);
+        return crate::tui::browser::browse(result);
+    }
+
+    log_timings(start, query_duration, render_start);
+    Ok(())
+}
+
+/// Polls the query forever, printing only rows past the cursor column's watermark each time.
+fn follow_query<W: Write>(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    column: &str,
+    mut write: W,
+) -> Result<(), Error> {
+    let mode = command.mode();
+    let mut cursor = Cursor::new(column);
+    loop {
+        let poll = cursor.apply(query);
+        log::debug!("Polling query: {}", poll);
+        let r = crate::connect::retry_unauthorized(connection, |c| {
+            c.connection.query_raw(&poll, command.timeouts())
+        })
+        .inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, &poll, err);
+        })?;
+        let r = post_fetch(command, cfg, r)?;
+        let mut result = logsh_core::query::result(&r).inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, &poll, err);
+        })?;
+        post_process(command, &mut result)?;
+        let rows = cursor.advance(&mut result)?;
+        log::info!("Poll returned {} new rows.", rows);
+        if rows > 0 {
+            crate::fmt::render_result(
+                &result,
+                None,
+                mode,
+                command.template.as_deref(),
+                &mut write,
+            )?;
+            if command.stats {
+                render_statistics(&mut write, mode, result.statistics.as_ref())?;
+            }
+            write.flush()?;
+        }
+
+        std::thread::sleep(command.interval.into());
+    }
+}
+
+/// Runs the query every interval, showing the result again only when it changed. The server
+/// can skip sending an unchanged result when it tags results with ETags. On a terminal the
+/// screen is cleared and redrawn, with a line saying how many rows changed.
+fn watch_query<W: Write>(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    mut write: W,
+) -> Result<(), Error> {
+    let mode = command.mode();
+    let terminal = std::io::stdout().is_terminal();
+    let mut watch = Watch::new(query);
+    loop {
+        let poll = crate::connect::retry_unauthorized(connection, |c| {
+            watch.poll(&c.connection, command.timeouts())
+        })
+        .inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, query, err);
+        })?;
+        let changed = match poll {
+            Poll::Changed(changed) => changed,
+            Poll::Unchanged => {
+                log::debug!("Result unchanged, not rendering it again.");
+                std::thread::sleep(command.interval.into());
+                continue;
+            }
+        };
+
+        let r = post_fetch(command, cfg, watch.body().unwrap_or_default().to_string())?;
+        let mut result = logsh_core::query::result(&r).inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, query, err);
+        })?;
+        post_process(command, &mut result)?;
+
+        if terminal {
+            crossterm::queue!(
+                write,
+                crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
+                crossterm::cursor::MoveTo(0, 0)
+            )?;
+        }
+        crate::fmt::render_result(&result, None, mode, command.template.as_deref(), &mut write)?;
+        if terminal {
+            writeln!(
+                write,
+                "{}",
+                format!(
+                    "{} of {} rows changed, updated {}. Every {}, Ctrl+C to stop.",
+                    changed.len(),
+                    result.results.len(),
+                    chrono::Local::now().format("%H:%M:%S"),
+                    command.interval
+                )
+                .bright_black()
+            )?;
+        }
+        write.flush()?;
+
+        std::thread::sleep(command.interval.into());
+    }
+}
+
+/// Prints the number of rows the query returns. The query is wrapped with `| count` so the
+/// server does the counting, unless options that change the rows need them loaded here. Exits
+/// with status 1 when there are no rows, so the command works in shell conditionals.
+fn count_query<W: Write>(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    mut write: W,
+) -> Result<(), Error> {
+    let local = command.distinct.is_some() || !command.derive.is_empty() || command.fans_out();
+    let query = if local {
+        query.to_string()
+    } else {
+        format!("{}\n| count", query.trim_end().trim_end_matches(';'))
+    };
+
+    let text = if command.fans_out() {
+        query_fan_out(cfg, connection, &query, command)?
+    } else {
+        crate::connect::retry_unauthorized(connection, |c| {
+            c.connection.query_raw(&query, command.timeouts())
+        })
+        .inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, &query, err);
+        })?
+    };
+    let text = post_fetch(command, cfg, text)?;
+    let mut result = logsh_core::query::result(&text).inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, &query, err);
+    })?;
+
+    let count = if local {
+        post_process(command, &mut result)?;
+        result.results.len() as u64
+    } else {
+        let value = result
+            .results
+            .first()
+            .zip(result.header.first())
+            .and_then(|(row, column)| row.get(column.as_str()));
+        match value {
+            Some(value) => serde_json::from_str(value.get())
+                .map_err(|err| anyhow!("Unexpected count {}: {}", value.get(), err))?,
+            None => 0,
+        }
+    };
+
+    writeln!(write, "{}", count)?;
+    check_assertions(command, count as usize, &mut write)?;
+    // Assertions decide the exit status when given, so --fail-if-rows can pass on no rows.
+    if count == 0 && command.assertions().is_empty() {
+        write.flush()?;
+        return Err(ExitStatus(1).into());
+    }
+    Ok(())
+}
+
+/// A column of a `--schema-only` result. The type is None when the fetched row has no value.
+#[derive(Debug, serde::Serialize)]
+struct SchemaColumn<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    typ: Option<ColumnType>,
+}
+
+/// Prints the column names and inferred types of the query's result. The query is limited to
+/// one row, which is enough to infer types from without transferring the rest.
+fn schema_query<W: Write>(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    write: W,
+) -> Result<(), Error> {
+    let query = format!("{}\n| take 1", query.trim_end().trim_end_matches(';'));
+    let text = if command.fans_out() {
+        query_fan_out(cfg, connection, &query, command)?
+    } else {
+        crate::connect::retry_unauthorized(connection, |c| {
+            c.connection.query_raw(&query, command.timeouts())
+        })
+        .inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, &query, err);
+        })?
+    };
+    let text = post_fetch(command, cfg, text)?;
+    let result = logsh_core::query::result(&text).inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, &query, err);
+    })?;
+    let frame = DataFrame::from_result(&result)?;
+    let columns: Vec<SchemaColumn> = frame
+        .columns()
+        .map(|(name, column)| SchemaColumn {
+            name,
+            typ: (!column.iter().all(|cell| cell.is_null())).then(|| column.typ()),
+        })
+        .collect();
+
+    crate::fmt::render(write, command.mode(), &columns, |columns| {
+        let results = columns
+            .iter()
+            .map(|c| {
+                let typ = c.typ.map_or("unknown".to_string(), |t| t.to_string());
+                HashMap::from([
+                    ("Column".to_string(), serde_json::Value::from(c.name)),
+                    ("Type".to_string(), serde_json::Value::String(typ)),
+                ])
+            })
+            .collect();
+        QueryResultFmt {
+            header: vec!["Column".to_string(), "Type".to_string()],
+            results,
+        }
+    })
+}
+
+/// Adds derived columns to a response and masks redacted ones, in that order, so derived
+/// columns are masked too.
+fn post_fetch(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    text: String,
+) -> Result<String, Error> {
+    let text = match command.derive.is_empty() {
+        true => text,
+        false => logsh_core::transform::derive(&text, &command.derive)?,
+    };
+    let redact = command.redact_patterns(cfg);
+    match logsh_core::redact::redact_columns(&text, &redact)
+        .map_err(|err| anyhow!("Failed to redact columns: {}", err))?
+    {
+        Cow::Owned(redacted) => Ok(redacted),
+        Cow::Borrowed(_) => Ok(text),
+    }
+}
+
+/// One `--batch-stdin` query's result, or why it failed, written as a JSON document.
+#[derive(serde::Serialize)]
+struct BatchDocument<'a, 'r> {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    query: &'a str,
+    #[serde(flatten)]
+    result: Option<&'a QueryResult<'r>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Runs each query read from stdin in turn on one connection, so a token refreshed for one
+/// query serves the rest. A failed query doesn't stop the batch, but fails the command at the end.
+fn batch_query<W: Write>(command: &QueryCommand, mut write: W) -> Result<(), Error> {
+    let mode = command.mode();
+    if !matches!(
+        mode,
+        OutputMode::Json | OutputMode::JsonPretty | OutputMode::Csv
+    ) {
+        return Err(anyhow!(
+            "--batch-stdin writes json, json-pretty or csv output."
+        ));
+    }
+
+    let input = crate::edit::read_stdin("queries", "--query")?;
+    let queries = logsh_core::query::split_batch(&input);
+    if queries.is_empty() {
+        return Err(anyhow!("No queries found between the separators."));
+    }
+
+    let cfg = config::load()?;
+    let mut connection: config::ConnectionConfig = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let dir = command
+        .batch_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut failed = 0;
+    let mut written = HashSet::new();
+    for (i, batch) in queries.iter().enumerate() {
+        let index = i + 1;
+        log::info!("Running query {} of {}.", index, queries.len());
+        let outcome = batch_text(command, &cfg, &mut connection, &batch.query).and_then(|text| {
+            let mut result = logsh_core::query::result(&text)?;
+            post_process(command, &mut result)?;
+            match mode {
+                OutputMode::Csv => {
+                    let name = batch
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("query-{:03}", index));
+                    let path = batch_file(&dir, &name, &mut written);
+                    let file = File::create(&path)
+                        .map_err(|err| anyhow!("Failed to create {}: {}", path.display(), err))?;
+                    crate::fmt::render_result(
+                        &result,
+                        Some(&text),
+                        mode,
+                        None,
+                        BufWriter::new(file),
+                    )?;
+                    writeln!(write, "{}", path.display())?;
+                }
+                _ => write_batch_document(&mut write, mode, index, batch, Some(&result), None)?,
+            }
             Ok(())
+        });
+
+        if let Err(err) = outcome {
+            failed += 1;
+            match mode {
+                OutputMode::Csv => log::error!("Query {} failed: {}", index, err),
+                _ => write_batch_document(
+                    &mut write,
+                    mode,
+                    index,
+                    batch,
+                    None,
+                    Some(err.to_string()),
+                )?,
+            }
         }
-        OutputMode::Csv => {
-            log::trace!("Outputting CSV");
-            logsh_core::csv::write_csv(&result, write)
-                .map_err(|e| anyhow!("Failed to convert to CSV: {}", e))
+    }
+
+    write.flush()?;
+    if failed > 0 {
+        return Err(anyhow!("{} of {} queries failed.", failed, queries.len()));
+    }
+    Ok(())
+}
+
+/// The response text of one batch query, with derived columns added and redacted.
+fn batch_text(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+) -> Result<String, Error> {
+    let query = logsh_core::snippet::expand(query)?;
+    if !command.no_validate {
+        logsh_core::kusto::validate(&query).inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, &query, err);
+        })?;
+    }
+    let text = crate::connect::retry_unauthorized(connection, |c| {
+        c.connection.query_raw(&query, command.timeouts())
+    })
+    .inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, &query, err);
+    })?;
+    post_fetch(command, cfg, text)
+}
+
+fn write_batch_document<W: Write>(
+    write: &mut W,
+    mode: OutputMode,
+    index: usize,
+    batch: &logsh_core::query::BatchQuery,
+    result: Option<&QueryResult<'_>>,
+    error: Option<String>,
+) -> Result<(), Error> {
+    let document = BatchDocument {
+        index,
+        name: batch.name.as_deref(),
+        query: &batch.query,
+        result,
+        error,
+    };
+    match mode {
+        OutputMode::JsonPretty => writeln!(write, "{}", serde_json::to_string_pretty(&document)?)?,
+        _ => writeln!(write, "{}", serde_json::to_string(&document)?)?,
+    }
+    Ok(())
+}
+
+/// The CSV file of a batch query, with characters that aren't safe in file names replaced.
+/// Queries sharing a name get numbered files, rather than overwriting the earlier ones.
+fn batch_file(dir: &Path, name: &str, written: &mut HashSet<PathBuf>) -> PathBuf {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    let mut path = dir.join(format!("{}.csv", name));
+    let mut n = 1;
+    while written.contains(&path) {
+        n += 1;
+        path = dir.join(format!("{}-{}.csv", name, n));
+    }
+    written.insert(path.clone());
+    path
+}
+
+/// Fails with [ASSERTION_FAILED_EXIT_CODE] as the exit status when the row count fails one of
+/// the command's assertions, after flushing the output already written.
+fn check_assertions<W: Write>(
+    command: &QueryCommand,
+    rows: usize,
+    write: &mut W,
+) -> Result<(), Error> {
+    let failed: Vec<String> = command
+        .assertions()
+        .iter()
+        .filter(|assertion| !assertion.matches(rows))
+        .map(ToString::to_string)
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    write.flush()?;
+    log::error!(
+        "Assertion failed: {} (the query returned {} rows).",
+        failed.join(", "),
+        rows
+    );
+    Err(ExitStatus(ASSERTION_FAILED_EXIT_CODE).into())
+}
+
+/// Runs the query against each subscription or connection the command fans out to.
+fn query_fan_out(
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    command: &QueryCommand,
+) -> Result<String, Error> {
+    match command.all_subscriptions {
+        true => query_all_subscriptions(cfg, connection, query, command),
+        false => query_group(cfg, query, command),
+    }
+}
+
+/// Runs the query against each of the user's subscriptions, and merges the responses into one
+/// with a leading `subscription` column.
+fn query_all_subscriptions(
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    command: &QueryCommand,
+) -> Result<String, Error> {
+    let subscriptions = crate::connect::retry_unauthorized(connection, |c| {
+        c.connection.subscriptions(c.connection.user_id)
+    })?;
+    if subscriptions.is_empty() {
+        return Err(anyhow!("No subscriptions found for {}.", connection.name));
+    }
+    log::info!("Querying {} subscriptions.", subscriptions.len());
+
+    let targets = subscriptions
+        .into_iter()
+        .map(|subscription| {
+            let mut conn = connection.connection.clone();
+            conn.default_subscription = Some(subscription.account_id);
+            (subscription.account_name, conn)
+        })
+        .collect();
+    merge_fan_out(cfg, query, command, SUBSCRIPTION_COLUMN, targets)
+}
+
+/// Runs the query against the default subscription of each connection in the command's
+/// groups, and merges the responses into one with a leading `connection` column.
+fn query_group(
+    cfg: &config::Configuration,
+    query: &str,
+    command: &QueryCommand,
+) -> Result<String, Error> {
+    let connections = cfg.connections_in(&command.group);
+    if connections.is_empty() {
+        return Err(crate::connect::no_connections(cfg, &command.group));
+    }
+    log::info!("Querying {} connections.", connections.len());
+
+    let targets = connections
+        .into_iter()
+        .map(|c| (c.name, c.connection))
+        .collect();
+    merge_fan_out(cfg, query, command, CONNECTION_COLUMN, targets)
+}
+
+/// Runs the query on each labelled connection, a few at a time, and merges the responses into
+/// one with a leading `column` of labels. Targets whose query fails are skipped with a warning,
+/// unless every one of them fails.
+fn merge_fan_out(
+    cfg: &config::Configuration,
+    query: &str,
+    command: &QueryCommand,
+    column: &str,
+    targets: Vec<(String, Connection)>,
+) -> Result<String, Error> {
+    let timeouts = command.timeouts();
+    let mut responses = Vec::with_capacity(targets.len());
+    for chunk in targets.chunks(PARALLEL_SUBSCRIPTIONS) {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|(_, conn)| scope.spawn(move || conn.query_raw(query, timeouts)))
+                .collect();
+            for ((label, _), handle) in chunk.iter().zip(handles) {
+                let response = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                responses.push((label, response));
+            }
+        });
+    }
+
+    let mut header = vec![column.to_string()];
+    let mut results = Vec::new();
+    let mut first_error = None;
+    for (label, response) in responses {
+        let text = match response {
+            Ok(text) => text,
+            Err(err) => {
+                log::warn!("Query failed for {} {}: {}", column, label, err);
+                first_error.get_or_insert(err);
+                continue;
+            }
+        };
+        let result = match logsh_core::query::result(&text) {
+            Ok(result) => result,
+            Err(err) => {
+                log::warn!("Query failed for {} {}: {}", column, label, err);
+                first_error.get_or_insert(err);
+                continue;
+            }
+        };
+
+        for c in result.header.iter() {
+            if !header.contains(c) {
+                header.push(c.to_string());
+            }
+        }
+        for row in result.results.iter() {
+            let mut merged = HashMap::from([(
+                column.to_string(),
+                serde_json::Value::String(label.to_string()),
+            )]);
+            for (c, value) in row.iter() {
+                merged.insert(c.to_string(), serde_json::from_str(value.get())?);
+            }
+            results.push(merged);
+        }
+    }
+
+    if results.is_empty() {
+        if let Some(err) = first_error {
+            crate::fmt::print_query_error(cfg, query, &err);
+            return Err(err.into());
+        }
+    }
+
+    Ok(serde_json::to_string(&QueryResultFmt { header, results })?)
+}
+
+/// Fetches the query's execution plan and writes it as an indented tree, or as JSON for the
+/// JSON output modes.
+fn explain_query<W: Write>(
+    command: &QueryCommand,
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    write: W,
+) -> Result<(), Error> {
+    let plan = crate::connect::retry_unauthorized(connection, |c| {
+        c.connection.explain(query, command.timeouts())
+    })
+    .inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, query, err);
+    })?;
+
+    crate::fmt::render_with(write, command.mode(), &plan, |write, _, plan| {
+        if plan.stages.is_empty() {
+            writeln!(
+                write,
+                "{}",
+                "The server returned an empty plan.".bright_black()
+            )?;
+        }
+        for stage in plan.stages.iter() {
+            write_plan_stage(write, stage, "", None)?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes a stage and the stages feeding it. `last` is None for a root, otherwise whether the
+/// stage is the last child of its parent.
+fn write_plan_stage<W: Write>(
+    write: &mut W,
+    stage: &PlanStage,
+    prefix: &str,
+    last: Option<bool>,
+) -> Result<(), Error> {
+    let (branch, indent) = match last {
+        None => ("", ""),
+        Some(false) => ("├─ ", "│  "),
+        Some(true) => ("└─ ", "   "),
+    };
+    let mut line = format!(
+        "{}{}{}",
+        prefix,
+        branch,
+        stage.operator.bright_blue().bold()
+    );
+    if let Some(detail) = stage.detail.as_ref() {
+        line.push_str(&format!(" {}", crate::fmt::highlight::highlight(detail)));
+    }
+    if let Some(rows) = stage.estimated_rows {
+        line.push_str(&format!("  {}", format!("~{} rows", rows).bright_black()));
+    }
+    if let Some(shards) = stage.shards {
+        let shards = if shards == 1 {
+            "1 shard".to_string()
+        } else {
+            format!("{} shards", shards)
+        };
+        line.push_str(&format!("  {}", shards.yellow()));
+    }
+    writeln!(write, "{}", line)?;
+
+    let prefix = format!("{}{}", prefix, indent);
+    for (i, child) in stage.children.iter().enumerate() {
+        write_plan_stage(write, child, &prefix, Some(i + 1 == stage.children.len()))?;
+    }
+    Ok(())
+}
+
+/// Streams the query response into an anonymous temporary file, returning the file and its size.
+fn spill_response(
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    command: &QueryCommand,
+) -> Result<(File, u64), Error> {
+    let mut file = tempfile::tempfile()
+        .map_err(|err| anyhow!("Failed to create query spill file: {}", err))?;
+    let bytes = {
+        let mut writer = BufWriter::new(&mut file);
+        let progress = crate::progress::reporter();
+        let bytes = crate::connect::retry_unauthorized(connection, |c| {
+            c.connection
+                .query_to_writer(query, command.timeouts(), &mut writer, &*progress)
+        })
+        .inspect_err(|err| {
+            crate::fmt::print_query_error(cfg, query, err);
+        })?;
+        writer
+            .flush()
+            .map_err(|err| anyhow!("Failed to write query spill file: {}", err))?;
+        bytes
+    };
+
+    log::debug!("Spilled {} response bytes to disk.", bytes);
+    file.seek(SeekFrom::Start(0))?;
+    Ok((file, bytes))
+}
+
+/// A query response ready to be rendered to one or more outputs.
+struct Output<'r, 'a> {
+    spill: Option<&'r File>,
+    result: Option<&'r QueryResult<'a>>,
+    raw: Option<&'r str>,
+    template: Option<&'r str>,
+}
+
+impl<'r, 'a> Output<'r, 'a> {
+    /// Renders the response in the given mode. Returns the result shape when the result was loaded.
+    fn render<W: Write>(
+        &self,
+        mode: OutputMode,
+        stream: bool,
+        mut write: W,
+    ) -> Result<Option<Shape>, Error> {
+        if let (true, Some(mut file)) = (stream, self.spill) {
+            file.seek(SeekFrom::Start(0))?;
+            let mut reader = BufReader::new(file);
+            match mode {
+                OutputMode::Csv => {
+                    log::trace!("Streaming CSV from disk");
+                    logsh_core::csv::write_csv_from_reader(reader, write)
+                        .map_err(|e| anyhow!("Failed to convert to CSV: {}", e))?;
+                }
+                _ => {
+                    log::trace!("Streaming unformatted JSON from disk");
+                    std::io::copy(&mut reader, &mut write)?;
+                    writeln!(write)?;
+                }
+            }
+
+            return Ok(None);
+        }
+
+        let result = self
+            .result
+            .ok_or_else(|| anyhow!("Query result was not loaded."))?;
+        let shape = Shape {
+            rows: result.results.len(),
+            columns: result.header.len(),
+        };
+        crate::fmt::render_result(result, self.raw, mode, self.template, write)?;
+        Ok(Some(shape))
+    }
+
+    /// Renders without terminal colors or fitting tables to the terminal, for outputs that
+    /// aren't the terminal.
+    fn render_plain<W: Write>(
+        &self,
+        mode: OutputMode,
+        stream: bool,
+        write: W,
+    ) -> Result<Option<Shape>, Error> {
+        let colorize = colored::control::SHOULD_COLORIZE.should_colorize();
+        colored::control::set_override(false);
+        let fit = crate::fmt::set_fit_to_terminal(false);
+        let rendered = self.render(mode, stream, write);
+        crate::fmt::set_fit_to_terminal(fit);
+        colored::control::set_override(colorize);
+        rendered
+    }
+}
+
+/// HTML views older than this are removed when the next one is opened. The browser may still
+/// be loading the newest, so it's never removed straight away.
+const VIEW_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Creates a file for an HTML view in logsh's views directory, first removing views older than
+/// [VIEW_RETENTION].
+fn create_view() -> Result<(File, PathBuf), Error> {
+    let dir = logsh_core::config::get_views_path()?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|err| anyhow!("Failed to create {}: {}", dir.display(), err))?;
+    for entry in std::fs::read_dir(&dir)?.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > VIEW_RETENTION);
+        if expired && entry.path().extension().is_some_and(|e| e == "html") {
+            log::debug!("Removing old HTML view {}", entry.path().display());
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+
+    tempfile::Builder::new()
+        .prefix("logsh-")
+        .suffix(".html")
+        .tempfile_in(&dir)
+        .and_then(|file| file.keep().map_err(|err| err.error))
+        .map_err(|err| anyhow!("Failed to create HTML view file: {}", err))
+}
+
+/// Adds a run to the query history that `logsh stats` summarizes. A query doesn't fail for
+/// want of recording it.
+pub(crate) fn record_history(cfg: &config::Configuration, entry: &HistoryEntry) {
+    if let Err(err) = logsh_core::history::record(cfg, entry) {
+        log::warn!("Failed to record query history: {}", err);
+    }
+}
+
+/// Applies the client side result options. Returns true when the result is unchanged,
+/// meaning the raw response can still be written as-is.
+fn post_process(command: &QueryCommand, result: &mut QueryResult<'_>) -> Result<bool, QueryError> {
+    let mut unchanged = true;
+    if let Some(columns) = command.distinct.as_ref() {
+        let removed = result.distinct(columns)?;
+        log::debug!("Removed {} duplicate rows.", removed);
+        unchanged &= removed == 0;
+    }
+
+    Ok(unchanged)
+}
+
+fn render_summary<W: Write>(
+    mut write: W,
+    shape: Shape,
+    query_duration: Duration,
+    bytes: u64,
+) -> Result<(), Error> {
+    let summary = format!(
+        "{} {}, {} {} in {:.3}s, {} received",
+        shape.rows,
+        if shape.rows == 1 { "row" } else { "rows" },
+        shape.columns,
+        if shape.columns == 1 {
+            "column"
+        } else {
+            "columns"
+        },
+        query_duration.as_secs_f64(),
+        crate::fmt::format_bytes(bytes),
+    );
+    writeln!(write, "{}", summary.bright_black())
+        .map_err(|e| anyhow!("Failed to write summary: {}", e))
+}
+
+/// Renders server execution statistics as a two column table. Machine readable output modes
+/// keep stdout clean, so the panel goes to stderr for them.
+fn render_statistics<W: Write>(
+    write: W,
+    mode: OutputMode,
+    statistics: Option<&QueryStatistics>,
+) -> Result<(), Error> {
+    match mode {
+        OutputMode::Table | OutputMode::Markdown => write_statistics(write, mode, statistics),
+        _ => write_statistics(std::io::stderr(), OutputMode::Table, statistics),
+    }
+}
+
+fn write_statistics<W: Write>(
+    mut write: W,
+    mode: OutputMode,
+    statistics: Option<&QueryStatistics>,
+) -> Result<(), Error> {
+    let statistics = match statistics {
+        Some(s) => s,
+        None => {
+            writeln!(
+                write,
+                "{}",
+                "The server did not return query statistics.".bright_black()
+            )?;
+            return Ok(());
         }
-    }?;
+    };
 
+    let mut rows: Vec<(String, String)> = Vec::new();
+    if let Some(v) = statistics.scanned_rows {
+        rows.push(("Scanned Rows".to_string(), v.to_string()));
+    }
+    if let Some(v) = statistics.scanned_bytes {
+        rows.push(("Scanned Bytes".to_string(), crate::fmt::format_bytes(v)));
+    }
+    if let Some(v) = statistics.shards {
+        rows.push(("Shards".to_string(), v.to_string()));
+    }
+    if let Some(v) = statistics.cache_hits {
+        rows.push(("Cache Hits".to_string(), v.to_string()));
+    }
+    if let Some(v) = statistics.execution_time_ms {
+        rows.push(("Execution Time".to_string(), format!("{}ms", v)));
+    }
+    for (k, v) in statistics.other.iter() {
+        let value = match v {
+            serde_json::Value::String(s) => s.to_string(),
+            v => v.to_string(),
+        };
+        rows.push((k.to_string(), value));
+    }
+
+    let is_markdown = matches!(mode, OutputMode::Markdown);
+    let mut table = Table::new();
+    table.style = if is_markdown {
+        crate::fmt::markdown_style()
+    } else {
+        TableStyle::thin()
+    };
+    let header = |s: &str| {
+        if is_markdown {
+            s.to_string()
+        } else {
+            s.bright_white().bold().to_string()
+        }
+    };
+    table.add_row(Row::new(vec![
+        TableCell::new_with_alignment(header("Statistic"), 1, Alignment::Left),
+        TableCell::new_with_alignment(header("Value"), 1, Alignment::Right),
+    ]));
+    for (name, value) in rows {
+        table.add_row(Row::new(vec![
+            TableCell::new_with_alignment(name, 1, Alignment::Left),
+            TableCell::new_with_alignment(value, 1, Alignment::Right),
+        ]));
+    }
+
+    writeln!(write, "{}", table.render()).map_err(|e| anyhow!("Failed to write statistics: {}", e))
+}
+
+fn log_timings(start: Instant, query_duration: Duration, render_start: Instant) {
     let render_duration = render_start.elapsed();
     let elapsed = start.elapsed();
     log::debug!(
@@ -135,97 +1698,4 @@ pub fn execute_query<W: Write>(command: QueryCommand, mut write: W) -> Result<()
         elapsed.as_secs_f64(),
         elapsed.as_millis()
     );
-    Ok(())
-}
-
-fn render_table<W: Write>(
-    result: logsh_core::query::QueryResult<'_>,
-    style: TableStyle,
-    is_markdown: bool,
-    mut write: W,
-) -> Result<(), Error> {
-    let mut table = Table::new();
-    table.style = style;
-    table.has_bottom_boarder = !is_markdown;
-    let mut header_row = Row::new(
-        result
-            .header
-            .iter()
-            .map(|s| {
-                if is_markdown {
-                    s.to_string()
-                } else {
-                    s.bright_white().bold().to_string()
-                }
-            })
-            .map(|f| TableCell::new_with_alignment(f, 1, Alignment::Center)),
-    );
-    header_row.has_separator = !is_markdown;
-    table.add_row(header_row);
-
-    let mut is_first = true;
-    for row in result.results {
-        let cells = result.header.iter().map(|header| match header.as_str() {
-            "json" => {
-                let str = header.as_str();
-                let json = row[str].get();
-                if let Ok(json) =
-                    serde_json::Value::from_str(json).and_then(|j| serde_json::to_string_pretty(&j))
-                {
-                    TableCell::new_with_alignment(json, 1, Alignment::Center)
-                } else {
-                    TableCell::new_with_alignment(json, 1, Alignment::Center)
-                }
-            }
-            _ => {
-                let str = header.as_str();
-                let json = row[str].get();
-
-                if let Ok(json) = serde_json::Value::from_str(json) {
-                    if !is_markdown {
-                        match json {
-                            serde_json::Value::Null => {
-                                return TableCell::new_with_alignment(
-                                    "<null>".bright_black(),
-                                    1,
-                                    Alignment::Center,
-                                )
-                            }
-                            serde_json::Value::Bool(b) => {
-                                return TableCell::new_with_alignment(
-                                    if b { "true".green() } else { "false".red() },
-                                    1,
-                                    Alignment::Center,
-                                )
-                            }
-                            serde_json::Value::Number(n) => {
-                                return TableCell::new_with_alignment(n, 1, Alignment::Left)
-                            }
-                            serde_json::Value::String(s) => {
-                                return TableCell::new_with_alignment(s, 1, Alignment::Center)
-                            }
-                            _ => { /* noop */ }
-                        }
-                    }
-
-                    if let Ok(serialized) = serde_json::to_string_pretty(&json) {
-                        return TableCell::new_with_alignment(serialized, 1, Alignment::Center);
-                    }
-                }
-
-                TableCell::new_with_alignment(json, 1, Alignment::Center)
-            }
-        });
-
-        let mut row = Row::new(cells);
-        row.has_separator = !is_markdown || is_first;
-        table.add_row(row);
-
-        is_first = false;
-    }
-
-    log::trace!("Render table.");
-
-    let table = table.render();
-    writeln!(write, "{}", table).map_err(|e| anyhow!("Failed to write table: {}", e))
 }
@@ -0,0 +1,156 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Error};
+
+use logsh_core::kusto::{tokenize, TokenKind, OPERATORS};
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    name = "fmt",
+    about = "Format a query with one operator per line, single spaces and lowercase operators."
+)]
+pub struct FmtCommand {
+    #[arg(help = "File holding the query. If not provided, will read from stdin.")]
+    file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print nothing and fail when the query isn't already formatted, for CI."
+    )]
+    check: bool,
+}
+
+pub fn execute_fmt<W: Write>(command: FmtCommand, mut write: W) -> Result<(), Error> {
+    let query = match command.file.as_ref() {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?,
+        None => crate::edit::read_stdin("query", "a file argument")?,
+    };
+
+    let formatted = format_query(&query);
+    if command.check {
+        if formatted != query {
+            let source = command
+                .file
+                .as_ref()
+                .map_or("Query".to_string(), |path| path.display().to_string());
+            return Err(anyhow!(
+                "{} isn't formatted. Run \"logsh query fmt\".",
+                source
+            ));
+        }
+        return Ok(());
+    }
+
+    write!(write, "{}", formatted)?;
+    Ok(())
+}
+
+/// Canonical layout of a query: each top level pipe starts a line, runs of whitespace become a
+/// single space, commas are followed by one, operators are lowercase, and statements end their
+/// line. Strings and comments are kept as written.
+pub fn format_query(query: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0usize;
+    let mut space = false;
+    let mut after_pipe = false;
+    let mut statement_end = false;
+    for token in tokenize(query) {
+        let mut text = token.text;
+        if token.kind == TokenKind::Whitespace {
+            space = true;
+            continue;
+        }
+        // The next statement starts a new line, but a trailing comment stays on the last one.
+        if std::mem::take(&mut statement_end) && token.kind != TokenKind::Comment {
+            end_line(&mut out);
+        }
+
+        match token.kind {
+            TokenKind::Pipe if depth == 0 => {
+                end_line(&mut out);
+                out.push('|');
+                space = true;
+                after_pipe = true;
+                continue;
+            }
+            TokenKind::Punctuation => match text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth = depth.saturating_sub(1),
+                _ => {}
+            },
+            TokenKind::Operator | TokenKind::Keyword | TokenKind::Identifier if after_pipe => {
+                if let Some(operator) = OPERATORS.iter().find(|op| op.eq_ignore_ascii_case(text)) {
+                    text = operator;
+                }
+            }
+            _ => {}
+        }
+
+        let tight = matches!(text, "," | ")" | "]" | "}" | ";") || out.ends_with(['(', '[', '{']);
+        if space && !tight && !out.is_empty() && !out.ends_with('\n') {
+            out.push(' ');
+        }
+        space = text == ",";
+        after_pipe = false;
+        out.push_str(text);
+
+        if token.kind == TokenKind::Comment {
+            end_line(&mut out);
+        }
+        statement_end = text == ";" && depth == 0;
+    }
+
+    end_line(&mut out);
+    out
+}
+
+fn end_line(out: &mut String) {
+    out.truncate(out.trim_end_matches([' ', '\t']).len());
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_each_top_level_pipe_on_a_line() {
+        assert_eq!(
+            format_query("T   |  WHERE a>1|Take 10"),
+            "T\n| where a>1\n| take 10\n"
+        );
+    }
+
+    #[test]
+    fn keeps_nested_pipes_on_their_line() {
+        assert_eq!(
+            format_query("union (T | take 1),(U  |   take 2)"),
+            "union (T | take 1), (U | take 2)\n"
+        );
+    }
+
+    #[test]
+    fn keeps_strings_and_comments_as_written() {
+        assert_eq!(
+            format_query("T | where msg ==  'A  |  B' // Keep  This\n| count"),
+            "T\n| where msg == 'A  |  B' // Keep  This\n| count\n"
+        );
+    }
+
+    #[test]
+    fn ends_statements_on_their_line() {
+        assert_eq!(
+            format_query("let x = 1; T | take x; // done"),
+            "let x = 1;\nT\n| take x; // done\n"
+        );
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let once = format_query("let x=1;T|summarize count() by a , b|order by a desc");
+        assert_eq!(format_query(&once), once);
+    }
+}
@@ -0,0 +1,372 @@
+use std::{borrow::Cow, cell::Cell, path::PathBuf, time::Instant};
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use logsh_core::{
+    config,
+    connect::Timeouts,
+    error::{ConfigError, ConnectError},
+    history::HistoryEntry,
+    kusto,
+    logship_client::LogshClientHandler,
+    metadata::{self, Metadata},
+    saved_query, snippet,
+};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::{DefaultHistory, History},
+    validate::Validator,
+    Config, Context, Editor, Helper,
+};
+
+use crate::{fmt::highlight, OutputMode};
+
+const PROMPT: &str = "logsh> ";
+const CONTINUATION_PROMPT: &str = "    .. ";
+
+/// Entries kept in the REPL history file.
+const HISTORY_SIZE: usize = 1000;
+
+const COMMANDS: &[&str] = &[".edit", ".exit", ".help", ".refresh", ".saved", ".snippet"];
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Run queries interactively, with completion of tables, columns and operators.")]
+pub struct ReplCommand {
+    #[arg(
+        short,
+        long,
+        value_parser = crate::fmt::parse::OutputModeParser::query(),
+        help = "Output result format"
+    )]
+    output: Option<OutputMode>,
+}
+
+pub fn execute_repl(command: ReplCommand) -> Result<(), Error> {
+    let cfg = config::load()?;
+    let mut connection = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let mode = command.output.unwrap_or_else(OutputMode::configured);
+    let conn_handler = LogshClientHandler::new();
+
+    let editor_config = Config::builder()
+        .max_history_size(HISTORY_SIZE)?
+        .history_ignore_dups(true)?
+        .build();
+    let mut editor: Editor<QueryHelper, DefaultHistory> = Editor::with_config(editor_config)?;
+    editor.set_helper(Some(QueryHelper {
+        metadata: load_metadata(&conn_handler),
+        saved: saved_query::load()
+            .map(|saved| saved.queries.into_keys().collect())
+            .unwrap_or_default(),
+        snippets: snippet::load()
+            .map(|snippets| snippets.snippets.into_keys().collect())
+            .unwrap_or_default(),
+        continuation: Cell::new(false),
+    }));
+    let history = history_path();
+    if let Some(history) = history.as_ref() {
+        let _ = editor.load_history(history);
+        // Files written before lines were deduplicated may repeat them.
+        let entries: Vec<String> = editor.history().iter().cloned().collect();
+        let _ = editor.clear_history();
+        for entry in entries {
+            remember(editor.history_mut(), &entry);
+        }
+    }
+    println!(
+        "{} {} {}",
+        "# Connected to".bright_black(),
+        connection.name.blue(),
+        "Type .help for commands, Tab to complete.".bright_black()
+    );
+
+    let mut query = String::new();
+    // Snippet text inserted with .snippet, placed in front of the cursor of the next line.
+    let mut insert = String::new();
+    // The last query run, which .edit starts from.
+    let mut last = String::new();
+    loop {
+        let continuation = !query.is_empty();
+        if let Some(helper) = editor.helper() {
+            helper.continuation.set(continuation);
+        }
+        let prompt = if continuation {
+            CONTINUATION_PROMPT
+        } else {
+            PROMPT
+        };
+        let line = match editor.readline_with_initial(prompt, (&std::mem::take(&mut insert), "")) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                query.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        // A trailing pipe continues the query on the next line.
+        query.push_str(&line);
+        if line.trim_end().ends_with('|') {
+            query.push('\n');
+            continue;
+        }
+
+        let input = std::mem::take(&mut query);
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+        remember(editor.history_mut(), input);
+        // Saved as it's entered, so a closed terminal doesn't lose the session's history.
+        if let Some(history) = history.as_ref() {
+            let _ = editor.save_history(history);
+        }
+
+        let mut composed = false;
+        let text = match input.split_once(char::is_whitespace).unwrap_or((input, "")) {
+            (".exit" | ".quit", _) => break,
+            (".edit" | "\\e", _) => match crate::edit::compose(&last) {
+                Ok(text) => {
+                    composed = true;
+                    remember(editor.history_mut(), &text);
+                    text
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                    continue;
+                }
+            },
+            (".help", _) => {
+                print_help();
+                continue;
+            }
+            (".refresh", _) => {
+                match metadata::refresh(&conn_handler) {
+                    Ok(refreshed) => {
+                        if let Some(helper) = editor.helper_mut() {
+                            helper.metadata = Some(refreshed);
+                        }
+                    }
+                    Err(err) => log::error!("Failed to refresh metadata: {}", err),
+                }
+                continue;
+            }
+            (".saved", name) => match saved_query::load()
+                .map_err(Error::from)
+                .and_then(|saved| Ok(saved.get(name.trim())?.query.to_string()))
+            {
+                Ok(saved) => saved,
+                Err(err) => {
+                    log::error!("{}", err);
+                    continue;
+                }
+            },
+            (".snippet", name) => {
+                match crate::snippet::find(name.trim()) {
+                    Ok(snippet) => insert = snippet.text,
+                    Err(err) => log::error!("{}", err),
+                }
+                continue;
+            }
+            (other, _) if other.starts_with('.') => {
+                log::error!("Unknown command \"{}\". Type .help for commands.", other);
+                continue;
+            }
+            _ => input.to_string(),
+        };
+
+        if let Err(err) = run(&cfg, &mut connection, &text, mode) {
+            log::error!("{}", err);
+        }
+        // Offered even when the query failed, so a long composed query isn't lost to a typo.
+        if composed {
+            if let Err(err) = crate::edit::offer_save(&text, None) {
+                log::error!("Failed to save the query: {}", err);
+            }
+        }
+        last = text;
+    }
+
+    Ok(())
+}
+
+fn run(
+    cfg: &config::Configuration,
+    connection: &mut config::ConnectionConfig,
+    query: &str,
+    mode: OutputMode,
+) -> Result<(), Error> {
+    let query = &snippet::expand(query)?;
+    kusto::validate(query).inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, query, err);
+    })?;
+    let start = Instant::now();
+    let text = crate::connect::retry_unauthorized(connection, |c| {
+        c.connection.query_raw(query, Timeouts::default())
+    })
+    .inspect_err(|err| {
+        let entry = HistoryEntry::failed(&connection.name, query, start.elapsed());
+        crate::query::record_history(cfg, &entry);
+        crate::fmt::print_query_error(cfg, query, err);
+    })?;
+    let duration = start.elapsed();
+    let text = logsh_core::redact::redact_columns(&text, &cfg.redact_columns)
+        .map_err(|err| anyhow!("Failed to redact columns: {}", err))?;
+    let result = logsh_core::query::result(&text).inspect_err(|err| {
+        crate::fmt::print_query_error(cfg, query, err);
+    })?;
+    let rows = Some(result.results.len());
+    let entry = HistoryEntry::new(&connection.name, query, duration, rows, text.len() as u64);
+    crate::query::record_history(cfg, &entry);
+    crate::fmt::render_result(&result, Some(&text), mode, None, std::io::stdout())?;
+    Ok(())
+}
+
+/// Cached metadata for completion, refreshed when it's past its TTL. Completion still works
+/// from stale metadata when the server can't be reached.
+fn load_metadata(conn_handler: &LogshClientHandler) -> Option<Metadata> {
+    match metadata::get(conn_handler) {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+            log::warn!("Completing from cached metadata: {}", err);
+            metadata::cached(conn_handler).ok().flatten()
+        }
+    }
+}
+
+/// The REPL's input history, next to the configuration file. Kept apart from the query
+/// history, since it holds REPL commands and partial input too. None in read-only mode.
+fn history_path() -> Option<PathBuf> {
+    if config::read_only() {
+        return None;
+    }
+
+    let config = config::get_configuration_path().ok()?;
+    let path = config.parent()?.join("repl_history");
+    let previous = path.with_file_name("repl-history");
+    if !path.exists() && previous.exists() {
+        let _ = std::fs::rename(&previous, &path);
+    }
+    Some(path)
+}
+
+/// Adds a line to the history, moving it to the end when it's already there, so each line is
+/// found once when searching with Ctrl-R.
+fn remember(history: &mut DefaultHistory, line: &str) {
+    if history.iter().any(|entry| entry == line) {
+        let entries: Vec<String> = history
+            .iter()
+            .filter(|entry| *entry != line)
+            .cloned()
+            .collect();
+        let _ = history.clear();
+        for entry in entries {
+            let _ = history.add_owned(entry);
+        }
+    }
+    let _ = history.add(line);
+}
+
+fn print_help() {
+    println!("Enter a query to run it. End a line with | to continue the query on the next line.");
+    println!("  .saved <NAME>    Run a saved query.");
+    println!("  .snippet <NAME>  Insert a snippet into the next line.");
+    println!(
+        "  .edit, \\e        Compose a query in $VISUAL or $EDITOR, starting from the last one."
+    );
+    println!("  .refresh         Fetch table and column names again.");
+    println!("  .exit            Leave the REPL. Ctrl-D works too.");
+    println!("  Ctrl-R           Search earlier input. Press Ctrl-R again for older matches.");
+}
+
+struct QueryHelper {
+    metadata: Option<Metadata>,
+    saved: Vec<String>,
+    snippets: Vec<String>,
+    /// Set while reading the second or later line of a query, which starts after a pipe.
+    continuation: Cell<bool>,
+}
+
+impl QueryHelper {
+    fn candidates(&self, line: &str, start: usize) -> Vec<&str> {
+        let before = line[..start].trim_end();
+        if let Some(command) = before.strip_prefix('.') {
+            return match command {
+                "saved" => self.saved.iter().map(String::as_str).collect(),
+                "snippet" => self.snippets.iter().map(String::as_str).collect(),
+                _ => vec![],
+            };
+        }
+        if before.is_empty() && line[start..].starts_with('.') {
+            return COMMANDS.to_vec();
+        }
+
+        let tables = self
+            .metadata
+            .iter()
+            .flat_map(|m| m.tables.iter().map(|t| t.name.as_str()));
+        if before.ends_with('|') || (before.is_empty() && self.continuation.get()) {
+            kusto::OPERATORS.to_vec()
+        } else if before.is_empty() {
+            tables.collect()
+        } else {
+            let columns = self.metadata.iter().flat_map(|m| m.column_names());
+            columns.chain(tables).collect()
+        }
+    }
+}
+
+impl Completer for QueryHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+            .map_or(0, |i| i + 1);
+        let word = line[start..pos].to_lowercase();
+        let mut matches: Vec<&str> = self
+            .candidates(line, start)
+            .into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&word))
+            .collect();
+        matches.sort_unstable();
+        matches.dedup();
+
+        let pairs = matches
+            .into_iter()
+            .map(|c| Pair {
+                display: c.to_string(),
+                replacement: c.to_string(),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for QueryHelper {
+    type Hint = String;
+}
+
+impl Highlighter for QueryHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight::highlight(line))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Validator for QueryHelper {}
+
+impl Helper for QueryHelper {}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use colored::Colorize;
+use logsh_core::{
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+    saved_query::{self, SavedQuery},
+};
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Debug, Subcommand)]
+pub enum SavedQueryCommand {
+    #[clap(about = "Save a named query. Run it with \"logsh query --saved <NAME>\".")]
+    Save {
+        #[arg(help = "Saved query name.")]
+        name: String,
+        #[arg(
+            short,
+            long,
+            help = "Query to save. If not provided, will read from stdin."
+        )]
+        query: Option<String>,
+        #[arg(short, long, help = "Description of the saved query.")]
+        description: Option<String>,
+    },
+    #[clap(visible_alias = "ls", about = "List saved queries.")]
+    List {
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+    #[clap(visible_alias = "rm", about = "Remove a saved query.")]
+    Remove {
+        #[arg(add = crate::complete::saved_queries(), help = "Saved query name.")]
+        name: String,
+    },
+    #[clap(about = "Share a saved query with the default subscription.")]
+    Publish {
+        #[arg(add = crate::complete::saved_queries(), help = "Saved query name.")]
+        name: String,
+    },
+    #[clap(about = "Fetch the default subscription's shared queries.")]
+    Pull {
+        #[arg(
+            long,
+            help = "Replace local queries even when they are newer than the shared version."
+        )]
+        force: bool,
+    },
+}
+
+pub fn execute_saved_query<W: Write>(
+    command: SavedQueryCommand,
+    mut write: W,
+) -> Result<(), Error> {
+    match command {
+        SavedQueryCommand::Save {
+            name,
+            query,
+            description,
+        } => {
+            let query = match query {
+                Some(q) => q,
+                None => crate::edit::read_stdin("query", "--query")?,
+            };
+
+            if name.trim().is_empty() || query.trim().is_empty() {
+                return Err(anyhow!("A saved query needs a name and a query."));
+            }
+
+            let mut saved = saved_query::load()?;
+            let query = SavedQuery::new(&name, &query, description);
+            if let Some(replaced) = saved.insert(query) {
+                log::info!("Replacing saved query \"{}\".", replaced.name);
+            }
+            saved_query::save(saved)?;
+            Ok(())
+        }
+        SavedQueryCommand::List { output } => {
+            let saved = saved_query::load()?;
+            let list: Vec<_> = saved.queries.values().collect();
+            crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &list,
+                |list| {
+                    let results = list
+                        .iter()
+                        .map(|q| {
+                            HashMap::from([
+                                ("Name".to_string(), Value::String(q.name.to_string())),
+                                (
+                                    "Description".to_string(),
+                                    q.description
+                                        .as_ref()
+                                        .map_or(Value::Null, |d| Value::String(d.to_string())),
+                                ),
+                                ("Query".to_string(), Value::String(q.query.to_string())),
+                                ("Updated".to_string(), Value::String(q.updated.to_rfc3339())),
+                            ])
+                        })
+                        .collect();
+                    QueryResultFmt {
+                        header: ["Name", "Description", "Query", "Updated"]
+                            .map(String::from)
+                            .to_vec(),
+                        results,
+                    }
+                },
+            )
+        }
+        SavedQueryCommand::Remove { name } => {
+            let mut saved = saved_query::load()?;
+            if saved.queries.remove(name.trim()).is_none() {
+                log::info!("No saved query with name: \"{}\".", name.red());
+                return Ok(());
+            }
+
+            saved_query::save(saved)?;
+            Ok(())
+        }
+        SavedQueryCommand::Publish { name } => {
+            let saved = saved_query::load()?;
+            let query = saved.get(&name)?;
+            let conn_handler = LogshClientHandler::new();
+            let published = saved_query::publish(&conn_handler, query)?;
+            writeln!(
+                write,
+                "Published saved query {}.",
+                published.name.bright_blue()
+            )?;
+            Ok(())
+        }
+        SavedQueryCommand::Pull { force } => {
+            let conn_handler = LogshClientHandler::new();
+            let remote = saved_query::pull(&conn_handler)?;
+            let total = remote.len();
+            let mut saved = saved_query::load()?;
+            let changed = saved.merge(remote, force);
+            saved_query::save(saved)?;
+
+            writeln!(
+                write,
+                "Pulled {} shared queries, {} updated locally.",
+                total,
+                changed.len()
+            )?;
+            for name in changed {
+                writeln!(write, "  {}", name.bright_blue())?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, io::Write, time::Duration};
+
+use anyhow::Error;
+use clap::Subcommand;
+use logsh_core::{
+    capabilities::{self, Capability, ServerVersion},
+    config,
+    error::{ConfigError, ConnectError},
+    query::QueryResultFmt,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::OutputMode;
+
+/// How long each endpoint health check may take.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The parts of the server whose health is shown, by health component and label.
+const ENDPOINTS: [(&str, &str); 2] = [("query", "Query"), ("inflow", "Ingest")];
+
+#[derive(Subcommand)]
+#[clap(about = "Inspect the logship server of the default connection.")]
+pub enum ServerCommand {
+    #[clap(
+        about = "Show the server's version, build, region, features and endpoint health. The server-side counterpart to \"logsh version\"."
+    )]
+    Info {
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+        #[arg(
+            long,
+            help = "Ask the server for its version again instead of using the cached one."
+        )]
+        refresh: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerInfo {
+    connection: String,
+    server: String,
+    /// None when the server doesn't report its version.
+    #[serde(flatten)]
+    version: Option<ServerVersion>,
+    endpoints: Vec<EndpointHealth>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointHealth {
+    name: &'static str,
+    latency_ms: Option<u64>,
+    status: String,
+}
+
+pub fn execute_server<W: Write>(command: ServerCommand, write: W) -> Result<(), Error> {
+    match command {
+        ServerCommand::Info { output, refresh } => {
+            let cfg = config::load()?;
+            let conn = cfg
+                .get_default_connection()
+                .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+            let version = match refresh {
+                true => capabilities::refresh(&conn.connection),
+                false => capabilities::get(&conn.connection),
+            }
+            .inspect_err(|err| {
+                crate::fmt::print_connect_error(&cfg, err);
+            })?;
+
+            let endpoints = std::thread::scope(|scope| {
+                let handles: Vec<_> = ENDPOINTS
+                    .iter()
+                    .map(|(component, name)| {
+                        let connection = &conn.connection;
+                        let handle =
+                            scope.spawn(move || connection.health(Some(component), HEALTH_TIMEOUT));
+                        (*name, handle)
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|(name, handle)| {
+                        let result = handle
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                        let (latency_ms, status) = crate::connect::ping_status(result);
+                        EndpointHealth {
+                            name,
+                            latency_ms,
+                            status,
+                        }
+                    })
+                    .collect()
+            });
+
+            let info = ServerInfo {
+                connection: conn.name.to_string(),
+                server: conn.connection.server.to_string(),
+                version,
+                endpoints,
+            };
+            crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &info,
+                info_rows,
+            )
+        }
+    }
+}
+
+/// One Property and Value row per detail, like `logsh whoami`.
+fn info_rows(info: &ServerInfo) -> QueryResultFmt {
+    let or_none = |v: Option<String>| v.map_or(Value::Null, Value::String);
+    let mut properties = vec![
+        (
+            "Connection".to_string(),
+            Value::String(info.connection.clone()),
+        ),
+        ("Server".to_string(), Value::String(info.server.clone())),
+    ];
+    match &info.version {
+        Some(version) => {
+            let features = match &version.capabilities {
+                Some(capabilities) => {
+                    let mut features: Vec<String> = Capability::ALL
+                        .iter()
+                        .filter(|c| version.supports(**c))
+                        .map(|c| c.description().to_string())
+                        .collect();
+                    let known: Vec<&str> = Capability::ALL.iter().map(|c| c.name()).collect();
+                    features.extend(
+                        capabilities
+                            .iter()
+                            .filter(|c| !known.iter().any(|k| k.eq_ignore_ascii_case(c)))
+                            .cloned(),
+                    );
+                    features.join(", ")
+                }
+                None => "unknown, the server doesn't report them".to_string(),
+            };
+            properties.extend([
+                (
+                    "Version".to_string(),
+                    Value::String(version.version.clone()),
+                ),
+                ("Build".to_string(), or_none(version.build.clone())),
+                ("Region".to_string(), or_none(version.region.clone())),
+                (
+                    "API Version".to_string(),
+                    or_none(version.api_version.map(|v| v.to_string())),
+                ),
+                ("Features".to_string(), Value::String(features)),
+            ]);
+        }
+        None => properties.push((
+            "Version".to_string(),
+            Value::String("unknown, the server doesn't report it".to_string()),
+        )),
+    }
+    for endpoint in info.endpoints.iter() {
+        let status = match endpoint.latency_ms {
+            Some(ms) => format!("{} ({} ms)", endpoint.status, ms),
+            None => endpoint.status.clone(),
+        };
+        properties.push((format!("{} Endpoint", endpoint.name), Value::String(status)));
+    }
+
+    let results = properties
+        .into_iter()
+        .map(|(property, value)| {
+            HashMap::from([
+                ("Property".to_string(), Value::String(property)),
+                ("Value".to_string(), value),
+            ])
+        })
+        .collect();
+    QueryResultFmt {
+        header: vec!["Property".to_string(), "Value".to_string()],
+        results,
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Error};
+use clap::Subcommand;
+use colored::Colorize;
+use logsh_core::{
+    query::QueryResultFmt,
+    snippet::{self, Snippet},
+};
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Debug, Subcommand)]
+#[clap(about = "Manage reusable query fragments. Reference one in a query with {{snippet:NAME}}.")]
+pub enum SnippetCommand {
+    #[clap(about = "Save a named query fragment, such as a filter or a summarize clause.")]
+    Add {
+        #[arg(help = "Snippet name.")]
+        name: String,
+        #[arg(
+            short,
+            long,
+            help = "Fragment to save. If not provided, will read from stdin."
+        )]
+        query: Option<String>,
+        #[arg(short, long = "tag", help = "Tag the snippet. Repeat for more tags.")]
+        tags: Vec<String>,
+        #[arg(short, long, help = "Description of the snippet.")]
+        description: Option<String>,
+    },
+    #[clap(visible_alias = "ls", about = "List snippets, best matches first.")]
+    List {
+        #[arg(help = "Fuzzy search of names, tags, descriptions and text.")]
+        search: Option<String>,
+        #[arg(short, long, help = "Only list snippets with this tag.")]
+        tag: Option<String>,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+    #[clap(about = "Print a snippet's text, for pasting into a query. Names match fuzzily.")]
+    Insert {
+        #[arg(help = "Snippet name.")]
+        name: String,
+    },
+    #[clap(visible_alias = "rm", about = "Remove a snippet.")]
+    Remove {
+        #[arg(help = "Snippet name.")]
+        name: String,
+    },
+}
+
+pub fn execute_snippet<W: Write>(command: SnippetCommand, mut write: W) -> Result<(), Error> {
+    match command {
+        SnippetCommand::Add {
+            name,
+            query,
+            tags,
+            description,
+        } => {
+            let text = match query {
+                Some(q) => q,
+                None => crate::edit::read_stdin("snippet", "--query")?,
+            };
+
+            if name.trim().is_empty() || text.trim().is_empty() {
+                return Err(anyhow!("A snippet needs a name and a query fragment."));
+            }
+            if name.contains("}}") {
+                return Err(anyhow!("Snippet names can't contain \"}}}}\"."));
+            }
+
+            let mut snippets = snippet::load()?;
+            let snippet = Snippet::new(&name, &text, tags, description);
+            if snippets
+                .snippets
+                .insert(snippet.name.clone(), snippet)
+                .is_some()
+            {
+                log::info!("Replacing snippet \"{}\".", name);
+            }
+            snippet::save(snippets)?;
+            Ok(())
+        }
+        SnippetCommand::List {
+            search,
+            tag,
+            output,
+        } => {
+            let snippets = snippet::load()?;
+            let all: Vec<&Snippet> = snippets
+                .snippets
+                .values()
+                .filter(|s| tag.as_ref().is_none_or(|tag| s.has_tag(tag)))
+                .collect();
+            let list = match search.as_ref() {
+                Some(search) => rank(all, search),
+                None => all,
+            };
+
+            crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &list,
+                |list| {
+                    let results = list
+                        .iter()
+                        .map(|s| {
+                            HashMap::from([
+                                ("Name".to_string(), Value::String(s.name.to_string())),
+                                ("Tags".to_string(), Value::String(s.tags.join(", "))),
+                                (
+                                    "Description".to_string(),
+                                    s.description
+                                        .as_ref()
+                                        .map_or(Value::Null, |d| Value::String(d.to_string())),
+                                ),
+                                ("Text".to_string(), Value::String(s.text.to_string())),
+                            ])
+                        })
+                        .collect();
+                    QueryResultFmt {
+                        header: ["Name", "Tags", "Description", "Text"]
+                            .map(String::from)
+                            .to_vec(),
+                        results,
+                    }
+                },
+            )
+        }
+        SnippetCommand::Insert { name } => {
+            writeln!(write, "{}", find(&name)?.text)?;
+            Ok(())
+        }
+        SnippetCommand::Remove { name } => {
+            let mut snippets = snippet::load()?;
+            if snippets.snippets.remove(&name).is_none() {
+                log::info!("No snippet with name: \"{}\".", name.red());
+                return Ok(());
+            }
+
+            snippet::save(snippets)?;
+            Ok(())
+        }
+    }
+}
+
+/// Finds a snippet by name, exactly or fuzzily.
+pub fn find(name: &str) -> Result<Snippet, Error> {
+    let snippets = snippet::load()?;
+    if let Ok(snippet) = snippets.get(name) {
+        return Ok(snippet.clone());
+    }
+
+    let all: Vec<&Snippet> = snippets.snippets.values().collect();
+    let found = logsh_core::fuzzy::find_one(&all, |s| s.name.as_str(), name, "snippet")?;
+    Ok((*found).clone())
+}
+
+/// Keeps the snippets matching `search` and orders them by how well they match. Names count
+/// more than tags, and tags more than descriptions or text.
+fn rank<'a>(snippets: Vec<&'a Snippet>, search: &str) -> Vec<&'a Snippet> {
+    let search = search.to_lowercase();
+    let score = |s: &Snippet| {
+        let name = s.name.to_lowercase();
+        if name == search {
+            Some(0)
+        } else if name.contains(&search) {
+            Some(1)
+        } else if s.has_tag(&search) {
+            Some(2)
+        } else if logsh_core::fuzzy::is_subsequence(&search, &name) {
+            Some(3)
+        } else if s.tags.iter().any(|t| t.contains(&search))
+            || s.description
+                .as_ref()
+                .is_some_and(|d| d.to_lowercase().contains(&search))
+            || s.text.to_lowercase().contains(&search)
+        {
+            Some(4)
+        } else {
+            None
+        }
+    };
+
+    let mut ranked: Vec<(usize, &Snippet)> = snippets
+        .into_iter()
+        .filter_map(|s| score(s).map(|score| (score, s)))
+        .collect();
+    ranked.sort_by_key(|(score, _)| *score);
+    ranked.into_iter().map(|(_, s)| s).collect()
+}
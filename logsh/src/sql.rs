@@ -0,0 +1,134 @@
+use std::{io::Write, path::Path};
+
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use logsh_core::{
+    config,
+    error::{ConfigError, ConnectError},
+    sql::SqlContext,
+};
+
+use crate::{fmt::parse::OptionalDurationArg, OutputMode};
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Join query results and local files with SQL, in an in-memory SQLite database.")]
+pub struct SqlCommand {
+    #[arg(help = "SQL to run. Tables are named by --query, --saved and --file.")]
+    sql: String,
+
+    #[arg(
+        short,
+        long = "query",
+        value_name = "TABLE=QUERY",
+        help = "Run a query and load its result as a table. Repeat for more tables."
+    )]
+    queries: Vec<String>,
+
+    #[arg(
+        short,
+        long = "saved",
+        value_name = "TABLE=NAME",
+        help = "Run a saved query and load its result as a table. Repeat for more tables."
+    )]
+    saved: Vec<String>,
+
+    #[arg(
+        short,
+        long = "file",
+        value_name = "[TABLE=]PATH",
+        help = "Load a CSV file, or a result saved with \"-o json\", as a table. The table is named after the file unless given. Repeat for more tables."
+    )]
+    files: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = crate::fmt::parse::OutputModeParser::query(),
+        help = "Output result format"
+    )]
+    output: Option<OutputMode>,
+
+    #[arg(
+        short,
+        long,
+        help = "Timeout of each query, and of the SQL. Use \"none\" to disable timeout.",
+        default_value = "5m"
+    )]
+    timeout: OptionalDurationArg,
+}
+
+pub fn execute_sql<W: Write>(command: SqlCommand, write: W) -> Result<(), Error> {
+    let context = SqlContext::new()?;
+
+    let mut queries = Vec::new();
+    for arg in command.queries.iter() {
+        let (table, query) = split_table(arg).ok_or_else(|| needs_table(arg))?;
+        queries.push((table, query.to_string()));
+    }
+    if !command.saved.is_empty() {
+        let saved = logsh_core::saved_query::load()?;
+        for arg in command.saved.iter() {
+            let (table, name) = split_table(arg).ok_or_else(|| needs_table(arg))?;
+            queries.push((table, saved.get(name)?.query.to_string()));
+        }
+    }
+
+    if !queries.is_empty() {
+        let cfg = config::load()?;
+        let mut connection = cfg
+            .get_default_connection()
+            .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+        for (table, query) in queries {
+            let query = logsh_core::snippet::expand(&query)?;
+            let text = crate::connect::retry_unauthorized(&mut connection, |c| {
+                c.connection.query_raw(&query, command.timeout.into())
+            })
+            .inspect_err(|err| crate::fmt::print_query_error(&cfg, &query, err))?;
+            let rows = context.load_result(table, &text)?;
+            log::info!("Loaded {} rows into {}.", rows, table.blue());
+        }
+    }
+
+    for arg in command.files.iter() {
+        let (table, path) = match split_table(arg) {
+            Some(source) => source,
+            None => (
+                Path::new(arg)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| needs_table(arg))?,
+                arg.as_str(),
+            ),
+        };
+        let rows = context
+            .load_file(table, Path::new(path))
+            .map_err(|err| anyhow!("Failed to load {}: {}", path, err))?;
+        log::info!("Loaded {} rows into {}.", rows, table.blue());
+    }
+
+    let text = context.query(&command.sql, command.timeout.into())?;
+    let result = logsh_core::query::result(&text)?;
+    crate::fmt::render_result(
+        &result,
+        Some(&text),
+        command.output.unwrap_or_else(OutputMode::configured),
+        None,
+        write,
+    )?;
+    Ok(())
+}
+
+/// Splits a `TABLE=VALUE` argument.
+fn split_table(arg: &str) -> Option<(&str, &str)> {
+    arg.split_once('=')
+        .filter(|(table, _)| !table.trim().is_empty())
+        .map(|(table, value)| (table.trim(), value))
+}
+
+fn needs_table(arg: &str) -> Error {
+    anyhow!(
+        "\"{}\" needs a table name, as TABLE={}",
+        arg,
+        arg.trim_start_matches('=')
+    )
+}
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, io::Write, time::Duration};
+
+use anyhow::{anyhow, Error};
+use chrono::{Local, Utc};
+use colored::Colorize;
+use logsh_core::{
+    history::{self, QueryUsage, Usage},
+    query::QueryResultFmt,
+};
+use serde_json::Value;
+
+use crate::OutputMode;
+
+/// Characters of a query shown in a table before it's cut short.
+const QUERY_WIDTH: usize = 60;
+
+#[derive(Debug, clap::Args)]
+#[clap(
+    about = "Summarize the local query history: the most used and slowest queries, and latency and rows per connection. Queries are only recorded once \"query_history\": true is set in the configuration file."
+)]
+pub struct StatsCommand {
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "7d",
+        help = "Only count queries run within this long."
+    )]
+    since: humantime::Duration,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 10,
+        help = "Queries listed in each table."
+    )]
+    top: usize,
+
+    #[arg(
+        short,
+        long,
+        help = "Output result format. Formats other than table, markdown and JSON list the most used queries."
+    )]
+    output: Option<OutputMode>,
+}
+
+pub fn execute_stats<W: Write>(command: StatsCommand, write: W) -> Result<(), Error> {
+    let since = chrono::Duration::from_std(command.since.into())
+        .ok()
+        .and_then(|since| Utc::now().checked_sub_signed(since))
+        .ok_or_else(|| anyhow!("--since {} is too long.", command.since))?;
+    let entries = history::load(since)?;
+    let summary = history::summarize(&entries, since, command.top);
+
+    let mode = command.output.unwrap_or_else(OutputMode::configured);
+    crate::fmt::render_with(write, mode, &summary, |write, mode, summary| match mode {
+        OutputMode::Table | OutputMode::Markdown => {
+            let total = &summary.total;
+            writeln!(
+                write,
+                "{} queries since {}, {} failed. {} rows and {} retrieved, {} on average.",
+                total.runs.to_string().bright_blue(),
+                since.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                total.failures,
+                total.rows.to_string().bright_blue(),
+                crate::fmt::format_bytes(total.bytes),
+                format_ms(total.average_ms)
+            )?;
+            if total.runs == 0 {
+                if !logsh_core::config::load()?.query_history {
+                    writeln!(
+                        write,
+                        "Queries aren't recorded. Set \"query_history\": true in {} to record them.",
+                        logsh_core::config::get_configuration_path()?.display()
+                    )?;
+                }
+                return Ok(());
+            }
+
+            writeln!(write, "\n{}", "Most used queries".bold())?;
+            crate::fmt::render_rows(&mut *write, mode, query_rows(&summary.most_used, true))?;
+            writeln!(write, "\n{}", "Slowest queries".bold())?;
+            crate::fmt::render_rows(&mut *write, mode, query_rows(&summary.slowest, true))?;
+            writeln!(write, "\n{}", "Connections".bold())?;
+            let connections = summary
+                .connections
+                .iter()
+                .map(|c| (c.connection.to_string(), &c.usage));
+            crate::fmt::render_rows(
+                &mut *write,
+                mode,
+                usage_rows("Connection", connections, true),
+            )
+        }
+        _ => crate::fmt::render_rows(write, mode, query_rows(&summary.most_used, false)),
+    })
+}
+
+fn query_rows(queries: &[QueryUsage], human: bool) -> QueryResultFmt {
+    let queries = queries.iter().map(|q| {
+        let query = match (human, q.query.char_indices().nth(QUERY_WIDTH)) {
+            (true, Some((end, _))) => format!("{}…", &q.query[..end]),
+            _ => q.query.to_string(),
+        };
+        (query, &q.usage)
+    });
+    usage_rows("Query", queries, human)
+}
+
+/// One row per query or connection. Humans get readable sizes and durations, machines get the
+/// raw values.
+fn usage_rows<'a>(
+    name: &str,
+    usages: impl Iterator<Item = (String, &'a Usage)>,
+    human: bool,
+) -> QueryResultFmt {
+    let results = usages
+        .map(|(value, usage)| {
+            let (average, max, bytes) = match human {
+                true => (
+                    Value::String(format_ms(usage.average_ms)),
+                    Value::String(format_ms(usage.max_ms)),
+                    Value::String(crate::fmt::format_bytes(usage.bytes)),
+                ),
+                false => (
+                    Value::from(usage.average_ms),
+                    Value::from(usage.max_ms),
+                    Value::from(usage.bytes),
+                ),
+            };
+            HashMap::from([
+                (name.to_string(), Value::String(value)),
+                ("Runs".to_string(), Value::from(usage.runs)),
+                ("Failed".to_string(), Value::from(usage.failures)),
+                ("AvgTime".to_string(), average),
+                ("MaxTime".to_string(), max),
+                ("Rows".to_string(), Value::from(usage.rows)),
+                ("Bytes".to_string(), bytes),
+            ])
+        })
+        .collect();
+
+    QueryResultFmt {
+        header: [
+            name, "Runs", "Failed", "AvgTime", "MaxTime", "Rows", "Bytes",
+        ]
+        .map(String::from)
+        .to_vec(),
+        results,
+    }
+}
+
+fn format_ms(ms: u64) -> String {
+    humantime::format_duration(Duration::from_millis(ms)).to_string()
+}
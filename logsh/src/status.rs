@@ -0,0 +1,213 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use logsh_core::{
+    capabilities, config::ConnectionConfig, connect::Connection, error::ConnectError, metadata,
+    query::QueryResultFmt,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{fmt, whoami, OutputMode};
+
+/// What bare `logsh` reports about the default connection.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    status: &'static str,
+    connection: Option<String>,
+    server: Option<String>,
+    username: Option<String>,
+    subscription_id: Option<uuid::Uuid>,
+    subscription_name: Option<String>,
+    token_expires: Option<DateTime<Utc>>,
+    server_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Status {
+    fn unconfigured() -> Self {
+        Self {
+            status: "unconfigured",
+            connection: None,
+            server: None,
+            username: None,
+            subscription_id: None,
+            subscription_name: None,
+            token_expires: None,
+            server_version: None,
+            error: None,
+        }
+    }
+}
+
+/// The name of a subscription, from the cached metadata of any connection, or otherwise the
+/// accounts the server says the user belongs to.
+fn subscription_name(
+    connection: &Connection,
+    user: uuid::Uuid,
+    subscription: uuid::Uuid,
+    offline: bool,
+) -> Option<String> {
+    let cached = metadata::cached_subscriptions()
+        .map_err(|err| log::debug!("Unable to read cached subscriptions: {}", err))
+        .unwrap_or_default();
+    if let Some(found) = cached.into_iter().find(|s| s.account_id == subscription) {
+        return Some(found.account_name);
+    }
+    if offline {
+        return None;
+    }
+
+    match connection.subscriptions(user) {
+        Ok(subscriptions) => subscriptions
+            .into_iter()
+            .find(|s| s.account_id == subscription)
+            .map(|s| s.account_name),
+        Err(err) => {
+            log::debug!("Unable to list subscriptions: {}", err);
+            None
+        }
+    }
+}
+
+/// The server's version and build, cached for a day. Offline, whatever was cached last.
+fn server_version(connection: &Connection, offline: bool) -> Option<String> {
+    let version = match offline {
+        true => capabilities::cached(connection).map_err(|err| err.to_string()),
+        false => capabilities::get(connection).map_err(|err| err.to_string()),
+    };
+    let version = version
+        .map_err(|err| log::debug!("Unable to get the server version: {}", err))
+        .ok()??;
+    Some(match version.build {
+        Some(build) => format!("{} ({})", version.version, build),
+        None => version.version,
+    })
+}
+
+fn resolve(conn: &ConnectionConfig, offline: bool) -> (Status, Option<ConnectError>) {
+    let connection = &conn.connection;
+    let mut status = Status {
+        status: "connected",
+        connection: Some(conn.name.to_string()),
+        server: Some(connection.server.to_string()),
+        username: None,
+        subscription_id: connection.default_subscription(),
+        subscription_name: None,
+        token_expires: connection.token_expires(),
+        server_version: None,
+        error: None,
+    };
+
+    let (user_id, username, cached) = match whoami::resolve_user(connection, offline) {
+        Ok(user) => user,
+        Err(err) => {
+            status.status = "notConnected";
+            status.error = Some(err.to_string());
+            return (status, Some(err));
+        }
+    };
+
+    // The server couldn't be reached, so don't wait on it again for the rest.
+    let offline = offline || cached;
+    if cached {
+        status.status = "cached";
+    }
+    status.username = Some(username);
+    status.subscription_name = status
+        .subscription_id
+        .and_then(|s| subscription_name(connection, user_id, s, offline));
+    status.server_version = server_version(connection, offline);
+    (status, None)
+}
+
+/// Shows the default connection, who it's logged in as, its subscription and the server's
+/// version, as a table or for scripts.
+pub fn execute_status<W: Write>(
+    offline: bool,
+    output: Option<OutputMode>,
+    write: W,
+) -> Result<(), Error> {
+    let mode = output.unwrap_or_else(OutputMode::configured);
+    let cfg = logsh_core::config::load()?;
+    let (status, err) = match cfg.get_default_connection() {
+        Some(conn) => resolve(&conn, offline),
+        None => (Status::unconfigured(), None),
+    };
+
+    let table = matches!(mode, OutputMode::Table);
+    if table {
+        match status.status {
+            "connected" => println!("Status: {}", "Connected".green()),
+            "cached" => println!("Status: {}", "Cached".yellow()),
+            "notConnected" => {
+                println!("Status: {}", "Not Connected".red());
+                if let Some(err) = &err {
+                    fmt::print_connect_error(&cfg, err);
+                }
+            }
+            _ => {
+                println!(
+                    "Status: {} {}",
+                    "You don't have any connections configured yet!".red(),
+                    "Configuration Required.".red()
+                );
+                fmt::print_add_connection_help();
+            }
+        }
+    }
+
+    if status.status != "unconfigured" || !table {
+        fmt::render(write, mode, &status, |status| {
+            let or_none = |v: Option<String>| v.map_or(Value::Null, Value::String);
+            let subscription = match (&status.subscription_name, status.subscription_id) {
+                (Some(name), Some(id)) => Some(format!("{} ({})", name, id)),
+                (None, Some(id)) => Some(id.to_string()),
+                _ => None,
+            };
+            let properties = [
+                ("Connection", or_none(status.connection.clone())),
+                ("Server", or_none(status.server.clone())),
+                ("Username", or_none(status.username.clone())),
+                ("Subscription", or_none(subscription)),
+                (
+                    "Token Expires",
+                    or_none(status.token_expires.map(|e| e.to_rfc3339())),
+                ),
+                ("Server Version", or_none(status.server_version.clone())),
+            ];
+            let results = properties
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(property, value)| {
+                    HashMap::from([
+                        ("Property".to_string(), Value::String(property.to_string())),
+                        ("Value".to_string(), value),
+                    ])
+                })
+                .collect();
+            QueryResultFmt {
+                header: vec!["Property".to_string(), "Value".to_string()],
+                results,
+            }
+        })?;
+    }
+
+    if table {
+        println!(
+            "{} {} {}",
+            "# Execute".bright_black(),
+            "logsh --help".blue(),
+            "to view available commands.".bright_black()
+        );
+    }
+
+    match err {
+        Some(err) => Err(anyhow!("Status check failed: {err}")),
+        None => Ok(()),
+    }
+}
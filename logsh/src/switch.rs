@@ -0,0 +1,65 @@
+use anyhow::Error;
+use colored::Colorize;
+use logsh_core::{config, logship_client::LogshClientHandler, tenant::list_tenants};
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Switch the default connection, and optionally its default subscription.")]
+pub struct UseCommand {
+    #[arg(
+        add = crate::complete::connections(),
+        help = "Connection name. Partial names are matched."
+    )]
+    connection: String,
+
+    #[arg(
+        add = crate::complete::subscriptions(),
+        help = "Subscription name or ID. Partial names are matched."
+    )]
+    subscription: Option<String>,
+}
+
+impl UseCommand {
+    pub fn new(connection: &str) -> Self {
+        Self {
+            connection: connection.to_string(),
+            subscription: None,
+        }
+    }
+}
+
+pub fn execute_use(command: UseCommand) -> Result<(), Error> {
+    let mut cfg = config::load()?;
+    let mut names: Vec<String> = cfg.connections.keys().cloned().collect();
+    names.sort();
+    let name =
+        logsh_core::fuzzy::find_one(&names, |n| n.as_str(), &command.connection, "connection")?
+            .to_string();
+
+    let tenant = match command.subscription {
+        Some(query) => {
+            let user_id = cfg.connections[&name].user_id;
+            let mut tenants =
+                list_tenants(&LogshClientHandler::for_connection(&name), user_id, false)?;
+            tenants.sort_by(|a, b| a.account_name.cmp(&b.account_name));
+            Some(crate::tenant::find_subscription(&tenants, &query)?)
+        }
+        None => None,
+    };
+
+    cfg.default_connection = name.clone();
+    if let (Some(tenant), Some(c)) = (tenant.as_ref(), cfg.connections.get_mut(&name)) {
+        c.default_subscription = Some(tenant.account_id);
+    }
+    config::save(cfg)?;
+
+    match tenant {
+        Some(tenant) => println!(
+            "Default connection set to {} with subscription {} ({})",
+            name.blue(),
+            tenant.account_name.blue(),
+            tenant.account_id
+        ),
+        None => println!("Default connection set to {}", name.blue()),
+    }
+    Ok(())
+}
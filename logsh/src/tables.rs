@@ -0,0 +1,110 @@
+use anyhow::Error;
+use clap::Subcommand;
+use logsh_core::{
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+    tables::{describe_table, list_tables, TableModel},
+};
+use serde_json::Value;
+use std::{collections::HashMap, io::Write};
+
+use crate::OutputMode;
+
+#[derive(Subcommand)]
+#[clap(visible_alias = "table", about = "Discover queryable tables.")]
+pub enum TablesCommand {
+    #[clap(about = "List tables", visible_alias = "ls")]
+    List {
+        #[arg(short, long, help = "Only list tables in this schema.")]
+        schema: Option<String>,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+    #[clap(about = "Describe a table and its columns")]
+    Describe {
+        #[arg(help = "Table name.")]
+        name: String,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+}
+
+pub fn execute_tables<W: Write>(command: TablesCommand, write: W) -> Result<(), Error> {
+    let conn_handler = LogshClientHandler::new();
+    match command {
+        TablesCommand::List { schema, output } => {
+            let mut tables = list_tables(&conn_handler, schema.as_deref())?;
+            tables.sort_by(|a, b| (&a.schema, &a.name).cmp(&(&b.schema, &b.name)));
+            let mode = output.unwrap_or_else(OutputMode::configured);
+            crate::fmt::render(write, mode, &tables, |tables| table_rows(tables, mode))
+        }
+        TablesCommand::Describe { name, output } => {
+            let details = describe_table(&conn_handler, &name)?;
+            let mode = output.unwrap_or_else(OutputMode::configured);
+            crate::fmt::render_with(write, mode, &details, |write, mode, details| match mode {
+                OutputMode::Table | OutputMode::Markdown => {
+                    crate::fmt::render_rows(
+                        &mut *write,
+                        mode,
+                        table_rows(std::slice::from_ref(&details.table), mode),
+                    )?;
+                    crate::fmt::render_rows(write, mode, column_rows(&details.columns))
+                }
+                _ => crate::fmt::render_rows(write, mode, column_rows(&details.columns)),
+            })
+        }
+    }
+}
+
+fn table_rows(tables: &[TableModel], mode: OutputMode) -> QueryResultFmt {
+    // Humans get readable sizes and times, machines get the raw values.
+    let human = matches!(mode, OutputMode::Table | OutputMode::Markdown);
+    let results = tables
+        .iter()
+        .map(|t| {
+            let size = match (human, t.size_bytes) {
+                (true, Some(b)) => Value::String(crate::fmt::format_bytes(b)),
+                (_, b) => b.map_or(Value::Null, Value::from),
+            };
+            let last_ingest = t.last_ingest.map_or(Value::Null, |l| {
+                Value::String(if human {
+                    l.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                } else {
+                    l.to_rfc3339()
+                })
+            });
+            HashMap::from([
+                ("Schema".to_string(), Value::String(t.schema.to_string())),
+                ("Table".to_string(), Value::String(t.name.to_string())),
+                (
+                    "Rows".to_string(),
+                    t.row_count.map_or(Value::Null, Value::from),
+                ),
+                ("Size".to_string(), size),
+                ("LastIngest".to_string(), last_ingest),
+            ])
+        })
+        .collect();
+
+    QueryResultFmt {
+        header: ["Schema", "Table", "Rows", "Size", "LastIngest"]
+            .map(String::from)
+            .to_vec(),
+        results,
+    }
+}
+
+fn column_rows(columns: &[logsh_core::tables::ColumnModel]) -> QueryResultFmt {
+    QueryResultFmt {
+        header: vec!["Column".to_string(), "Type".to_string()],
+        results: columns
+            .iter()
+            .map(|c| {
+                HashMap::from([
+                    ("Column".to_string(), Value::String(c.name.to_string())),
+                    ("Type".to_string(), Value::String(c.typ.to_string())),
+                ])
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Write},
+};
+
+use anyhow::anyhow;
+use clap::Subcommand;
+use dialoguer::{Confirm, Select};
+use logsh_core::{
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+    tenant::{self, create_tenant, delete_tenant, list_tenants, rename_tenant, TenantModel},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Subcommand)]
+#[clap(
+    visible_aliases = ["sub", "account", "tenant"],
+    about = "Subscription management."
+)]
+pub enum TenantCommand {
+    #[clap(about = "List subscriptions", visible_alias = "ls")]
+    List {
+        #[arg(long, help = "Include all subscriptions.")]
+        include_all: bool,
+        #[arg(short, long, help = "Output result format")]
+        output: Option<OutputMode>,
+    },
+    #[clap(about = "Set the default subscription for the current connection.")]
+    Default {
+        #[arg(
+            add = crate::complete::subscriptions(),
+            help = "Subscription name or ID to set as default. Partial names are matched. Pick from a list if not provided."
+        )]
+        subscription: Option<String>,
+    },
+    #[clap(about = "Create a subscription")]
+    Create {
+        #[arg(help = "Name of the new subscription.")]
+        name: String,
+    },
+    #[clap(about = "Rename a subscription")]
+    Rename {
+        #[arg(
+            add = crate::complete::subscriptions(),
+            help = "Full name or ID of the subscription to rename."
+        )]
+        subscription: String,
+        #[arg(help = "New subscription name.")]
+        name: String,
+    },
+    #[clap(about = "Delete a subscription")]
+    Delete {
+        #[arg(
+            add = crate::complete::subscriptions(),
+            help = "Full name or ID of the subscription to delete."
+        )]
+        subscription: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Subscription {
+    name: String,
+    id: uuid::Uuid,
+    is_default: bool,
+}
+
+pub fn execute_tenant<W: Write>(command: TenantCommand, write: W) -> Result<(), anyhow::Error> {
+    match command {
+        TenantCommand::Default { subscription } => {
+            let default_config = logsh_core::config::load()?;
+            let default_connection = default_config
+                .get_default_connection()
+                .ok_or(anyhow!("No default connection found."))?;
+            let conn_handler = LogshClientHandler::new();
+
+            let subscriptions =
+                list_tenants(&conn_handler, default_connection.connection.user_id, false)?;
+
+            let subscription = match subscription {
+                Some(query) => find_subscription(&subscriptions, &query)?,
+                None => {
+                    let mut subscriptions = subscriptions.clone();
+                    subscriptions.sort_by(|a, b| a.account_name.cmp(&b.account_name));
+                    let id = select_tenant(&subscriptions)?
+                        .ok_or(anyhow!("No subscription selected."))?;
+                    subscriptions
+                        .into_iter()
+                        .find(|s| s.account_id == id)
+                        .ok_or(anyhow!("Subscription not found."))?
+                }
+            };
+
+            let mut config = default_config;
+            config.connections.iter_mut().for_each(|c| {
+                if c.0 != default_connection.name.as_str() {
+                    return;
+                }
+
+                c.1.default_subscription = Some(subscription.account_id);
+            });
+            logsh_core::config::save(config)?;
+
+            println!(
+                "Default subscription set to {} ({})",
+                subscription.account_name,
+                crate::fmt::link::subscription_id(subscription.account_id)
+            );
+            Ok(())
+        }
+        TenantCommand::Create { name } => {
+            let conn_handler = LogshClientHandler::new();
+            let subscription = create_tenant(&conn_handler, &name)?;
+            println!(
+                "Created subscription {} ({})",
+                subscription.account_name,
+                crate::fmt::link::subscription_id(subscription.account_id)
+            );
+            Ok(())
+        }
+        TenantCommand::Rename { subscription, name } => {
+            let conn_handler = LogshClientHandler::new();
+            let id = resolve_id(&conn_handler, &subscription, "rename")?;
+            let subscription = rename_tenant(&conn_handler, id, &name)?;
+            println!(
+                "Renamed subscription {} to {}",
+                subscription.account_id, subscription.account_name
+            );
+            Ok(())
+        }
+        TenantCommand::Delete { subscription } => {
+            let conn_handler = LogshClientHandler::new();
+            let id = resolve_id(&conn_handler, &subscription, "delete")?;
+            delete_tenant(&conn_handler, id)?;
+            Ok(())
+        }
+        TenantCommand::List {
+            include_all,
+            output,
+        } => {
+            let default_config = logsh_core::config::load()?;
+            let default_connection = default_config
+                .get_default_connection()
+                .ok_or(anyhow!("No default connection found."))?;
+            let conn_handler = LogshClientHandler::new();
+
+            let subscriptions = list_tenants(
+                &conn_handler,
+                default_connection.connection.user_id,
+                include_all,
+            )?;
+
+            let subscriptions: Vec<_> = subscriptions
+                .into_iter()
+                .map(|s| Subscription {
+                    is_default: default_connection
+                        .connection
+                        .default_subscription
+                        .is_some_and(|d| d == s.account_id),
+                    name: s.account_name,
+                    id: s.account_id,
+                })
+                .collect();
+            let id = crate::fmt::link::set_id_column(Some("subscription"));
+            let rendered = crate::fmt::render(
+                write,
+                output.unwrap_or_else(OutputMode::configured),
+                &subscriptions,
+                |s| subscription_rows(s),
+            );
+            crate::fmt::link::set_id_column(id);
+            rendered
+        }
+    }
+}
+
+fn subscription_rows(subscriptions: &[Subscription]) -> QueryResultFmt {
+    let results = subscriptions
+        .iter()
+        .map(|s| {
+            HashMap::from([
+                ("Name".to_string(), Value::String(s.name.to_string())),
+                ("ID".to_string(), Value::String(s.id.to_string())),
+                ("Default".to_string(), Value::Bool(s.is_default)),
+            ])
+        })
+        .collect();
+
+    QueryResultFmt {
+        header: vec!["Name".to_string(), "ID".to_string(), "Default".to_string()],
+        results,
+    }
+}
+
+/// The subscription `query` names, by ID or by whole, leading or partial name. Looser matches
+/// are confirmed first when attached to a terminal, and refused otherwise, so a typo can't act
+/// on the wrong subscription.
+pub fn find_subscription(
+    tenants: &[TenantModel],
+    query: &str,
+) -> Result<TenantModel, anyhow::Error> {
+    let (found, kind) = tenant::resolve(tenants, query)?;
+    if kind != tenant::MatchKind::Fuzzy {
+        return Ok(found.clone());
+    }
+
+    let described = format!("{} ({})", found.account_name, found.account_id);
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Err(anyhow!(
+            "\"{}\" only partly matches subscription {}. Give its full name or ID.",
+            query,
+            described
+        ));
+    }
+
+    let confirmed = Confirm::with_theme(&*crate::prompt::theme())
+        .with_prompt(format!("Use subscription {}?", described))
+        .default(true)
+        .interact()?;
+    match confirmed {
+        true => Ok(found.clone()),
+        false => Err(anyhow!("No subscription selected.")),
+    }
+}
+
+/// The ID of the subscription `query` names by its ID or whole name, among every subscription
+/// the default connection's user can see. Renames and deletes can't be undone, so the start or
+/// part of a name isn't enough.
+fn resolve_id(
+    conn_handler: &LogshClientHandler,
+    query: &str,
+    action: &str,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let connection = conn_handler.get_connection()?;
+    let subscriptions = list_tenants(conn_handler, connection.user_id, true)?;
+    match tenant::resolve(&subscriptions, query)? {
+        (found, tenant::MatchKind::Exact) => Ok(found.account_id),
+        (found, _) => Err(anyhow!(
+            "\"{}\" only partly matches subscription {} ({}). Give its full name or ID to {} it.",
+            query,
+            found.account_name,
+            found.account_id,
+            action
+        )),
+    }
+}
+
+/// Picks a default subscription when a connection has several. Asks when attached to a
+/// terminal, and otherwise takes the first one.
+pub fn pick_tenant(tenants: &[TenantModel]) -> Option<uuid::Uuid> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        let first = tenants.first()?;
+        log::warn!(
+            "Multiple subscriptions available. Using {} as the default, change it with \"logsh sub default\".",
+            first.account_name
+        );
+        return Some(first.account_id);
+    }
+
+    match select_tenant(tenants) {
+        Ok(Some(id)) => Some(id),
+        Ok(None) => {
+            log::warn!("No default subscription selected.");
+            None
+        }
+        Err(err) => {
+            log::warn!("Failed to select a subscription: {}", err);
+            tenants.first().map(|t| t.account_id)
+        }
+    }
+}
+
+/// Shows an arrow key selectable list of subscriptions. Returns None when cancelled.
+fn select_tenant(tenants: &[TenantModel]) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    if tenants.is_empty() {
+        return Err(anyhow!("No subscriptions available."));
+    }
+
+    let items: Vec<String> = tenants
+        .iter()
+        .map(|t| format!("{} ({})", t.account_name, t.account_id))
+        .collect();
+    let selection = Select::with_theme(&*crate::prompt::theme())
+        .with_prompt("Select a default subscription")
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+
+    Ok(selection.map(|i| tenants[i].account_id))
+}
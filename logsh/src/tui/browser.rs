@@ -0,0 +1,357 @@
+use std::str::FromStr;
+
+use anyhow::Error;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use logsh_core::query::QueryResult;
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, Wrap},
+    Frame,
+};
+use serde_json::Value;
+
+use super::Screen;
+
+const MIN_WIDTH: u16 = 3;
+const MAX_WIDTH: u16 = 200;
+const DEFAULT_MAX_WIDTH: u16 = 40;
+const COLUMN_SPACING: u16 = 1;
+
+/// Browses a query result in a full-screen grid until the user quits.
+pub fn browse(result: &QueryResult<'_>) -> Result<(), Error> {
+    let mut browser = Browser::new(result);
+    let mut screen = Screen::enter()?;
+    loop {
+        screen.terminal().draw(|frame| browser.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !browser.handle(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+enum Mode {
+    Grid,
+    Search,
+    Detail,
+}
+
+struct Browser<'r, 'a> {
+    result: &'r QueryResult<'a>,
+    /// Single line text of every cell, by row then column.
+    cells: Vec<Vec<String>>,
+    widths: Vec<u16>,
+    mode: Mode,
+    row: usize,
+    column: usize,
+    top: usize,
+    left: usize,
+    page: usize,
+    search: String,
+    message: Option<String>,
+    detail_scroll: u16,
+}
+
+impl<'r, 'a> Browser<'r, 'a> {
+    fn new(result: &'r QueryResult<'a>) -> Self {
+        let cells: Vec<Vec<String>> = result
+            .results
+            .iter()
+            .map(|row| {
+                result
+                    .header
+                    .iter()
+                    .map(|h| {
+                        row.get(h.as_str())
+                            .map_or(String::new(), |v| super::cell(v.get()))
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths = result
+            .header
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let longest = cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(std::iter::once(h.chars().count()))
+                    .max()
+                    .unwrap_or_default();
+                (longest.min(DEFAULT_MAX_WIDTH as usize) as u16).max(MIN_WIDTH)
+            })
+            .collect();
+
+        Self {
+            result,
+            cells,
+            widths,
+            mode: Mode::Grid,
+            row: 0,
+            column: 0,
+            top: 0,
+            left: 0,
+            page: 1,
+            search: String::new(),
+            message: None,
+            detail_scroll: 0,
+        }
+    }
+
+    /// Applies a key press. Returns false when the browser should close.
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        match self.mode {
+            Mode::Grid => return self.handle_grid(key),
+            Mode::Search => match key.code {
+                KeyCode::Enter => {
+                    self.mode = Mode::Grid;
+                    self.find(true);
+                }
+                KeyCode::Esc => self.mode = Mode::Grid,
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => self.search.push(c),
+                _ => {}
+            },
+            Mode::Detail => match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.mode = Mode::Grid,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.detail_scroll = self.detail_scroll.saturating_sub(1)
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.detail_scroll = self.detail_scroll.saturating_add(1)
+                }
+                KeyCode::PageUp => {
+                    self.detail_scroll = self.detail_scroll.saturating_sub(self.page as u16)
+                }
+                KeyCode::PageDown => {
+                    self.detail_scroll = self.detail_scroll.saturating_add(self.page as u16)
+                }
+                KeyCode::Home | KeyCode::Char('g') => self.detail_scroll = 0,
+                _ => {}
+            },
+        }
+
+        true
+    }
+
+    fn handle_grid(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+        let last_row = self.cells.len().saturating_sub(1);
+        let last_column = self.widths.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.row = self.row.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.row = (self.row + 1).min(last_row),
+            KeyCode::PageUp => self.row = self.row.saturating_sub(self.page),
+            KeyCode::PageDown => self.row = (self.row + self.page).min(last_row),
+            KeyCode::Home | KeyCode::Char('g') => self.row = 0,
+            KeyCode::End | KeyCode::Char('G') => self.row = last_row,
+            KeyCode::Left | KeyCode::Char('h') => self.column = self.column.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => self.column = (self.column + 1).min(last_column),
+            KeyCode::Char('+') | KeyCode::Char('>') => self.resize(2),
+            KeyCode::Char('-') | KeyCode::Char('<') => self.resize(-2),
+            KeyCode::Char('/') => {
+                self.search.clear();
+                self.mode = Mode::Search;
+            }
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            KeyCode::Enter if !self.cells.is_empty() => {
+                self.detail_scroll = 0;
+                self.mode = Mode::Detail;
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn resize(&mut self, delta: i32) {
+        if let Some(width) = self.widths.get_mut(self.column) {
+            *width = (*width as i32 + delta).clamp(MIN_WIDTH as i32, MAX_WIDTH as i32) as u16;
+        }
+    }
+
+    /// Moves to the next (or previous) cell containing the search text, ignoring case.
+    fn find(&mut self, forward: bool) {
+        if self.search.is_empty() || self.widths.is_empty() {
+            return;
+        }
+
+        let needle = self.search.to_lowercase();
+        let columns = self.widths.len();
+        let count = self.cells.len() * columns;
+        let current = self.row * columns + self.column;
+        let found = (1..=count)
+            .map(|step| {
+                if forward {
+                    (current + step) % count
+                } else {
+                    (current + count - step) % count
+                }
+            })
+            .find(|i| {
+                self.cells[i / columns][i % columns]
+                    .to_lowercase()
+                    .contains(&needle)
+            });
+        match found {
+            Some(i) => {
+                self.row = i / columns;
+                self.column = i % columns;
+            }
+            None => self.message = Some(format!("No match for \"{}\".", self.search)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.size());
+        match self.mode {
+            Mode::Detail => self.draw_detail(frame, chunks[0]),
+            _ => self.draw_grid(frame, chunks[0]),
+        }
+        self.draw_status(frame, chunks[1]);
+    }
+
+    fn draw_grid(&mut self, frame: &mut Frame, area: Rect) {
+        // The header takes the first line.
+        self.page = (area.height as usize).saturating_sub(1).max(1);
+        if self.row < self.top {
+            self.top = self.row;
+        } else if self.row >= self.top + self.page {
+            self.top = self.row + 1 - self.page;
+        }
+
+        // Scroll horizontally until the selected column fits.
+        self.left = self.left.min(self.column);
+        while self.left < self.column && self.span(self.left, self.column) > area.width {
+            self.left += 1;
+        }
+        let mut visible = self.left;
+        while visible + 1 < self.widths.len() && self.span(self.left, visible + 1) <= area.width {
+            visible += 1;
+        }
+        let columns = self.left..(visible + 1).min(self.widths.len());
+
+        let widths: Vec<Constraint> = columns
+            .clone()
+            .map(|c| Constraint::Length(self.widths[c]))
+            .collect();
+        let header = Row::new(columns.clone().map(|c| {
+            let style = if c == self.column {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            Cell::from(self.result.header[c].as_str()).style(style)
+        }))
+        .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+
+        let end = (self.top + self.page).min(self.cells.len());
+        let rows = (self.top..end).map(|r| {
+            let selected = r == self.row;
+            Row::new(columns.clone().map(|c| {
+                let text = self.cells[r][c].as_str();
+                let mut style = if text == "<null>" {
+                    Style::default().fg(Color::DarkGray)
+                } else {
+                    Style::default()
+                };
+                if selected && c == self.column {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Cell::from(text).style(style)
+            }))
+            .style(if selected {
+                Style::default().bg(Color::Blue).fg(Color::White)
+            } else {
+                Style::default()
+            })
+        });
+
+        let table = Table::new(rows)
+            .header(header)
+            .widths(&widths)
+            .column_spacing(COLUMN_SPACING);
+        frame.render_widget(table, area);
+    }
+
+    fn draw_detail(&mut self, frame: &mut Frame, area: Rect) {
+        self.page = (area.height as usize).saturating_sub(2).max(1);
+        let row = &self.result.results[self.row];
+        let mut lines = Vec::new();
+        for h in self.result.header.iter() {
+            lines.push(Line::from(Span::styled(
+                h.as_str(),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            let value = row
+                .get(h.as_str())
+                .map_or(String::new(), |v| detail(v.get()));
+            lines.extend(value.lines().map(|l| Line::from(format!("  {}", l))));
+        }
+
+        let block = Block::default().borders(Borders::ALL).title(format!(
+            " Row {} of {} ",
+            self.row + 1,
+            self.cells.len()
+        ));
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(paragraph, area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let text = match (&self.mode, self.message.as_ref()) {
+            (Mode::Search, _) => format!("/{}", self.search),
+            (Mode::Detail, _) => "↑↓ scroll  Esc back".to_string(),
+            (Mode::Grid, Some(message)) => message.to_string(),
+            (Mode::Grid, None) => format!(
+                "Row {}/{}  Column {}/{}  ↑↓←→ move  +/- width  / search  n/N next/previous  Enter details  q quit",
+                (self.row + 1).min(self.cells.len()),
+                self.cells.len(),
+                (self.column + 1).min(self.widths.len()),
+                self.widths.len(),
+            ),
+        };
+        let status = Paragraph::new(text).style(Style::default().fg(Color::Black).bg(Color::Gray));
+        frame.render_widget(status, area);
+    }
+
+    /// Width of the columns from first through last, including the spacing between them.
+    fn span(&self, first: usize, last: usize) -> u16 {
+        self.widths[first..=last]
+            .iter()
+            .fold(0u16, |acc, w| acc.saturating_add(*w))
+            .saturating_add(COLUMN_SPACING * (last - first) as u16)
+    }
+}
+
+/// Full text of a cell for the detail view. Nested values are pretty printed.
+fn detail(json: &str) -> String {
+    match Value::from_str(json) {
+        Ok(Value::Null) => "<null>".to_string(),
+        Ok(Value::String(s)) => match Value::from_str(&s) {
+            // Strings holding JSON documents are common in log data.
+            Ok(v @ (Value::Object(_) | Value::Array(_))) => {
+                serde_json::to_string_pretty(&v).unwrap_or(s)
+            }
+            _ => s,
+        },
+        Ok(v) => serde_json::to_string_pretty(&v).unwrap_or_else(|_| json.to_string()),
+        Err(_) => json.to_string(),
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{IsTerminal, Stdout},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error};
+use crossterm::{
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use serde_json::Value;
+
+pub mod browser;
+
+/// Owns the terminal while a full-screen view runs. The terminal is restored when this is
+/// dropped, so an error or panic in a view doesn't leave the shell in raw mode.
+pub(crate) struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    _restore: Restore,
+}
+
+/// Leaves the alternate screen and turns raw mode off when dropped. Made before raw mode is
+/// turned on, so the terminal is restored however entering the screen fails.
+struct Restore;
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+impl Screen {
+    pub(crate) fn enter() -> Result<Self, Error> {
+        let mut stdout = std::io::stdout();
+        if !stdout.is_terminal() {
+            return Err(anyhow!(
+                "Full-screen views require an interactive terminal."
+            ));
+        }
+
+        let restore = Restore;
+        enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen)?;
+
+        let mut screen = Self {
+            terminal: Terminal::new(CrosstermBackend::new(stdout))?,
+            _restore: restore,
+        };
+        screen.terminal.hide_cursor()?;
+        Ok(screen)
+    }
+
+    pub(crate) fn terminal(&mut self) -> &mut Terminal<CrosstermBackend<Stdout>> {
+        &mut self.terminal
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+/// Single line text of a cell. Strings are shown without quotes and nested values as compact JSON.
+pub(crate) fn cell(json: &str) -> String {
+    let text = match Value::from_str(json) {
+        Ok(Value::Null) => "<null>".to_string(),
+        Ok(Value::String(s)) => s,
+        Ok(v) => v.to_string(),
+        Err(_) => json.to_string(),
+    };
+    text.replace(['\r', '\n', '\t'], " ")
+}
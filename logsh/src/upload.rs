@@ -1,13 +1,53 @@
-use anyhow::Error;
-use logsh_core::config;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use crate::fmt::parse::OptionalDurationArg;
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use logsh_core::{
+    config,
+    logship_client::LogshClientHandler,
+    query::QueryResultFmt,
+    stream::StreamOptions,
+    tenant::list_tenants,
+    upload::{
+        ColumnMapping, CsvOptions, Delimiter, JsonOptions, UploadOptions, UploadReport, STDIN,
+    },
+    upload_state,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{fmt::parse::OptionalDurationArg, OutputMode};
 
 #[derive(Debug, clap::Args)]
-#[clap(about = "Upload CSV files to your logship server.")]
+#[clap(about = "Upload files, or stdin, to your logship server.")]
 pub struct UploadCommand {
+    #[arg(add = crate::complete::schemas())]
     schema: String,
-    path: String,
+    #[arg(
+        required = true,
+        help = "Files to upload in turn, or - to upload stdin as it's read."
+    )]
+    paths: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["delimiter", "has_header", "skip_rows", "columns", "flatten"],
+        help = "Send each file in chunks of --chunk-size whole records, recording how far the server got, so running the same upload again after a failure carries on where it stopped. Works for csv, ndjson and jsonl files. See \"logsh uploads\"."
+    )]
+    resume: bool,
+
+    #[arg(
+        long,
+        value_name = "EXT",
+        required_if_eq("paths", "-"),
+        help = "Format of the upload, such as csv, json or ndjson. Defaults to the file's extension."
+    )]
+    format: Option<String>,
 
     #[arg(
         short,
@@ -16,15 +56,447 @@ pub struct UploadCommand {
         default_value = "none"
     )]
     timeout: OptionalDurationArg,
+
+    #[arg(
+        long,
+        default_value = ",",
+        help = "Field delimiter of a CSV file, such as \";\" or \"tab\"."
+    )]
+    delimiter: Delimiter,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Whether the first row of a CSV file names its columns. Use --has-header=false for files without one."
+    )]
+    has_header: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Lines to skip at the start of a CSV file, before the header."
+    )]
+    skip_rows: usize,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "COLUMNS",
+        help = "Comma separated columns to upload, in order. Each is NAME, naming the column at that position, or SOURCE=NAME, taking a header column or 1-based index. Other columns are dropped."
+    )]
+    columns: Option<Vec<ColumnMapping>>,
+
+    #[arg(
+        long,
+        help = "Expand nested objects of a JSON or NDJSON file into dotted columns, such as request.headers.host."
+    )]
+    flatten: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 3,
+        requires = "flatten",
+        help = "Levels of nesting expanded by --flatten. Objects nested deeper are uploaded as JSON strings."
+    )]
+    max_depth: usize,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = StreamOptions::default().chunk_size,
+        help = "Bytes sent at a time when stdin or a rewritten file is streamed. Up to eight chunks are buffered."
+    )]
+    chunk_size: usize,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1s",
+        help = "Longest a partial chunk waits before it's sent, so slow input from stdin still arrives."
+    )]
+    flush_interval: humantime::Duration,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write a JSON manifest of the upload to PATH, or - for stdout, listing each file's bytes, rows when known, duration, status and the server's response. With -, the summary of several files goes to stderr."
+    )]
+    report: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Output format of the summary printed after uploading several files."
+    )]
+    output: Option<OutputMode>,
+
+    #[arg(
+        short,
+        long,
+        add = crate::complete::subscriptions(),
+        help = "Subscription name or ID to upload to, instead of the connection's default. Partial names are matched."
+    )]
+    subscription: Option<String>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+#[clap(about = "Show or forget unfinished \"upload --resume\" uploads.")]
+pub enum UploadsCommand {
+    #[clap(
+        visible_alias = "ls",
+        about = "List unfinished --resume uploads, how far each got and why it stopped."
+    )]
+    List {
+        #[arg(short, long, help = "Output format.")]
+        output: Option<OutputMode>,
+    },
+    #[clap(
+        about = "Forget unfinished --resume uploads, so uploading their files again starts from the beginning."
+    )]
+    Clear {
+        #[arg(help = "Files whose uploads to forget. All uploads when none are given.")]
+        paths: Vec<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Only forget uploads whose file was deleted or changed since, which can't resume."
+        )]
+        stale: bool,
+
+        #[arg(
+            long,
+            value_name = "DURATION",
+            help = "Only forget uploads last attempted longer ago than this, such as 7d."
+        )]
+        older_than: Option<humantime::Duration>,
+    },
+}
+
+/// What `--report` writes, so pipelines can check what an upload sent.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest<'a> {
+    schema: &'a str,
+    connection: &'a str,
+    server: &'a str,
+    subscription: Option<uuid::Uuid>,
+    started: DateTime<Utc>,
+    finished: DateTime<Utc>,
+    files: &'a [UploadedFile],
+}
+
+/// One file of an upload, and how it went.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadedFile {
+    path: String,
+    bytes: Option<u64>,
+    rows: Option<usize>,
+    duration_ms: u64,
+    status: &'static str,
+    http_status: Option<u16>,
+    response: Option<Value>,
+    error: Option<String>,
+}
+
+impl UploadedFile {
+    fn new(path: &str, started: Instant, result: &Result<UploadReport, Error>) -> Self {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(report) => Self {
+                path: path.to_string(),
+                bytes: Some(report.bytes),
+                rows: report.rows,
+                duration_ms,
+                status: "uploaded",
+                http_status: Some(report.status),
+                response: report.response.clone(),
+                error: None,
+            },
+            Err(err) => Self {
+                path: path.to_string(),
+                bytes: None,
+                rows: None,
+                duration_ms,
+                status: "failed",
+                http_status: None,
+                response: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+pub fn execute_uploads<W: Write>(command: UploadsCommand, write: W) -> Result<(), Error> {
+    match command {
+        UploadsCommand::List { output } => list_pending(write, output),
+        UploadsCommand::Clear {
+            paths,
+            stale,
+            older_than,
+        } => clear_pending(write, &paths, stale, older_than),
+    }
 }
 
 pub fn execute_upload(args: UploadCommand) -> Result<(), Error> {
+    if args.resume && config::read_only() {
+        return Err(anyhow!(
+            "--resume records how far uploads got next to the configuration, which read-only mode doesn't write."
+        ));
+    }
+
     let cfg = config::load()?;
-    let connection = cfg
+    let mut connection = cfg
         .connections
-        .get(&cfg.default_connection)
-        .or_else(|| cfg.connections.values().next())
+        .get_key_value(&cfg.default_connection)
+        .or_else(|| cfg.connections.iter().next())
+        .map(|(name, connection)| config::ConnectionConfig {
+            name: name.to_string(),
+            connection: connection.clone(),
+        })
         .ok_or(anyhow::anyhow!("Connection does not exist"))?;
-    logsh_core::upload::execute(&args.schema, &args.path, connection, args.timeout.into())?;
+    let subscription = match args.subscription.as_deref() {
+        Some(query) => {
+            let tenants = list_tenants(
+                &LogshClientHandler::for_connection(&connection.name),
+                connection.connection.user_id,
+                false,
+            )?;
+            Some(crate::tenant::find_subscription(&tenants, query)?.account_id)
+        }
+        None => None,
+    };
+    let options = UploadOptions {
+        csv: CsvOptions {
+            delimiter: args.delimiter,
+            has_header: args.has_header,
+            skip_rows: args.skip_rows,
+            columns: args.columns.clone(),
+        },
+        json: JsonOptions {
+            flatten: args.flatten.then_some(args.max_depth),
+        },
+        format: args.format.clone(),
+        stream: StreamOptions {
+            chunk_size: args.chunk_size,
+            max_buffered: args.chunk_size.saturating_mul(8),
+            flush_interval: args.flush_interval.into(),
+        },
+    };
+    if args.paths.len() > 1 && args.paths.iter().any(|p| p == STDIN) {
+        return Err(anyhow!("Stdin can only be uploaded on its own."));
+    }
+
+    let progress = crate::progress::reporter();
+    let started = Utc::now();
+    let mut files = Vec::with_capacity(args.paths.len());
+    let mut first_error = None;
+    for path in args.paths.iter() {
+        let upload = |c: &config::ConnectionConfig| {
+            let mut target = c.connection.clone();
+            target.default_subscription = subscription.or(target.default_subscription);
+            let execute = match args.resume {
+                true => logsh_core::upload::execute_resumable,
+                false => logsh_core::upload::execute,
+            };
+            execute(
+                &args.schema,
+                path,
+                &options,
+                &target,
+                args.timeout.into(),
+                progress.clone(),
+            )
+        };
+
+        let start = Instant::now();
+        // Stdin can only be read once, so it isn't sent again after logging in.
+        let result = match path == STDIN {
+            true => upload(&connection),
+            false => crate::connect::retry_unauthorized(&mut connection, upload),
+        }
+        .map_err(Error::from);
+        files.push(UploadedFile::new(path, start, &result));
+        if let Err(err) = result {
+            log::warn!("Failed to upload {}: {}", path, err);
+            first_error.get_or_insert(err);
+        }
+    }
+
+    if let Some(report) = args.report.as_ref() {
+        let manifest = Manifest {
+            schema: &args.schema,
+            connection: &connection.name,
+            server: &connection.connection.server,
+            subscription: subscription.or(connection.connection.default_subscription()),
+            started,
+            finished: Utc::now(),
+            files: &files,
+        };
+        write_manifest(report, &manifest)?;
+    }
+
+    if files.len() > 1 {
+        let mode = args.output.unwrap_or_else(OutputMode::configured);
+        let rows = |files: &Vec<UploadedFile>| summary_rows(files, mode);
+        // A report on stdout is for a pipeline to parse, so the summary keeps out of its way.
+        match args.report.as_ref().is_some_and(|r| r.as_os_str() == STDIN) {
+            true => crate::fmt::render(std::io::stderr(), mode, &files, rows)?,
+            false => crate::fmt::render(std::io::stdout(), mode, &files, rows)?,
+        }
+    }
+
+    let failures = files.iter().filter(|f| f.error.is_some()).count();
+    match (failures, first_error) {
+        (_, None) => Ok(()),
+        (1, Some(err)) if files.len() == 1 => Err(err),
+        (failures, Some(_)) => Err(anyhow!(
+            "{} of {} files failed to upload.",
+            failures,
+            files.len()
+        )),
+    }
+}
+
+/// Writes the manifest as pretty JSON to the path, or to stdout for `-`.
+fn write_manifest(path: &Path, manifest: &Manifest) -> Result<(), Error> {
+    if path.as_os_str() == STDIN {
+        let mut stdout = std::io::stdout().lock();
+        serde_json::to_writer_pretty(&mut stdout, manifest)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+
+    let json = serde_json::to_string_pretty(manifest)?;
+    std::fs::write(path, json + "\n")
+        .map_err(|err| anyhow!("Failed to write report {}: {}", path.display(), err))?;
+    log::info!("Wrote upload report to {}", path.display());
+    Ok(())
+}
+
+/// One row per file of a multi-file upload.
+fn summary_rows(files: &[UploadedFile], mode: OutputMode) -> QueryResultFmt {
+    let human = matches!(mode, OutputMode::Table | OutputMode::Markdown);
+    let results = files
+        .iter()
+        .map(|f| {
+            let (bytes, duration) = match human {
+                true => (
+                    Value::from(f.bytes.map(crate::fmt::format_bytes)),
+                    Value::from(format!("{:.1}s", f.duration_ms as f64 / 1000.0)),
+                ),
+                false => (Value::from(f.bytes), Value::from(f.duration_ms)),
+            };
+            let status = match &f.error {
+                Some(err) => format!("{}: {}", f.status, err),
+                None => f.status.to_string(),
+            };
+            HashMap::from([
+                ("File".to_string(), Value::from(f.path.clone())),
+                ("Bytes".to_string(), bytes),
+                ("Rows".to_string(), Value::from(f.rows)),
+                ("Duration".to_string(), duration),
+                ("Status".to_string(), Value::from(status)),
+            ])
+        })
+        .collect();
+    QueryResultFmt {
+        header: ["File", "Bytes", "Rows", "Duration", "Status"]
+            .map(String::from)
+            .to_vec(),
+        results,
+    }
+}
+
+/// Lists unfinished resumable uploads, most recently attempted first.
+fn list_pending<W: Write>(mut write: W, output: Option<OutputMode>) -> Result<(), Error> {
+    let mut uploads = upload_state::load()?.uploads;
+    uploads.sort_by_key(|u| std::cmp::Reverse(u.updated));
+    let mode = output.unwrap_or_else(OutputMode::configured);
+    if uploads.is_empty() && matches!(mode, OutputMode::Table) {
+        writeln!(write, "No unfinished uploads.")?;
+        return Ok(());
+    }
+
+    crate::fmt::render(write, mode, &uploads, |uploads| {
+        let human = matches!(mode, OutputMode::Table | OutputMode::Markdown);
+        let results = uploads
+            .iter()
+            .map(|u| {
+                let uploaded = match human {
+                    true => Value::from(format!(
+                        "{} of {}",
+                        crate::fmt::format_bytes(u.offset),
+                        crate::fmt::format_bytes(u.size)
+                    )),
+                    false => Value::from(u.offset),
+                };
+                HashMap::from([
+                    ("File".to_string(), Value::from(u.path.clone())),
+                    ("Schema".to_string(), Value::from(u.schema.clone())),
+                    ("Server".to_string(), Value::from(u.server.clone())),
+                    (
+                        "Subscription".to_string(),
+                        Value::from(u.subscription.to_string()),
+                    ),
+                    ("Uploaded".to_string(), uploaded),
+                    ("Status".to_string(), Value::from(u.status())),
+                    ("Updated".to_string(), Value::from(u.updated.to_rfc3339())),
+                    ("Error".to_string(), Value::from(u.error.clone())),
+                ])
+            })
+            .collect();
+        QueryResultFmt {
+            header: [
+                "File",
+                "Schema",
+                "Server",
+                "Subscription",
+                "Uploaded",
+                "Status",
+                "Updated",
+                "Error",
+            ]
+            .map(String::from)
+            .to_vec(),
+            results,
+        }
+    })
+}
+
+/// Forgets the resumable uploads of `paths`, or all of them, that pass the filters.
+fn clear_pending<W: Write>(
+    mut write: W,
+    paths: &[PathBuf],
+    stale: bool,
+    older_than: Option<humantime::Duration>,
+) -> Result<(), Error> {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|p| {
+            std::fs::canonicalize(p)
+                .unwrap_or_else(|_| p.clone())
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    let cutoff = older_than
+        .map(|d| chrono::Duration::from_std(d.into()).map(|d| Utc::now() - d))
+        .transpose()?;
+    let removed = upload_state::clear(|u| {
+        (paths.is_empty() || paths.contains(&u.path))
+            && (!stale || u.is_stale())
+            && cutoff.is_none_or(|cutoff| u.updated < cutoff)
+    })?;
+    writeln!(
+        write,
+        "Forgot {} unfinished upload{}.",
+        removed,
+        if removed == 1 { "" } else { "s" }
+    )?;
     Ok(())
 }
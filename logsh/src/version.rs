@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Error};
+use logsh_core::progress::ProgressWriter;
 use self_update::self_replace;
 use std::io::{stdin, Write};
 
@@ -136,10 +137,14 @@ pub fn version<W: Write>(mut write: W, command: VersionCommand, level: u8) -> Re
             log::debug!("Temporary asset path: {:?}", path);
             let empty = ::std::fs::File::create(&path)?;
 
-            self_update::Download::from_url(&asset.download_url)
+            let progress = crate::progress::reporter();
+            progress.start(&format!("Downloading {}", asset.name), None);
+            let download = self_update::Download::from_url(&asset.download_url)
                 .set_header(reqwest::header::ACCEPT, "application/octet-stream".parse()?)
-                .show_progress(true)
-                .download_to(&empty)?;
+                .show_progress(false)
+                .download_to(ProgressWriter::new(&empty, &*progress));
+            progress.finish();
+            download?;
 
             self_replace::self_replace(path)?;
         } else {
@@ -0,0 +1,122 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use logsh_core::{
+    config,
+    connect::Connection,
+    error::{ConfigError, ConnectError},
+    query::QueryResultFmt,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::OutputMode;
+
+#[derive(Debug, clap::Args)]
+#[clap(about = "Show the identity used by the default connection.")]
+pub struct WhoAmICommand {
+    #[arg(short, long, help = "Output result format")]
+    output: Option<OutputMode>,
+    #[arg(
+        long,
+        help = "Show the identity saved in the configuration without contacting the server."
+    )]
+    offline: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Identity {
+    user_id: uuid::Uuid,
+    username: String,
+    connection: String,
+    server: String,
+    auth_type: Option<&'static str>,
+    token_expires: Option<DateTime<Utc>>,
+    default_subscription: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cached: bool,
+}
+
+/// Asks the server who the connection is logged in as. With `offline`, or when the server
+/// can't be reached, falls back to the user id and name saved at login, returning `true` for
+/// cached.
+pub fn resolve_user(
+    connection: &Connection,
+    offline: bool,
+) -> Result<(uuid::Uuid, String, bool), ConnectError> {
+    if !offline {
+        match connection.who_am_i() {
+            Ok(user) => return Ok((user.user_id, user.user_name, false)),
+            Err(err) if err.is_unreachable() => {
+                log::warn!("{}. Showing the cached identity.", err)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok((connection.user_id, connection.username.to_string(), true))
+}
+
+pub fn execute_whoami<W: Write>(command: WhoAmICommand, write: W) -> Result<(), Error> {
+    let cfg = config::load()?;
+    let conn = cfg
+        .get_default_connection()
+        .ok_or(ConnectError::Config(ConfigError::NoDefaultConnection))?;
+    let (user_id, username, cached) =
+        resolve_user(&conn.connection, command.offline).inspect_err(|err| {
+            crate::fmt::print_connect_error(&cfg, err);
+        })?;
+
+    let identity = Identity {
+        user_id,
+        username,
+        connection: conn.name.to_string(),
+        server: conn.connection.server.to_string(),
+        auth_type: conn.connection.auth_type(),
+        token_expires: conn.connection.token_expires(),
+        default_subscription: conn.connection.default_subscription(),
+        cached,
+    };
+
+    crate::fmt::render(
+        write,
+        command.output.unwrap_or_else(OutputMode::configured),
+        &identity,
+        |identity| {
+            let or_none = |v: Option<String>| v.map_or(Value::Null, Value::String);
+            let mut properties = vec![
+                ("User Id", Value::String(identity.user_id.to_string())),
+                ("Username", Value::String(identity.username.clone())),
+                ("Connection", Value::String(identity.connection.clone())),
+                ("Server", Value::String(identity.server.clone())),
+                ("Auth Type", or_none(identity.auth_type.map(String::from))),
+                (
+                    "Token Expires",
+                    or_none(identity.token_expires.map(|e| e.to_rfc3339())),
+                ),
+                (
+                    "Default Subscription",
+                    or_none(identity.default_subscription.map(|s| s.to_string())),
+                ),
+            ];
+            if identity.cached {
+                properties.push(("Source", Value::String("cached".to_string())));
+            }
+            let results = properties
+                .into_iter()
+                .map(|(property, value)| {
+                    HashMap::from([
+                        ("Property".to_string(), Value::String(property.to_string())),
+                        ("Value".to_string(), value),
+                    ])
+                })
+                .collect();
+            QueryResultFmt {
+                header: vec!["Property".to_string(), "Value".to_string()],
+                results,
+            }
+        },
+    )
+}